@@ -7,7 +7,6 @@ network-visualizer = Network Visualizer
 profiler = Profiler
 pathfinding-lines = Pathfinding Lines
 network-debug = Network Debug
-networking-disabled-message = Networking not enabled on this platform.
 
 profiler = Profiler
 
@@ -29,6 +28,14 @@ predicted-frames = Predicted Frames
 predicted = predicted
 player = Player
 
+network-conditions = Network Conditions
+simulate-network-conditions = Simulate Network Conditions
+latency = Latency
+jitter = Jitter
+packet-loss = Packet Loss
+duplication = Duplication
+reordering = Reordering
+
 snapshot = Snapshot
 take-snapshot = Take Snapshot
 restore-snapshot = Restore Snapshot
@@ -0,0 +1,14 @@
+status-effect-speed-boost = Speed
+status-effect-slow = Slow
+status-effect-reversed-controls = Confused
+status-effect-frozen = Frozen
+status-effect-invincible = Invincible
+status-effect-on-fire = On Fire
+player-lives = P{ $player } Lives: { $lives }
+match-round = Round { $round } of { $count }
+player-wins = P{ $player } Wins: { $wins }
+match-winner = Player { $player } Wins!
+match-no-winner = Nobody Wins!
+round-winner = Player { $player } Wins the Round!
+round-no-winner = Nobody Wins the Round!
+next-round-hint = The next round is about to start
@@ -4,4 +4,7 @@ waiting-for-map = Waiting for other player to select map...
 default-maps = Default Maps
 experimental-maps = Experimental Maps
 user-maps = User Maps
-builtin-maps = Builtin Maps
\ No newline at end of file
+builtin-maps = Builtin Maps
+
+waiting-for-map-transfer = Waiting for other players to get the map...
+downloading-map = Downloading map: { $current } / { $total }
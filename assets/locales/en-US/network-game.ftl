@@ -2,21 +2,85 @@ lan = LAN
 online = Online
 host = Host
 join = Join
+spectate = Spectate
 servers = Servers
 players = Players
 no-servers = No Servers
+direct-connect = Direct Connect
+host-port = Players can also join directly on port { $port }
 server-name = Server Name
 start-server = Start Server
 stop-server = Stop Server
 fish-fight = Fish Fight
 configure-match = Configure Match
 player-count = Player Count
+local-player-count = Players on This Computer
 search = Search
 searching = Searching...
 search-for-match = Search for Match
+create-lobby = Create Private Lobby
+join-lobby = Join Lobby
+lobby-code = Lobby Code
+lobby-code-is = Lobby Code: { $code }
 connecting = Connecting...
 joining = Joining...
+spectators = Spectators: { $count }
+spectating = { $player ->
+    [all] Spectating
+   *[other] Spectating Player { $player }
+}
+spectator-controls = ◀ / ▶ to switch player
+network-player = P{ $player } { $name }
+ping = { $ms } ms
+rollback-frames = Rollback: { $frames } frames
+waiting-for-player = Waiting for { $players }...
 connected-and-querying = Connected
 waiting-for-players = Waiting for Players: { $current } / { $total }
 match-ready = Match Ready!
 error = Error
+verifying-players = Checking game versions...
+match-setup-protocol-mismatch = Player { $player } is using network protocol version { $remote }, but you are using version { $local }. Everybody must be running the same version of the game.
+match-setup-game-mismatch = Player { $player } is running Jumpy { $remote }, but you are running Jumpy { $local }. Everybody must be running the same version of the game.
+match-setup-asset-mismatch = Player { $player } has different game assets than you. Everybody must be running the same version of the game, without modified assets.
+network-error = Network Error
+network-error-invalid-address = The server address could not be found. Make sure it is in the format `host:port`.
+network-error-connection-failed = Could not connect. The server or player may be offline or unreachable.
+network-error-connection-lost = The connection was lost.
+network-error-player-disconnected = Player { $player } disconnected.
+network-error-invalid-message = Received an invalid message. Everybody must be running the same version of the game.
+network-error-service = A network service could not be started.
+network-error-invalid-lobby-code = That lobby code is not valid. Lobby codes look like `ABCDE-4-1`.
+network-error-untrusted-certificate = The matchmaking server's certificate could not be verified. Check the server and its certificate fingerprint in the network settings.
+network-error-certificate-changed = The host's identity changed since you last played with them, so someone may be impersonating them. If they reinstalled the game, forget the known LAN hosts in the network settings and try again.
+network-error-bind-failed = Could not listen for network connections. The address may be invalid or the port in use by another program, try different ones in the network settings.
+network-error-kicked = The host removed you from the match.
+network-error-map-transfer-failed = Could not get the selected map from the host.
+lobby-title = Lobby
+lobby-ready = Ready
+lobby-unready = Not Ready
+lobby-not-ready = Not ready
+start-match = Start Match
+leave = Leave
+kick = Kick
+kicked-player = Kicked Player
+match-rules = Match Rules
+map-pool = Map Pool
+lives = Lives
+lives-default = Default
+rounds = Rounds
+items = Items
+on = On
+off = Off
+chat = Chat
+chat-hint = Say something...
+send = Send
+quick-chat-hello = Hello!
+quick-chat-good-luck = Good luck!
+quick-chat-ready = Ready when you are!
+quick-chat-wait = Wait a moment!
+quick-chat-good-game = Good game!
+quick-chat-thanks = Thanks!
+map-voting = Map Voting
+map-votes = { $map } ({ $votes })
+own-map-votes = { $map } ({ $votes }) - Your Vote
+map-vote-result = { $map } won the vote!
@@ -12,3 +12,17 @@ action = Action
 # Networking settings
 networking = Networking
 matchmaking-server = Matchmaking Server
+server-fingerprint = Server Certificate Fingerprint
+known-lan-hosts = Known LAN Hosts
+bind-address = Listen Address
+bind-ports = Listen Port(s)
+forget = Forget
+disconnected-players = Disconnected Players
+disconnect-policy-ai-takeover = AI Takes Over
+disconnect-policy-remove = Removed Next Round
+input-delay = Input Delay (Frames)
+prediction-window = Prediction Window (Frames)
+automatic = Automatic
+network-hud = Connection Info During Matches
+shown = Shown
+hidden = Hidden
@@ -0,0 +1,70 @@
+//! Benchmarks for saving and restoring the game state, which network games do on every frame to be
+//! able to roll back.
+//!
+//! The match is played by four players on one of the core maps, loaded from the game's `assets`
+//! directory. Run the benchmarks with `cargo bench -p jumpy_core --features test-support`.
+
+use bevy::math::Vec2;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use jumpy_core::{
+    bevy_prelude::*,
+    input::PlayerControl,
+    test_support::{self, PLAYER_COUNT},
+};
+
+/// The number of frames played before measuring, so that the map and the players have spawned.
+const WARMUP_FRAMES: u32 = 120;
+
+/// Create a session on the [`test_support::MAP`] with [`PLAYER_COUNT`] players, and play it until
+/// the map and the players have spawned.
+fn four_player_session() -> (CoreSession, bevy::ecs::world::World) {
+    let (mut session, mut bevy_world) = test_support::four_player_session();
+    for frame in 0..WARMUP_FRAMES {
+        play_frame(&mut session, &mut bevy_world, frame);
+    }
+
+    (session, bevy_world)
+}
+
+/// Advance the session by a frame, with inputs that keep the players running and jumping around.
+fn play_frame(session: &mut CoreSession, bevy_world: &mut bevy::ecs::world::World, frame: u32) {
+    session.update_input(|inputs| {
+        for (player, input) in inputs.players.iter_mut().take(PLAYER_COUNT).enumerate() {
+            let phase = (frame as usize / 30 + player) % 4;
+            input.control = PlayerControl {
+                move_direction: Vec2::new(if phase < 2 { 1.0 } else { -1.0 }, 0.0),
+                jump_pressed: phase % 2 == 0,
+                jump_just_pressed: phase % 2 == 0 && frame % 30 == 0,
+                shoot_pressed: phase == 3,
+                ..Default::default()
+            };
+        }
+    });
+    session.advance(bevy_world);
+}
+
+fn snapshot_benchmark(c: &mut Criterion) {
+    let (mut session, mut bevy_world) = four_player_session();
+    let mut group = c.benchmark_group("four player match");
+
+    group.bench_function("snapshot", |b| b.iter(|| black_box(session.snapshot())));
+
+    // Restoring the saved state copies its stores, since GGRS keeps it for later rollbacks
+    let snapshot = session.snapshot();
+    group.bench_function("restore", |b| b.iter(|| session.restore(snapshot.clone())));
+
+    // A frame of a network game where nobody rolls back
+    let mut frame = WARMUP_FRAMES;
+    group.bench_function("frame with snapshot", |b| {
+        b.iter(|| {
+            play_frame(&mut session, &mut bevy_world, frame);
+            frame += 1;
+            black_box(session.snapshot())
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, snapshot_benchmark);
+criterion_main!(benches);
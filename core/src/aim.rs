@@ -0,0 +1,298 @@
+//! Directional aiming of held items, and charged throws.
+//!
+//! When an [`AimMeta::mode`] is set, items that have `allow_aim` set in their [`ElementMeta`] are
+//! aimed by holding shoot and pointing with the movement stick, and are used when shoot is
+//! released. While aiming, the player stands still and an aim indicator is shown.
+//!
+//! When [`AimMeta::throw_charge_time`] is set, held items are thrown when grab is released instead
+//! of when it is pressed, and are thrown harder the longer grab was held.
+//!
+//! The aim direction is only ever derived from the player's [`PlayerControl`], so it is the same on
+//! every client of a network game, after the movement direction has been quantized.
+
+use std::time::Duration;
+
+use crate::prelude::*;
+
+pub fn install(session: &mut CoreSession) {
+    session
+        .serializer
+        .register_component::<AimableItem>()
+        .register_component::<PlayerAim>();
+
+    session
+        .stages
+        .add_system_to_stage(CoreStage::First, hydrate_player_aim)
+        .add_system_to_stage(CoreStage::First, hydrate_aimable_items)
+        .add_system_to_stage(CoreStage::First, update_player_aim)
+        .add_system_to_stage(CoreStage::PostUpdate, update_aim_indicators);
+}
+
+/// The minimum movement stick length that will change the aim direction.
+const AIM_DEADZONE: f32 = 0.3;
+
+/// `tan(22.5°)`, the slope at which the aim direction switches between straight and diagonal in
+/// [`AimMode::EightWay`].
+const TAN_22_5: f32 = 0.414_213_57;
+
+impl AimMode {
+    /// Get the aim direction for the given movement stick direction, or `None` if the stick is
+    /// within the deadzone.
+    ///
+    /// This only uses operations that give the same result on every platform, so that it can be
+    /// used in network games.
+    pub fn direction(&self, move_direction: Vec2) -> Option<Vec2> {
+        if move_direction.length_squared() < AIM_DEADZONE * AIM_DEADZONE {
+            return None;
+        }
+
+        match self {
+            AimMode::Disabled => None,
+            AimMode::EightWay => {
+                let (abs_x, abs_y) = (move_direction.x.abs(), move_direction.y.abs());
+                let x = if abs_x > abs_y * TAN_22_5 {
+                    move_direction.x.signum()
+                } else {
+                    0.0
+                };
+                let y = if abs_y > abs_x * TAN_22_5 {
+                    move_direction.y.signum()
+                } else {
+                    0.0
+                };
+                Some(Vec2::new(x, y).normalize())
+            }
+            AimMode::Analog => Some(move_direction.normalize()),
+        }
+    }
+}
+
+/// Marker component for items that can be aimed.
+#[derive(Clone, Copy, Debug, TypeUlid, Default, Serialize, Deserialize)]
+#[ulid = "01H4D0Q9B8HNVP0PK12EJM8JDN"]
+pub struct AimableItem;
+
+/// The aiming and throw charging state of a player.
+#[derive(Clone, Debug, TypeUlid, Serialize, Deserialize)]
+#[ulid = "01H4D0Q9B8VQ5EF6XNKQKPNR54"]
+pub struct PlayerAim {
+    /// Whether the player is currently aiming their item.
+    pub aiming: bool,
+    /// The normalized direction that the player is aiming or facing, in world space.
+    pub direction: Vec2,
+    /// Set for the frame that the player releases shoot after aiming, when the item should be
+    /// used.
+    pub fire: bool,
+    /// How long grab has been held, if the player is charging a throw.
+    pub charge: Option<Duration>,
+    /// Set for the frame that the player releases grab after charging, when the item should be
+    /// thrown.
+    pub throw: bool,
+    /// The charge of the throw, between `0.0` and `1.0`, for the frame that the item is thrown.
+    pub throw_charge: f32,
+    /// The entity used to show the aim indicator.
+    pub indicator: Option<Entity>,
+}
+
+impl Default for PlayerAim {
+    fn default() -> Self {
+        Self {
+            aiming: false,
+            direction: Vec2::X,
+            fire: false,
+            charge: None,
+            throw: false,
+            throw_charge: 0.0,
+            indicator: None,
+        }
+    }
+}
+
+/// Give every player a [`PlayerAim`], and an aim indicator attached to it.
+fn hydrate_player_aim(
+    game_meta: Res<CoreMetaArc>,
+    mut entities: ResMut<Entities>,
+    player_indexes: Comp<PlayerIdx>,
+    mut player_aims: CompMut<PlayerAim>,
+    mut attachments: CompMut<Attachment>,
+    mut atlas_sprites: CompMut<AtlasSprite>,
+    mut transforms: CompMut<Transform>,
+) {
+    let mut not_hydrated_bitset = player_aims.bitset().clone();
+    not_hydrated_bitset.bit_not();
+    not_hydrated_bitset.bit_and(player_indexes.bitset());
+
+    let players = entities
+        .iter_with_bitset(&not_hydrated_bitset)
+        .collect::<Vec<_>>();
+
+    // The indicator is only shown while aiming
+    let indicator_atlas = (game_meta.config.aim.mode != AimMode::Disabled)
+        .then_some(game_meta.config.aim.indicator_atlas.as_ref())
+        .flatten();
+
+    for player_ent in players {
+        let indicator = indicator_atlas.map(|atlas| {
+            let ent = entities.create();
+            atlas_sprites.insert(
+                ent,
+                AtlasSprite {
+                    color: Color::NONE,
+                    ..AtlasSprite::new(atlas.clone())
+                },
+            );
+            transforms.insert(ent, default());
+            attachments.insert(
+                ent,
+                Attachment {
+                    entity: player_ent,
+                    offset: Vec3::ZERO,
+                    sync_animation: false,
+                    sync_color: false,
+                },
+            );
+            ent
+        });
+
+        player_aims.insert(
+            player_ent,
+            PlayerAim {
+                indicator,
+                ..default()
+            },
+        );
+    }
+}
+
+/// Mark the items that have `allow_aim` set in their metadata with [`AimableItem`], when aiming is
+/// enabled.
+fn hydrate_aimable_items(
+    game_meta: Res<CoreMetaArc>,
+    entities: Res<Entities>,
+    items: Comp<Item>,
+    element_handles: Comp<ElementHandle>,
+    element_assets: BevyAssets<ElementMeta>,
+    mut aimable_items: CompMut<AimableItem>,
+) {
+    if game_meta.config.aim.mode == AimMode::Disabled {
+        return;
+    }
+
+    let mut not_hydrated_bitset = aimable_items.bitset().clone();
+    not_hydrated_bitset.bit_not();
+    not_hydrated_bitset.bit_and(items.bitset());
+
+    for entity in entities.iter_with_bitset(&not_hydrated_bitset) {
+        let Some(element_handle) = element_handles.get(entity) else { continue };
+        let Some(element_meta) = element_assets.get(&element_handle.get_bevy_handle()) else {
+            continue;
+        };
+
+        if element_meta.allow_aim {
+            aimable_items.insert(entity, AimableItem);
+        }
+    }
+}
+
+/// Update the aim direction and throw charge from the player inputs.
+///
+/// While aiming, the player's movement input is cleared so that the player stands still.
+fn update_player_aim(
+    time: Res<Time>,
+    game_meta: Res<CoreMetaArc>,
+    entities: Res<Entities>,
+    player_indexes: Comp<PlayerIdx>,
+    inventories: Comp<Inventory>,
+    aimable_items: Comp<AimableItem>,
+    mut player_aims: CompMut<PlayerAim>,
+    mut atlas_sprites: CompMut<AtlasSprite>,
+    mut player_inputs: ResMut<PlayerInputs>,
+) {
+    let aim_meta = &game_meta.config.aim;
+
+    for (player_ent, (player_idx, inventory, aim)) in
+        entities.iter_with((&player_indexes, &inventories, &mut player_aims))
+    {
+        let control = &mut player_inputs.players[player_idx.0].control;
+        let Some(sprite) = atlas_sprites.get_mut(player_ent) else { continue };
+        let facing = if sprite.flip_x { -Vec2::X } else { Vec2::X };
+        let aimable = inventory
+            .0
+            .map(|item| aimable_items.contains(item))
+            .unwrap_or(false);
+
+        aim.fire = false;
+        aim.throw = false;
+        aim.throw_charge = 0.0;
+
+        if aimable && control.shoot_pressed {
+            if !aim.aiming {
+                aim.aiming = true;
+                aim.direction = facing;
+            }
+            if let Some(direction) = aim_meta.mode.direction(control.move_direction) {
+                aim.direction = direction;
+            }
+            if aim.direction.x != 0.0 {
+                sprite.flip_x = aim.direction.x < 0.0;
+            }
+
+            control.move_direction = Vec2::ZERO;
+            control.moving = false;
+            control.just_moved = false;
+        } else if aim.aiming {
+            aim.aiming = false;
+            aim.fire = aimable;
+        } else {
+            aim.direction = facing;
+        }
+
+        if aim_meta.throw_charge_time.is_zero() || inventory.0.is_none() {
+            aim.charge = None;
+        } else if control.grab_just_pressed {
+            aim.charge = Some(Duration::ZERO);
+        } else if let Some(charge) = &mut aim.charge {
+            if control.grab_pressed {
+                *charge += time.delta();
+            } else {
+                aim.throw = true;
+                aim.throw_charge =
+                    (charge.as_secs_f32() / aim_meta.throw_charge_time.as_secs_f32()).min(1.0);
+                aim.charge = None;
+            }
+        }
+    }
+}
+
+/// Show the aim indicator of the players that are aiming.
+fn update_aim_indicators(
+    game_meta: Res<CoreMetaArc>,
+    entities: Res<Entities>,
+    player_aims: Comp<PlayerAim>,
+    mut attachments: CompMut<Attachment>,
+    mut atlas_sprites: CompMut<AtlasSprite>,
+) {
+    let distance = game_meta.config.aim.indicator_distance;
+
+    for (player_ent, aim) in entities.iter_with(&player_aims) {
+        let Some(indicator) = aim.indicator else { continue };
+        let flip_x = atlas_sprites
+            .get(player_ent)
+            .map(|x| x.flip_x)
+            .unwrap_or_default();
+
+        if let Some(attachment) = attachments.get_mut(indicator) {
+            // Attachment offsets are flipped along with the player sprite
+            let flip_factor = if flip_x { -1.0 } else { 1.0 };
+            attachment.offset =
+                (aim.direction * Vec2::new(flip_factor, 1.0) * distance).extend(1.0);
+        }
+        if let Some(sprite) = atlas_sprites.get_mut(indicator) {
+            sprite.color = if aim.aiming {
+                Color::WHITE
+            } else {
+                Color::NONE
+            };
+        }
+    }
+}
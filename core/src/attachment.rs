@@ -8,6 +8,12 @@
 use crate::prelude::*;
 
 pub fn install(session: &mut CoreSession) {
+    session
+        .serializer
+        .register_component::<Attachment>()
+        .register_component::<PlayerBodyAttachment>()
+        .register_component::<HadPlayerBodyAttachmentMarker>();
+
     session
         .stages
         .add_system_to_stage(CoreStage::Last, update_player_body_attachments)
@@ -24,7 +30,7 @@ pub fn install(session: &mut CoreSession) {
 /// Attachments have special behavior built-in for attaching to other [`Sprite`] or [`AtlasSprite`]
 /// entities. When attached to a sprite, the attached item will automatically flip it's offset when
 /// the sprite is flipped, and will also synchronize it's own flip value, if it has a sprite component.
-#[derive(Clone, Copy, TypeUlid, Debug)]
+#[derive(Clone, Copy, TypeUlid, Debug, Serialize, Deserialize)]
 #[ulid = "01GQJQ07Z0S07B7EWDA4HW938Q"]
 pub struct Attachment {
     /// The entity to attach to.
@@ -122,7 +128,7 @@ pub fn update_attachments(
 /// This is similar to the [`Attachment`] component, but it is special in the way that it will
 /// follow the body as it bobs up and down in animations such as standing and walking. This makes it
 /// useful for things like hats, etc., that will stick to the player's body.
-#[derive(Clone, TypeUlid)]
+#[derive(Clone, TypeUlid, Serialize, Deserialize)]
 #[ulid = "01GQQSZS823YZS2RBAPFNBKB8B"]
 pub struct PlayerBodyAttachment {
     /// The player to attach to.
@@ -154,7 +160,7 @@ impl PlayerBodyAttachment {
 /// This is used by the [`update_player_body_attachments`] system internally.
 ///
 /// It keeps track whether or not an entity had a [`PlayerBodyAttachment`] on the last frame.
-#[derive(Clone, Copy, TypeUlid)]
+#[derive(Clone, Copy, TypeUlid, Serialize, Deserialize)]
 #[ulid = "01GQQWDPHAJKNM686ZY425V4XF"]
 struct HadPlayerBodyAttachmentMarker;
 
@@ -9,6 +9,11 @@ use crate::{
 
 /// Install this module.
 pub fn install(session: &mut CoreSession) {
+    session
+        .serializer
+        .register_component::<Bullet>()
+        .register_component::<BulletHandle>();
+
     session
         .stages
         .add_system_to_stage(CoreStage::PreUpdate, hydrate)
@@ -16,17 +21,19 @@ pub fn install(session: &mut CoreSession) {
 }
 
 /// Bullet component.
-#[derive(Clone, Debug, TypeUlid, Copy)]
+#[derive(Clone, Debug, TypeUlid, Copy, Serialize, Deserialize)]
 #[ulid = "01GQX3KM2A4WPV2NKJNG85TJ3P"]
 pub struct Bullet {
-    /// The direction that the bullet is moving on the X axis.
-    pub direction: f32,
+    /// The normalized direction that the bullet is moving in.
+    ///
+    /// The bullet velocity from its [`BulletMeta`] is rotated to point in this direction.
+    pub direction: Vec2,
     /// The player entity that shot the bullet.
     pub owner: Entity,
 }
 
 /// Component containing the bullet's metadata handle.
-#[derive(Deref, DerefMut, TypeUlid, Clone)]
+#[derive(Deref, DerefMut, TypeUlid, Clone, Serialize, Deserialize)]
 #[ulid = "01GR1WH27X84VX22G0JY9J71PC"]
 pub struct BulletHandle(pub Handle<BulletMeta>);
 
@@ -115,7 +122,7 @@ fn update(
         // Move bullet
         let position = {
             let position = transforms.get_mut(entity).unwrap();
-            position.translation += bullet.direction * velocity.extend(0.0);
+            position.translation += bullet.direction.rotate(*velocity).extend(0.0);
 
             let emote_size = Vec2::new(*body_diameter * 6.0, *body_diameter * 3.5);
             emote_regions.insert(entity, EmoteRegion::basic(Emote::Alarm, emote_size, true));
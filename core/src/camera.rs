@@ -4,6 +4,14 @@ use crate::prelude::*;
 
 /// Install this module.
 pub fn install(session: &mut CoreSession) {
+    session
+        .serializer
+        .register_component::<ParallaxBackgroundSprite>()
+        .register_component::<CameraState>()
+        // The camera only follows the players, so it doesn't need to be rolled back with them
+        .skip_snapshots::<ParallaxBackgroundSprite>()
+        .skip_snapshots::<CameraState>();
+
     session
         .stages
         .add_system_to_stage(CoreStage::Last, camera_controller);
@@ -13,7 +21,7 @@ pub fn install(session: &mut CoreSession) {
 }
 
 /// A sprite that is spawned as a part of the parallax background.
-#[derive(Clone, TypeUlid)]
+#[derive(Clone, TypeUlid, Serialize, Deserialize)]
 #[ulid = "01GPP1V3PCENFWC8H6H705ST80"]
 pub struct ParallaxBackgroundSprite {
     /// The sprite with `idx` of `0` will be centered, with indexes increasing and decreasing
@@ -24,7 +32,7 @@ pub struct ParallaxBackgroundSprite {
 }
 
 /// The state of the camera.
-#[derive(Clone, Debug, TypeUlid, Default)]
+#[derive(Clone, Debug, TypeUlid, Default, Serialize, Deserialize)]
 #[ulid = "01GPV6M1KY0GBRQVJ3WG5CSBBS"]
 pub struct CameraState {
     /// A rectangle around the player that is larger than the player, and will always move to
@@ -38,6 +46,9 @@ pub struct CameraState {
     /// Disables the default camera controller. Useful, for example, when taking over the camera
     /// from the editor.
     pub disable_controller: bool,
+    /// Makes the camera follow only this player, as long as they are on the map. Used by network
+    /// spectators to watch a single player.
+    pub focused_player: Option<usize>,
 }
 
 /// Implemenets the camera controller.
@@ -117,10 +128,13 @@ fn camera_controller(
     let mut min = Vec2::new(f32::MAX, f32::MAX);
     let mut max = Vec2::new(f32::MIN, f32::MIN);
 
-    let players: Vec<usize> = entities
+    let mut players: Vec<usize> = entities
         .iter_with(&player_indexes)
         .map(|x| x.1 .0)
         .collect();
+    if let Some(focused) = camera_state.focused_player.filter(|x| players.contains(x)) {
+        players = vec![focused];
+    }
     let player_count = players.len();
 
     for player_idx in players {
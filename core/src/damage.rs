@@ -6,6 +6,11 @@ use crate::prelude::*;
 
 /// Install this module.
 pub fn install(session: &mut CoreSession) {
+    session
+        .serializer
+        .register_component::<DamageRegion>()
+        .register_component::<DamageRegionOwner>();
+
     session
         .stages
         .add_system_to_stage(CoreStage::PostUpdate, kill_players_in_damage_region);
@@ -15,7 +20,7 @@ pub fn install(session: &mut CoreSession) {
 ///
 /// While this _might_ change in the future, damage regions will kill players immediately, so there
 /// is no "damage" field.
-#[derive(Debug, Clone, Default, TypeUlid)]
+#[derive(Debug, Clone, Default, TypeUlid, Serialize, Deserialize)]
 #[ulid = "01GP1X5MBXZNEC4Y0WF5AKCA3Z"]
 pub struct DamageRegion {
     /// The size of the damage region in pixels
@@ -32,7 +37,7 @@ impl DamageRegion {
 /// A component that may be added to a damage region entity to indicate the triggering entity.
 ///
 /// If this entity is a player, it will not be harmed by the damage region.
-#[derive(Debug, Clone, TypeUlid)]
+#[derive(Debug, Clone, TypeUlid, Serialize, Deserialize)]
 #[ulid = "01GP1X4NM7GMEKKZ4FEZ1RK3T0"]
 pub struct DamageRegionOwner(pub Entity);
 
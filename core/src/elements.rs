@@ -16,6 +16,7 @@ pub mod crate_item;
 pub mod decoration;
 pub mod fish_school;
 pub mod grenade;
+pub mod item_spawner;
 pub mod kick_bomb;
 pub mod mine;
 pub mod musket;
@@ -30,7 +31,7 @@ pub mod sword;
 pub mod urchin;
 
 /// Marker component added to map elements that have been hydrated.
-#[derive(Clone, TypeUlid)]
+#[derive(Clone, TypeUlid, Serialize, Deserialize)]
 #[ulid = "01GP42Q5GCY5Y4JC7SQ1YRHYKN"]
 pub struct MapElementHydrated;
 
@@ -39,12 +40,12 @@ pub struct MapElementHydrated;
 ///
 /// This is useful for map elements that spawn items: when the item falls off the map, it should
 /// de-hydrate it's spawner, so that the spawner will re-spawn the item in it's default state.
-#[derive(Clone, TypeUlid, Deref, DerefMut)]
+#[derive(Clone, TypeUlid, Deref, DerefMut, Serialize, Deserialize)]
 #[ulid = "01GP9NY0Y50Y2A8M4A7E9NN8VE"]
 pub struct DehydrateOutOfBounds(pub Entity);
 
 /// Component containing an element's metadata handle.
-#[derive(Clone, TypeUlid, Deref, DerefMut, Default)]
+#[derive(Clone, TypeUlid, Deref, DerefMut, Default, Serialize, Deserialize)]
 #[ulid = "01GP421CHN323T2614F19PA5E9"]
 pub struct ElementHandle(pub Handle<ElementMeta>);
 
@@ -62,7 +63,7 @@ impl ElementKillCallback {
     }
 }
 
-#[derive(Clone, TypeUlid)]
+#[derive(Clone, TypeUlid, Serialize, Deserialize)]
 #[ulid = "01H0AQGTZCVZJXPF2KSR73TQTR"]
 pub struct Spawner {
     /// The group identifier where all of the elements are meant to be shared between them
@@ -88,7 +89,7 @@ impl Spawner {
     }
 }
 
-#[derive(Resource, TypeUlid, Clone, Default)]
+#[derive(Resource, TypeUlid, Clone, Default, Serialize, Deserialize)]
 #[ulid = "01H11SA5GRF6Y6N20XKB8JR5TE"]
 pub struct SpawnerEntities {
     pub entities_per_spawner_group_identifier: HashMap<String, Vec<Entity>>,
@@ -228,6 +229,16 @@ impl<'a> SpawnerManager<'a> {
 }
 
 pub fn install(session: &mut CoreSession) {
+    // The kill callbacks hold systems, so they can't be saved, and are added back by the modules
+    // that create them when a session is loaded.
+    session
+        .serializer
+        .register_component::<MapElementHydrated>()
+        .register_component::<DehydrateOutOfBounds>()
+        .register_component::<ElementHandle>()
+        .register_component::<Spawner>()
+        .register_resource::<SpawnerEntities>();
+
     session
         .stages
         .add_system_to_stage(CoreStage::First, handle_out_of_bounds_items);
@@ -249,6 +260,7 @@ pub fn install(session: &mut CoreSession) {
     slippery_seaweed::install(session);
     slippery::install(session);
     spike::install(session);
+    item_spawner::install(session);
 }
 
 fn handle_out_of_bounds_items(
@@ -1,13 +1,15 @@
 use crate::{prelude::*, random::GlobalRng};
 
 pub fn install(session: &mut CoreSession) {
+    session.serializer.register_component::<CrabCritter>();
+
     session
         .stages
         .add_system_to_stage(CoreStage::PreUpdate, hydrate)
         .add_system_to_stage(CoreStage::PostUpdate, update_crabs);
 }
 
-#[derive(Default, Clone, TypeUlid, Debug, Copy)]
+#[derive(Default, Clone, TypeUlid, Debug, Copy, Serialize, Deserialize)]
 #[ulid = "01GQ0HN03T4KVTB95CDZ6YG48G"]
 pub enum CrabState {
     #[default]
@@ -28,7 +30,7 @@ impl CrabState {
     }
 }
 
-#[derive(TypeUlid, Clone, Default)]
+#[derive(TypeUlid, Clone, Default, Serialize, Deserialize)]
 #[ulid = "01GQ0J08W112T1JVB0QJ42HJSE"]
 pub struct CrabCritter {
     state: CrabState,
@@ -3,6 +3,11 @@ use crate::{physics::collisions::TileCollisionKind, prelude::*};
 use std::time::Duration;
 
 pub fn install(session: &mut CoreSession) {
+    session
+        .serializer
+        .register_component::<IdleCrate>()
+        .register_component::<ThrownCrate>();
+
     session
         .stages
         .add_system_to_stage(CoreStage::PreUpdate, hydrate_crates)
@@ -10,11 +15,11 @@ pub fn install(session: &mut CoreSession) {
         .add_system_to_stage(CoreStage::PostUpdate, update_thrown_crates);
 }
 
-#[derive(Clone, TypeUlid)]
+#[derive(Clone, TypeUlid, Serialize, Deserialize)]
 #[ulid = "01GREP3MZXY4A14PQ8GRKS0RVY"]
 struct IdleCrate;
 
-#[derive(Clone, TypeUlid)]
+#[derive(Clone, TypeUlid, Serialize, Deserialize)]
 #[ulid = "01GREP80RJSH9T9MWC88CG2G03"]
 struct ThrownCrate {
     owner: Entity,
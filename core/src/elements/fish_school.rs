@@ -3,26 +3,31 @@ use std::time::Duration;
 use crate::{prelude::*, random::GlobalRng};
 
 pub fn install(session: &mut CoreSession) {
+    session
+        .serializer
+        .register_component::<FishSchool>()
+        .register_component::<Fish>();
+
     session
         .stages
         .add_system_to_stage(CoreStage::PreUpdate, hydrate)
         .add_system_to_stage(CoreStage::PostUpdate, update_fish_schools);
 }
 
-#[derive(Default, Clone, TypeUlid, Debug, Deref, DerefMut)]
+#[derive(Default, Clone, TypeUlid, Debug, Deref, DerefMut, Serialize, Deserialize)]
 #[ulid = "01GTF48H2WEFC3GSED8V7JNN0K"]
 pub struct FishSchool {
     fish: Vec<Entity>,
 }
 
-#[derive(Clone, TypeUlid, Debug)]
+#[derive(Clone, TypeUlid, Debug, Serialize, Deserialize)]
 #[ulid = "01GTF49M4WVD4B3G8W9QVV2JCX"]
 pub struct Fish {
     state: FishState,
     state_timer: Timer,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum FishState {
     /// Moving to a location
     Moving { from: Vec2, to: Vec2 },
@@ -2,6 +2,11 @@ use crate::prelude::*;
 use std::time::Duration;
 
 pub fn install(session: &mut CoreSession) {
+    session
+        .serializer
+        .register_component::<IdleGrenade>()
+        .register_component::<LitGrenade>();
+
     session
         .stages
         .add_system_to_stage(CoreStage::PreUpdate, hydrate)
@@ -9,11 +14,11 @@ pub fn install(session: &mut CoreSession) {
         .add_system_to_stage(CoreStage::PostUpdate, update_idle_grenades);
 }
 
-#[derive(Clone, TypeUlid, Debug, Copy)]
+#[derive(Clone, TypeUlid, Debug, Copy, Serialize, Deserialize)]
 #[ulid = "01GPRSBWQ3X0QJC37BDDQXDN84"]
 pub struct IdleGrenade;
 
-// #[derive(Clone, TypeUlid, Debug, Copy)]
+// #[derive(Clone, TypeUlid, Debug, Copy, Serialize, Deserialize)]
 #[derive(Clone, TypeUlid, Debug)]
 #[ulid = "01GPY9N9CBR6EFJX0RS2H2K58J"]
 pub struct LitGrenade {
@@ -30,6 +35,7 @@ fn hydrate(
     mut element_handles: CompMut<ElementHandle>,
     element_assets: BevyAssets<ElementMeta>,
     mut idle_grenades: CompMut<IdleGrenade>,
+    mut item_uses: CompMut<ItemUses>,
     mut atlas_sprites: CompMut<AtlasSprite>,
     mut animated_sprites: CompMut<AnimatedSprite>,
     mut bodies: CompMut<KinematicBody>,
@@ -64,6 +70,7 @@ fn hydrate(
             bounciness,
             throw_velocity,
             angular_velocity,
+            uses,
             ..
         } = &element_meta.builtin
         {
@@ -72,6 +79,7 @@ fn hydrate(
             let entity = entities.create();
             items.insert(entity, Item);
             idle_grenades.insert(entity, IdleGrenade);
+            item_uses.insert(entity, ItemUses::new(uses.clone()));
             item_throws.insert(
                 entity,
                 ItemThrow::strength(*throw_velocity).with_spin(*angular_velocity),
@@ -113,6 +121,7 @@ fn update_idle_grenades(
     mut commands: Commands,
     entities: Res<Entities>,
     items_used: Comp<ItemUsed>,
+    mut item_uses: CompMut<ItemUses>,
     element_handles: Comp<ElementHandle>,
     element_assets: BevyAssets<ElementMeta>,
     mut audio_events: ResMut<AudioEvents>,
@@ -136,7 +145,12 @@ fn update_idle_grenades(
         };
         let fuse_time = *fuse_time;
 
-        if items_used.get(entity).is_some() {
+        if items_used.get(entity).is_some()
+            && item_uses
+                .get_mut(entity)
+                .map(|uses| uses.try_use())
+                .unwrap_or(true)
+        {
             // Animate Grenade
             let animated_sprite = animated_sprites.get_mut(entity).unwrap();
             animated_sprite.frames = Arc::from([3, 4, 5]);
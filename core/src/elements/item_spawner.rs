@@ -0,0 +1,206 @@
+//! Item spawners, that keep a map supplied with items during long matches.
+//!
+//! An item spawner doesn't spawn items directly. Instead it creates an [`ItemSpawnerSlot`] entity
+//! with the [`ElementHandle`] of the item that was picked from its loot table, which is then
+//! hydrated by the item's own element implementation, just like an item placed in the map.
+
+use std::time::Duration;
+
+use crate::{prelude::*, random::GlobalRng};
+
+pub fn install(session: &mut CoreSession) {
+    session
+        .serializer
+        .register_component::<ItemSpawner>()
+        .register_component::<ItemSpawnerSlot>();
+
+    session
+        .stages
+        .add_system_to_stage(CoreStage::PreUpdate, hydrate)
+        .add_system_to_stage(CoreStage::First, update);
+}
+
+/// How many times per second the telegraph sprite blinks before an item spawns.
+const TELEGRAPH_BLINK_RATE: f32 = 6.0;
+
+#[derive(Clone, Debug, TypeUlid, Default, Serialize, Deserialize)]
+#[ulid = "01H4D0DJX5QYSZGAB11ZF2NPJG"]
+pub struct ItemSpawner {
+    pub loot_table: Vec<LootTableEntry>,
+    pub respawn_time: Duration,
+    pub telegraph_time: Duration,
+    /// The slot that the current item was spawned from, if it hasn't been taken yet.
+    pub slot: Option<Entity>,
+    /// The timer until the next item is spawned.
+    pub timer: Timer,
+}
+
+/// Marker component for the entities that an [`ItemSpawner`] spawns items from.
+///
+/// Once the item has been taken, the slot's [`ElementHandle`] is removed so that the item won't be
+/// re-spawned from the slot, and the slot is cleaned up once the item no longer refers to it.
+#[derive(Clone, Debug, TypeUlid, Default, Serialize, Deserialize)]
+#[ulid = "01H4D0DJX50T5YAQ0EMV8DQSJ9"]
+pub struct ItemSpawnerSlot;
+
+fn hydrate(
+    entities: Res<Entities>,
+    mut hydrated: CompMut<MapElementHydrated>,
+    element_handles: Comp<ElementHandle>,
+    element_assets: BevyAssets<ElementMeta>,
+    mut item_spawners: CompMut<ItemSpawner>,
+    mut atlas_sprites: CompMut<AtlasSprite>,
+    mut spawner_manager: SpawnerManager,
+) {
+    let mut not_hydrated_bitset = hydrated.bitset().clone();
+    not_hydrated_bitset.bit_not();
+    not_hydrated_bitset.bit_and(element_handles.bitset());
+
+    for entity in entities.iter_with_bitset(&not_hydrated_bitset) {
+        let element_handle = element_handles.get(entity).unwrap();
+        let Some(element_meta) = element_assets.get(&element_handle.get_bevy_handle()) else {
+            continue;
+        };
+
+        if let BuiltinElementKind::ItemSpawner {
+            loot_table,
+            respawn_time,
+            telegraph_time,
+            telegraph_atlas,
+            spawn_on_start,
+        } = &element_meta.builtin
+        {
+            hydrated.insert(entity, MapElementHydrated);
+            atlas_sprites.insert(
+                entity,
+                AtlasSprite {
+                    color: Color::NONE,
+                    ..AtlasSprite::new(telegraph_atlas.clone())
+                },
+            );
+            item_spawners.insert(
+                entity,
+                ItemSpawner {
+                    loot_table: loot_table.clone(),
+                    respawn_time: *respawn_time,
+                    telegraph_time: *telegraph_time,
+                    slot: None,
+                    timer: Timer::new(
+                        if *spawn_on_start {
+                            Duration::ZERO
+                        } else {
+                            *respawn_time
+                        },
+                        TimerMode::Once,
+                    ),
+                },
+            );
+            spawner_manager.create_spawner(entity, vec![]);
+        }
+    }
+}
+
+fn update(
+    time: Res<Time>,
+    rng: Res<GlobalRng>,
+    mut entities: ResMut<Entities>,
+    mut item_spawners: CompMut<ItemSpawner>,
+    mut item_spawner_slots: CompMut<ItemSpawnerSlot>,
+    hydrated: Comp<MapElementHydrated>,
+    mut element_handles: CompMut<ElementHandle>,
+    mut transforms: CompMut<Transform>,
+    mut atlas_sprites: CompMut<AtlasSprite>,
+    item_spawns: Comp<DehydrateOutOfBounds>,
+    inventories: Comp<Inventory>,
+) {
+    let held_items = entities
+        .iter_with(&inventories)
+        .filter_map(|(_ent, inventory)| inventory.0)
+        .collect::<Vec<_>>();
+    // The items spawned from each slot
+    let slot_items = entities
+        .iter_with(&item_spawns)
+        .map(|(item_ent, spawn)| (spawn.0, item_ent))
+        .filter(|(spawn_ent, _)| item_spawner_slots.contains(*spawn_ent))
+        .collect::<Vec<_>>();
+
+    let mut new_slots = Vec::new();
+    for (spawner_ent, item_spawner) in entities.iter_with(&mut item_spawners) {
+        if let Some(slot) = item_spawner.slot {
+            // The slot is de-hydrated by the item when the item is used up or falls out of the map.
+            let is_used = !hydrated.contains(slot);
+            let is_held = slot_items
+                .iter()
+                .any(|(slot_ent, item_ent)| *slot_ent == slot && held_items.contains(item_ent));
+
+            if is_used || is_held {
+                element_handles.remove(slot);
+                item_spawner.slot = None;
+                item_spawner.timer = Timer::new(item_spawner.respawn_time, TimerMode::Once);
+            }
+            continue;
+        }
+
+        item_spawner.timer.tick(time.delta());
+
+        let Some(sprite) = atlas_sprites.get_mut(spawner_ent) else { continue };
+        if item_spawner.timer.finished() {
+            sprite.color = Color::NONE;
+
+            let Some(element) = pick_loot(&item_spawner.loot_table, rng.f32()) else { continue };
+            let Some(transform) = transforms.get(spawner_ent).copied() else { continue };
+            new_slots.push((spawner_ent, element, transform));
+        } else if item_spawner.timer.remaining() <= item_spawner.telegraph_time {
+            let blink_on = (item_spawner.timer.remaining_secs() * TELEGRAPH_BLINK_RATE) as u32 % 2;
+            sprite.color = Color::WHITE.with_a(if blink_on == 0 { 0.8 } else { 0.3 });
+        }
+    }
+
+    for (spawner_ent, element, transform) in new_slots {
+        let slot = entities.create();
+        item_spawner_slots.insert(slot, ItemSpawnerSlot);
+        element_handles.insert(slot, ElementHandle(element));
+        transforms.insert(slot, transform);
+
+        if let Some(item_spawner) = item_spawners.get_mut(spawner_ent) {
+            item_spawner.slot = Some(slot);
+        }
+    }
+
+    // Clean up the slots that have been emptied and aren't referred to by their item anymore
+    let unused_slots = entities
+        .iter_with(&item_spawner_slots)
+        .map(|(slot_ent, _)| slot_ent)
+        .filter(|slot_ent| {
+            !element_handles.contains(*slot_ent)
+                && !slot_items.iter().any(|(ent, _)| ent == slot_ent)
+        })
+        .collect::<Vec<_>>();
+    for slot_ent in unused_slots {
+        entities.kill(slot_ent);
+    }
+}
+
+/// Pick an element from the loot table, according to the entry weights, using a random number
+/// between `0.0` and `1.0`.
+fn pick_loot(loot_table: &[LootTableEntry], random: f32) -> Option<Handle<ElementMeta>> {
+    let total_weight = loot_table.iter().map(|x| x.weight.max(0.0)).sum::<f32>();
+    if total_weight <= 0.0 {
+        return None;
+    }
+
+    let mut remaining = random * total_weight;
+    for entry in loot_table {
+        remaining -= entry.weight.max(0.0);
+        if remaining < 0.0 {
+            return Some(entry.element.clone());
+        }
+    }
+
+    // Rounding errors may leave us just past the end of the table
+    loot_table
+        .iter()
+        .rev()
+        .find(|x| x.weight > 0.0)
+        .map(|x| x.element.clone())
+}
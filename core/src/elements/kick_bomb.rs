@@ -1,6 +1,11 @@
 use crate::prelude::*;
 
 pub fn install(session: &mut CoreSession) {
+    session
+        .serializer
+        .register_component::<IdleKickBomb>()
+        .register_component::<LitKickBomb>();
+
     session
         .stages
         .add_system_to_stage(CoreStage::PreUpdate, hydrate)
@@ -8,11 +13,11 @@ pub fn install(session: &mut CoreSession) {
         .add_system_to_stage(CoreStage::PostUpdate, update_idle_kick_bombs);
 }
 
-#[derive(Clone, TypeUlid, Debug, Copy)]
+#[derive(Clone, TypeUlid, Debug, Copy, Serialize, Deserialize)]
 #[ulid = "01GQ0ZWBNA8HZRXYKZXCT05CXT"]
 pub struct IdleKickBomb;
 
-#[derive(Clone, TypeUlid, Debug)]
+#[derive(Clone, TypeUlid, Debug, Serialize, Deserialize)]
 #[ulid = "01GQ0ZWFYZSHJPESPY9QPSTARR"]
 pub struct LitKickBomb {
     arm_delay: Timer,
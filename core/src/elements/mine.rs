@@ -2,6 +2,11 @@ use crate::prelude::*;
 use std::time::Duration;
 
 pub fn install(session: &mut CoreSession) {
+    session
+        .serializer
+        .register_component::<IdleMine>()
+        .register_component::<ThrownMine>();
+
     session
         .stages
         .add_system_to_stage(CoreStage::PreUpdate, hydrate)
@@ -9,11 +14,11 @@ pub fn install(session: &mut CoreSession) {
         .add_system_to_stage(CoreStage::PostUpdate, update_idle_mines);
 }
 
-#[derive(Clone, TypeUlid, Debug, Copy)]
+#[derive(Clone, TypeUlid, Debug, Copy, Serialize, Deserialize)]
 #[ulid = "01GPRSBWQ3X0QJC37BDDQXDNF3"]
 pub struct IdleMine;
 
-#[derive(Clone, TypeUlid, Debug)]
+#[derive(Clone, TypeUlid, Debug, Serialize, Deserialize)]
 #[ulid = "01GPRSBWQ3X0QJC37BDQXDNASF"]
 pub struct ThrownMine {
     // The mine won't explode until this timer finishes.
@@ -3,16 +3,17 @@ use std::time::Duration;
 use crate::prelude::*;
 
 pub fn install(session: &mut CoreSession) {
+    session.serializer.register_component::<Musket>();
+
     session
         .stages
         .add_system_to_stage(CoreStage::PreUpdate, hydrate)
         .add_system_to_stage(CoreStage::PostUpdate, update);
 }
 
-#[derive(Clone, Debug, TypeUlid, Default)]
+#[derive(Clone, Debug, TypeUlid, Default, Serialize, Deserialize)]
 #[ulid = "01GQWRRV9HV52X9JAYYF1AFFS7"]
 pub struct Musket {
-    pub ammo: usize,
     pub cooldown: Timer,
 }
 
@@ -23,6 +24,7 @@ fn hydrate(
     mut element_handles: CompMut<ElementHandle>,
     element_assets: BevyAssets<ElementMeta>,
     mut muskets: CompMut<Musket>,
+    mut item_uses: CompMut<ItemUses>,
     mut atlas_sprites: CompMut<AtlasSprite>,
     mut bodies: CompMut<KinematicBody>,
     mut transforms: CompMut<Transform>,
@@ -51,7 +53,7 @@ fn hydrate(
             atlas,
             fin_anim,
             grab_offset,
-            max_ammo,
+            ammo,
             body_size,
             can_rotate,
             bounciness,
@@ -66,9 +68,7 @@ fn hydrate(
             items.insert(entity, Item);
             item_throws.insert(
                 entity,
-                ItemThrow::strength(*throw_velocity)
-                    .with_spin(*angular_velocity)
-                    .with_system(musket_drop(entity, *max_ammo)),
+                ItemThrow::strength(*throw_velocity).with_spin(*angular_velocity),
             );
             item_grabs.insert(
                 entity,
@@ -81,10 +81,10 @@ fn hydrate(
             muskets.insert(
                 entity,
                 Musket {
-                    ammo: *max_ammo,
                     cooldown: Timer::new(Duration::from_millis(0), TimerMode::Once),
                 },
             );
+            item_uses.insert(entity, ItemUses::new(ammo.clone()));
             atlas_sprites.insert(entity, AtlasSprite::new(atlas.clone()));
             respawn_points.insert(entity, DehydrateOutOfBounds(spawner_ent));
             transforms.insert(entity, transform);
@@ -114,13 +114,14 @@ fn update(
     element_assets: BevyAssets<ElementMeta>,
 
     mut muskets: CompMut<Musket>,
+    mut item_uses: CompMut<ItemUses>,
     transforms: CompMut<Transform>,
     mut sprites: CompMut<AtlasSprite>,
     mut audio_events: ResMut<AudioEvents>,
 
     player_inventories: PlayerInventories,
+    player_aims: Comp<PlayerAim>,
     mut items_used: CompMut<ItemUsed>,
-    items_dropped: CompMut<ItemDropped>,
     time: Res<Time>,
 
     mut bodies: CompMut<KinematicBody>,
@@ -131,7 +132,6 @@ fn update(
         };
 
         let BuiltinElementKind::Musket {
-            max_ammo,
             shoot_fps,
             shoot_atlas,
             shoot_frames,
@@ -163,28 +163,40 @@ fn update(
                 items_used.remove(entity);
             }
             if item_used && musket.cooldown.finished() {
-                // Empty
-                if musket.ammo.eq(&0) {
+                // Subtract ammo, unless we're empty
+                let Some(ammo) = item_uses.get_mut(entity) else { continue };
+                if !ammo.try_use() {
                     audio_events.play(empty_shoot_sound.clone(), *empty_shoot_sound_volume);
                     continue;
                 }
 
-                // Reset fire cooldown and subtract ammo
+                // Reset fire cooldown
                 musket.cooldown = Timer::new(*cooldown, TimerMode::Once);
-                musket.ammo = musket.ammo.saturating_sub(1).clamp(0, musket.ammo);
                 audio_events.play(shoot_sound.clone(), *shoot_sound_volume);
 
                 let player_sprite = sprites.get_mut(player).unwrap();
                 let player_flip_x = player_sprite.flip_x;
                 let player_body = bodies.get_mut(player).unwrap();
 
+                // Shoot in the direction the player is aiming, or straight ahead
+                let direction = player_aims
+                    .get(player)
+                    .map(|aim| aim.direction)
+                    .unwrap_or(if player_flip_x { -Vec2::X } else { Vec2::X });
+                // The angle of the shot relative to the way the sprite is facing
+                let angle = if player_flip_x {
+                    -direction.y.atan2(-direction.x)
+                } else {
+                    direction.y.atan2(direction.x)
+                };
+
                 //Set kickback
-                player_body.velocity.x = if player_flip_x { 1.0 } else { -1.0 } * kickback;
+                player_body.velocity.x = -direction.x * kickback;
 
                 let mut shoot_animation_transform = *transforms.get(entity).unwrap();
                 shoot_animation_transform.translation.z += 1.0;
-                shoot_animation_transform.translation.x +=
-                    if player_sprite.flip_x { -15.0 } else { 15.0 };
+                shoot_animation_transform.translation += (direction * 15.0).extend(0.0);
+                shoot_animation_transform.rotation = Quat::from_rotation_z(angle);
 
                 let shoot_fps = *shoot_fps;
                 let shoot_frames = *shoot_frames;
@@ -233,7 +245,7 @@ fn update(
                                 ent,
                                 Bullet {
                                     owner: player,
-                                    direction: if player_flip_x { -1.0 } else { 1.0 },
+                                    direction,
                                 },
                             );
                             transforms.insert(ent, shoot_animation_transform);
@@ -243,19 +255,5 @@ fn update(
                 );
             }
         }
-
-        // If the item was dropped
-        if items_dropped.get(entity).is_some() {
-            // reload gun
-            musket.ammo = *max_ammo;
-        }
     }
 }
-
-fn musket_drop(entity: Entity, max_ammo: usize) -> System {
-    (move |mut muskets: CompMut<Musket>| {
-        // Reload musket
-        muskets.get_mut(entity).unwrap().ammo = max_ammo;
-    })
-    .system()
-}
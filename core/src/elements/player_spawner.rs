@@ -1,6 +1,14 @@
+use std::time::Duration;
+
 use crate::{prelude::*, MAX_PLAYERS};
 
 pub fn install(session: &mut CoreSession) {
+    session
+        .serializer
+        .register_component::<PlayerSpawner>()
+        .register_resource::<CurrentSpawner>()
+        .register_resource::<PlayerRespawns>();
+
     session
         .stages
         .add_system_to_stage(CoreStage::First, hydrate)
@@ -8,15 +16,68 @@ pub fn install(session: &mut CoreSession) {
 }
 
 /// Marker component for player spawners.
-#[derive(Clone, Debug, TypeUlid)]
+#[derive(Clone, Debug, TypeUlid, Serialize, Deserialize)]
 #[ulid = "01GP4YT7VARRVHFWJ46HNSN09P"]
 pub struct PlayerSpawner;
 
 /// Resource that stores the next spawner to use when spawning a player.
-#[derive(Clone, Debug, TypeUlid, Default)]
+#[derive(Clone, Debug, TypeUlid, Default, Serialize, Deserialize)]
 #[ulid = "01GP4YVEQGVQATG3KSPC0SD37N"]
 pub struct CurrentSpawner(pub usize);
 
+/// Resource that tracks player deaths and respawn delays, according to the [`RespawnMeta`] in the
+/// game config.
+#[derive(Clone, Debug, TypeUlid, Default, Serialize, Deserialize)]
+#[ulid = "01H4D08RC48RMZ1QA4HQJR2JAN"]
+pub struct PlayerRespawns {
+    /// How long the match has been running.
+    pub match_time: Duration,
+    /// The number of times each player has died.
+    pub deaths: [u32; MAX_PLAYERS],
+    /// Whether or not each player was alive during the last update.
+    pub was_alive: [bool; MAX_PLAYERS],
+    /// The time left before each dead player respawns.
+    pub delays: [Option<Timer>; MAX_PLAYERS],
+    /// How the round ended, once no more than one player is left that is alive or can respawn.
+    ///
+    /// Rounds only end when the players have limited lives, or a respawn time limit. See the
+    /// [`rounds`][crate::rounds] module for what happens next.
+    pub outcome: Option<MatchOutcome>,
+}
+
+/// How a round, or a whole match, with limited respawns ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MatchOutcome {
+    /// The player with the given index is the last one standing.
+    Winner(usize),
+    /// Nobody is left standing.
+    NoWinner,
+}
+
+impl PlayerRespawns {
+    /// Get the number of lives the player has left, or `None` if lives are unlimited.
+    pub fn lives_remaining(&self, player_idx: usize, meta: &RespawnMeta) -> Option<u32> {
+        meta.lives
+            .map(|lives| lives.saturating_sub(self.deaths[player_idx]))
+    }
+
+    /// Whether or not the player is allowed to (re)spawn right now.
+    pub fn can_spawn(&self, player_idx: usize, meta: &RespawnMeta) -> bool {
+        // Players can always make their first spawn
+        if self.deaths[player_idx] == 0 {
+            return true;
+        }
+
+        let has_lives = self.lives_remaining(player_idx, meta) != Some(0);
+        let in_time = meta
+            .time_limit
+            .map(|limit| self.match_time < limit)
+            .unwrap_or(true);
+
+        has_lives && in_time
+    }
+}
+
 fn hydrate(
     entities: Res<Entities>,
     mut hydrated: CompMut<MapElementHydrated>,
@@ -45,18 +106,28 @@ fn hydrate(
 }
 
 fn update(
+    time: Res<Time>,
+    game_meta: Res<CoreMetaArc>,
     mut entities: ResMut<Entities>,
     mut current_spawner: ResMut<CurrentSpawner>,
+    mut respawns: ResMut<PlayerRespawns>,
     player_spawners: Comp<PlayerSpawner>,
     mut player_indexes: CompMut<PlayerIdx>,
     mut transforms: CompMut<Transform>,
     player_inputs: Res<PlayerInputs>,
     mut spawner_manager: SpawnerManager,
 ) {
+    let respawn_meta = &game_meta.config.respawn;
+    respawns.match_time += time.delta();
+
     let alive_players = entities
         .iter_with(&player_indexes)
         .map(|(_ent, pidx)| pidx.0)
         .collect::<Vec<_>>();
+    let mut player_positions = entities
+        .iter_with((&player_indexes, &transforms))
+        .map(|(_ent, (_pidx, transform))| transform.translation.truncate())
+        .collect::<Vec<_>>();
     let spawn_points = entities
         .iter_with((&player_spawners, &transforms))
         .map(|(_ent, (_spawner, transform))| transform.translation)
@@ -65,28 +136,112 @@ fn update(
     // For every player
     for i in 0..MAX_PLAYERS {
         let player = &player_inputs.players[i];
+        let is_alive = alive_players.contains(&i);
+
+        // If the player was alive last frame, but isn't anymore, its body has just been cleaned up.
+        if respawns.was_alive[i] && !is_alive {
+            respawns.deaths[i] += 1;
+            respawns.delays[i] = Some(Timer::new(respawn_meta.delay, TimerMode::Once));
+        }
+        respawns.was_alive[i] = is_alive;
 
-        // If the player is active, but not alive
-        if player.active && !alive_players.contains(&i) {
-            // Increment the spawner index
-            current_spawner.0 += 1;
-            current_spawner.0 %= spawn_points.len().max(1);
+        // If the player is active, and hasn't left the match, but not alive
+        if !player.active || player.left_match || is_alive {
+            continue;
+        }
+
+        // Wait for the respawn delay
+        if let Some(delay) = &mut respawns.delays[i] {
+            delay.tick(time.delta());
+            if !delay.finished() {
+                continue;
+            }
+        }
+
+        if !respawns.can_spawn(i, respawn_meta) {
+            continue;
+        }
+
+        // Pick the next spawner that is far enough from the other players
+        let Some(spawner_idx) = select_spawn_point(
+            &spawn_points,
+            &player_positions,
+            current_spawner.0 + 1,
+            respawn_meta.safe_distance,
+        ) else { continue };
+        current_spawner.0 = spawner_idx;
+        let mut spawn_point = spawn_points[spawner_idx];
 
-            let Some(mut spawn_point) = spawn_points.get(current_spawner.0).copied() else { return };
+        // Make sure each player spawns at a different z level ( give enough room for 10 players
+        // to fit between map layers )
+        spawn_point.z += i as f32 * MAP_LAYERS_GAP_DEPTH / 10.0;
 
-            // Make sure each player spawns at a different z level ( give enough room for 10 players
-            // to fit between map layers )
-            spawn_point.z += i as f32 * MAP_LAYERS_GAP_DEPTH / 10.0;
+        let player_ent = entities.create();
+        player_indexes.insert(player_ent, PlayerIdx(i));
+        transforms.insert(player_ent, Transform::from_translation(spawn_point));
 
-            let player_ent = entities.create();
-            player_indexes.insert(player_ent, PlayerIdx(i));
-            transforms.insert(player_ent, Transform::from_translation(spawn_point));
+        spawner_manager.insert_spawned_entity_into_grouped_spawner(
+            player_ent,
+            &player_spawners,
+            &entities,
+        );
+
+        respawns.delays[i] = None;
+        respawns.was_alive[i] = true;
+        player_positions.push(spawn_point.truncate());
+    }
 
-            spawner_manager.insert_spawned_entity_into_grouped_spawner(
-                player_ent,
-                &player_spawners,
-                &entities,
-            );
+    // End the match when no more than one player is left that is alive or can still respawn
+    let respawns_limited = respawn_meta.lives.is_some() || respawn_meta.time_limit.is_some();
+    if respawns_limited && respawns.outcome.is_none() {
+        let active_players = (0..MAX_PLAYERS)
+            .filter(|i| player_inputs.players[*i].active)
+            .collect::<Vec<_>>();
+        let standing = active_players
+            .iter()
+            .copied()
+            .filter(|i| {
+                respawns.was_alive[*i]
+                    || (!player_inputs.players[*i].left_match
+                        && respawns.can_spawn(*i, respawn_meta))
+            })
+            .collect::<Vec<_>>();
+
+        if standing.is_empty() {
+            respawns.outcome = Some(MatchOutcome::NoWinner);
+        } else if standing.len() == 1 && active_players.len() > 1 {
+            respawns.outcome = Some(MatchOutcome::Winner(standing[0]));
         }
     }
 }
+
+/// Get the index of the first spawn point, starting at `start` and wrapping around, that is at
+/// least `safe_distance` away from every player position.
+///
+/// If there is no such spawn point, the one that is the furthest from its closest player is used.
+fn select_spawn_point(
+    spawn_points: &[Vec3],
+    player_positions: &[Vec2],
+    start: usize,
+    safe_distance: f32,
+) -> Option<usize> {
+    let mut best: Option<(usize, f32)> = None;
+
+    for offset in 0..spawn_points.len() {
+        let idx = (start + offset) % spawn_points.len();
+        let spawn_point = spawn_points[idx].truncate();
+        let closest_player = player_positions
+            .iter()
+            .map(|pos| pos.distance(spawn_point))
+            .fold(f32::INFINITY, f32::min);
+
+        if closest_player >= safe_distance {
+            return Some(idx);
+        }
+        if best.map(|(_, dist)| closest_player > dist).unwrap_or(true) {
+            best = Some((idx, closest_player));
+        }
+    }
+
+    best.map(|(idx, _)| idx)
+}
@@ -1,17 +1,21 @@
 use crate::prelude::*;
 
 pub fn install(session: &mut CoreSession) {
+    session.serializer.register_component::<Slippery>();
+
     session
         .stages
         .add_system_to_stage(CoreStage::PreUpdate, hydrate)
         .add_system_to_stage(CoreStage::PreUpdate, update);
 }
 
-#[derive(Clone, Debug, TypeUlid, Default)]
+#[derive(Clone, Debug, TypeUlid, Default, Serialize, Deserialize)]
 #[ulid = "01GTF77BGTAPJNTYEXKP6A2862"]
 pub struct Slippery {
     pub player_slide: f32,
     pub body_friction: f32,
+    pub status_effect: Option<StatusEffectMeta>,
+    pub contacts: StatusEffectContacts,
 }
 
 fn hydrate(
@@ -38,6 +42,7 @@ fn hydrate(
             body_size,
             player_slide,
             body_friction,
+            status_effect,
         } = &element_meta.builtin
         {
             hydrated.insert(entity, MapElementHydrated);
@@ -55,6 +60,8 @@ fn hydrate(
                 Slippery {
                     player_slide: *player_slide,
                     body_friction: *body_friction,
+                    status_effect: status_effect.clone(),
+                    contacts: default(),
                 },
             );
         }
@@ -63,17 +70,28 @@ fn hydrate(
 
 pub fn update(
     entities: Res<Entities>,
-    slippery: CompMut<Slippery>,
+    mut slippery: CompMut<Slippery>,
     collision_world: CollisionWorld,
     mut bodies: CompMut<KinematicBody>,
+    mut status_effects: CompMut<StatusEffects>,
 ) {
-    for (slippery_ent, slippery) in entities.iter_with(&slippery) {
+    for (slippery_ent, slippery) in entities.iter_with(&mut slippery) {
+        let mut touching = Vec::new();
         for (p_ent, body) in entities.iter_with(&mut bodies) {
             if collision_world
                 .actor_collisions(p_ent)
                 .contains(&slippery_ent)
             {
                 body.frame_friction_override = Some(slippery.body_friction);
+                touching.push(p_ent);
+            }
+        }
+
+        if let Some(effect) = &slippery.status_effect {
+            for p_ent in slippery.contacts.update(touching) {
+                if let Some(effects) = status_effects.get_mut(p_ent) {
+                    effects.apply(effect);
+                }
             }
         }
     }
@@ -1,13 +1,15 @@
 use crate::prelude::*;
 
 pub fn install(session: &mut CoreSession) {
+    session.serializer.register_component::<SlipperySeaweed>();
+
     session
         .stages
         .add_system_to_stage(CoreStage::PreUpdate, hydrate)
         .add_system_to_stage(PlayerStateStage, update);
 }
 
-#[derive(Clone, Debug, TypeUlid, Default)]
+#[derive(Clone, Debug, TypeUlid, Default, Serialize, Deserialize)]
 #[ulid = "01GSWXSJEQXEC0TMWVBHVMHG65"]
 pub struct SlipperySeaweed;
 
@@ -3,13 +3,15 @@ use std::collections::HashMap;
 use crate::prelude::*;
 
 pub fn install(session: &mut CoreSession) {
+    session.serializer.register_component::<Snail>();
+
     session
         .stages
         .add_system_to_stage(CoreStage::PreUpdate, hydrate)
         .add_system_to_stage(CoreStage::PostUpdate, update_snails);
 }
 
-#[derive(Clone, TypeUlid, Debug, Copy)]
+#[derive(Clone, TypeUlid, Debug, Copy, Serialize, Deserialize)]
 #[ulid = "01GTA646V5SESHBAP325MNT22Y"]
 pub enum Snail {
     Hiding(f32),
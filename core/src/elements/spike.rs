@@ -1,15 +1,20 @@
 use crate::prelude::*;
 
 pub fn install(session: &mut CoreSession) {
+    session.serializer.register_component::<Spike>();
+
     session
         .stages
         .add_system_to_stage(CoreStage::PreUpdate, hydrate)
         .add_system_to_stage(CoreStage::PostUpdate, update);
 }
 
-#[derive(Clone, Debug, TypeUlid, Default)]
+#[derive(Clone, Debug, TypeUlid, Default, Serialize, Deserialize)]
 #[ulid = "01H3DBQHJGFKT8G81BMEMN9FN6"]
-pub struct Spike;
+pub struct Spike {
+    pub status_effect: Option<StatusEffectMeta>,
+    pub contacts: StatusEffectContacts,
+}
 
 fn hydrate(
     entities: Res<Entities>,
@@ -37,6 +42,7 @@ fn hydrate(
             start_frame,
             end_frame,
             fps,
+            status_effect,
             ..
         } = &element_meta.builtin
         {
@@ -59,7 +65,13 @@ fn hydrate(
                     ..default()
                 },
             );
-            spikes.insert(entity, spike::default());
+            spikes.insert(
+                entity,
+                Spike {
+                    status_effect: status_effect.clone(),
+                    contacts: default(),
+                },
+            );
         }
     }
 }
@@ -72,15 +84,23 @@ fn update(
     invincibles: CompMut<Invincibility>,
     mut commands: Commands,
     transforms: Comp<Transform>,
+    mut status_effects: CompMut<StatusEffects>,
 ) {
-    for (entity, (_, pos)) in entities.iter_with((&mut spikes, &transforms)) {
-        collision_world
-            .actor_collisions_filtered(entity, |e| {
-                player_indexes.contains(e) && invincibles.get(e).is_none()
-            })
-            .into_iter()
-            .for_each(|player| {
+    for (entity, (spike, pos)) in entities.iter_with((&mut spikes, &transforms)) {
+        let players = collision_world.actor_collisions_filtered(entity, |e| {
+            player_indexes.contains(e) && invincibles.get(e).is_none()
+        });
+
+        if let Some(effect) = &spike.status_effect {
+            for player in spike.contacts.update(players) {
+                if let Some(effects) = status_effects.get_mut(player) {
+                    effects.apply(effect);
+                }
+            }
+        } else {
+            for player in players {
                 commands.add(PlayerCommand::kill(player, Some(pos.translation.xy())));
-            });
+            }
+        }
     }
 }
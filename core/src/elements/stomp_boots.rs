@@ -1,6 +1,11 @@
 use crate::prelude::*;
 
 pub fn install(session: &mut CoreSession) {
+    session
+        .serializer
+        .register_component::<WearingStompBoots>()
+        .register_component::<StompBoots>();
+
     session
         .stages
         .add_system_to_stage(CoreStage::PreUpdate, hydrate)
@@ -10,11 +15,11 @@ pub fn install(session: &mut CoreSession) {
 
 /// Marker component added to things ( presumably players, but not necessarily! ) that are wearing
 /// stomp boots
-#[derive(Debug, Clone, Copy, Default, TypeUlid)]
+#[derive(Debug, Clone, Copy, Default, TypeUlid, Serialize, Deserialize)]
 #[ulid = "01GR0P6HDCXJA6P2VNN8E1TH6Q"]
 pub struct WearingStompBoots;
 
-#[derive(Copy, Clone, Debug, TypeUlid)]
+#[derive(Copy, Clone, Debug, TypeUlid, Serialize, Deserialize)]
 #[ulid = "01GR0G9X4TME8E7NG0Z3DD26QW"]
 pub struct StompBoots;
 
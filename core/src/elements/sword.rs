@@ -3,20 +3,25 @@ use std::time::Duration;
 use crate::{damage::DamageRegion, prelude::*};
 
 pub fn install(session: &mut CoreSession) {
+    session
+        .serializer
+        .register_component::<Sword>()
+        .register_load_hook(restore_sword_drop_systems);
+
     session
         .stages
         .add_system_to_stage(CoreStage::PreUpdate, hydrate)
         .add_system_to_stage(CoreStage::PostUpdate, update);
 }
 
-#[derive(Copy, Clone, Debug, TypeUlid, Default)]
+#[derive(Copy, Clone, Debug, TypeUlid, Default, Serialize, Deserialize)]
 #[ulid = "01GP9NA6ZQGMC0YY9K2XFJH9KA"]
 pub struct Sword {
     pub state: SwordState,
     pub dropped_time: f32,
 }
 
-#[derive(Default, Clone, Copy, Debug)]
+#[derive(Default, Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum SwordState {
     #[default]
     Idle,
@@ -35,6 +40,7 @@ fn hydrate(
     mut element_handles: CompMut<ElementHandle>,
     element_assets: BevyAssets<ElementMeta>,
     mut swords: CompMut<Sword>,
+    mut item_uses: CompMut<ItemUses>,
     mut atlas_sprites: CompMut<AtlasSprite>,
     mut bodies: CompMut<KinematicBody>,
     mut transforms: CompMut<Transform>,
@@ -68,6 +74,7 @@ fn hydrate(
             bounciness,
             throw_velocity,
             angular_velocity,
+            durability,
             ..
         } = &element_meta.builtin
         {
@@ -90,6 +97,9 @@ fn hydrate(
                 },
             );
             swords.insert(entity, Sword::default());
+            if let Some(durability) = durability {
+                item_uses.insert(entity, ItemUses::new(durability.clone()));
+            }
             atlas_sprites.insert(entity, AtlasSprite::new(atlas.clone()));
             respawn_points.insert(entity, DehydrateOutOfBounds(spawner_ent));
             transforms.insert(entity, transform);
@@ -126,6 +136,7 @@ fn update(
     mut sprites: CompMut<AtlasSprite>,
     bodies: CompMut<KinematicBody>,
     mut items_used: CompMut<ItemUsed>,
+    mut item_uses: CompMut<ItemUses>,
     player_indexes: Comp<PlayerIdx>,
     player_inventories: PlayerInventories,
     mut commands: Commands,
@@ -274,7 +285,13 @@ fn update(
             let item_used = items_used.get(entity).is_some();
             if item_used {
                 items_used.remove(entity);
-                if matches!(sword.state, SwordState::Idle) {
+                // Swords without durability never wear out
+                if matches!(sword.state, SwordState::Idle)
+                    && item_uses
+                        .get_mut(entity)
+                        .map(|durability| durability.try_use())
+                        .unwrap_or(true)
+                {
                     sprite.index = 8;
                     sword.state = SwordState::Swinging { frame: 0 };
                     audio_events.play(sound.clone(), *sound_volume);
@@ -308,6 +325,22 @@ fn update(
     }
 }
 
+/// Add the drop systems back to the swords of a loaded session, since systems can't be saved.
+fn restore_sword_drop_systems(world: &World) {
+    world
+        .run_initialized_system(
+            |entities: Res<Entities>, swords: Comp<Sword>, mut item_throws: CompMut<ItemThrow>| {
+                for (entity, (_sword, item_throw)) in
+                    entities.iter_with((&swords, &mut item_throws))
+                {
+                    *item_throw = item_throw.clone().with_system(sword_drop(entity));
+                }
+                Ok(())
+            },
+        )
+        .unwrap();
+}
+
 fn sword_drop(entity: Entity) -> System {
     (move |mut swords: CompMut<Sword>, mut sprites: CompMut<AtlasSprite>| {
         // Put sword in rest position
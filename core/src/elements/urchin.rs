@@ -1,13 +1,15 @@
 use crate::prelude::*;
 
 pub fn install(session: &mut CoreSession) {
+    session.serializer.register_component::<Urchin>();
+
     session
         .stages
         .add_system_to_stage(CoreStage::PreUpdate, hydrate)
         .add_system_to_stage(CoreStage::PostUpdate, update_urchins);
 }
 
-#[derive(Default, Clone, TypeUlid, Debug, Copy)]
+#[derive(Default, Clone, TypeUlid, Debug, Copy, Serialize, Deserialize)]
 #[ulid = "01GT7TBHRZCC1BR7T1PCR5KA4T"]
 pub struct Urchin;
 
@@ -4,10 +4,11 @@ use crate::{prelude::*, MAX_PLAYERS};
 
 pub fn install(session: &mut CoreSession) {
     session.world.init_resource::<PlayerInputs>();
+    session.serializer.register_resource::<PlayerInputs>();
 }
 
 /// The inputs for each player in this simulation frame.
-#[derive(Clone, Debug, TypeUlid)]
+#[derive(Clone, Debug, TypeUlid, Serialize, Deserialize)]
 #[ulid = "01GP233N26N8DQAAS1WDGYM14X"]
 pub struct PlayerInputs {
     pub players: Vec<PlayerInput>,
@@ -24,7 +25,7 @@ impl Default for PlayerInputs {
 
 /// Player input, not just controls, but also other status that comes from the player, such as the
 /// selected player and whether the player is actually active.
-#[derive(Default, Clone, Debug, TypeUlid)]
+#[derive(Default, Clone, Debug, TypeUlid, Serialize, Deserialize)]
 #[ulid = "01GP2356AYJ5NW8GJ3WF0TCCY3"]
 pub struct PlayerInput {
     /// The player is currently "connected" and actively providing input.
@@ -39,10 +40,13 @@ pub struct PlayerInput {
     pub editor_input: Option<EditorInput>,
     /// Whether or not this is an AI player.
     pub is_ai: bool,
+    /// The player has left the match. It doesn't respawn, and it is removed from the match at the
+    /// start of the next round.
+    pub left_match: bool,
 }
 
 /// Player control input state
-#[derive(Default, Clone, Debug)]
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
 #[repr(C)]
 pub struct PlayerControl {
     pub move_direction: Vec2,
@@ -5,8 +5,20 @@
 use crate::prelude::{player_spawner::PlayerSpawner, *};
 
 pub fn install(session: &mut CoreSession) {
+    session
+        .serializer
+        .register_component::<Item>()
+        .register_component::<Inventory>()
+        .register_component::<ItemDropped>()
+        .register_component::<ItemGrabbed>()
+        .register_component::<ItemUsed>()
+        .register_component::<ItemUses>()
+        .register_component::<ItemGrab>()
+        .register_component::<ItemThrow>();
+
     session
         .stages
+        .add_system_to_stage(CoreStage::PostUpdate, update_item_uses)
         .add_system_to_stage(CoreStage::Last, grab_items)
         .add_system_to_stage(CoreStage::Last, throw_dropped_items);
 }
@@ -14,12 +26,12 @@ pub fn install(session: &mut CoreSession) {
 /// Marker component for items.
 ///
 /// Items are any entity that players can pick up and use.
-#[derive(Clone, Copy, TypeUlid)]
+#[derive(Clone, Copy, TypeUlid, Serialize, Deserialize)]
 #[ulid = "01GP4DBSEB3R6ZNBNNTSY36GW4"]
 pub struct Item;
 
 /// An intventory component, indicating another entity that the player is carrying.
-#[derive(Clone, TypeUlid, Default, Deref, DerefMut)]
+#[derive(Clone, TypeUlid, Default, Deref, DerefMut, Serialize, Deserialize)]
 #[ulid = "01GP4D6M2QBSKZMEZMM22YGG41"]
 pub struct Inventory(pub Option<Entity>);
 
@@ -71,7 +83,7 @@ impl<'a> SystemParam for PlayerInventories<'a> {
 }
 
 /// Marker component added to items when they are dropped.
-#[derive(Clone, Copy, TypeUlid)]
+#[derive(Clone, Copy, TypeUlid, Serialize, Deserialize)]
 #[ulid = "01GP4DH23M7M2CXVWADPZHW54F"]
 pub struct ItemDropped {
     /// The player that dropped the item
@@ -79,7 +91,7 @@ pub struct ItemDropped {
 }
 
 /// Marker component added to items when they are grabbed.
-#[derive(Clone, Copy, TypeUlid)]
+#[derive(Clone, Copy, TypeUlid, Serialize, Deserialize)]
 #[ulid = "01GP4DJ2RPYTDPKSKEK8JKK9VT"]
 pub struct ItemGrabbed {
     /// The player that grabbed the item
@@ -87,19 +99,64 @@ pub struct ItemGrabbed {
 }
 
 /// Marker component added to items when they are used.
-#[derive(Clone, Copy, TypeUlid)]
+#[derive(Clone, Copy, TypeUlid, Serialize, Deserialize)]
 #[ulid = "01GP4DJ84TFB8Z7H9VY7Y0R47H"]
 pub struct ItemUsed {
     /// The player that used the item
     pub owner: Entity,
 }
 
+/// Component tracking how many more times an item can be used, such as the ammo left in a gun, or
+/// the durability of a sword.
+///
+/// Items are responsible for calling [`ItemUses::try_use`] when they are used, while the
+/// [`update_item_uses`] system takes care of the refill, auto-drop, and despawn behaviors
+/// configured in the [`ItemUsesMeta`].
+#[derive(Clone, TypeUlid, Debug, Default, Serialize, Deserialize)]
+#[ulid = "01H4D0H2XNZW4ZWBEE85SV9VR5"]
+pub struct ItemUses {
+    pub meta: ItemUsesMeta,
+    /// The number of uses left.
+    pub remaining: usize,
+    /// The time left before the item disappears, if it is lying on the ground after being dropped.
+    pub despawn_timer: Option<Timer>,
+}
+
+impl ItemUses {
+    pub fn new(meta: ItemUsesMeta) -> Self {
+        Self {
+            remaining: meta.max,
+            meta,
+            despawn_timer: None,
+        }
+    }
+
+    /// Consume a use of the item, returning `false` if the item is empty.
+    pub fn try_use(&mut self) -> bool {
+        if self.remaining == 0 {
+            return false;
+        }
+        self.remaining -= 1;
+        true
+    }
+
+    /// Whether or not the item has no uses left.
+    pub fn is_empty(&self) -> bool {
+        self.remaining == 0
+    }
+
+    /// Refill the item to its maximum number of uses.
+    pub fn refill(&mut self) {
+        self.remaining = self.meta.max;
+    }
+}
+
 /// Component defining the grab settings when an item is grabbed.
 ///
 /// Mainly handled by the [`grab_items`] system which consumes the
 /// [`ItemGrabbed`] components for entities which have this component.
 /// [`Item`] is required for the system to take affect.
-#[derive(Clone, Copy, TypeUlid)]
+#[derive(Clone, Copy, TypeUlid, Serialize, Deserialize)]
 #[ulid = "01GTJHWG4C2AW6KCY0P11MZ1KW"]
 pub struct ItemGrab {
     pub fin_anim: Key,
@@ -151,7 +208,7 @@ pub fn grab_items(
 /// Mainly handled by the [`throw_dropped_items`] system which consumes the
 /// [`ItemDropped`] components for entities which have this component.
 /// [`Item`] is required for the system to take affect.
-#[derive(Clone, TypeUlid)]
+#[derive(Clone, TypeUlid, Serialize, Deserialize)]
 #[ulid = "01GSGE6N4TSEMQ1DKDP5Y66TE4"]
 pub struct ItemThrow {
     normal: Vec2,
@@ -162,6 +219,9 @@ pub struct ItemThrow {
     roll: Vec2,
     spin: f32,
     /// An optional system value that gets run once on throw.
+    ///
+    /// Systems can't be saved, so elements that use one add it back with a load hook.
+    #[serde(skip)]
     system: Option<Arc<AtomicRefCell<System>>>,
 }
 
@@ -237,10 +297,13 @@ impl ItemThrow {
 
 pub fn throw_dropped_items(
     entities: Res<Entities>,
+    game_meta: Res<CoreMetaArc>,
     item_throws: Comp<ItemThrow>,
     items: Comp<Item>,
+    aimable_items: Comp<AimableItem>,
     player_inputs: Res<PlayerInputs>,
     player_indexes: Comp<PlayerIdx>,
+    player_aims: Comp<PlayerAim>,
     mut items_dropped: CompMut<ItemDropped>,
     mut bodies: CompMut<KinematicBody>,
     mut attachments: CompMut<PlayerBodyAttachment>,
@@ -269,17 +332,35 @@ pub fn throw_dropped_items(
                 Vec2::ONE
             };
 
-            let throw_velocity = item_throw.velocity_from_control(
-                &player_inputs
-                    .players
-                    .get(player_indexes.get(player).unwrap().0)
-                    .unwrap()
-                    .control,
-            );
+            let control = &player_inputs
+                .players
+                .get(player_indexes.get(player).unwrap().0)
+                .unwrap()
+                .control;
+            let aim_meta = &game_meta.config.aim;
+
+            // Aimable items are thrown in the direction of the stick, otherwise we pick one of the
+            // fixed throws.
+            let aimed_velocity = aimable_items
+                .contains(entity)
+                .then(|| aim_meta.mode.direction(control.move_direction))
+                .flatten()
+                // The throw velocity is relative to the direction the player is facing
+                .map(|direction| direction * horizontal_flip_factor * item_throw.fast.length());
+            let mut throw_velocity =
+                aimed_velocity.unwrap_or_else(|| item_throw.velocity_from_control(control));
+
+            // Charged throws are stronger
+            if let Some(aim) = player_aims.get(player) {
+                throw_velocity *= 1.0 + (aim_meta.throw_charge_max - 1.0) * aim.throw_charge;
+            }
 
-            // Use the item's spawner depth as the drop depth
-            if let Some(item_spawner) = item_spawners.get(entity) {
-                let map_layer = map_layers.get(item_spawner.0).unwrap();
+            // Use the item's spawner depth as the drop depth. Items from an item spawner's slot
+            // don't have a map layer of their own, so they use the fallback.
+            if let Some(map_layer) = item_spawners
+                .get(entity)
+                .and_then(|item_spawner| map_layers.get(item_spawner.0))
+            {
                 transform.translation.z = z_depth_for_map_layer(map_layer.layer_idx);
             } else {
                 // Grab a random player spawner and use that for the z depth
@@ -298,3 +379,55 @@ pub fn throw_dropped_items(
         }
     }
 }
+
+/// Handles the behaviors of items with [`ItemUses`] when they are dropped, empty, or have been
+/// lying on the ground for too long.
+pub fn update_item_uses(
+    time: Res<Time>,
+    entities: Res<Entities>,
+    mut commands: Commands,
+    mut item_uses: CompMut<ItemUses>,
+    items_dropped: Comp<ItemDropped>,
+    player_inventories: PlayerInventories,
+    item_spawners: Comp<DehydrateOutOfBounds>,
+    mut hydrated: CompMut<MapElementHydrated>,
+) {
+    for (entity, uses) in entities.iter_with(&mut item_uses) {
+        if items_dropped.contains(entity) {
+            if uses.meta.refill_on_drop {
+                uses.refill();
+            }
+            uses.despawn_timer = uses
+                .meta
+                .despawn_time
+                .map(|time| Timer::new(time, TimerMode::Once));
+            continue;
+        }
+
+        // If the item is being held
+        if let Some(inventory) = player_inventories
+            .iter()
+            .find_map(|x| x.filter(|x| x.inventory == entity))
+        {
+            uses.despawn_timer = None;
+
+            if uses.is_empty() && uses.meta.drop_when_empty {
+                commands.add(PlayerCommand::set_inventory(inventory.player, None));
+            }
+
+        // If the item is lying on the ground
+        } else if let Some(timer) = &mut uses.despawn_timer {
+            timer.tick(time.delta());
+
+            if timer.finished() {
+                // Cause the item to respawn by un-hydrating it's spawner.
+                if let Some(spawner) = item_spawners.get(entity) {
+                    hydrated.remove(**spawner);
+                }
+                commands.add(move |mut entities: ResMut<Entities>| {
+                    entities.kill(entity);
+                });
+            }
+        }
+    }
+}
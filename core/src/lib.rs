@@ -34,6 +34,7 @@ pub mod external {
     pub use bones_lib;
 }
 
+pub mod aim;
 pub mod attachment;
 pub mod bullet;
 pub mod camera;
@@ -51,7 +52,11 @@ pub mod metadata;
 pub mod physics;
 pub mod player;
 pub mod random;
+pub mod rounds;
 pub mod session;
+pub mod status_effect;
+#[cfg(any(test, feature = "test-support"))]
+pub mod test_support;
 pub mod utils;
 
 /// The target fixed frames-per-second that the game sumulation runs at.
@@ -104,4 +109,7 @@ pub fn install_modules(session: &mut session::CoreSession) {
     attachment::install(session);
     bullet::install(session);
     editor::install(session);
+    status_effect::install(session);
+    rounds::install(session);
+    aim::install(session);
 }
@@ -5,6 +5,11 @@ use std::time::Duration;
 use crate::{prelude::*, FPS};
 
 pub fn install(session: &mut CoreSession) {
+    session
+        .serializer
+        .register_component::<Lifetime>()
+        .register_component::<Invincibility>();
+
     session
         .stages
         .add_system_to_stage(CoreStage::PostUpdate, lifetime_system)
@@ -18,7 +23,7 @@ pub fn install(session: &mut CoreSession) {
 /// > later, after it was spawned.
 /// >
 /// > Also, the age and lifetime are public, subject to other system's modification.
-#[derive(Copy, Clone, Default, TypeUlid)]
+#[derive(Copy, Clone, Default, TypeUlid, Serialize, Deserialize)]
 #[ulid = "01GP9SS1WH06QC352CZ8BKSTZE"]
 pub struct Lifetime {
     /// How long the entity should be allowed to live in seconds.
@@ -53,7 +58,7 @@ fn lifetime_system(mut entities: ResMut<Entities>, mut lifetimes: CompMut<Lifeti
 /// A timer that can be used to make an entity invincible for a certain amount of time
 ///
 /// This is a general purpose invinvibility timer, but will serve as our spawn protection timer.
-#[derive(Clone, Default, TypeUlid, Debug)]
+#[derive(Clone, Default, TypeUlid, Debug, Serialize, Deserialize)]
 #[ulid = "01GV3P99HFCZSC2MMXHA9394EJ"]
 pub struct Invincibility(Timer);
 
@@ -61,6 +66,11 @@ impl Invincibility {
     pub fn new(duration: Duration) -> Self {
         Self(Timer::new(duration, TimerMode::Once))
     }
+
+    /// Get the time left before the entity stops being invincible.
+    pub fn remaining(&self) -> Duration {
+        self.0.remaining()
+    }
 }
 
 fn invincibility(
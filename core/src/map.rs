@@ -10,6 +10,14 @@ use ::bevy::utils::HashSet;
 use crate::prelude::{collisions::TileCollisionKind, *};
 
 pub fn install(session: &mut CoreSession) {
+    session
+        .serializer
+        .register_resource::<LoadedMap>()
+        .register_resource::<MapSpawned>()
+        .register_resource::<SpawnedMapMeta>()
+        .register_resource::<NavGraph>()
+        .register_component::<SpawnedMapLayerMeta>();
+
     session
         .stages
         .add_system_to_stage(CoreStage::First, spawn_map)
@@ -17,12 +25,12 @@ pub fn install(session: &mut CoreSession) {
 }
 
 /// Resource containing the map metadata for this game session.
-#[derive(Clone, TypeUlid, Deref, DerefMut, Default)]
+#[derive(Clone, TypeUlid, Deref, DerefMut, Default, Serialize, Deserialize)]
 #[ulid = "01GP2H6K9H3JEEMXFCKV4TGMWZ"]
 pub struct LoadedMap(pub Arc<MapMeta>);
 
 /// Resource indicating whether the map has been spawned.
-#[derive(Clone, TypeUlid, Default, Deref, DerefMut)]
+#[derive(Clone, TypeUlid, Default, Deref, DerefMut, Serialize, Deserialize)]
 #[ulid = "01GP3Z38HKE37JB6GRHHPPTY38"]
 pub struct MapSpawned(pub bool);
 
@@ -40,7 +48,7 @@ pub fn z_depth_for_map_layer(layer_idx: usize) -> f32 {
 /// Resource containing essential the map metadata for the map once spawned. This allows the
 /// complete map metadata to be re-constructed from the world after the map has been spawned and
 /// potentially modified.
-#[derive(TypeUlid, Clone)]
+#[derive(TypeUlid, Clone, Serialize, Deserialize)]
 #[ulid = "01GSR8V683B3EH5QAB2PMGN9J7"]
 pub struct SpawnedMapMeta {
     pub name: Arc<str>,
@@ -68,7 +76,7 @@ impl Default for SpawnedMapMeta {
 ///
 /// This is used when exporting the world to `MapMeta` to decide which layer to put an element or
 /// tile layer in.
-#[derive(TypeUlid, Clone, Copy, Default)]
+#[derive(TypeUlid, Clone, Copy, Default, Serialize, Deserialize)]
 #[ulid = "01GSR8GSRJHGTJ8J9Y38W7C5S3"]
 pub struct SpawnedMapLayerMeta {
     /// The layer index of the layer that the element belongs to in the map.
@@ -76,7 +84,7 @@ pub struct SpawnedMapLayerMeta {
 }
 
 /// The map navigation graph resource.
-#[derive(Clone, Debug, Deref, DerefMut, TypeUlid, Default)]
+#[derive(Clone, Debug, Deref, DerefMut, TypeUlid, Default, Serialize, Deserialize)]
 #[ulid = "01GQWP4QG11NBVX3M289TXAK6W"]
 pub struct NavGraph(pub Arc<NavGraphInner>);
 
@@ -86,7 +94,7 @@ pub type NavGraphInner = petgraph::graphmap::DiGraphMap<NavNode, NavGraphEdge>;
 /// The type of nodes in the map navigation graph.
 ///
 /// This is merely a wrapper around [`UVec2`] to add an [`Ord`] implementation.
-#[derive(PartialEq, Eq, Clone, Copy, Debug, Hash, Deref, DerefMut)]
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Hash, Deref, DerefMut, Serialize, Deserialize)]
 pub struct NavNode(pub IVec2);
 
 impl NavNode {
@@ -141,7 +149,7 @@ impl std::cmp::PartialOrd for NavNode {
 }
 
 /// Represents the way to get from one tile to another tile in the navigation graph.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NavGraphEdge {
     /// The sequence of inputs for each frame, required to get to the connected tile.
     pub inputs: VecDeque<PlayerControl>,
@@ -168,6 +176,8 @@ fn spawn_map(
     mut camera_states: CompMut<CameraState>,
     mut spawned_map_layer_metas: CompMut<SpawnedMapLayerMeta>,
     mut spawned_map_meta: ResMut<SpawnedMapMeta>,
+    game_meta: Res<CoreMetaArc>,
+    element_assets: BevyAssets<ElementMeta>,
 ) {
     if map_spawned.0 {
         return;
@@ -266,6 +276,13 @@ fn spawn_map(
         );
 
         for element_meta in &layer.elements {
+            if game_meta.config.disable_items
+                && element_assets
+                    .get(&element_meta.element.get_bevy_handle())
+                    .map_or(false, |x| x.builtin.is_item())
+            {
+                continue;
+            }
             let element_ent = entities.create();
 
             spawned_map_layer_metas.insert(element_ent, SpawnedMapLayerMeta { layer_idx });
@@ -100,4 +100,101 @@ pub struct CoreConfigMeta {
     #[serde(default)]
     #[serde(with = "humantime_serde")]
     pub respawn_invincibility_time: Duration,
+    #[serde(default)]
+    pub respawn: RespawnMeta,
+    #[serde(default)]
+    pub aim: AimMeta,
+    #[serde(default)]
+    pub rounds: RoundsMeta,
+    /// Whether to leave the items and item spawners out of the map when it is spawned.
+    #[serde(default)]
+    pub disable_items: bool,
+}
+
+/// Settings for how players come back after being killed.
+///
+/// The default is for players to respawn immediately, forever.
+#[derive(BonesBevyAssetLoad, Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+#[serde(default)]
+pub struct RespawnMeta {
+    /// The number of lives each player has. When not set, players have unlimited lives.
+    pub lives: Option<u32>,
+    /// How long into the match players may still respawn. When not set, there is no limit.
+    #[serde(with = "humantime_serde")]
+    pub time_limit: Option<Duration>,
+    /// How long after a player's body has been cleaned up before it respawns.
+    #[serde(with = "humantime_serde")]
+    pub delay: Duration,
+    /// Players will prefer spawners that are at least this far away from any other player.
+    pub safe_distance: f32,
+}
+
+/// Settings for matches that are played over several rounds.
+///
+/// A round is over once no more than one player is left standing, so rounds only end when the
+/// players have limited lives or a respawn time limit. The default is a single round.
+#[derive(BonesBevyAssetLoad, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+#[serde(default)]
+pub struct RoundsMeta {
+    /// The number of rounds in a match. The map is reset at the start of every round.
+    pub count: u32,
+    /// How long the outcome of a round is shown before the next round starts.
+    #[serde(with = "humantime_serde")]
+    pub end_delay: Duration,
+}
+
+impl Default for RoundsMeta {
+    fn default() -> Self {
+        Self {
+            count: 1,
+            end_delay: Duration::from_secs(3),
+        }
+    }
+}
+
+/// Settings for aiming items and charging throws.
+#[derive(BonesBevyAssetLoad, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+#[serde(default)]
+pub struct AimMeta {
+    /// How items are aimed. Aiming is disabled by default.
+    pub mode: AimMode,
+    /// The sprite shown in the direction that the player is aiming.
+    pub indicator_atlas: Option<Handle<Atlas>>,
+    /// How far from the center of the player to show the aim indicator.
+    pub indicator_distance: f32,
+    /// How long grab has to be held to throw an item at full strength. When zero, items are
+    /// thrown as soon as grab is pressed, without charging.
+    #[serde(with = "humantime_serde")]
+    pub throw_charge_time: Duration,
+    /// The multiplier applied to the throw velocity of a fully charged throw.
+    pub throw_charge_max: f32,
+}
+
+impl Default for AimMeta {
+    fn default() -> Self {
+        Self {
+            mode: default(),
+            indicator_atlas: None,
+            indicator_distance: 40.0,
+            throw_charge_time: Duration::ZERO,
+            throw_charge_max: 1.0,
+        }
+    }
 }
+
+/// How the movement stick is translated to an aim direction.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AimMode {
+    /// Items aren't aimed, and are used as soon as shoot is pressed.
+    #[default]
+    Disabled,
+    /// Aim is snapped to the closest of the 8 cardinal and diagonal directions.
+    EightWay,
+    /// Aim follows the stick direction exactly.
+    Analog,
+}
+
+impl bones_bevy_asset::BonesBevyAssetLoad for AimMode {}
@@ -27,7 +27,7 @@ impl Serialize for ColorMeta {
             (b * 255.0) as u8,
             (a * 255.0) as u8,
         ];
-        let hex = format!("#{r:X}{g:X}{b:X}{a:X}");
+        let hex = format!("#{r:02X}{g:02X}{b:02X}{a:02X}");
 
         serializer.serialize_str(&hex)
     }
@@ -11,6 +11,9 @@ pub struct ElementMeta {
     pub category: String,
     #[serde(default)]
     pub builtin: BuiltinElementKind,
+    /// Whether the item can be aimed by holding shoot and pointing with the movement stick.
+    #[serde(default)]
+    pub allow_aim: bool,
 
     #[serde(default)]
     pub editor: ElementEditorMeta,
@@ -91,6 +94,8 @@ pub enum BuiltinElementKind {
         bounciness: f32,
         #[serde(default)]
         angular_velocity: f32,
+        #[serde(default)]
+        uses: ItemUsesMeta,
     },
     /// An animated decoration such as seaweed or anemones
     AnimatedDecoration {
@@ -180,6 +185,10 @@ pub enum BuiltinElementKind {
         bounciness: f32,
         throw_velocity: f32,
         cooldown_frames: usize,
+        /// The number of swings before the sword is worn out. When not set the sword can be
+        /// swung forever.
+        #[serde(default)]
+        durability: Option<ItemUsesMeta>,
     },
     /// The throwable crate item
     Crate {
@@ -282,7 +291,8 @@ pub enum BuiltinElementKind {
         angular_velocity: f32,
         atlas: Handle<Atlas>,
 
-        max_ammo: usize,
+        #[serde(alias = "max_ammo", deserialize_with = "deserialize_musket_ammo")]
+        ammo: ItemUsesMeta,
         #[serde(with = "humantime_serde")]
         cooldown: Duration,
         bullet_meta: Handle<BulletMeta>,
@@ -309,6 +319,9 @@ pub enum BuiltinElementKind {
         body_size: Vec2,
         player_slide: f32,
         body_friction: f32,
+        /// An effect applied to players while they are standing on it.
+        #[serde(default)]
+        status_effect: Option<StatusEffectMeta>,
     },
     Spike {
         atlas: Handle<Atlas>,
@@ -316,5 +329,136 @@ pub enum BuiltinElementKind {
         start_frame: usize,
         end_frame: usize,
         fps: f32,
+        /// An effect applied to players that touch the spikes.
+        ///
+        /// If this is set, the spikes will apply the effect instead of killing the player.
+        #[serde(default)]
+        status_effect: Option<StatusEffectMeta>,
     },
+    /// Periodically spawns an item picked from a loot table, whenever its previous item is gone.
+    ItemSpawner {
+        /// The items that may be spawned, and how likely they are to be picked.
+        loot_table: Vec<LootTableEntry>,
+        /// How long to wait after the previous item has been taken before spawning the next one.
+        #[serde(with = "humantime_serde")]
+        respawn_time: Duration,
+        /// How long before an item spawns to start showing the telegraph sprite.
+        #[serde(with = "humantime_serde")]
+        telegraph_time: Duration,
+        /// The sprite that blinks in place of the item before it spawns.
+        telegraph_atlas: Handle<Atlas>,
+        /// Whether to spawn the first item as soon as the map starts, instead of after the
+        /// `respawn_time`.
+        #[serde(default)]
+        spawn_on_start: bool,
+    },
+}
+
+impl BuiltinElementKind {
+    /// Whether or not the element is an item that players can pick up, or spawns such items.
+    pub fn is_item(&self) -> bool {
+        matches!(
+            self,
+            BuiltinElementKind::Grenade { .. }
+                | BuiltinElementKind::Sword { .. }
+                | BuiltinElementKind::Crate { .. }
+                | BuiltinElementKind::Mine { .. }
+                | BuiltinElementKind::StompBoots { .. }
+                | BuiltinElementKind::KickBomb { .. }
+                | BuiltinElementKind::Musket { .. }
+                | BuiltinElementKind::ItemSpawner { .. }
+        )
+    }
+}
+
+/// An item that may be picked by an [`ItemSpawner`][BuiltinElementKind::ItemSpawner].
+#[derive(BonesBevyAssetLoad, Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct LootTableEntry {
+    pub element: Handle<ElementMeta>,
+    /// The chance of this item being picked, relative to the other items in the table.
+    #[serde(default = "LootTableEntry::default_weight")]
+    pub weight: f32,
 }
+
+impl LootTableEntry {
+    fn default_weight() -> f32 {
+        1.0
+    }
+}
+
+/// Metadata for items that can only be used a limited number of times before they are empty.
+#[derive(BonesBevyAssetLoad, Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ItemUsesMeta {
+    /// Whether the uses are shown as ammo or as durability in the HUD.
+    #[serde(default)]
+    pub kind: ItemUsesKind,
+    /// The number of times the item can be used.
+    #[serde(default = "ItemUsesMeta::default_max")]
+    pub max: usize,
+    /// Whether the item is refilled to `max` uses when it is dropped.
+    #[serde(default)]
+    pub refill_on_drop: bool,
+    /// Whether the player automatically drops the item once it is empty.
+    #[serde(default)]
+    pub drop_when_empty: bool,
+    /// How long the item may lie on the ground after being dropped before it disappears. When not
+    /// set the item never disappears.
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    pub despawn_time: Option<Duration>,
+}
+
+impl Default for ItemUsesMeta {
+    fn default() -> Self {
+        Self {
+            kind: default(),
+            max: Self::default_max(),
+            refill_on_drop: false,
+            drop_when_empty: false,
+            despawn_time: None,
+        }
+    }
+}
+
+impl ItemUsesMeta {
+    fn default_max() -> usize {
+        1
+    }
+}
+
+/// Deserialize the ammo of a musket, which may also be given as the number of rounds, the way it
+/// was written when it was the `max_ammo` field.
+fn deserialize_musket_ammo<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<ItemUsesMeta, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum MusketAmmo {
+        MaxAmmo(usize),
+        Uses(ItemUsesMeta),
+    }
+
+    Ok(match MusketAmmo::deserialize(deserializer)? {
+        // Muskets have always been reloaded when they are dropped
+        MusketAmmo::MaxAmmo(max) => ItemUsesMeta {
+            max,
+            refill_on_drop: true,
+            ..default()
+        },
+        MusketAmmo::Uses(uses) => uses,
+    })
+}
+
+/// How the uses of an item are presented to the player.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ItemUsesKind {
+    /// Each use is shown as a separate round of ammunition.
+    #[default]
+    Ammo,
+    /// The uses are shown as a bar that wears down.
+    Durability,
+}
+
+impl bones_bevy_asset::BonesBevyAssetLoad for ItemUsesKind {}
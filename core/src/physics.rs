@@ -27,6 +27,17 @@ impl StageLabel for PhysicsStage {
 }
 
 pub fn install(session: &mut CoreSession) {
+    session
+        .serializer
+        .register_component::<KinematicBody>()
+        .register_component::<Collider>()
+        .register_component::<Actor>()
+        .register_component::<Solid>()
+        .register_component::<TileRapierHandle>()
+        .register_component::<TileCollisionKind>()
+        .register_resource::<RapierContext>()
+        .snapshot_resource_as::<RapierContext>();
+
     session
         .stages
         // TODO: Think again about exactly how to organize the physics sync systems. At the time of
@@ -48,7 +59,7 @@ pub fn install(session: &mut CoreSession) {
 ///
 /// For now, all kinematic bodies have axis-aligned, rectangular colliders. This may or may not
 /// change in the future.
-#[derive(Default, Debug, Clone, Copy, TypeUlid)]
+#[derive(Default, Debug, Clone, Copy, TypeUlid, Serialize, Deserialize)]
 #[ulid = "01GNF5NRJR7CXZ9NKEBQEGN8R1"]
 #[repr(C)]
 pub struct KinematicBody {
@@ -15,15 +15,20 @@ use crate::impl_system_param;
 use crate::prelude::*;
 
 /// Resource containing the data structures needed for rapier collision detection.
-#[derive(TypeUlid, Default)]
+///
+/// The caches are left out when the context is serialized, since they are rebuilt as needed. In
+/// snapshots, the context is kept as a [`RapierSnapshot`] instead, which it is re-created from.
+#[derive(TypeUlid, Default, Serialize, Deserialize)]
 #[ulid = "01GRYFB82VW5CXGK56KKPAVA5B"]
 pub struct RapierContext {
+    #[serde(skip)]
     pub collision_pipeline: rapier::CollisionPipeline,
     pub broad_phase: rapier::BroadPhase,
     pub narrow_phase: rapier::NarrowPhase,
     pub query_pipeline: rapier::QueryPipeline,
     pub collider_set: rapier::ColliderSet,
     pub rigid_body_set: rapier::RigidBodySet,
+    #[serde(skip)]
     pub collider_shape_cache: ColliderShapeCache,
     pub collision_cache: CollisionCache,
 }
@@ -47,6 +52,9 @@ impl Clone for RapierContext {
 
 /// A cache containing a map of entities, to the list of entities that each entity is currently
 /// intersecting with.
+///
+/// Unlike the other caches, this is part of the game state, since it is only updated by collision
+/// events. Each list is sorted by entity, so that it doesn't depend on the order of the events.
 #[derive(Default)]
 pub struct CollisionCache {
     /// The collisions in the cache.
@@ -92,6 +100,20 @@ impl Clone for CollisionCache {
     }
 }
 
+impl Serialize for CollisionCache {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.collisions.borrow().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for CollisionCache {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Self {
+            collisions: Arc::new(AtomicRefCell::new(IndexMap::deserialize(deserializer)?)),
+        })
+    }
+}
+
 /// Update the collision cache with rapier collision events.
 impl rapier::EventHandler for &mut CollisionCache {
     fn handle_collision_event(
@@ -106,16 +128,13 @@ impl rapier::EventHandler for &mut CollisionCache {
                 let a_ent = RapierUserData::entity(colliders.get(a).unwrap().user_data);
                 let b_ent = RapierUserData::entity(colliders.get(b).unwrap().user_data);
 
-                self.collisions
-                    .borrow_mut()
-                    .entry(a_ent)
-                    .or_default()
-                    .push(b_ent);
-                self.collisions
-                    .borrow_mut()
-                    .entry(b_ent)
-                    .or_default()
-                    .push(a_ent);
+                let key = |e: &Entity| (e.index(), e.generation());
+                let mut collisions = self.collisions.borrow_mut();
+                for (entity, other_entity) in [(a_ent, b_ent), (b_ent, a_ent)] {
+                    let entity_collisions = collisions.entry(entity).or_default();
+                    let i = entity_collisions.partition_point(|e| key(e) <= key(&other_entity));
+                    entity_collisions.insert(i, other_entity);
+                }
             }
             rapier::CollisionEvent::Stopped(a, b, _) => {
                 let Some(a_ent) = colliders.get(a).map(|x| RapierUserData::entity(x.user_data)) else {
@@ -150,6 +169,72 @@ impl rapier::EventHandler for &mut CollisionCache {
     }
 }
 
+/// The collider of an actor or solid in a [`RapierSnapshot`]: its rapier user data, position,
+/// shape, and whether or not it is enabled.
+type SnapshotCollider = (
+    u128,
+    rapier::Isometry<rapier::Real>,
+    rapier::SharedShape,
+    bool,
+);
+
+/// The physics state that is kept in snapshots in place of the [`RapierContext`].
+///
+/// Copying the whole context on every frame is expensive, and only the collision cache in it is
+/// game state, so the rest of it is re-created when the snapshot is restored: the map tiles from
+/// their components, and the actors and solids from where they were when the collision pipeline
+/// last stepped, which is what the next collision events are found from.
+pub struct RapierSnapshot {
+    collisions: IndexMap<Entity, Vec<Entity>, EntityBuildHasher>,
+    /// The colliders of the actors and solids, in the order of their rigid body handles.
+    colliders: Vec<SnapshotCollider>,
+}
+
+impl SnapshotAs for RapierContext {
+    type Snapshot = RapierSnapshot;
+
+    fn snapshot_as(world: &World) -> RapierSnapshot {
+        let ctx = world.resource::<RapierContext>();
+        let ctx = ctx.borrow();
+        let colliders = ctx
+            .rigid_body_set
+            .iter()
+            .filter(|(_, body)| body.is_dynamic())
+            .map(|(_, body)| {
+                let collider = &ctx.collider_set[body.colliders()[0]];
+                (
+                    body.user_data,
+                    *collider.position(),
+                    collider.shared_shape().clone(),
+                    collider.is_enabled(),
+                )
+            })
+            .collect();
+
+        RapierSnapshot {
+            collisions: ctx.collision_cache.collisions.borrow().clone(),
+            colliders,
+        }
+    }
+
+    fn restore_from(world: &mut World, snapshot: &RapierSnapshot) {
+        world.insert_resource(RapierContext {
+            collision_cache: CollisionCache {
+                collisions: Arc::new(AtomicRefCell::new(snapshot.collisions.clone())),
+            },
+            ..default()
+        });
+
+        let mut colliders = Some(snapshot.colliders.clone());
+        world
+            .run_initialized_system(move |mut collision_world: CollisionWorld| {
+                collision_world.rebuild(&colliders.take().unwrap());
+                Ok(())
+            })
+            .unwrap();
+    }
+}
+
 impl_system_param! {
     pub struct CollisionWorld<'a> {
         entities: Res<'a, Entities>,
@@ -179,13 +264,13 @@ impl_system_param! {
 }
 
 /// An actor in the physics simulation.
-#[derive(Default, Clone, Copy, Debug, Pod, Zeroable, TypeUlid)]
+#[derive(Default, Clone, Copy, Debug, Pod, Zeroable, TypeUlid, Serialize, Deserialize)]
 #[ulid = "01GNF73PE03HFCE5MP8WC8ZKB6"]
 #[repr(C)]
 pub struct Actor;
 
 /// A solid in the physics simulation.
-#[derive(Default, Clone, Copy, Debug, Pod, Zeroable, TypeUlid)]
+#[derive(Default, Clone, Copy, Debug, Pod, Zeroable, TypeUlid, Serialize, Deserialize)]
 #[ulid = "01GNF73B5D1M7JYN0F65HMR2MW"]
 #[repr(C)]
 pub struct Solid;
@@ -193,7 +278,7 @@ pub struct Solid;
 /// A collider body in the physics simulation.
 ///
 /// This is used for actors and solids in the simulation, not for tiles.
-#[derive(Default, Clone, Debug, TypeUlid)]
+#[derive(Default, Clone, Debug, TypeUlid, Serialize, Deserialize)]
 #[ulid = "01GNF72YMMDM831S0TGAR2SWZ9"]
 #[repr(C)]
 pub struct Collider {
@@ -218,7 +303,7 @@ pub struct Collider {
 }
 
 /// Component added to tiles that have been given corresponding rapier colliders.
-#[derive(Default, Clone, Debug, TypeUlid, Deref, DerefMut)]
+#[derive(Default, Clone, Debug, TypeUlid, Deref, DerefMut, Serialize, Deserialize)]
 #[ulid = "01GRYDJ1NVAA07RWSPGRJR6809"]
 pub struct TileRapierHandle(pub rapier::RigidBodyHandle);
 
@@ -257,6 +342,26 @@ pub enum TileCollisionKind {
 
 impl BonesBevyAssetLoad for TileCollisionKind {}
 
+/// Create the dynamic rigid body and the collider for the actor or solid `entity`.
+fn insert_collider_body(
+    rigid_body_set: &mut rapier::RigidBodySet,
+    collider_set: &mut rapier::ColliderSet,
+    entity: Entity,
+    shape: rapier::SharedShape,
+) -> rapier::RigidBodyHandle {
+    let body_handle = rigid_body_set
+        .insert(rapier::RigidBodyBuilder::dynamic().user_data(RapierUserData::from(entity)));
+    collider_set.insert_with_parent(
+        rapier::ColliderBuilder::new(shape)
+            .active_events(rapier::ActiveEvents::COLLISION_EVENTS)
+            .active_collision_types(rapier::ActiveCollisionTypes::all())
+            .user_data(RapierUserData::from(entity)),
+        body_handle,
+        rigid_body_set,
+    );
+    body_handle
+}
+
 impl<'a> CollisionWorld<'a> {
     /// Updates the collision world with the entity's actual transforms.
     ///
@@ -366,18 +471,7 @@ impl<'a> CollisionWorld<'a> {
 
             // Get the handle to the rapier collider, creating it if it doesn't exist.
             let rapier_handle = collider.rapier_handle.get_or_insert_with(|| {
-                let body_handle = rigid_body_set.insert(
-                    rapier::RigidBodyBuilder::dynamic().user_data(RapierUserData::from(ent)),
-                );
-                collider_set.insert_with_parent(
-                    rapier::ColliderBuilder::new(shared_shape.clone())
-                        .active_events(rapier::ActiveEvents::COLLISION_EVENTS)
-                        .active_collision_types(rapier::ActiveCollisionTypes::all())
-                        .user_data(RapierUserData::from(ent)),
-                    body_handle,
-                    rigid_body_set,
-                );
-                body_handle
+                insert_collider_body(rigid_body_set, collider_set, ent, shared_shape.clone())
             });
             let rapier_body = rigid_body_set.get_mut(*rapier_handle).unwrap();
 
@@ -395,6 +489,63 @@ impl<'a> CollisionWorld<'a> {
         }
     }
 
+    /// Re-create the rapier bodies after the context has been replaced with one that only has the
+    /// collision cache of a [`RapierSnapshot`], with the snapshot's actor and solid `colliders`.
+    fn rebuild(&mut self, colliders: &[SnapshotCollider]) {
+        puffin::profile_function!();
+
+        // The handles in the components point to the bodies of the old context
+        *self.tile_rapier_handles = default();
+        for (_, collider) in self.entities.iter_with(&mut self.colliders) {
+            collider.rapier_handle = None;
+        }
+        self.update_tiles();
+
+        let RapierContext {
+            broad_phase,
+            collider_set,
+            query_pipeline,
+            rigid_body_set,
+            narrow_phase,
+            collision_pipeline,
+            ..
+        } = &mut *self.ctx;
+
+        for (user_data, position, shape, enabled) in colliders {
+            let entity = RapierUserData::entity(*user_data);
+            let body_handle =
+                insert_collider_body(rigid_body_set, collider_set, entity, shape.clone());
+            let rapier_body = rigid_body_set.get_mut(body_handle).unwrap();
+            rapier_body.set_position(*position, true);
+            collider_set
+                .get_mut(rapier_body.colliders()[0])
+                .unwrap()
+                .set_enabled(*enabled);
+
+            // The bodies of entities that were killed since the last step are kept, so that they
+            // are removed from the collision cache by the next update, like they would have been.
+            if self.entities.is_alive(entity) {
+                if let Some(collider) = self.colliders.get_mut(entity) {
+                    collider.rapier_handle = Some(body_handle);
+                }
+            }
+        }
+
+        // Step without handling the collision events, since the collisions that the bodies start
+        // with are already in the collision cache.
+        collision_pipeline.step(
+            0.0,
+            broad_phase,
+            narrow_phase,
+            rigid_body_set,
+            collider_set,
+            None,
+            &(),
+            &(),
+        );
+        query_pipeline.update(rigid_body_set, collider_set);
+    }
+
     /// Update all of the map tile collisions.
     ///
     /// You should only need to call this when spawning or otherwise completely rebuilding the map
@@ -3,18 +3,24 @@ use ::bevy::utils::HashMap;
 use super::*;
 
 /// Resource that maps [`ColliderShape`]s to a rapier [`SharedShape`][rapier::SharedShape].
-#[derive(Clone, Deref, DerefMut, TypeUlid, Default)]
+///
+/// The map is shared between the snapshots of the world, and only copied when a new shape is
+/// added to it, since the same few shapes are used for the whole match.
+#[derive(Clone, Deref, TypeUlid, Default)]
 #[ulid = "01GRVP9XNY2AA8D9FTSC51HE6E"]
-pub struct ColliderShapeCache(HashMap<ColliderShape, rapier::SharedShape>);
+pub struct ColliderShapeCache(Arc<HashMap<ColliderShape, rapier::SharedShape>>);
 
 impl ColliderShapeCache {
-    pub fn shared_shape(&mut self, shape: ColliderShape) -> &mut rapier::SharedShape {
-        self.entry(shape).or_insert_with(|| shape.shared_shape())
+    pub fn shared_shape(&mut self, shape: ColliderShape) -> &rapier::SharedShape {
+        if !self.0.contains_key(&shape) {
+            Arc::make_mut(&mut self.0).insert(shape, shape.shared_shape());
+        }
+        &self.0[&shape]
     }
 }
 
 /// The Jumpy collision shape type.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum ColliderShape {
     Circle { diameter: f32 },
     Rectangle { size: Vec2 },
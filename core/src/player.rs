@@ -23,11 +23,24 @@ const PLAYER_COLORS: [Color; 4] = [
 
 pub fn install(session: &mut CoreSession) {
     state::install(session);
+    session
+        .serializer
+        .register_component::<PlayerIdx>()
+        .register_component::<PlayerLayers>()
+        .register_component::<EmoteState>()
+        .register_component::<EmoteRegion>()
+        .register_component::<PlayerKilled>()
+        .register_component::<AiPlayer>()
+        .register_component::<Hat>()
+        .register_resource::<PlayersHaveSpawned>()
+        .register_snapshot_component::<ElementKillCallback>()
+        .register_load_hook(restore_player_kill_callbacks);
 
     // Add other player systems
     session
         .stages
         .add_system_to_stage(CoreStage::First, hydrate_players)
+        .add_system_to_stage(CoreStage::First, player_ai_takeover)
         .add_system_to_stage(CoreStage::First, player_ai_system)
         .add_system_to_stage(CoreStage::PostUpdate, play_itemless_fin_animations)
         .add_system_to_stage(CoreStage::PostUpdate, player_facial_animations)
@@ -37,12 +50,12 @@ pub fn install(session: &mut CoreSession) {
 }
 
 /// The player index, for example Player 1, Player 2, and so on.
-#[derive(Clone, TypeUlid, Deref, DerefMut)]
+#[derive(Clone, TypeUlid, Deref, DerefMut, Serialize, Deserialize)]
 #[ulid = "01GP49B2AMTYB6W8DWKBRF27FT"]
 pub struct PlayerIdx(pub usize);
 
 /// Contains the entities of the extra player layers, such as the player face and fin.
-#[derive(Clone, TypeUlid)]
+#[derive(Clone, TypeUlid, Serialize, Deserialize)]
 #[ulid = "01GQQRZ4V5WSRJTA1VTA816Z9T"]
 pub struct PlayerLayers {
     pub fin_anim: Key,
@@ -60,7 +73,7 @@ impl PlayerLayers {
 }
 
 /// A component representing the current emote state of a player.
-#[derive(Clone, TypeUlid, Default)]
+#[derive(Clone, TypeUlid, Default, Serialize, Deserialize)]
 #[ulid = "01GR4Q7MJF132EFY1RZZWECJK0"]
 enum EmoteState {
     /// The player is not emoting
@@ -73,7 +86,7 @@ enum EmoteState {
 /// A component representing a region in which a player should emote in some way.
 ///
 /// For example, a lit grenade could have a
-#[derive(Debug, Clone, TypeUlid)]
+#[derive(Debug, Clone, TypeUlid, Serialize, Deserialize)]
 #[ulid = "01GR2QVYXRX5V689C4EMBDS3AQ"]
 pub struct EmoteRegion {
     /// The entity that owns this emote region.
@@ -117,7 +130,7 @@ impl EmoteRegion {
 }
 
 /// A kind of emote the player can make.
-#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Emote {
     /// The player is alarmed!! Like a lit grenade was just thrown at them.
     #[default]
@@ -135,7 +148,7 @@ impl Emote {
 /// Marker component indicating that a player has been killed.
 ///
 /// This usually means their death animation is playing, and they are about to be de-spawned.
-#[derive(Clone, TypeUlid)]
+#[derive(Clone, TypeUlid, Serialize, Deserialize)]
 #[ulid = "01GP49AK25A8S9G2GYNAVE4PTN"]
 pub struct PlayerKilled {
     pub hit_from: Option<Vec2>,
@@ -259,7 +272,7 @@ impl PlayerCommand {
     }
 }
 
-#[derive(Clone, Debug, TypeUlid)]
+#[derive(Clone, Debug, TypeUlid, Serialize, Deserialize)]
 #[ulid = "01GQWND0P969BCZF5JET9MY944"]
 pub struct AiPlayer {
     /// Tick timer that is used for AI pausing logic.
@@ -316,6 +329,28 @@ impl FromWorld for PathfindingDebugLines {
     }
 }
 
+/// Let the AI take control of players that became AI players after they spawned, such as network
+/// players that disconnected.
+fn player_ai_takeover(
+    entities: Res<Entities>,
+    player_inputs: Res<PlayerInputs>,
+    player_indexes: Comp<PlayerIdx>,
+    player_states: Comp<PlayerState>,
+    mut ai_players: CompMut<AiPlayer>,
+) {
+    let new_ai_players = entities
+        .iter_with((&player_indexes, &player_states))
+        .filter(|(ent, (player_idx, _))| {
+            player_inputs.players[player_idx.0].is_ai && !ai_players.contains(*ent)
+        })
+        .map(|(ent, _)| ent)
+        .collect::<Vec<_>>();
+
+    for ent in new_ai_players {
+        ai_players.insert(ent, default());
+    }
+}
+
 fn player_ai_system(
     entities: Res<Entities>,
     nav_graph: ResMut<NavGraph>,
@@ -480,7 +515,7 @@ fn player_ai_system(
 ///
 /// TODO: This is probably temporary and will be removed when we have the proper tournament flow
 /// down.
-#[derive(Debug, Clone, TypeUlid, Default)]
+#[derive(Debug, Clone, TypeUlid, Default, Serialize, Deserialize)]
 #[ulid = "01H3F91HNY3X2QXCGKGZDTXVAM"]
 struct PlayersHaveSpawned {
     /// For each player, whether they have spawned before.
@@ -488,7 +523,7 @@ struct PlayersHaveSpawned {
 }
 
 /// Marker component for a player hat.
-#[derive(Debug, Clone, TypeUlid)]
+#[derive(Debug, Clone, TypeUlid, Serialize, Deserialize)]
 #[ulid = "01H3FDDXMSMVV8VMX1HRRFMXH0"]
 struct Hat(Handle<HatMeta>);
 
@@ -796,6 +831,25 @@ fn hydrate_players(
     }
 }
 
+/// Add the kill callbacks back to the players of a loaded session, since systems can't be saved.
+fn restore_player_kill_callbacks(world: &World) {
+    world
+        .run_initialized_system(
+            |entities: Res<Entities>,
+             player_indexes: Comp<PlayerIdx>,
+             mut element_kill_callbacks: CompMut<ElementKillCallback>| {
+                for (player_entity, _) in entities.iter_with(&player_indexes) {
+                    element_kill_callbacks.insert(
+                        player_entity,
+                        ElementKillCallback::new(player_kill_callback(player_entity)),
+                    );
+                }
+                Ok(())
+            },
+        )
+        .unwrap();
+}
+
 fn player_kill_callback(player_entity: Entity) -> System {
     (move |mut entities: ResMut<Entities>,
            attachments: Comp<Attachment>,
@@ -7,7 +7,7 @@ use states::*;
 mod states;
 
 /// The state of the player controller.
-#[derive(Clone, TypeUlid, Default)]
+#[derive(Clone, TypeUlid, Default, Serialize, Deserialize)]
 #[ulid = "01GP4E4BH47RN41QS66QBD679Q"]
 pub struct PlayerState {
     /// The ID for the current state.
@@ -38,6 +38,8 @@ impl PlayerState {
 }
 
 pub fn install(session: &mut CoreSession) {
+    session.serializer.register_component::<PlayerState>();
+
     // Add the player state stage
     session
         .stages
@@ -64,11 +66,14 @@ fn update_player_state_age(entities: Res<Entities>, mut player_states: CompMut<P
 
 fn use_drop_or_grab_items_system(id: Key) -> System {
     (move |entities: Res<Entities>,
+           game_meta: Res<CoreMetaArc>,
            player_inputs: Res<PlayerInputs>,
            player_indexes: Comp<PlayerIdx>,
            player_states: Comp<PlayerState>,
            player_assets: BevyAssets<PlayerMeta>,
+           player_aims: Comp<PlayerAim>,
            items: Comp<Item>,
+           aimable_items: Comp<AimableItem>,
            collision_world: CollisionWorld,
            mut inventories: CompMut<Inventory>,
            mut audio_events: ResMut<AudioEvents>,
@@ -91,6 +96,9 @@ fn use_drop_or_grab_items_system(id: Key) -> System {
             let Some(meta) = player_assets.get(&meta_handle) else { continue; };
 
             let control = &player_inputs.players[player_idx.0].control;
+            let aim = player_aims.get(player_ent);
+            let charging_throws = !game_meta.config.aim.throw_charge_time.is_zero();
+
             // If we are grabbing
             if control.grab_just_pressed {
                 if inventory.is_none() {
@@ -115,18 +123,32 @@ fn use_drop_or_grab_items_system(id: Key) -> System {
                         audio_events.play(meta.sounds.grab.clone(), meta.sounds.grab_volume);
                     }
 
-                // If we are already carrying an item
-                } else {
+                // If we are already carrying an item, and throws aren't charged
+                } else if !charging_throws {
                     // Drop it
                     commands.add(PlayerCommand::set_inventory(player_ent, None));
 
                     // Play drop sound
                     audio_events.play(meta.sounds.drop.clone(), meta.sounds.drop_volume);
                 }
+
+            // If we released grab after charging a throw
+            } else if inventory.is_some() && aim.map(|x| x.throw).unwrap_or(false) {
+                // Throw the item
+                commands.add(PlayerCommand::set_inventory(player_ent, None));
+
+                // Play drop sound
+                audio_events.play(meta.sounds.drop.clone(), meta.sounds.drop_volume);
             }
 
-            // If we are using an item
-            if control.shoot_just_pressed && inventory.is_some() {
+            // If we are using an item. Aimable items are used when shoot is released, after
+            // aiming.
+            let use_item = match inventory.0 {
+                Some(item) if aimable_items.contains(item) => aim.map(|x| x.fire).unwrap_or(false),
+                Some(_) => control.shoot_just_pressed,
+                None => false,
+            };
+            if use_item {
                 commands.add(PlayerCommand::use_item(player_ent));
             }
         }
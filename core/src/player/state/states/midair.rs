@@ -48,6 +48,7 @@ pub fn handle_player_state(
     mut sprites: CompMut<AtlasSprite>,
     mut animations: CompMut<AnimationBankSprite>,
     mut bodies: CompMut<KinematicBody>,
+    status_effects: Comp<StatusEffects>,
 ) {
     let players = entities.iter_with((
         &player_states,
@@ -56,7 +57,7 @@ pub fn handle_player_state(
         &mut sprites,
         &mut bodies,
     ));
-    for (_player_ent, (player_state, player_idx, animation, sprite, body)) in players {
+    for (player_ent, (player_state, player_idx, animation, sprite, body)) in players {
         if player_state.current != ID {
             continue;
         }
@@ -79,12 +80,18 @@ pub fn handle_player_state(
             body.velocity.y = body.velocity.y.max(-meta.stats.slow_fall_speed);
         }
 
+        let speed_factor = status_effects
+            .get(player_ent)
+            .map(|x| x.speed_factor())
+            .unwrap_or(1.0);
+        let air_speed = meta.stats.air_speed * speed_factor;
+
         // Walk in movement direction
-        body.velocity.x += meta.stats.accel_air_speed * control.move_direction.x;
+        body.velocity.x += meta.stats.accel_air_speed * speed_factor * control.move_direction.x;
         if control.move_direction.x.is_sign_positive() {
-            body.velocity.x = body.velocity.x.min(meta.stats.air_speed);
+            body.velocity.x = body.velocity.x.min(air_speed);
         } else {
-            body.velocity.x = body.velocity.x.max(-meta.stats.air_speed);
+            body.velocity.x = body.velocity.x.max(-air_speed);
         }
 
         if control.move_direction.x == 0.0 {
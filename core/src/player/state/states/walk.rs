@@ -44,6 +44,7 @@ pub fn handle_player_state(
     mut animations: CompMut<AnimationBankSprite>,
     mut bodies: CompMut<KinematicBody>,
     mut audio_events: ResMut<AudioEvents>,
+    status_effects: Comp<StatusEffects>,
 ) {
     let players = entities.iter_with((
         &player_states,
@@ -52,7 +53,7 @@ pub fn handle_player_state(
         &mut sprites,
         &mut bodies,
     ));
-    for (_player_ent, (player_state, player_idx, animation, sprite, body)) in players {
+    for (player_ent, (player_state, player_idx, animation, sprite, body)) in players {
         if player_state.current != ID {
             continue;
         }
@@ -78,18 +79,18 @@ pub fn handle_player_state(
             body.velocity.y = meta.stats.jump_speed;
         }
 
+        let speed_factor = status_effects
+            .get(player_ent)
+            .map(|x| x.speed_factor())
+            .unwrap_or(1.0);
+        let walk_speed = meta.stats.walk_speed * speed_factor;
+
         // Walk in movement direction
-        body.velocity.x += meta.stats.accel_walk_speed * control.move_direction.x;
+        body.velocity.x += meta.stats.accel_walk_speed * speed_factor * control.move_direction.x;
         if control.move_direction.x.is_sign_positive() {
-            body.velocity.x = body
-                .velocity
-                .x
-                .min(meta.stats.walk_speed * control.move_direction.x);
+            body.velocity.x = body.velocity.x.min(walk_speed * control.move_direction.x);
         } else {
-            body.velocity.x = body
-                .velocity
-                .x
-                .max(meta.stats.walk_speed * control.move_direction.x);
+            body.velocity.x = body.velocity.x.max(walk_speed * control.move_direction.x);
         }
 
         // Point in movement direction
@@ -2,9 +2,9 @@
 
 pub use {
     crate::{
-        attachment::*, bullet::*, camera::*, damage::*, debug::*, debug::*, elements::*,
+        aim::*, attachment::*, bullet::*, camera::*, damage::*, debug::*, debug::*, elements::*,
         globals::*, input::*, item::*, item::*, lifetime::*, map::*, metadata::*, physics::*,
-        player::*, session::*, utils::*, MAX_PLAYERS,
+        player::*, rounds::*, session::*, status_effect::*, utils::*, MAX_PLAYERS,
     },
     bones_bevy_asset::{BevyAssets, BonesBevyAsset, BonesBevyAssetLoad},
     bones_lib::prelude::*,
@@ -5,12 +5,13 @@ pub use turborand::prelude::*;
 
 pub fn install(session: &mut CoreSession) {
     session.world.init_resource::<GlobalRng>();
+    session.serializer.register_resource::<GlobalRng>();
 }
 
 /// Resource that can produce deterministic, pseudo-random numbers.
 ///
 /// Access in a system with [`Res<GlobalRng>`].
-#[derive(Clone, TypeUlid, Deref, DerefMut)]
+#[derive(Clone, TypeUlid, Deref, DerefMut, Serialize, Deserialize)]
 #[ulid = "01GQ0K6DDA9KKQTM3WDK1R91TE"]
 pub struct GlobalRng(AtomicRng);
 
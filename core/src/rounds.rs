@@ -0,0 +1,160 @@
+//! Matches that are played over several rounds.
+//!
+//! A round is over once its [`PlayerRespawns::outcome`] is set, and from then on the players can't
+//! move anymore. After the [`RoundsMeta::end_delay`] the next round is started by
+//! [`CoreSession::advance()`], which resets the map but keeps the [`MatchRounds`], or the match is
+//! over if that was the last round.
+
+use crate::{elements::player_spawner::*, prelude::*, MAX_PLAYERS};
+
+pub fn install(session: &mut CoreSession) {
+    session.world.init_resource::<MatchRounds>();
+    session.serializer.register_resource::<MatchRounds>();
+
+    session
+        .stages
+        .add_system_to_stage(CoreStage::First, update_rounds)
+        .add_system_to_stage(CoreStage::First, freeze_players);
+}
+
+/// Resource that tracks the rounds of the match, according to the [`RoundsMeta`] in the game
+/// config.
+///
+/// Unlike the rest of the world, this is carried over from one round to the next.
+#[derive(Clone, Debug, TypeUlid, Default, Serialize, Deserialize)]
+#[ulid = "01H4F9ECH9MQKJKCK9SMVJBBSC"]
+pub struct MatchRounds {
+    /// The index of the current round, starting at zero.
+    pub round: u32,
+    /// The number of rounds that each player has won.
+    pub wins: [u32; MAX_PLAYERS],
+    /// The time left before the next round starts, once the current round is over.
+    pub end_timer: Option<Timer>,
+    /// Set once the current round is over and the next round should be started.
+    pub start_next_round: bool,
+    /// How the match ended, once its last round is over.
+    pub outcome: Option<MatchOutcome>,
+}
+
+impl MatchRounds {
+    /// Whether or not the current round is the last one of the match.
+    pub fn is_last_round(&self, meta: &RoundsMeta) -> bool {
+        self.round + 1 >= meta.count
+    }
+}
+
+/// Count the round wins, and end the round or the match once the round outcome has been shown
+/// for long enough.
+fn update_rounds(
+    time: Res<Time>,
+    game_meta: Res<CoreMetaArc>,
+    respawns: Res<PlayerRespawns>,
+    mut rounds: ResMut<MatchRounds>,
+) {
+    let rounds_meta = &game_meta.config.rounds;
+    let Some(round_outcome) = respawns.outcome else { return };
+    if rounds.outcome.is_some() || rounds.start_next_round {
+        return;
+    }
+
+    // Count the win as soon as the round is over
+    if rounds.end_timer.is_none() {
+        if let MatchOutcome::Winner(player) = round_outcome {
+            rounds.wins[player] += 1;
+        }
+        rounds.end_timer = Some(Timer::new(rounds_meta.end_delay, TimerMode::Once));
+    }
+
+    let end_timer = rounds.end_timer.as_mut().unwrap();
+    end_timer.tick(time.delta());
+    if !end_timer.finished() {
+        return;
+    }
+
+    if !rounds.is_last_round(rounds_meta) {
+        rounds.start_next_round = true;
+        return;
+    }
+
+    // The player that won the most rounds wins the match, unless several players are tied
+    let most_wins = rounds.wins.iter().copied().max().unwrap_or_default();
+    let leaders = (0..MAX_PLAYERS)
+        .filter(|i| rounds.wins[*i] == most_wins)
+        .collect::<Vec<_>>();
+    rounds.outcome = Some(match leaders[..] {
+        [player] if most_wins > 0 => MatchOutcome::Winner(player),
+        _ => MatchOutcome::NoWinner,
+    });
+}
+
+/// Clear the controls of every player once the round is over, so that nobody can move until the
+/// next round starts, or for good once the match is over.
+fn freeze_players(respawns: Res<PlayerRespawns>, mut player_inputs: ResMut<PlayerInputs>) {
+    if respawns.outcome.is_none() {
+        return;
+    }
+
+    for player in &mut player_inputs.players {
+        player.control = default();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_support::four_player_session_info;
+
+    use super::*;
+
+    #[test]
+    fn test_rounds() {
+        let (mut info, mut bevy_world) = four_player_session_info();
+        let mut meta = CoreMeta::clone(&info.meta);
+        meta.config.rounds.count = 2;
+        let end_frames = (meta.config.rounds.end_delay.as_secs_f32() * crate::FPS) as u32 + 10;
+        info.meta = Arc::new(meta);
+        let mut session = CoreSession::new(info);
+
+        let mut play_round = |session: &mut CoreSession, outcome: MatchOutcome| {
+            for _ in 0..30 {
+                session.advance(&mut bevy_world);
+            }
+            session
+                .world
+                .resource::<PlayerRespawns>()
+                .borrow_mut()
+                .outcome = Some(outcome);
+
+            // Nobody can move once the round is over
+            session.update_input(|inputs| inputs.players[0].control.jump_pressed = true);
+            session.advance(&mut bevy_world);
+            session.update_input(|inputs| assert!(!inputs.players[0].control.jump_pressed));
+
+            for _ in 0..end_frames {
+                session.advance(&mut bevy_world);
+            }
+            session.world.resource::<MatchRounds>().borrow().clone()
+        };
+
+        // The next round starts on a new map, with the win counted, and without the players that
+        // left the match
+        session.update_input(|inputs| inputs.players[3].left_match = true);
+        let rounds = play_round(&mut session, MatchOutcome::Winner(1));
+        assert_eq!(rounds.round, 1);
+        assert_eq!(rounds.wins, [0, 1, 0, 0]);
+        assert_eq!(rounds.outcome, None);
+        assert_eq!(
+            session.world.resource::<PlayerRespawns>().borrow().outcome,
+            None
+        );
+        session.update_input(|inputs| {
+            assert!(inputs.players[2].active);
+            assert!(!inputs.players[3].active);
+        });
+
+        // After the last round, the player with the most wins wins the match
+        let rounds = play_round(&mut session, MatchOutcome::Winner(1));
+        assert_eq!(rounds.round, 1);
+        assert_eq!(rounds.wins, [0, 2, 0, 0]);
+        assert_eq!(rounds.outcome, Some(MatchOutcome::Winner(1)));
+    }
+}
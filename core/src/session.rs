@@ -1,6 +1,12 @@
 //! [`CoreSession`] implementation: the entrypoint for using `jumpy_core`.
 
-use crate::prelude::*;
+use crate::{prelude::*, random::GlobalRng};
+
+pub use serialize::{SaveAs, SessionSerializeError, SessionSerializer};
+pub use snapshot::{Snapshot, SnapshotAs};
+
+pub mod serialize;
+pub mod snapshot;
 
 /// Implementation of the Jumpy match session.
 ///
@@ -8,6 +14,7 @@ use crate::prelude::*;
 ///
 /// - Provide input to the game.
 /// - Snapshot/Restore the game state.
+/// - Save/Load the game state as bytes.
 /// - Access the session's ECS [`World`] ( for instance, to render the game ).
 ///
 /// All of this is done **without** rendering anything. The game systems logic will create
@@ -59,6 +66,9 @@ pub struct CoreSession {
     ///
     /// Used during [`advance()`][Self::advance] to borrow the bevy world.
     pub scratch_world: Option<::bevy::ecs::world::World>,
+    /// The component and resource types that are included when [saving][Self::save] the session,
+    /// and in its [snapshots][Self::snapshot].
+    pub serializer: SessionSerializer,
 }
 
 /// Information needed to start a game session.
@@ -93,8 +103,30 @@ impl CoreSession {
             scratch_world: Some(::bevy::ecs::world::World::new()),
             info: info.clone(),
             time_step: 1.0 / crate::FPS,
+            serializer: default(),
         };
 
+        // Save the bones components and resources that hold the game state. The sprites stay in
+        // snapshots even though they are rendered, since the game reads their flip and animation
+        // frame, and entities like the urchins get new sprites when they respawn. The camera is
+        // only ever rendered, so it is left out of them.
+        session
+            .serializer
+            .register_component::<Transform>()
+            .register_component::<Sprite>()
+            .register_component::<AtlasSprite>()
+            .register_component::<AnimatedSprite>()
+            .register_component::<AnimationBankSprite>()
+            .register_component::<Tile>()
+            .register_component::<TileLayer>()
+            .register_component::<Camera>()
+            .register_component::<CameraShake>()
+            .register_resource::<ClearColor>()
+            .register_resource_as::<Time>()
+            .skip_snapshots::<Camera>()
+            .skip_snapshots::<CameraShake>()
+            .skip_snapshots::<ClearColor>();
+
         // Install modules
         crate::install_modules(&mut session);
 
@@ -200,6 +232,46 @@ impl CoreSession {
                 input.editor_input = None;
             }
         }
+
+        if self
+            .world
+            .resource::<MatchRounds>()
+            .borrow()
+            .start_next_round
+        {
+            self.start_next_round();
+        }
+    }
+
+    /// Reset the map for the next round of the match.
+    ///
+    /// The world is replaced with the world of a new session, which only keeps the
+    /// [`MatchRounds`], the player inputs, the random number generator, and the time of the
+    /// previous round. This only depends on the simulation state, so every client of a network game
+    /// starts the next round on the same frame.
+    ///
+    /// The players that [left the match][PlayerInput::left_match] are removed from it here.
+    fn start_next_round(&mut self) {
+        let mut world = Self::new(self.info.clone()).world;
+        world.insert_resource(self.world.resource::<CoreMetaArc>().borrow().clone());
+
+        let mut player_inputs = self.world.resource::<PlayerInputs>().borrow().clone();
+        for player in &mut player_inputs.players {
+            if player.left_match {
+                player.active = false;
+            }
+        }
+        world.insert_resource(player_inputs);
+        world.insert_resource(self.world.resource::<GlobalRng>().borrow().clone());
+        world.insert_resource(self.world.resource::<Time>().borrow().clone());
+
+        let mut rounds = self.world.resource::<MatchRounds>().borrow().clone();
+        rounds.round += 1;
+        rounds.end_timer = None;
+        rounds.start_next_round = false;
+        world.insert_resource(rounds);
+
+        self.world = world;
     }
 
     /// Export the current map metadata by scanning the world entities. This means that the export
@@ -285,15 +357,122 @@ impl CoreSession {
         self.world.run_initialized_system(export_system).unwrap()
     }
 
+    /// Get a checksum of the game state, which is compared between network players to detect when
+    /// they get out of sync.
+    ///
+    /// This hashes the state that de-syncs show up in and that is cheap enough to hash every frame:
+    /// the transforms and physics bodies of the entities, the player states and inventories, and
+    /// the random number generator.
+    pub fn checksum(&self) -> u128 {
+        self.world
+            .run_initialized_system(
+                |entities: Res<Entities>,
+                 rng: Res<GlobalRng>,
+                 transforms: Comp<Transform>,
+                 bodies: Comp<KinematicBody>,
+                 player_indexes: Comp<PlayerIdx>,
+                 player_states: Comp<PlayerState>,
+                 inventories: Comp<Inventory>| {
+                    let mut hasher = FnvHasher::default();
+                    for (entity, transform) in entities.iter_with(&transforms) {
+                        hasher.write(&entity.index().to_le_bytes());
+                        for value in transform
+                            .translation
+                            .to_array()
+                            .into_iter()
+                            .chain(transform.rotation.to_array())
+                            .chain(transform.scale.to_array())
+                        {
+                            hasher.write(&value.to_bits().to_le_bytes());
+                        }
+                    }
+                    for (entity, body) in entities.iter_with(&bodies) {
+                        hasher.write(&entity.index().to_le_bytes());
+                        hasher.write_serialized(body);
+                    }
+                    for (entity, (player_idx, state, inventory)) in
+                        entities.iter_with((&player_indexes, &player_states, &inventories))
+                    {
+                        hasher.write(&entity.index().to_le_bytes());
+                        hasher.write_serialized(&(player_idx, state, inventory));
+                    }
+                    hasher.write_serialized(&*rng);
+                    Ok(hasher.finish() as u128)
+                },
+            )
+            .unwrap()
+    }
+
     /// Snapshot the world state
-    pub fn snapshot(&self) -> World {
-        self.world.clone()
+    ///
+    /// Network games take a snapshot every frame to be able to roll back, so only the types
+    /// registered with the [`serializer`][Self::serializer] are kept in it, and the stores in it are
+    /// shared until they are restored. See the [`snapshot`] module for the details.
+    pub fn snapshot(&self) -> Snapshot {
+        self.serializer.snapshot(&self.world)
+    }
+
+    /// Restore the world state from a [`snapshot()`][Self::snapshot]
+    ///
+    /// The [`Path2d`] components are cleared, since they are only the debug lines that get drawn
+    /// again as the debug systems run.
+    pub fn restore(&mut self, snapshot: Snapshot) {
+        snapshot.restore(&mut self.world);
+        self.world
+            .run_initialized_system(|mut paths: CompMut<Path2d>| {
+                *paths = default();
+                Ok(())
+            })
+            .unwrap();
+    }
+
+    /// Save the world state as bytes.
+    ///
+    /// Unlike a [`snapshot()`][Self::snapshot], the bytes can be written to disk or sent over the
+    /// network. Only the types registered with the [`serializer`][Self::serializer] are saved. See
+    /// the [`serialize`] module for the format.
+    pub fn save(&self) -> Result<Vec<u8>, SessionSerializeError> {
+        self.serializer.save(&self.world)?.to_bytes()
     }
 
-    /// Restore the world state
+    /// Load a world state saved with [`save()`][Self::save].
     ///
-    /// Will write the current state to `world`.
-    pub fn restore(&mut self, world: &mut World) {
-        std::mem::swap(&mut self.world, world)
+    /// The session must have been created with the same map and players as the saved session.
+    /// The state is only replaced if it could be loaded completely.
+    pub fn load(&mut self, bytes: &[u8]) -> Result<(), SessionSerializeError> {
+        let saved = serialize::SerializedSession::from_bytes(bytes)?;
+
+        // Start from a new session, so that the types that aren't saved are in their initial
+        // state. The map and the players are in the saved state already, along with the markers
+        // that stop them from being spawned and hydrated again when the session is advanced.
+        let mut world = Self::new(self.info.clone()).world;
+        world.insert_resource(self.world.resource::<CoreMetaArc>().borrow().clone());
+        self.serializer.load(&mut world, &saved)?;
+        self.world = world;
+
+        Ok(())
+    }
+}
+
+/// [`Time`] holds [`Instant`][std::time::Instant]s, which can't be saved, so the elapsed time and
+/// the last delta are saved instead.
+impl SaveAs for Time {
+    type Saved = (std::time::Duration, std::time::Duration);
+
+    fn save_as(&self) -> Self::Saved {
+        (self.elapsed(), self.delta())
+    }
+
+    fn load_from((elapsed, delta): Self::Saved) -> Self {
+        let mut time = Time::default();
+        // The first update doesn't set the delta, so the session is still on its first frame.
+        if elapsed.is_zero() {
+            return time;
+        }
+        // Advance to the frame before the saved one, and then by the saved delta, so that both
+        // the elapsed time and the delta match.
+        time.advance_exact(elapsed - delta);
+        time.advance_exact(delta);
+        time
     }
 }
@@ -0,0 +1,508 @@
+//! Serialization of the session's simulation state to bytes.
+//!
+//! A [`CoreSession`] can be saved with [`CoreSession::save()`] and loaded back into a session
+//! for the same map and players with [`CoreSession::load()`]. This makes it possible to keep a
+//! session on disk, attach it to a bug report, or send it to a peer that joins a match late.
+//!
+//! ## Format
+//!
+//! The bytes start with the [`MAGIC`] bytes, followed by the [`FORMAT_VERSION`] as a
+//! little-endian `u32`, followed by a [`SerializedSession`] encoded with [`postcard`].
+//!
+//! Each component store and resource is saved as a separate blob, tagged with the [`TypeUlid`] of
+//! its type, so tools can list the contents of a save without knowing the types in it. Only the
+//! types registered with the session's [`SessionSerializer`] are saved, and the other types are
+//! left the same as in a newly created session when loading, so any type that holds simulation
+//! state should be registered in the `install()` function of its module. Resources that can't be
+//! serialized directly can be saved as another type with [`SaveAs`], and state that can't be saved
+//! at all, such as components holding a [`System`], can be restored with a load hook.
+//!
+//! Changing the serialized layout of a registered type breaks existing saves, so whenever that
+//! happens, the [`FORMAT_VERSION`] must be bumped.
+
+use std::collections::BTreeMap;
+
+use serde::de::DeserializeOwned;
+
+use crate::prelude::*;
+
+use super::snapshot::{self, TypeSnapshotter};
+
+/// The bytes at the start of every saved session.
+pub const MAGIC: [u8; 4] = *b"JMPS";
+
+/// The version of the saved session format.
+///
+/// Sessions saved with another version can't be loaded.
+pub const FORMAT_VERSION: u32 = 2;
+
+/// An error while saving or loading a session.
+#[derive(Debug)]
+pub enum SessionSerializeError {
+    /// The bytes don't start with the [`MAGIC`] bytes.
+    NotASession,
+    /// The session was saved with another [`FORMAT_VERSION`].
+    UnsupportedVersion(u32),
+    /// The save contains a type that isn't registered with the [`SessionSerializer`].
+    UnknownType { ulid: Ulid, name: String },
+    /// The saved entities can't be re-created.
+    InvalidEntities,
+    /// A value could not be encoded or decoded.
+    Postcard(postcard::Error),
+}
+
+impl std::fmt::Display for SessionSerializeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotASession => write!(f, "Data is not a saved session"),
+            Self::UnsupportedVersion(version) => write!(
+                f,
+                "Session was saved with format version {version}, but only version \
+                {FORMAT_VERSION} is supported"
+            ),
+            Self::UnknownType { ulid, name } => {
+                write!(f, "Session contains unknown type `{name}` ( {ulid} )")
+            }
+            Self::InvalidEntities => write!(f, "Session contains invalid entities"),
+            Self::Postcard(e) => write!(f, "Error encoding session data: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for SessionSerializeError {}
+
+impl From<postcard::Error> for SessionSerializeError {
+    fn from(e: postcard::Error) -> Self {
+        Self::Postcard(e)
+    }
+}
+
+/// The decoded contents of a saved session.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct SerializedSession {
+    /// The `(index, generation)` of every entity that is alive, in index order.
+    pub entities: Vec<(u32, u32)>,
+    /// The saved resources.
+    pub resources: Vec<SerializedType>,
+    /// The saved component stores.
+    pub components: Vec<SerializedType>,
+}
+
+/// A resource or component store in a [`SerializedSession`].
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SerializedType {
+    /// The [`TypeUlid`] of the type.
+    pub ulid: u128,
+    /// The Rust type name, to make saves easier to inspect.
+    pub name: String,
+    /// The [`postcard`] encoded data.
+    ///
+    /// For resources this is the resource itself, and for component stores it is a list of
+    /// `(entity, component)` pairs.
+    pub data: Vec<u8>,
+}
+
+impl SerializedSession {
+    /// Decode a saved session, without loading it.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SessionSerializeError> {
+        let Some(bytes) = bytes.strip_prefix(&MAGIC) else {
+            return Err(SessionSerializeError::NotASession);
+        };
+        if bytes.len() < 4 {
+            return Err(SessionSerializeError::NotASession);
+        }
+        let (version, bytes) = bytes.split_at(4);
+        let version = u32::from_le_bytes(version.try_into().unwrap());
+        if version != FORMAT_VERSION {
+            return Err(SessionSerializeError::UnsupportedVersion(version));
+        }
+
+        Ok(postcard::from_bytes(bytes)?)
+    }
+
+    /// Encode the session, including the header.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, SessionSerializeError> {
+        let mut bytes = Vec::from(MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        Ok(postcard::to_extend(self, bytes)?)
+    }
+}
+
+/// Functions to save and load a registered type.
+#[derive(Clone, Copy)]
+struct TypeSerializer {
+    ulid: Ulid,
+    name: &'static str,
+    save: fn(&World) -> Result<Option<Vec<u8>>, postcard::Error>,
+    load: fn(&mut World, &[u8]) -> Result<(), postcard::Error>,
+}
+
+/// The list of component and resource types that are saved with the session, and kept in
+/// [snapshots][super::snapshot].
+///
+/// Modules register the types that hold their state in their `install()` function:
+///
+/// ```ignore
+/// pub fn install(session: &mut CoreSession) {
+///     session
+///         .serializer
+///         .register_component::<KinematicBody>()
+///         .register_resource::<RapierContext>();
+/// }
+/// ```
+///
+/// State that can't be saved, such as components that hold a [`System`], can be restored after
+/// loading with a [load hook][Self::register_load_hook].
+#[derive(Clone, Default)]
+pub struct SessionSerializer {
+    resources: Vec<TypeSerializer>,
+    components: Vec<TypeSerializer>,
+    load_hooks: Vec<fn(&World)>,
+    pub(super) resource_snapshots: Vec<TypeSnapshotter>,
+    pub(super) component_snapshots: Vec<TypeSnapshotter>,
+}
+
+impl SessionSerializer {
+    /// Save the component store for `T` with the session.
+    pub fn register_component<T>(&mut self) -> &mut Self
+    where
+        T: TypeUlid + Serialize + DeserializeOwned + Clone + Sync + Send + 'static,
+    {
+        snapshot::register(
+            &mut self.component_snapshots,
+            TypeSnapshotter::component::<T>(),
+            false,
+        );
+        register(
+            &mut self.components,
+            TypeSerializer {
+                ulid: T::ULID,
+                name: std::any::type_name::<T>(),
+                save: save_component::<T>,
+                load: load_component::<T>,
+            },
+        );
+        self
+    }
+
+    /// Save the resource `T` with the session.
+    pub fn register_resource<T>(&mut self) -> &mut Self
+    where
+        T: TypeUlid + Serialize + DeserializeOwned + Clone + Sync + Send + 'static,
+    {
+        snapshot::register(
+            &mut self.resource_snapshots,
+            TypeSnapshotter::resource::<T>(),
+            false,
+        );
+        register(
+            &mut self.resources,
+            TypeSerializer {
+                ulid: T::ULID,
+                name: std::any::type_name::<T>(),
+                save: save_resource::<T>,
+                load: load_resource::<T>,
+            },
+        );
+        self
+    }
+
+    /// Save the resource `T`, which can't be serialized itself, as its [`SaveAs::Saved`] value.
+    pub fn register_resource_as<T: SaveAs>(&mut self) -> &mut Self {
+        snapshot::register(
+            &mut self.resource_snapshots,
+            TypeSnapshotter::resource::<T>(),
+            false,
+        );
+        register(
+            &mut self.resources,
+            TypeSerializer {
+                ulid: T::ULID,
+                name: std::any::type_name::<T>(),
+                save: save_resource_as::<T>,
+                load: load_resource_as::<T>,
+            },
+        );
+        self
+    }
+
+    /// Run `hook` on the world after a session is loaded into it.
+    ///
+    /// Hooks run after all of the saved types are loaded, in the order they were registered.
+    pub fn register_load_hook(&mut self, hook: fn(&World)) -> &mut Self {
+        self.load_hooks.push(hook);
+        self
+    }
+
+    /// Save the registered types in the `world`.
+    pub fn save(&self, world: &World) -> Result<SerializedSession, SessionSerializeError> {
+        puffin::profile_function!();
+
+        let entities = world
+            .run_initialized_system(|entities: Res<Entities>| {
+                Ok(entities
+                    .iter_with_bitset(entities.bitset())
+                    .map(|entity| (entity.index(), entity.generation()))
+                    .collect::<Vec<_>>())
+            })
+            .unwrap();
+
+        let save_types = |types: &[TypeSerializer]| {
+            let mut saved = Vec::with_capacity(types.len());
+            for ty in types {
+                if let Some(data) = (ty.save)(world)? {
+                    saved.push(SerializedType {
+                        ulid: ty.ulid.0,
+                        name: ty.name.into(),
+                        data,
+                    });
+                }
+            }
+            Ok::<_, SessionSerializeError>(saved)
+        };
+
+        Ok(SerializedSession {
+            entities,
+            resources: save_types(&self.resources)?,
+            components: save_types(&self.components)?,
+        })
+    }
+
+    /// Load a saved session into the `world`.
+    ///
+    /// The `world` should be the world of a newly created [`CoreSession`] that hasn't been stepped
+    /// yet, so that the only state in it is the state created when the session is initialized.
+    pub fn load(
+        &self,
+        world: &mut World,
+        session: &SerializedSession,
+    ) -> Result<(), SessionSerializeError> {
+        puffin::profile_function!();
+
+        world.insert_resource(create_entities(&session.entities)?);
+
+        for (types, saved) in [
+            (&self.resources, &session.resources),
+            (&self.components, &session.components),
+        ] {
+            for saved in saved {
+                let Some(ty) = types.iter().find(|ty| ty.ulid.0 == saved.ulid) else {
+                    return Err(SessionSerializeError::UnknownType {
+                        ulid: Ulid(saved.ulid),
+                        name: saved.name.clone(),
+                    });
+                };
+                (ty.load)(world, &saved.data)?;
+            }
+        }
+
+        for hook in &self.load_hooks {
+            hook(world);
+        }
+
+        Ok(())
+    }
+}
+
+/// A resource that is saved as another type, because it can't be serialized itself.
+///
+/// See [`SessionSerializer::register_resource_as()`].
+pub trait SaveAs: TypeUlid + Clone + Sync + Send + 'static {
+    /// The type that is saved in place of the resource.
+    type Saved: Serialize + DeserializeOwned;
+
+    /// Get the value to save for the resource.
+    fn save_as(&self) -> Self::Saved;
+
+    /// Re-create the resource from its saved value.
+    fn load_from(saved: Self::Saved) -> Self;
+}
+
+/// Add a type to the list of registered types, unless it is already in it.
+fn register(types: &mut Vec<TypeSerializer>, ty: TypeSerializer) {
+    if !types.iter().any(|x| x.ulid == ty.ulid) {
+        types.push(ty);
+    }
+}
+
+fn save_component<T>(world: &World) -> Result<Option<Vec<u8>>, postcard::Error>
+where
+    T: TypeUlid + Serialize + Clone + Sync + Send + 'static,
+{
+    world
+        .run_initialized_system(|entities: Res<Entities>, components: Comp<T>| {
+            let components = entities.iter_with(&components).collect::<Vec<_>>();
+            Ok(postcard::to_allocvec(&components).map(Some))
+        })
+        .unwrap()
+}
+
+fn load_component<T>(world: &mut World, data: &[u8]) -> Result<(), postcard::Error>
+where
+    T: TypeUlid + DeserializeOwned + Clone + Sync + Send + 'static,
+{
+    let mut components: Vec<(Entity, T)> = postcard::from_bytes(data)?;
+    world
+        .run_initialized_system(move |mut store: CompMut<T>| {
+            for (entity, component) in components.drain(..) {
+                store.insert(entity, component);
+            }
+            Ok(())
+        })
+        .unwrap();
+    Ok(())
+}
+
+fn save_resource<T>(world: &World) -> Result<Option<Vec<u8>>, postcard::Error>
+where
+    T: TypeUlid + Serialize + Clone + Sync + Send + 'static,
+{
+    world
+        .get_resource::<T>()
+        .map(|resource| postcard::to_allocvec(&*resource.borrow()))
+        .transpose()
+}
+
+fn load_resource<T>(world: &mut World, data: &[u8]) -> Result<(), postcard::Error>
+where
+    T: TypeUlid + DeserializeOwned + Clone + Sync + Send + 'static,
+{
+    world.insert_resource(postcard::from_bytes::<T>(data)?);
+    Ok(())
+}
+
+fn save_resource_as<T: SaveAs>(world: &World) -> Result<Option<Vec<u8>>, postcard::Error> {
+    world
+        .get_resource::<T>()
+        .map(|resource| postcard::to_allocvec(&resource.borrow().save_as()))
+        .transpose()
+}
+
+fn load_resource_as<T: SaveAs>(world: &mut World, data: &[u8]) -> Result<(), postcard::Error> {
+    world.insert_resource(T::load_from(postcard::from_bytes(data)?));
+    Ok(())
+}
+
+/// Create an [`Entities`] resource where exactly the given `(index, generation)` entities are
+/// alive.
+///
+/// The entities are re-created by creating and killing entities until each index has the saved
+/// generation. The generation of the dead entities isn't saved, so references to entities that
+/// were already dead when the session was saved aren't kept apart from newly created entities.
+fn create_entities(alive: &[(u32, u32)]) -> Result<Entities, SessionSerializeError> {
+    let alive = alive.iter().copied().collect::<BTreeMap<_, _>>();
+    let end = alive.keys().last().map(|index| index + 1).unwrap_or(0);
+
+    let mut entities = Entities::default();
+    let mut dead = Vec::new();
+    for index in 0..end {
+        let mut entity = entities.create();
+        if entity.index() != index {
+            return Err(SessionSerializeError::InvalidEntities);
+        }
+
+        let Some(&generation) = alive.get(&index) else {
+            dead.push(entity);
+            continue;
+        };
+        while entity.generation() < generation {
+            entities.kill(entity);
+            entity = entities.create();
+            if entity.index() != index {
+                return Err(SessionSerializeError::InvalidEntities);
+            }
+        }
+        if entity.generation() != generation {
+            return Err(SessionSerializeError::InvalidEntities);
+        }
+    }
+
+    // Kill the dead entities starting from the last one, so the lowest indexes are re-used first
+    for entity in dead.into_iter().rev() {
+        entities.kill(entity);
+    }
+
+    Ok(entities)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_support::{four_player_session, PLAYER_COUNT};
+
+    use super::*;
+
+    /// Advance the session by a frame, with inputs that keep the players running, jumping, and
+    /// shooting around.
+    fn play_frame(
+        session: &mut CoreSession,
+        bevy_world: &mut ::bevy::ecs::world::World,
+        frame: u32,
+    ) {
+        session.update_input(|inputs| {
+            for (player, input) in inputs.players.iter_mut().take(PLAYER_COUNT).enumerate() {
+                let phase = (frame as usize / 30 + player) % 4;
+                input.control = PlayerControl {
+                    move_direction: Vec2::new(if phase < 2 { 1.0 } else { -1.0 }, 0.0),
+                    jump_pressed: phase % 2 == 0,
+                    jump_just_pressed: phase % 2 == 0 && frame % 30 == 0,
+                    grab_pressed: phase == 1,
+                    grab_just_pressed: phase == 1 && frame % 30 == 0,
+                    shoot_pressed: phase == 3,
+                    shoot_just_pressed: phase == 3 && frame % 30 == 0,
+                    ..default()
+                };
+            }
+        });
+        session.advance(bevy_world);
+    }
+
+    /// Get the `(index, generation)` of the entities that are alive in the session.
+    fn alive_entities(session: &CoreSession) -> Vec<(u32, u32)> {
+        session.serializer.save(&session.world).unwrap().entities
+    }
+
+    #[test]
+    fn test_save_load_advance() {
+        let (mut session, mut bevy_world) = four_player_session();
+        let mut frame = 0;
+        for _ in 0..120 {
+            play_frame(&mut session, &mut bevy_world, frame);
+            frame += 1;
+        }
+
+        let bytes = session.save().unwrap();
+        let mut loaded = CoreSession::new(session.info.clone());
+        loaded.load(&bytes).unwrap();
+        assert_eq!(loaded.checksum(), session.checksum());
+        assert_eq!(alive_entities(&loaded), alive_entities(&session));
+
+        // The loaded session should play out the same as the original one, without spawning the
+        // map or the players again.
+        for _ in 0..240 {
+            play_frame(&mut session, &mut bevy_world, frame);
+            play_frame(&mut loaded, &mut bevy_world, frame);
+            assert_eq!(
+                loaded.checksum(),
+                session.checksum(),
+                "Loaded session is out of sync on frame {frame}"
+            );
+            assert_eq!(alive_entities(&loaded), alive_entities(&session));
+            frame += 1;
+        }
+    }
+
+    #[test]
+    fn test_invalid_header() {
+        let bytes = SerializedSession::default().to_bytes().unwrap();
+        let mut old_version = bytes.clone();
+        old_version[MAGIC.len()..MAGIC.len() + 4].copy_from_slice(&0u32.to_le_bytes());
+        assert!(matches!(
+            SerializedSession::from_bytes(&old_version),
+            Err(SessionSerializeError::UnsupportedVersion(0))
+        ));
+        assert!(matches!(
+            SerializedSession::from_bytes(b"JMP"),
+            Err(SessionSerializeError::NotASession)
+        ));
+    }
+}
@@ -0,0 +1,277 @@
+//! Snapshots of the session's simulation state, which network games take on every frame to be able
+//! to roll back.
+//!
+//! A [`Snapshot`] holds the same component stores and resources that are
+//! [saved][super::serialize] with the session, along with the [`Entities`], so any type that holds
+//! simulation state is already in it once it is registered with the [`SessionSerializer`].
+//! Registered types that are only used for rendering and don't affect the simulation, like the
+//! camera, can be left out with [`SessionSerializer::skip_snapshots()`]. They keep their current
+//! state when a snapshot is restored, so they shouldn't be left out if entities gain or lose them
+//! during the match. Resources that are expensive to copy but can be re-created from a smaller
+//! value are kept as that value instead, with [`SnapshotAs`].
+//!
+//! Every store in a snapshot is behind an [`Arc`], so snapshots are copy-on-write: cloning one, as
+//! GGRS does whenever it loads a saved state, doesn't copy any stores, and restoring a snapshot only
+//! copies the stores if the snapshot is still shared.
+
+use std::any::Any;
+
+use crate::prelude::*;
+
+use super::SessionSerializer;
+
+/// The snapshot of a single type, which is downcast by the function that restores it.
+type SnapshotData = Arc<dyn Any + Sync + Send>;
+
+/// The simulation state of a [`CoreSession`], taken with [`CoreSession::snapshot()`].
+///
+/// Cloning a snapshot is cheap, since it only clones an [`Arc`] for every store in it.
+#[derive(Clone, Default)]
+pub struct Snapshot {
+    entities: Arc<Entities>,
+    /// The snapshots of the registered types, along with the functions that restore them, in the
+    /// order that they are restored.
+    types: Vec<(fn(&mut World, SnapshotData), SnapshotData)>,
+}
+
+impl Snapshot {
+    /// Write the state in the snapshot to the `world`.
+    ///
+    /// The component stores are restored before the resources, so that the resources registered
+    /// with [`SnapshotAs`] can be re-created from the restored components.
+    pub fn restore(self, world: &mut World) {
+        puffin::profile_function!();
+
+        world.insert_resource(unwrap_or_clone(self.entities));
+        for (restore, data) in self.types {
+            restore(world, data);
+        }
+    }
+}
+
+/// A resource that is kept in snapshots as another value, because it is expensive to copy but can
+/// be re-created from that value and the rest of the snapshot.
+///
+/// See [`SessionSerializer::snapshot_resource_as()`].
+pub trait SnapshotAs: TypeUlid + Sync + Send + 'static {
+    /// The value that is kept in snapshots in place of the resource.
+    type Snapshot: Sync + Send + 'static;
+
+    /// Get the value to keep in the snapshot for the resource in the `world`.
+    fn snapshot_as(world: &World) -> Self::Snapshot;
+
+    /// Re-create the resource in the `world` from its snapshot, once the [`Entities`] and the
+    /// component stores have been restored.
+    fn restore_from(world: &mut World, snapshot: &Self::Snapshot);
+}
+
+/// Functions to snapshot and restore a registered type.
+#[derive(Clone, Copy)]
+pub(super) struct TypeSnapshotter {
+    ulid: Ulid,
+    snapshot: fn(&World) -> Option<SnapshotData>,
+    restore: fn(&mut World, SnapshotData),
+}
+
+impl TypeSnapshotter {
+    pub(super) fn component<T: TypeUlid + Clone + Sync + Send + 'static>() -> Self {
+        Self {
+            ulid: T::ULID,
+            snapshot: snapshot_component::<T>,
+            restore: restore_component::<T>,
+        }
+    }
+
+    pub(super) fn resource<T: TypeUlid + Clone + Sync + Send + 'static>() -> Self {
+        Self {
+            ulid: T::ULID,
+            snapshot: snapshot_resource::<T>,
+            restore: restore_resource::<T>,
+        }
+    }
+
+    fn resource_as<T: SnapshotAs>() -> Self {
+        Self {
+            ulid: T::ULID,
+            snapshot: snapshot_resource_as::<T>,
+            restore: restore_resource_as::<T>,
+        }
+    }
+}
+
+/// Add a type to the list of snapshot types, or replace it if it is already in it and `replace` is
+/// set.
+pub(super) fn register(types: &mut Vec<TypeSnapshotter>, ty: TypeSnapshotter, replace: bool) {
+    match types.iter_mut().find(|x| x.ulid == ty.ulid) {
+        Some(existing) if replace => *existing = ty,
+        Some(_) => (),
+        None => types.push(ty),
+    }
+}
+
+impl SessionSerializer {
+    /// Keep the component store for `T` in snapshots, without saving it with the session.
+    ///
+    /// This is for components that can't be saved, such as components holding a [`System`], which
+    /// are restored with a [load hook][Self::register_load_hook] when loading instead.
+    pub fn register_snapshot_component<T>(&mut self) -> &mut Self
+    where
+        T: TypeUlid + Clone + Sync + Send + 'static,
+    {
+        register(
+            &mut self.component_snapshots,
+            TypeSnapshotter::component::<T>(),
+            false,
+        );
+        self
+    }
+
+    /// Keep the resource `T` in snapshots as its [`SnapshotAs::Snapshot`] value, instead of a copy
+    /// of it.
+    ///
+    /// This doesn't change how the resource is saved, if it is registered to be saved.
+    pub fn snapshot_resource_as<T: SnapshotAs>(&mut self) -> &mut Self {
+        register(
+            &mut self.resource_snapshots,
+            TypeSnapshotter::resource_as::<T>(),
+            true,
+        );
+        self
+    }
+
+    /// Leave the registered type `T` out of snapshots, while still saving it with the session.
+    ///
+    /// The type keeps its current state when a snapshot is restored, so this is only for
+    /// render-only state that entities don't gain or lose during the match. It must be called after
+    /// the type is registered.
+    pub fn skip_snapshots<T: TypeUlid>(&mut self) -> &mut Self {
+        self.resource_snapshots.retain(|ty| ty.ulid != T::ULID);
+        self.component_snapshots.retain(|ty| ty.ulid != T::ULID);
+        self
+    }
+
+    /// Take a snapshot of the registered types in the `world`.
+    pub fn snapshot(&self, world: &World) -> Snapshot {
+        puffin::profile_function!();
+
+        let entities = world.resource::<Entities>().borrow().clone();
+        let types = self
+            .component_snapshots
+            .iter()
+            .chain(&self.resource_snapshots)
+            .filter_map(|ty| Some((ty.restore, (ty.snapshot)(world)?)))
+            .collect();
+
+        Snapshot {
+            entities: Arc::new(entities),
+            types,
+        }
+    }
+}
+
+/// Take the value out of the `arc`, or copy it if the `arc` is shared.
+fn unwrap_or_clone<T: Clone>(arc: Arc<T>) -> T {
+    Arc::try_unwrap(arc).unwrap_or_else(|arc| (*arc).clone())
+}
+
+fn snapshot_component<T>(world: &World) -> Option<SnapshotData>
+where
+    T: TypeUlid + Clone + Sync + Send + 'static,
+{
+    world
+        .run_initialized_system(|store: Comp<T>| {
+            Ok(Some(
+                Arc::new(ComponentStore::<T>::clone(&store)) as SnapshotData
+            ))
+        })
+        .unwrap()
+}
+
+fn restore_component<T>(world: &mut World, data: SnapshotData)
+where
+    T: TypeUlid + Clone + Sync + Send + 'static,
+{
+    let mut snapshot = Some(unwrap_or_clone(
+        data.downcast::<ComponentStore<T>>().unwrap(),
+    ));
+    world
+        .run_initialized_system(move |mut store: CompMut<T>| {
+            *store = snapshot.take().unwrap();
+            Ok(())
+        })
+        .unwrap();
+}
+
+fn snapshot_resource<T>(world: &World) -> Option<SnapshotData>
+where
+    T: TypeUlid + Clone + Sync + Send + 'static,
+{
+    world
+        .get_resource::<T>()
+        .map(|resource| Arc::new(resource.borrow().clone()) as SnapshotData)
+}
+
+fn restore_resource<T>(world: &mut World, data: SnapshotData)
+where
+    T: TypeUlid + Clone + Sync + Send + 'static,
+{
+    world.insert_resource(unwrap_or_clone(data.downcast::<T>().unwrap()));
+}
+
+fn snapshot_resource_as<T: SnapshotAs>(world: &World) -> Option<SnapshotData> {
+    Some(Arc::new(T::snapshot_as(world)))
+}
+
+fn restore_resource_as<T: SnapshotAs>(world: &mut World, data: SnapshotData) {
+    T::restore_from(world, data.downcast::<T::Snapshot>().unwrap().as_ref());
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_support::{four_player_session, PLAYER_COUNT};
+
+    use super::*;
+
+    #[test]
+    fn test_restore_replay() {
+        let (mut session, mut bevy_world) = four_player_session();
+        let mut play_frame = |session: &mut CoreSession, frame: u32| {
+            session.update_input(|inputs| {
+                for (player, input) in inputs.players.iter_mut().take(PLAYER_COUNT).enumerate() {
+                    let phase = (frame as usize / 20 + player) % 4;
+                    input.control = PlayerControl {
+                        move_direction: Vec2::new(if phase < 2 { 1.0 } else { -1.0 }, 0.0),
+                        jump_pressed: phase % 2 == 0,
+                        jump_just_pressed: phase % 2 == 0 && frame % 20 == 0,
+                        grab_pressed: phase == 1,
+                        grab_just_pressed: phase == 1 && frame % 20 == 0,
+                        shoot_pressed: phase == 3,
+                        shoot_just_pressed: phase == 3 && frame % 20 == 0,
+                        ..default()
+                    };
+                }
+            });
+            session.advance(&mut bevy_world);
+            session.checksum()
+        };
+        for frame in 0..120 {
+            play_frame(&mut session, frame);
+        }
+
+        // Rolling back to the snapshot, even several times, should play out the same frames again
+        let snapshot = session.snapshot();
+        let checksums = (120..240)
+            .map(|frame| play_frame(&mut session, frame))
+            .collect::<Vec<_>>();
+        for _ in 0..2 {
+            session.restore(snapshot.clone());
+            for (frame, checksum) in (120..240).zip(&checksums) {
+                assert_eq!(
+                    play_frame(&mut session, frame),
+                    *checksum,
+                    "Restored session is out of sync on frame {frame}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,377 @@
+//! Temporary status effects on players, such as speed boosts or being frozen.
+//!
+//! Every player has a [`StatusEffects`] component holding the effects that are currently active on
+//! it. Effects are added with [`StatusEffects::apply`], which combines the new effect with any
+//! existing effect of the same kind according to its [`StatusEffectStacking`] rule.
+//!
+//! Map elements may apply an effect to the players that start touching them by specifying a
+//! [`StatusEffectMeta`] in their metadata, for example:
+//!
+//! ```yaml
+//! builtin: !Slippery
+//!   atlas: ./slippery.atlas.yaml
+//!   body_size: [33, 18]
+//!   player_slide: 4
+//!   body_friction: 0.95
+//!   status_effect:
+//!     kind: Slow
+//!     duration: 1s
+//!     strength: 2
+//! ```
+
+use std::time::Duration;
+
+use crate::prelude::*;
+
+pub fn install(session: &mut CoreSession) {
+    session
+        .serializer
+        .register_component::<StatusEffects>()
+        .register_component::<StatusEffectTinted>();
+
+    session
+        .stages
+        .add_system_to_stage(CoreStage::First, hydrate_status_effects)
+        .add_system_to_stage(CoreStage::First, apply_control_effects)
+        .add_system_to_stage(CoreStage::PostUpdate, tick_status_effects)
+        .add_system_to_stage(CoreStage::Last, tint_status_effects);
+}
+
+/// The different kinds of status effects.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum StatusEffectKind {
+    /// Multiplies the player's movement speed by the effect strength.
+    SpeedBoost,
+    /// Divides the player's movement speed by the effect strength.
+    Slow,
+    /// Inverts the player's horizontal movement input.
+    ReversedControls,
+    /// The player can't move, jump, or use items.
+    Frozen,
+    /// The player can't be killed.
+    Invincible,
+    /// The player is burning, and will be killed when the effect runs out, unless they are
+    /// invincible at that moment.
+    OnFire,
+}
+
+impl bones_bevy_asset::BonesBevyAssetLoad for StatusEffectKind {}
+
+impl StatusEffectKind {
+    /// The localization key for the name of the effect.
+    pub fn localization_key(&self) -> &'static str {
+        match self {
+            StatusEffectKind::SpeedBoost => "status-effect-speed-boost",
+            StatusEffectKind::Slow => "status-effect-slow",
+            StatusEffectKind::ReversedControls => "status-effect-reversed-controls",
+            StatusEffectKind::Frozen => "status-effect-frozen",
+            StatusEffectKind::Invincible => "status-effect-invincible",
+            StatusEffectKind::OnFire => "status-effect-on-fire",
+        }
+    }
+
+    /// The color that the player is tinted with while the effect is active.
+    ///
+    /// Invincibility has no tint because it is already shown by making the player flash.
+    pub fn tint(&self) -> Option<Color> {
+        match self {
+            StatusEffectKind::SpeedBoost => Some(Color::rgb(1.0, 1.0, 0.55)),
+            StatusEffectKind::Slow => Some(Color::rgb(0.6, 0.7, 1.0)),
+            StatusEffectKind::ReversedControls => Some(Color::rgb(0.85, 0.6, 1.0)),
+            StatusEffectKind::Frozen => Some(Color::rgb(0.55, 0.95, 1.0)),
+            StatusEffectKind::Invincible => None,
+            StatusEffectKind::OnFire => Some(Color::rgb(1.0, 0.55, 0.3)),
+        }
+    }
+}
+
+/// How an effect is combined with an effect of the same kind that is already active.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum StatusEffectStacking {
+    /// The existing effect is reset to the longest of the two durations, and keeps the strongest
+    /// of the two strengths.
+    #[default]
+    Refresh,
+    /// The new duration is added to the time remaining on the existing effect.
+    Extend,
+    /// Each application is tracked separately, up to `max_stacks`, and their strengths are
+    /// multiplied together.
+    Stack,
+    /// The new effect is ignored while an effect of the same kind is active.
+    Ignore,
+}
+
+impl bones_bevy_asset::BonesBevyAssetLoad for StatusEffectStacking {}
+
+/// Metadata describing a status effect that may be applied to a player.
+#[derive(BonesBevyAssetLoad, Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct StatusEffectMeta {
+    pub kind: StatusEffectKind,
+    #[serde(with = "humantime_serde")]
+    pub duration: Duration,
+    /// The strength of the effect. Only used by the speed effects, where it is the speed
+    /// multiplier or divisor.
+    #[serde(default = "StatusEffectMeta::default_strength")]
+    pub strength: f32,
+    #[serde(default)]
+    pub stacking: StatusEffectStacking,
+    /// The maximum number of stacks when using [`StatusEffectStacking::Stack`].
+    #[serde(default = "StatusEffectMeta::default_max_stacks")]
+    pub max_stacks: usize,
+}
+
+impl StatusEffectMeta {
+    fn default_strength() -> f32 {
+        1.5
+    }
+    fn default_max_stacks() -> usize {
+        3
+    }
+}
+
+/// A single active status effect.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StatusEffect {
+    pub kind: StatusEffectKind,
+    pub strength: f32,
+    pub timer: Timer,
+}
+
+impl StatusEffect {
+    pub fn new(meta: &StatusEffectMeta) -> Self {
+        Self {
+            kind: meta.kind,
+            strength: meta.strength,
+            timer: Timer::new(meta.duration, TimerMode::Once),
+        }
+    }
+}
+
+/// The list of status effects active on a player.
+#[derive(Clone, Debug, Default, TypeUlid, Deref, DerefMut, Serialize, Deserialize)]
+#[ulid = "01H4D01F485GMP0943F6C1BGT2"]
+pub struct StatusEffects(pub Vec<StatusEffect>);
+
+impl StatusEffects {
+    /// Apply a new effect, following its stacking rule.
+    pub fn apply(&mut self, meta: &StatusEffectMeta) {
+        let existing = self.0.iter_mut().filter(|x| x.kind == meta.kind);
+
+        match meta.stacking {
+            StatusEffectStacking::Refresh => {
+                if let Some(effect) = existing.last() {
+                    let duration = effect.timer.remaining().max(meta.duration);
+                    effect.timer = Timer::new(duration, TimerMode::Once);
+                    effect.strength = effect.strength.max(meta.strength);
+                    return;
+                }
+            }
+            StatusEffectStacking::Extend => {
+                if let Some(effect) = existing.last() {
+                    let duration = effect.timer.duration() + meta.duration;
+                    effect.timer.set_duration(duration);
+                    return;
+                }
+            }
+            StatusEffectStacking::Stack => {
+                if existing.count() >= meta.max_stacks {
+                    // Replace the stack that will run out first
+                    if let Some(effect) = self
+                        .0
+                        .iter_mut()
+                        .filter(|x| x.kind == meta.kind)
+                        .min_by_key(|x| x.timer.remaining())
+                    {
+                        *effect = StatusEffect::new(meta);
+                    }
+                    return;
+                }
+            }
+            StatusEffectStacking::Ignore => {
+                if existing.count() > 0 {
+                    return;
+                }
+            }
+        }
+
+        self.0.push(StatusEffect::new(meta));
+    }
+
+    /// Whether or not an effect of the given kind is active.
+    pub fn has(&self, kind: StatusEffectKind) -> bool {
+        self.0.iter().any(|x| x.kind == kind)
+    }
+
+    /// Remove all the effects of the given kind.
+    pub fn clear(&mut self, kind: StatusEffectKind) {
+        self.0.retain(|x| x.kind != kind);
+    }
+
+    /// Get the longest remaining time of the effects of the given kind.
+    pub fn remaining(&self, kind: StatusEffectKind) -> Option<Duration> {
+        self.0
+            .iter()
+            .filter(|x| x.kind == kind)
+            .map(|x| x.timer.remaining())
+            .max()
+    }
+
+    /// The factor that the player's movement speed should be multiplied by.
+    pub fn speed_factor(&self) -> f32 {
+        self.0.iter().fold(1.0, |factor, effect| match effect.kind {
+            StatusEffectKind::SpeedBoost => factor * effect.strength,
+            StatusEffectKind::Slow if effect.strength > 0.0 => factor / effect.strength,
+            _ => factor,
+        })
+    }
+
+    /// The tint of the most recently applied effect that has one.
+    pub fn tint(&self) -> Option<Color> {
+        self.0.iter().rev().find_map(|x| x.kind.tint())
+    }
+}
+
+/// The players that are touching an element that applies a status effect.
+///
+/// Elements only apply their effect when a player starts touching them, so that standing on them
+/// doesn't keep refreshing the effect.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct StatusEffectContacts(Vec<Entity>);
+
+impl StatusEffectContacts {
+    /// Set the players that are touching the element, and return the ones that weren't touching
+    /// it before.
+    pub fn update(&mut self, touching: Vec<Entity>) -> Vec<Entity> {
+        let started = touching
+            .iter()
+            .filter(|x| !self.0.contains(x))
+            .copied()
+            .collect();
+        self.0 = touching;
+        started
+    }
+}
+
+/// Marker component for players that are tinted by a status effect.
+#[derive(Clone, Copy, Debug, Default, TypeUlid, Serialize, Deserialize)]
+#[ulid = "01H4D0CDV38Y2AVFTC1E98DYRQ"]
+pub struct StatusEffectTinted;
+
+/// Give every player an empty [`StatusEffects`] component, so that effects may be applied to it.
+fn hydrate_status_effects(
+    entities: Res<Entities>,
+    player_indexes: Comp<PlayerIdx>,
+    mut status_effects: CompMut<StatusEffects>,
+) {
+    let mut not_hydrated_bitset = status_effects.bitset().clone();
+    not_hydrated_bitset.bit_not();
+    not_hydrated_bitset.bit_and(player_indexes.bitset());
+
+    for player_ent in entities.iter_with_bitset(&not_hydrated_bitset) {
+        status_effects.insert(player_ent, default());
+    }
+}
+
+/// Modify the player inputs of players that have their controls affected by an effect.
+///
+/// This runs after the AI, so that AI players are affected the same as everybody else.
+fn apply_control_effects(
+    entities: Res<Entities>,
+    status_effects: Comp<StatusEffects>,
+    player_indexes: Comp<PlayerIdx>,
+    mut player_inputs: ResMut<PlayerInputs>,
+) {
+    for (_ent, (effects, player_idx)) in entities.iter_with((&status_effects, &player_indexes)) {
+        let control = &mut player_inputs.players[player_idx.0].control;
+
+        if effects.has(StatusEffectKind::Frozen) {
+            *control = default();
+        } else if effects.has(StatusEffectKind::ReversedControls) {
+            control.move_direction.x = -control.move_direction.x;
+        }
+    }
+}
+
+/// Tick the status effect timers, and handle the effects that run out.
+fn tick_status_effects(
+    time: Res<Time>,
+    entities: Res<Entities>,
+    mut commands: Commands,
+    mut status_effects: CompMut<StatusEffects>,
+    mut invincibles: CompMut<Invincibility>,
+    transforms: Comp<Transform>,
+) {
+    for (player_ent, effects) in entities.iter_with(&mut status_effects) {
+        for effect in effects.iter_mut() {
+            effect.timer.tick(time.delta());
+        }
+
+        // Keep the player's invincibility timer at least as long as the effect.
+        if let Some(remaining) = effects.remaining(StatusEffectKind::Invincible) {
+            if invincibles
+                .get(player_ent)
+                .map(|x| x.remaining() < remaining)
+                .unwrap_or(true)
+            {
+                invincibles.insert(player_ent, Invincibility::new(remaining));
+            }
+        }
+
+        let burnt_out = effects
+            .iter()
+            .any(|x| x.kind == StatusEffectKind::OnFire && x.timer.finished());
+        if burnt_out && !invincibles.contains(player_ent) {
+            let pos = transforms.get(player_ent).map(|x| x.translation.xy());
+            commands.add(PlayerCommand::kill(player_ent, pos));
+        }
+
+        effects.retain(|x| !x.timer.finished());
+    }
+}
+
+/// Tint the player, and everything that is attached to its body, with the color of its active
+/// effects.
+///
+/// The color is only reset when the last effect with a tint is removed, so that other tints are
+/// left alone while the player has no effects.
+fn tint_status_effects(
+    entities: Res<Entities>,
+    status_effects: Comp<StatusEffects>,
+    mut tinted: CompMut<StatusEffectTinted>,
+    player_body_attachments: Comp<PlayerBodyAttachment>,
+    mut atlas_sprites: CompMut<AtlasSprite>,
+) {
+    let mut tints = Vec::new();
+    for (player_ent, effects) in entities.iter_with(&status_effects) {
+        if let Some(tint) = effects.tint() {
+            tinted.insert(player_ent, StatusEffectTinted);
+            tints.push((player_ent, tint));
+        } else if tinted.contains(player_ent) {
+            tinted.remove(player_ent);
+            tints.push((player_ent, Color::WHITE));
+        }
+    }
+
+    let mut tint_sprite = |ent: Entity, tint: Color| {
+        if let Some(sprite) = atlas_sprites.get_mut(ent) {
+            sprite.color = tint.with_a(sprite.color.a());
+        }
+    };
+
+    for &(player_ent, tint) in &tints {
+        tint_sprite(player_ent, tint);
+    }
+
+    for (ent, attachment) in entities.iter_with(&player_body_attachments) {
+        if !attachment.sync_color {
+            continue;
+        }
+        if let Some(&(_, tint)) = tints
+            .iter()
+            .find(|(player, _)| *player == attachment.player)
+        {
+            tint_sprite(ent, tint);
+        }
+    }
+}
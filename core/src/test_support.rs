@@ -0,0 +1,95 @@
+//! Helpers for the tests and benchmarks that play matches on the game's real assets.
+//!
+//! This module is only built for the crate's own tests, or with the `test-support` feature, which
+//! the benchmarks and the tests of other crates in the workspace enable.
+
+use std::collections::BTreeSet;
+
+use ::bevy::{
+    app::App,
+    asset::{Asset, AssetPlugin, Assets},
+    core::TaskPoolPlugin,
+    ecs::world::World,
+};
+use serde::de::DeserializeOwned;
+
+use crate::prelude::*;
+
+/// The map that [`four_player_session()`] is played on.
+pub const MAP: &str = "/map/levels/level_1.map.yaml";
+
+/// The number of players in a [`four_player_session()`].
+pub const PLAYER_COUNT: usize = 4;
+
+/// Read the YAML file at `path` in the game's `assets` directory.
+pub fn read_asset<T: DeserializeOwned>(path: &str) -> T {
+    let file = format!("{}/../assets{path}", env!("CARGO_MANIFEST_DIR"));
+    let yaml = std::fs::read_to_string(&file).unwrap_or_else(|e| panic!("Reading {file}: {e}"));
+    serde_yaml::from_str(&yaml).unwrap_or_else(|e| panic!("Parsing {file}: {e}"))
+}
+
+/// Read the asset at `path`, and add it to the `app` for the handles with that path.
+pub fn load_asset<T>(app: &mut App, path: &str) -> Handle<T>
+where
+    T: Asset + BonesBevyAsset + DeserializeOwned,
+{
+    let handle: Handle<T> = serde_yaml::from_str(&format!("'{path}'")).unwrap();
+    app.world
+        .resource_mut::<Assets<T>>()
+        .set_untracked(handle.get_bevy_handle(), read_asset(path));
+    handle
+}
+
+/// Get the session info for a match on the [`MAP`] with [`PLAYER_COUNT`] players, and the bevy
+/// world holding its assets.
+///
+/// Use this instead of [`four_player_session()`] to create several sessions for the same match.
+pub fn four_player_session_info() -> (CoreSessionInfo, World) {
+    let mut app = App::new();
+    app.add_plugin(TaskPoolPlugin::default())
+        .add_plugin(AssetPlugin::default())
+        .add_plugin(JumpyCoreAssetsPlugin);
+
+    // The elements are read from the map file, since their handles can't be turned back into paths
+    let map_yaml: serde_yaml::Value = read_asset(MAP);
+    let element_paths = map_yaml["layers"]
+        .as_sequence()
+        .into_iter()
+        .flatten()
+        .flat_map(|layer| layer["elements"].as_sequence().into_iter().flatten())
+        .filter_map(|element| element["element"].as_str())
+        .collect::<BTreeSet<_>>();
+    for path in element_paths {
+        load_asset::<ElementMeta>(&mut app, path);
+    }
+
+    let core_yaml: serde_yaml::Value = read_asset("/default.core.yaml");
+    let player_paths = core_yaml["players"]
+        .as_sequence()
+        .expect("No players in the core metadata")
+        .iter()
+        .filter_map(|x| x.as_str())
+        .collect::<Vec<_>>();
+    let player_info = std::array::from_fn(|i| {
+        (i < PLAYER_COUNT).then(|| GameSessionPlayerInfo {
+            player: load_asset(&mut app, player_paths[i % player_paths.len()]),
+            hat: None,
+            is_ai: false,
+        })
+    });
+
+    let info = CoreSessionInfo {
+        meta: Arc::new(read_asset("/default.core.yaml")),
+        map_meta: read_asset(MAP),
+        player_info,
+    };
+
+    (info, std::mem::take(&mut app.world))
+}
+
+/// Create a session on the [`MAP`] with [`PLAYER_COUNT`] players, and the bevy world holding its
+/// assets.
+pub fn four_player_session() -> (CoreSession, World) {
+    let (info, bevy_world) = four_player_session_info();
+    (CoreSession::new(info), bevy_world)
+}
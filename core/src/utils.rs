@@ -2,6 +2,8 @@
 
 mod easing;
 pub use easing::*;
+mod hash;
+pub use hash::*;
 mod math;
 pub use math::*;
 mod rect;
@@ -0,0 +1,49 @@
+use crate::prelude::*;
+
+/// A 64 bit FNV-1a hasher, for hashes that have to be the same for every player.
+///
+/// We don't use the standard library hasher because its output isn't guaranteed to stay the same
+/// between Rust versions, and peers may have been built with different compilers.
+#[derive(Debug, Clone, Copy)]
+pub struct FnvHasher(u64);
+
+impl Default for FnvHasher {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl FnvHasher {
+    /// Add bytes to the hash.
+    pub fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.write_u8(*byte);
+        }
+    }
+
+    /// Add a single byte to the hash.
+    #[inline]
+    pub fn write_u8(&mut self, byte: u8) {
+        self.0 ^= byte as u64;
+        self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+    }
+
+    /// Add the [`postcard`] serialization of `value` to the hash, without allocating it.
+    pub fn write_serialized<T: Serialize + ?Sized>(&mut self, value: &T) {
+        // Serializing into the hasher itself can't fail
+        *self = postcard::to_extend(value, *self).unwrap();
+    }
+
+    /// Get the hash of the bytes written so far.
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+impl Extend<u8> for FnvHasher {
+    fn extend<I: IntoIterator<Item = u8>>(&mut self, bytes: I) {
+        for byte in bytes {
+            self.write_u8(byte);
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use crate::prelude::*;
 
-#[derive(Debug, Copy, Clone, Pod, Zeroable, Default)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable, Default, Serialize, Deserialize)]
 #[repr(C)]
 pub struct Rect {
     pub min: Vec2,
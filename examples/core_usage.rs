@@ -184,7 +184,7 @@ fn update_game(world: &mut World) {
 }
 
 #[derive(Resource, Default)]
-struct Snapshot(pub Option<bones::World>);
+struct Snapshot(pub Option<jumpy_core::session::Snapshot>);
 
 fn snapshot_restore(
     mut snapshot: ResMut<Snapshot>,
@@ -200,8 +200,8 @@ fn snapshot_restore(
     }
 
     if keyboard.just_pressed(KeyCode::F10) {
-        if let Some(mut snapshot) = snapshot.0.clone() {
-            session.restore(&mut snapshot);
+        if let Some(snapshot) = snapshot.0.clone() {
+            session.restore(snapshot);
         }
     }
 }
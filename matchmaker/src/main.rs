@@ -0,0 +1,278 @@
+//! A local stand-in for the [bones matchmaker][bones_matchmaker], speaking the same
+//! [`bones_matchmaker_proto`] protocol, so that online matches and private lobbies can be tested
+//! without an internet connection.
+//!
+//! Start it with `cargo run -p jumpy_matchmaker`, and set the matchmaking server in the game's
+//! network settings to `127.0.0.1:65534`. The matchmaker uses a new self-signed certificate every
+//! time it starts, so also set the server certificate fingerprint to the one it logs on startup.
+//!
+//! Like the real matchmaker, clients that ask for the exact same [`MatchInfo`] are put in the same
+//! match, and once the match starts all of their messages are proxied through here. Unlike the
+//! real matchmaker, this keeps everything in memory and makes no effort to scale.
+//!
+//! [bones_matchmaker]: https://github.com/fishfolk/bones/tree/main/crates/bones_matchmaker
+
+use std::{collections::HashMap, net::SocketAddr, sync::Arc, time::Duration};
+
+use bones_matchmaker_proto::{
+    MatchInfo, MatchmakerRequest, MatchmakerResponse, RecvProxyMessage, SendProxyMessage,
+    TargetClient,
+};
+use clap::Parser;
+use quinn::{Connection, Endpoint, ServerConfig};
+use sha2::{Digest, Sha256};
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+/// The biggest reliable message that will be proxied, which needs to fit whole maps.
+const MAX_MESSAGE_SIZE: usize = 1024 * 1024;
+
+#[derive(Parser)]
+#[command(author, version, about)]
+struct Args {
+    /// The address to listen on.
+    #[arg(short, long, default_value = "0.0.0.0:65534")]
+    listen: SocketAddr,
+}
+
+/// The requested client count and match data of a match.
+type MatchKey = (usize, Vec<u8>);
+
+/// The clients waiting for each match to fill up.
+type Lobbies = Arc<Mutex<HashMap<MatchKey, Vec<Connection>>>>;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::init();
+    let args = Args::parse();
+
+    let endpoint = Endpoint::server(server_config()?, args.listen)?;
+    info!(addr = %args.listen, "Local matchmaker listening");
+
+    let lobbies = Lobbies::default();
+    while let Some(connecting) = endpoint.accept().await {
+        let lobbies = lobbies.clone();
+        tokio::spawn(async move {
+            match connecting.await {
+                Ok(conn) => {
+                    if let Err(e) = handle_client(conn, lobbies).await {
+                        warn!("Client error: {e}");
+                    }
+                }
+                Err(e) => warn!("Client failed to connect: {e}"),
+            }
+        });
+    }
+
+    Ok(())
+}
+
+/// Create the QUIC server config with a self-signed certificate.
+fn server_config() -> anyhow::Result<ServerConfig> {
+    let cert = rcgen::generate_simple_self_signed(vec!["matchmaker".into(), "localhost".into()])?;
+    let key = rustls::PrivateKey(cert.serialize_private_key_der());
+    let cert = rustls::Certificate(cert.serialize_der()?);
+
+    let fingerprint = Sha256::digest(&cert.0)
+        .iter()
+        .map(|x| format!("{x:02X}"))
+        .collect::<Vec<_>>()
+        .join(":");
+    info!(%fingerprint, "Generated certificate");
+
+    let mut transport_config = quinn::TransportConfig::default();
+    transport_config.keep_alive_interval(Some(Duration::from_secs(5)));
+
+    let mut server_config = ServerConfig::with_single_cert(vec![cert], key)?;
+    server_config.transport = Arc::new(transport_config);
+
+    Ok(server_config)
+}
+
+/// Accept the match request of a new client and add it to its lobby, starting the match if the
+/// lobby is full.
+async fn handle_client(conn: Connection, lobbies: Lobbies) -> anyhow::Result<()> {
+    let (mut send, mut recv) = conn.accept_bi().await?;
+    let request: MatchmakerRequest = postcard::from_bytes(&recv.read_to_end(256).await?)?;
+    #[allow(unreachable_patterns)]
+    let MatchInfo {
+        client_count,
+        match_data,
+    } = match request {
+        MatchmakerRequest::RequestMatch(info) => info,
+        _ => anyhow::bail!("Unexpected request from client"),
+    };
+    let client_count = client_count as usize;
+    if client_count == 0 {
+        anyhow::bail!("Client asked for a match with no clients");
+    }
+    info!(
+        %client_count,
+        match_data = %String::from_utf8_lossy(&match_data),
+        "Match requested"
+    );
+
+    send.write_all(&postcard::to_allocvec(&MatchmakerResponse::Accepted)?)
+        .await?;
+    send.finish().await?;
+
+    let key = (client_count, match_data);
+    let (clients, is_full) = {
+        let mut lobbies = lobbies.lock().await;
+        let lobby = lobbies.entry(key.clone()).or_default();
+        lobby.retain(|x| x.close_reason().is_none());
+        lobby.push(conn.clone());
+
+        if lobby.len() == client_count {
+            (lobbies.remove(&key).unwrap_or_default(), true)
+        } else {
+            (lobby.clone(), false)
+        }
+    };
+
+    if is_full {
+        return start_match(clients).await;
+    }
+    send_client_count(&clients).await;
+
+    // Take the client out of the lobby if it leaves before the match starts.
+    tokio::spawn(async move {
+        conn.closed().await;
+        let clients = {
+            let mut lobbies = lobbies.lock().await;
+            let Some(lobby) = lobbies.get_mut(&key) else { return };
+            let len = lobby.len();
+            lobby.retain(|x| x.stable_id() != conn.stable_id());
+            if lobby.len() == len {
+                return;
+            }
+            let clients = lobby.clone();
+            if clients.is_empty() {
+                lobbies.remove(&key);
+            }
+            clients
+        };
+        info!("Client left its lobby");
+        send_client_count(&clients).await;
+    });
+
+    Ok(())
+}
+
+/// Tell the clients in a lobby how many clients are in it.
+async fn send_client_count(clients: &[Connection]) {
+    let message = MatchmakerResponse::ClientCount(clients.len() as _);
+    for conn in clients {
+        if let Err(e) = send_uni(conn, &message).await {
+            warn!("Couldn't send client count: {e}");
+        }
+    }
+}
+
+/// Tell all of the clients that the match is starting, and start proxying their messages.
+async fn start_match(clients: Vec<Connection>) -> anyhow::Result<()> {
+    let random_seed = rand::random();
+    let client_count = clients.len();
+    info!(%client_count, "Starting match");
+
+    for (i, conn) in clients.iter().enumerate() {
+        let message = MatchmakerResponse::Success {
+            random_seed,
+            player_idx: i as _,
+            client_count: client_count as _,
+        };
+        send_uni(conn, &message).await?;
+    }
+
+    for i in 0..client_count {
+        tokio::spawn(proxy_reliable(i, clients.clone()));
+        tokio::spawn(proxy_unreliable(i, clients.clone()));
+    }
+
+    Ok(())
+}
+
+/// Forward the reliable messages sent by client `from` to the other clients in its match.
+async fn proxy_reliable(from: usize, clients: Vec<Connection>) {
+    while let Ok(mut stream) = clients[from].accept_uni().await {
+        let message = match stream.read_to_end(MAX_MESSAGE_SIZE).await {
+            Ok(data) => postcard::from_bytes::<SendProxyMessage>(&data),
+            Err(e) => {
+                warn!(%from, "Couldn't read reliable message: {e}");
+                continue;
+            }
+        };
+        let Ok(SendProxyMessage { target_client, message }) = message else {
+            warn!(%from, "Ignoring invalid reliable message");
+            continue;
+        };
+
+        let Ok(data) = postcard::to_allocvec(&RecvProxyMessage {
+            from_client: from as _,
+            message,
+        }) else {
+            continue;
+        };
+        for conn in targets(from, &target_client, &clients) {
+            let conn = conn.clone();
+            let data = data.clone();
+            tokio::spawn(async move {
+                let result = async {
+                    let mut send = conn.open_uni().await?;
+                    send.write_all(&data).await?;
+                    send.finish().await?;
+                    Ok::<_, anyhow::Error>(())
+                };
+                if let Err(e) = result.await {
+                    warn!("Couldn't proxy reliable message: {e}");
+                }
+            });
+        }
+    }
+    info!(%from, "Client left the match");
+}
+
+/// Forward the unreliable messages sent by client `from` to the other clients in its match.
+async fn proxy_unreliable(from: usize, clients: Vec<Connection>) {
+    while let Ok(data) = clients[from].read_datagram().await {
+        let Ok(SendProxyMessage { target_client, message }) = postcard::from_bytes(&data) else {
+            warn!(%from, "Ignoring invalid unreliable message");
+            continue;
+        };
+
+        let Ok(data) = postcard::to_allocvec(&RecvProxyMessage {
+            from_client: from as _,
+            message,
+        }) else {
+            continue;
+        };
+        for conn in targets(from, &target_client, &clients) {
+            conn.send_datagram(data.clone().into()).ok();
+        }
+    }
+}
+
+/// Get the connections that a message sent by client `from` should go to.
+fn targets<'a>(
+    from: usize,
+    target: &TargetClient,
+    clients: &'a [Connection],
+) -> Vec<&'a Connection> {
+    clients
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| match target {
+            TargetClient::All => *i != from,
+            TargetClient::One(client) => *i == *client as usize,
+        })
+        .map(|(_, conn)| conn)
+        .collect()
+}
+
+/// Send a message to a client over a new unidirectional stream.
+async fn send_uni(conn: &Connection, message: &MatchmakerResponse) -> anyhow::Result<()> {
+    let mut send = conn.open_uni().await?;
+    send.write_all(&postcard::to_allocvec(message)?).await?;
+    send.finish().await?;
+    Ok(())
+}
@@ -0,0 +1,253 @@
+//! A WebSocket relay for network matches, for players that can't use QUIC, like the ones playing
+//! the web build.
+//!
+//! The relay speaks the same [`bones_matchmaker_proto`] protocol as the matchmaker, but over a
+//! WebSocket connection, with every message in its own binary WebSocket message. Clients that ask
+//! for the exact same [`MatchInfo`] are put in the same match, and once the match starts all of
+//! their messages are proxied through here.
+//!
+//! Start it with `cargo run -p jumpy_relay`, and set the matchmaking server in the game's network
+//! settings to `ws://127.0.0.1:65533`. Put it behind a reverse proxy that terminates TLS to serve
+//! `wss://` URLs, which browsers require on pages served over HTTPS.
+//!
+//! WebSockets only have a single, reliable channel, so the unreliable GGRS messages are reliable
+//! too. That costs some latency when packets are lost, but lets native and browser players share
+//! matches.
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use bones_matchmaker_proto::{
+    MatchInfo, MatchmakerRequest, MatchmakerResponse, RecvProxyMessage, SendProxyMessage,
+    TargetClient,
+};
+use clap::Parser;
+use futures_util::{SinkExt, StreamExt};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::{mpsc, oneshot, Mutex},
+};
+use tokio_tungstenite::{tungstenite::Message, WebSocketStream};
+use tracing::{info, warn};
+
+#[derive(Parser)]
+#[command(author, version, about)]
+struct Args {
+    /// The address to listen on.
+    #[arg(short, long, default_value = "0.0.0.0:65533")]
+    listen: SocketAddr,
+}
+
+/// The requested client count and match data of a match.
+type MatchKey = (usize, Vec<u8>);
+
+/// A connected client, and the channel its messages are sent through.
+#[derive(Clone)]
+struct Client {
+    id: u64,
+    sender: mpsc::UnboundedSender<Vec<u8>>,
+}
+
+impl Client {
+    /// Send a message to the client.
+    fn send(&self, message: &impl serde::Serialize) -> anyhow::Result<()> {
+        self.sender.send(postcard::to_allocvec(message)?)?;
+        Ok(())
+    }
+}
+
+/// A client waiting in a lobby, and the channel to tell it its index in the match and the other
+/// clients once the match starts.
+struct WaitingClient {
+    client: Client,
+    start: oneshot::Sender<(usize, Arc<Vec<Client>>)>,
+}
+
+/// The clients waiting for each match to fill up.
+type Lobbies = Arc<Mutex<HashMap<MatchKey, Vec<WaitingClient>>>>;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::init();
+    let args = Args::parse();
+
+    let listener = TcpListener::bind(args.listen).await?;
+    info!(addr = %args.listen, "Relay listening");
+
+    let lobbies = Lobbies::default();
+    let next_id = AtomicU64::new(0);
+    loop {
+        let (stream, addr) = listener.accept().await?;
+        let id = next_id.fetch_add(1, Ordering::Relaxed);
+        let lobbies = lobbies.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_client(id, stream, lobbies).await {
+                warn!(%addr, "Client error: {e}");
+            }
+        });
+    }
+}
+
+/// Accept the match request of a new client and add it to its lobby, and then proxy its messages
+/// once the match starts.
+async fn handle_client(id: u64, stream: TcpStream, lobbies: Lobbies) -> anyhow::Result<()> {
+    let ws = tokio_tungstenite::accept_async(stream).await?;
+    let (mut sink, mut stream) = ws.split();
+
+    let (sender, mut receiver) = mpsc::unbounded_channel::<Vec<u8>>();
+    tokio::spawn(async move {
+        while let Some(data) = receiver.recv().await {
+            if sink.send(Message::Binary(data)).await.is_err() {
+                break;
+            }
+        }
+        sink.close().await.ok();
+    });
+    let client = Client { id, sender };
+
+    let Some(request) = recv_binary(&mut stream).await? else {
+        return Ok(());
+    };
+    let request: MatchmakerRequest = postcard::from_bytes(&request)?;
+    #[allow(unreachable_patterns)]
+    let MatchInfo {
+        client_count,
+        match_data,
+    } = match request {
+        MatchmakerRequest::RequestMatch(info) => info,
+        _ => anyhow::bail!("Unexpected request from client"),
+    };
+    let client_count = client_count as usize;
+    if client_count == 0 {
+        anyhow::bail!("Client asked for a match with no clients");
+    }
+    info!(
+        %client_count,
+        match_data = %String::from_utf8_lossy(&match_data),
+        "Match requested"
+    );
+    client.send(&MatchmakerResponse::Accepted)?;
+
+    let key = (client_count, match_data);
+    let (start_sender, mut start) = oneshot::channel();
+    {
+        let mut lobbies = lobbies.lock().await;
+        let lobby = lobbies.entry(key.clone()).or_default();
+        lobby.retain(|x| !x.client.sender.is_closed());
+        lobby.push(WaitingClient {
+            client: client.clone(),
+            start: start_sender,
+        });
+
+        if lobby.len() == client_count {
+            let lobby = lobbies.remove(&key).unwrap_or_default();
+            start_match(lobby);
+        } else {
+            send_client_count(lobby);
+        }
+    }
+
+    // Wait for the match to start, taking the client out of the lobby if it leaves first.
+    let (client_idx, clients) = loop {
+        tokio::select! {
+            started = &mut start => break started?,
+            message = recv_binary(&mut stream) => {
+                if message?.is_none() {
+                    leave_lobby(&lobbies, &key, id).await;
+                    return Ok(());
+                }
+                warn!(%id, "Ignoring message sent before the match started");
+            }
+        }
+    };
+
+    while let Some(data) = recv_binary(&mut stream).await? {
+        let Ok(SendProxyMessage { target_client, message }) = postcard::from_bytes(&data) else {
+            warn!(%client_idx, "Ignoring invalid message");
+            continue;
+        };
+
+        let Ok(data) = postcard::to_allocvec(&RecvProxyMessage {
+            from_client: client_idx as _,
+            message,
+        }) else {
+            continue;
+        };
+        for (i, target) in clients.iter().enumerate() {
+            let is_target = match target_client {
+                TargetClient::All => i != client_idx,
+                TargetClient::One(client) => i == client as usize,
+            };
+            if is_target {
+                target.sender.send(data.clone()).ok();
+            }
+        }
+    }
+    info!(%client_idx, "Client left the match");
+
+    Ok(())
+}
+
+/// Receive the next binary message from a client, or `None` if it closed the connection.
+async fn recv_binary(
+    stream: &mut futures_util::stream::SplitStream<WebSocketStream<TcpStream>>,
+) -> anyhow::Result<Option<Vec<u8>>> {
+    while let Some(message) = stream.next().await {
+        match message? {
+            Message::Binary(data) => return Ok(Some(data)),
+            Message::Close(_) => return Ok(None),
+            _ => (),
+        }
+    }
+    Ok(None)
+}
+
+/// Tell the clients in a lobby how many clients are in it.
+fn send_client_count(lobby: &[WaitingClient]) {
+    let message = MatchmakerResponse::ClientCount(lobby.len() as _);
+    for waiting in lobby {
+        if let Err(e) = waiting.client.send(&message) {
+            warn!("Couldn't send client count: {e}");
+        }
+    }
+}
+
+/// Take a client out of its lobby, and tell the clients left in it.
+async fn leave_lobby(lobbies: &Lobbies, key: &MatchKey, id: u64) {
+    let mut lobbies = lobbies.lock().await;
+    let Some(lobby) = lobbies.get_mut(key) else { return };
+    lobby.retain(|x| x.client.id != id);
+    info!("Client left its lobby");
+    if lobby.is_empty() {
+        lobbies.remove(key);
+    } else {
+        send_client_count(lobby);
+    }
+}
+
+/// Tell all of the clients that the match is starting, and hand them the other clients so they
+/// can start proxying their messages.
+fn start_match(lobby: Vec<WaitingClient>) {
+    let random_seed = rand::random();
+    let client_count = lobby.len();
+    info!(%client_count, "Starting match");
+
+    let clients = Arc::new(lobby.iter().map(|x| x.client.clone()).collect::<Vec<_>>());
+    for (i, waiting) in lobby.into_iter().enumerate() {
+        let message = MatchmakerResponse::Success {
+            random_seed,
+            player_idx: i as _,
+            client_count: client_count as _,
+        };
+        if let Err(e) = waiting.client.send(&message) {
+            warn!("Couldn't start match for client: {e}");
+        }
+        waiting.start.send((i, clients.clone())).ok();
+    }
+}
@@ -55,6 +55,22 @@ pub struct EngineConfig {
     /// `module=level` items.
     #[arg(short = 'l', long, default_value = DEFAULT_LOG_LEVEL)]
     pub log_level: String,
+
+    /// The IP address to listen for network connections on, overriding the network settings.
+    #[arg(long)]
+    pub bind_address: Option<String>,
+
+    /// The port, or `first-last` range of ports, to listen for network connections on, overriding
+    /// the network settings.
+    #[arg(long)]
+    pub port: Option<String>,
+
+    /// Skip verifying the certificates of the matchmaking server and LAN hosts.
+    ///
+    /// This is only meant for testing against development servers, and is ignored in release
+    /// builds.
+    #[arg(long)]
+    pub insecure_skip_cert_verification: bool,
 }
 
 impl EngineConfig {
@@ -91,6 +107,9 @@ impl EngineConfig {
             game_asset: "default.game.yaml".into(),
             log_level: DEFAULT_LOG_LEVEL.into(),
             sync_test_check_distance: 0,
+            bind_address: None,
+            port: None,
+            insecure_skip_cert_verification: false,
         }
     }
 }
@@ -214,6 +214,8 @@ impl<'w, 's> GameLoader<'w, 's> {
             // Spawn player input collectors.
             let settings = storage.get(Settings::STORAGE_KEY);
             let settings = settings.as_ref().unwrap_or(&game.default_settings);
+            #[cfg(not(target_arch = "wasm32"))]
+            crate::networking::set_endpoint_settings(&settings.network_bind);
             for player in 0..MAX_PLAYERS {
                 commands.spawn((
                     Name::new(format!("Player Input Collector {player}")),
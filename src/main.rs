@@ -57,7 +57,6 @@ pub mod ui;
 pub mod utils;
 
 pub mod camera;
-#[cfg(not(target_arch = "wasm32"))]
 pub mod networking;
 pub mod prelude;
 use prelude::*;
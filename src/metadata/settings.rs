@@ -13,8 +13,86 @@ use super::GameMeta;
 pub struct Settings {
     /// The player controller bindings
     pub player_controls: PlayerControlMethods,
-    /// The address of the matchmaking server to connect to for online games.
+    /// The address of the matchmaking server to connect to for online games, or the `ws://` or
+    /// `wss://` URL of a relay.
     pub matchmaking_server: String,
+    /// The SHA-256 fingerprint of the certificate the matchmaking server must have.
+    ///
+    /// When this is empty, the matchmaking server's certificate isn't verified. This is the default,
+    /// because the public matchmaker uses a self-signed certificate that changes when it restarts.
+    #[serde(default)]
+    pub matchmaking_server_fingerprint: String,
+    /// What happens to players that disconnect from network matches we host.
+    #[serde(default)]
+    pub disconnect_policy: DisconnectPolicy,
+    /// How the input delay and prediction window of network matches are picked.
+    #[serde(default)]
+    pub network_timing: NetworkTimingSettings,
+    /// The address and ports that we listen for network connections on.
+    #[serde(default)]
+    pub network_bind: NetworkBindSettings,
+    /// Whether the ping and connection quality of the other players are shown during network
+    /// matches.
+    #[serde(default = "default_show_network_hud")]
+    pub show_network_hud: bool,
+}
+
+fn default_show_network_hud() -> bool {
+    true
+}
+
+/// The address and ports that we listen for network connections on.
+///
+/// Hosting behind a firewall or port forward needs a known port, which can be set here.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct NetworkBindSettings {
+    /// The IP address to listen on, or an empty string to listen on all interfaces.
+    pub address: String,
+    /// The port, or `first-last` range of ports, to listen on. The first free port is used.
+    pub ports: String,
+}
+
+impl Default for NetworkBindSettings {
+    fn default() -> Self {
+        Self {
+            address: String::new(),
+            ports: "10000-11000".into(),
+        }
+    }
+}
+
+/// The input delay and prediction window settings for network matches.
+///
+/// Values that are `None` are picked automatically from the round-trip time to the other players.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct NetworkTimingSettings {
+    /// The number of frames that our inputs are delayed by.
+    pub input_delay: Option<usize>,
+    /// The number of frames we may predict ahead of the other players' inputs.
+    pub max_prediction_window: Option<usize>,
+}
+
+impl NetworkTimingSettings {
+    /// The largest input delay, in frames, that can be picked.
+    pub const MAX_INPUT_DELAY: usize = 4;
+    /// The smallest prediction window, in frames, that can be picked.
+    pub const MIN_PREDICTION_WINDOW: usize = 6;
+    /// The largest number of frames a client may predict beyond the confirmed frame before freezing
+    /// and waiting for inputs from other players.
+    pub const MAX_PREDICTION_WINDOW: usize = 12;
+}
+
+/// What happens to the slot of a player that disconnects in the middle of a network match.
+///
+/// The host's policy is used by all the players of the match, so that they stay in sync.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DisconnectPolicy {
+    /// The AI takes control of the player.
+    #[default]
+    AiTakeover,
+    /// The player stops moving and doesn't respawn after its next death, and it is removed from the
+    /// match at the start of the next round.
+    RemoveAtNextRound,
 }
 
 impl Settings {
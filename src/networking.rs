@@ -1,74 +1,166 @@
 #![doc = include_str!("./networking.md")]
 
-use ggrs::{NetworkStats, P2PSession, PlayerHandle};
+use std::{collections::VecDeque, time::Duration};
+
+use bevy::utils::Instant;
+
+use ggrs::{NetworkStats, P2PSession, PlayerHandle, SpectatorSession};
 use jumpy_core::input::PlayerControl;
-use rand::Rng;
 
 use crate::{
-    networking::debug::{NetworkDebugMessage, NETWORK_DEBUG_CHANNEL},
+    networking::{
+        debug::{NetworkDebugMessage, NETWORK_DEBUG_CHANNEL},
+        proto::{
+            LobbyMessage, MapSelectMessage, MatchSetupChannel, MatchSetupDecodeError,
+            MatchSetupMessage, PeerVersion, PlayerSelectMessage, MATCH_SETUP_PROTOCOL_VERSION,
+        },
+    },
     prelude::*,
 };
 
+#[cfg(not(target_arch = "wasm32"))]
 pub mod certs;
 pub mod debug;
+#[cfg(not(target_arch = "wasm32"))]
+mod endpoint;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod lan;
+pub mod lobby;
+pub mod loopback;
+pub mod map_transfer;
 pub mod online;
 pub mod proto;
+pub mod relay;
+pub mod simulator;
 
-/// The muliplier for the [`jumpy_core::FPS`] that will be used when playing an online match.
+#[cfg(not(target_arch = "wasm32"))]
+pub use endpoint::{endpoint_addr, network_endpoint, resolve_addr_blocking, set_endpoint_settings};
+
+/// The muliplier for the [`jumpy_core::FPS`] that will be used when playing a network match over a
+/// slow connection.
 ///
-/// Lowering the frame rate a little for online matches reduces bandwidth and may help overall
-/// gameplay. This may not be necessary once we improve network performance.
-pub const NETWORK_FRAME_RATE_FACTOR: f32 = 0.9;
+/// Lowering the frame rate a little reduces bandwidth and the number of frames that have to be
+/// re-simulated when rolling back. On good connections, matches run at the full frame rate.
+pub const NETWORK_REDUCED_FRAME_RATE_FACTOR: f32 = 0.9;
 
-/// Number of frames client may predict beyond confirmed frame before freezing and waiting
-/// for inputs from other players.
-pub const NETWORK_MAX_PREDICTION_WINDOW: usize = 10;
+/// The round-trip time above which the host picks the [`NETWORK_REDUCED_FRAME_RATE_FACTOR`] for
+/// the match.
+pub const NETWORK_FULL_FRAME_RATE_MAX_RTT: Duration = Duration::from_millis(120);
 
-/// The [`ggrs::Config`] implementation used by Jumpy.
-#[derive(Debug)]
-pub struct GgrsConfig;
-impl ggrs::Config for GgrsConfig {
-    type Input = proto::DensePlayerControl;
-    type State = bones::World;
-    /// Addresses are the same as the player handle for our custom socket.
-    type Address = usize;
+/// How often, in seconds, the input delay is re-evaluated during a match when it is picked
+/// automatically.
+const NETWORK_TIMING_UPDATE_INTERVAL: f32 = 2.0;
+
+/// How often, in frames, the game state checksums are compared with the other clients to detect
+/// de-syncs.
+///
+/// The checksums are small, so they are compared on every confirmed frame to find the exact frame
+/// that a de-sync started on.
+const DESYNC_DETECTION_INTERVAL: u32 = 1;
+
+/// The input delay and prediction window used by a client in a network match.
+///
+/// Both are local to each client, so they don't have to be the same for all players.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NetworkTiming {
+    /// The number of frames that local inputs are delayed by before they are sent to the other
+    /// players.
+    ///
+    /// Delaying inputs gives them time to reach the other players before they are needed, which
+    /// reduces how often and how far we have to roll back.
+    pub input_delay: usize,
+    /// The number of frames we may predict beyond the last confirmed frame.
+    pub max_prediction_window: usize,
 }
 
-/// The network endpoint used for all QUIC network communications.
-pub static NETWORK_ENDPOINT: Lazy<quinn::Endpoint> = Lazy::new(|| {
-    // Generate certificate
-    let (cert, key) = certs::generate_self_signed_cert().unwrap();
+impl NetworkTiming {
+    /// Pick the timing from the user's settings, using the measured round-trip time to the slowest
+    /// client for the values that are set to automatic.
+    ///
+    /// Without a measurement, a small input delay is used.
+    pub fn new(settings: &NetworkTimingSettings, rtt: Option<Duration>, fps: f32) -> Self {
+        let input_delay = settings
+            .input_delay
+            .unwrap_or_else(|| rtt.map(|x| Self::auto_input_delay(x, fps)).unwrap_or(1));
+        let max_prediction_window = settings.max_prediction_window.unwrap_or_else(|| {
+            // Predict far enough to cover the round trip that isn't hidden by the input delay.
+            let rtt_frames = rtt.map(|x| Self::frames(x, fps)).unwrap_or_default();
+            (rtt_frames.saturating_sub(input_delay) + NetworkTimingSettings::MIN_PREDICTION_WINDOW)
+                .clamp(
+                    NetworkTimingSettings::MIN_PREDICTION_WINDOW,
+                    NetworkTimingSettings::MAX_PREDICTION_WINDOW,
+                )
+        });
 
-    let mut transport_config = quinn::TransportConfig::default();
-    transport_config.keep_alive_interval(Some(std::time::Duration::from_secs(5)));
+        Self {
+            input_delay,
+            max_prediction_window,
+        }
+    }
 
-    let mut server_config = quinn::ServerConfig::with_single_cert([cert].to_vec(), key).unwrap();
-    server_config.transport = Arc::new(transport_config);
+    /// Get the input delay that covers half of the one-way latency, leaving the rest to
+    /// prediction.
+    pub fn auto_input_delay(rtt: Duration, fps: f32) -> usize {
+        (Self::frames(rtt / 2, fps) / 2).min(NetworkTimingSettings::MAX_INPUT_DELAY)
+    }
 
-    // Open Socket and create endpoint
-    let port = rand::thread_rng().gen_range(10000..=11000); // Bind a random port
-    info!(port, "Started network endpoint");
-    let socket = std::net::UdpSocket::bind(("0.0.0.0", port)).unwrap();
+    /// Get the number of frames, rounded up, that pass during `duration`.
+    fn frames(duration: Duration, fps: f32) -> usize {
+        (duration.as_secs_f32() * fps).ceil() as usize
+    }
+}
 
-    let client_config = rustls::ClientConfig::builder()
-        .with_safe_defaults()
-        .with_custom_certificate_verifier(certs::SkipServerVerification::new())
-        .with_no_client_auth();
-    let client_config = quinn::ClientConfig::new(Arc::new(client_config));
+/// Get the frame rate multiplier for a match, from the round-trip time to the slowest client.
+///
+/// The frame rate must be the same for everybody, so this is picked by the host and sent in the
+/// [`proto::MatchSettings`].
+pub fn network_frame_rate_factor(rtt: Option<Duration>) -> f32 {
+    match rtt {
+        Some(rtt) if rtt <= NETWORK_FULL_FRAME_RATE_MAX_RTT => 1.0,
+        _ => NETWORK_REDUCED_FRAME_RATE_FACTOR,
+    }
+}
 
-    let mut endpoint = quinn::Endpoint::new(
-        quinn::EndpointConfig::default(),
-        Some(server_config),
-        socket,
-        Arc::new(quinn_runtime_bevy::BevyIoTaskPoolExecutor),
-    )
-    .unwrap();
+/// How good the connection to a remote player is, as shown in the network HUD.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ConnectionQuality {
+    /// The connection is interrupted, or so slow that the game keeps freezing.
+    Poor,
+    /// The connection is slow enough that rollbacks may be noticeable.
+    Fair,
+    /// The connection is fast enough that rollbacks are barely noticeable.
+    Good,
+}
 
-    endpoint.set_default_client_config(client_config);
+impl ConnectionQuality {
+    /// The highest round-trip time, in milliseconds, of a [`Good`][Self::Good] connection.
+    const GOOD_MAX_PING: u128 = 80;
+    /// The highest round-trip time, in milliseconds, of a [`Fair`][Self::Fair] connection.
+    const FAIR_MAX_PING: u128 = 160;
 
-    endpoint
-});
+    /// Rate the connection to a remote player from its network stats.
+    pub fn new(stats: &NetworkStats, is_interrupted: bool) -> Self {
+        if is_interrupted {
+            Self::Poor
+        } else if stats.ping <= Self::GOOD_MAX_PING {
+            Self::Good
+        } else if stats.ping <= Self::FAIR_MAX_PING {
+            Self::Fair
+        } else {
+            Self::Poor
+        }
+    }
+}
+
+/// The [`ggrs::Config`] implementation used by Jumpy.
+#[derive(Debug)]
+pub struct GgrsConfig;
+impl ggrs::Config for GgrsConfig {
+    type Input = proto::DensePlayerControl;
+    type State = jumpy_core::session::Snapshot;
+    /// Addresses are the same as the player handle for our custom socket.
+    type Address = usize;
+}
 
 /// Resource containing the [`NetworkSocket`] implementation while there is a connection to a
 /// network game.
@@ -77,6 +169,18 @@ pub static NETWORK_ENDPOINT: Lazy<quinn::Endpoint> = Lazy::new(|| {
 #[derive(Resource, Deref, DerefMut)]
 pub struct NetworkMatchSocket(pub Box<dyn NetworkSocket>);
 
+impl NetworkMatchSocket {
+    /// Insert the socket for a newly established match, along with fresh match setup state.
+    pub fn insert(commands: &mut Commands, socket: Box<dyn NetworkSocket>) {
+        let socket = Box::new(simulator::SimulatedSocket::new(socket));
+        let (socket, lobby) = lobby::LobbySocket::new(socket);
+        commands.insert_resource(NetworkMatchSocket(Box::new(socket)));
+        commands.insert_resource(MatchSetupInbox::default());
+        commands.insert_resource(map_transfer::MapTransfer::default());
+        commands.insert_resource(lobby);
+    }
+}
+
 /// A type-erased [`ggrs::NonBlockingSocket`][crate::external::ggrs::NonBlockingSocket]
 /// implementation.
 #[derive(Deref, DerefMut)]
@@ -96,48 +200,458 @@ impl ggrs::NonBlockingSocket<usize> for BoxedNonBlockingSocket {
 ///
 /// The [`NetworkMatchSocket`] resource will contain an instance of this trait and will be used by
 /// the game to send network messages after a match has been established.
+///
+/// Sockets address the clients taking part in the match, which may each have several local
+/// players. The players are numbered in client order, so that every client owns a contiguous range
+/// of player indexes, see [`client_players()`][Self::client_players].
 pub trait NetworkSocket: Sync + Send {
     /// Get a GGRS socket from this network socket.
     fn ggrs_socket(&self) -> BoxedNonBlockingSocket;
     /// Send a reliable message to the given [`SocketTarget`].
     fn send_reliable(&self, target: SocketTarget, message: &[u8]);
-    /// Receive reliable messages from other players. The `usize` is the index of the player that
+    /// Receive reliable messages from other clients. The `usize` is the index of the client that
     /// sent the message.
     fn recv_reliable(&self) -> Vec<(usize, Vec<u8>)>;
     /// Close the connection.
     fn close(&self);
-    /// Get the player index of the local player.
-    fn player_idx(&self) -> usize;
+    /// Get the index of our client, which the other clients use as our address. The host is
+    /// always client `0`.
+    ///
+    /// For spectators, this is their spectator address, as returned by [`spectator_addr`].
+    fn client_idx(&self) -> usize;
+    /// Get the number of local players of every client, indexed by client.
+    fn client_player_counts(&self) -> [usize; MAX_PLAYERS];
+    /// Get the number of clients playing in this network match.
+    ///
+    /// Clients that were kicked from the match are still counted if there are clients after them,
+    /// but they have no players.
+    fn client_count(&self) -> usize {
+        self.client_player_counts()
+            .iter()
+            .rposition(|x| *x > 0)
+            .map_or(0, |x| x + 1)
+    }
+    /// Get the player count for this network match, counting the players of every client.
+    fn player_count(&self) -> usize {
+        self.client_player_counts().iter().sum()
+    }
+    /// Get the range of player indexes owned by the given client.
+    fn client_players(&self, client_idx: usize) -> std::ops::Range<usize> {
+        let counts = self.client_player_counts();
+        let Some(count) = counts.get(client_idx) else {
+            return 0..0;
+        };
+        let start = counts[0..client_idx].iter().sum();
+        start..(start + count)
+    }
+    /// Get the client that owns the player with the given index.
+    fn player_client(&self, player_idx: usize) -> Option<usize> {
+        (0..self.client_count()).find(|i| self.client_players(*i).contains(&player_idx))
+    }
     /// Return, for every player index, whether the player is a local player.
-    fn player_is_local(&self) -> [bool; MAX_PLAYERS];
-    /// Get the player count for this network match.
-    fn player_count(&self) -> usize;
+    fn player_is_local(&self) -> [bool; MAX_PLAYERS] {
+        let local_players = self.client_players(self.client_idx());
+        std::array::from_fn(|i| local_players.contains(&i))
+    }
+    /// Get the number of spectators watching this network match.
+    fn spectator_count(&self) -> usize;
+    /// Whether or not we are spectating the match instead of playing in it.
+    fn is_spectator(&self) -> bool {
+        self.client_idx() >= MAX_PLAYERS
+    }
+    /// Get the addresses of the spectators that we must send the confirmed inputs to.
+    ///
+    /// Only the host sends inputs to the spectators, so this is empty for the other players.
+    fn spectator_addrs(&self) -> Vec<usize>;
+    /// Take the next connection error that happened on the socket, if any.
+    fn take_error(&self) -> Option<NetworkError>;
+
+    /// Send a [`MatchSetupMessage`] reliably to the given [`SocketTarget`].
+    fn send_match_setup(&self, target: SocketTarget, message: &MatchSetupMessage) {
+        self.send_reliable(target, &message.encode());
+    }
+}
+
+/// Get the socket address of the spectator with the given index.
+///
+/// Spectators are addressed after the clients, so that they never collide with a client index.
+pub fn spectator_addr(spectator_idx: usize) -> usize {
+    MAX_PLAYERS + spectator_idx
 }
 
 /// The destination for a reliable network message.
 pub enum SocketTarget {
-    /// Send to a specific player.
-    Player(usize),
-    /// Broadcast to all players.
+    /// Send to a specific client, or to a spectator using its [`spectator_addr`].
+    Client(usize),
+    /// Broadcast to all clients and spectators that we are connected to.
     All,
 }
 
+/// An error that happened while looking for, setting up, or playing a network match.
+#[derive(Debug, Clone)]
+pub enum NetworkError {
+    /// A server address couldn't be parsed or resolved.
+    InvalidAddress(String),
+    /// We couldn't connect to a server or another player.
+    ConnectionFailed(String),
+    /// The connection to a server or another player was lost.
+    ConnectionLost(String),
+    /// A client disconnected from the match. This contains the index of its first player.
+    PlayerDisconnected(usize),
+    /// A message received from the network couldn't be understood.
+    InvalidMessage(String),
+    /// A network service, such as LAN discovery, couldn't be used.
+    Service(String),
+    /// A private lobby code couldn't be parsed.
+    InvalidLobbyCode(String),
+    /// The certificate of the matchmaking server couldn't be verified.
+    UntrustedCertificate(String),
+    /// A LAN host presented a different certificate than the last time we connected to it.
+    CertificateChanged(String),
+    /// The network endpoint couldn't be bound to the configured address and ports.
+    BindFailed(String),
+    /// The host kicked us from the match.
+    Kicked,
+    /// We couldn't get a valid copy of the selected map from the host.
+    MapTransferFailed(String),
+}
+
+impl NetworkError {
+    /// Get the localization query for the error message shown in the UI.
+    pub fn localization_query(&self) -> String {
+        match self {
+            NetworkError::InvalidAddress(_) => "network-error-invalid-address".into(),
+            NetworkError::ConnectionFailed(_) => "network-error-connection-failed".into(),
+            NetworkError::ConnectionLost(_) => "network-error-connection-lost".into(),
+            NetworkError::PlayerDisconnected(player) => {
+                format!("network-error-player-disconnected?player={}", player + 1)
+            }
+            NetworkError::InvalidMessage(_) => "network-error-invalid-message".into(),
+            NetworkError::Service(_) => "network-error-service".into(),
+            NetworkError::InvalidLobbyCode(_) => "network-error-invalid-lobby-code".into(),
+            NetworkError::UntrustedCertificate(_) => "network-error-untrusted-certificate".into(),
+            NetworkError::CertificateChanged(_) => "network-error-certificate-changed".into(),
+            NetworkError::BindFailed(_) => "network-error-bind-failed".into(),
+            NetworkError::Kicked => "network-error-kicked".into(),
+            NetworkError::MapTransferFailed(_) => "network-error-map-transfer-failed".into(),
+        }
+    }
+}
+
+impl std::fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NetworkError::InvalidAddress(e)
+            | NetworkError::ConnectionFailed(e)
+            | NetworkError::ConnectionLost(e)
+            | NetworkError::InvalidMessage(e)
+            | NetworkError::Service(e)
+            | NetworkError::UntrustedCertificate(e)
+            | NetworkError::CertificateChanged(e)
+            | NetworkError::BindFailed(e)
+            | NetworkError::MapTransferFailed(e) => write!(f, "{e}"),
+            NetworkError::PlayerDisconnected(player) => {
+                write!(f, "player {} disconnected", player + 1)
+            }
+            NetworkError::InvalidLobbyCode(code) => write!(f, "invalid lobby code `{code}`"),
+            NetworkError::Kicked => write!(f, "kicked from the match by the host"),
+        }
+    }
+}
+
+impl std::error::Error for NetworkError {}
+
+impl From<postcard::Error> for NetworkError {
+    fn from(e: postcard::Error) -> Self {
+        NetworkError::InvalidMessage(e.to_string())
+    }
+}
+
+/// Resource that routes the reliable [`MatchSetupMessage`]s received from the
+/// [`NetworkMatchSocket`] to the UI screens that consume them, and checks the versions of the
+/// other players.
+///
+/// Every consumer calls [`poll()`][Self::poll] before taking the messages on its own channel, so
+/// that one screen never swallows the messages meant for another.
+///
+/// This is reset whenever a new socket is inserted with [`NetworkMatchSocket::insert`].
+#[derive(Resource, Default)]
+pub struct MatchSetupInbox {
+    /// The messages received on each channel, that haven't been taken by their consumer yet.
+    queues: HashMap<MatchSetupChannel, Vec<(usize, MatchSetupMessage)>>,
+    /// The version of the local game, sent to the other players in our hello message.
+    local_version: Option<PeerVersion>,
+    /// Whether or not we have received a compatible hello message from each client.
+    verified: [bool; MAX_PLAYERS],
+    /// The error that aborted the match setup, if any.
+    error: Option<MatchSetupError>,
+    /// The pings that we are waiting for an answer to, by id, with the time they were sent.
+    pending_pings: HashMap<u32, Instant>,
+    /// The id of the next ping we send.
+    next_ping_id: u32,
+    /// When we last sent a ping.
+    last_ping: Option<Instant>,
+    /// The smoothed round-trip time to each other client.
+    round_trip_times: [Option<Duration>; MAX_PLAYERS],
+}
+
+/// How often the [`MatchSetupInbox`] pings the other clients.
+const MATCH_SETUP_PING_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How long we wait for the answer to a ping before giving up on it.
+const MATCH_SETUP_PING_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Error that prevents a network match from being set up.
+#[derive(Debug, Clone)]
+pub enum MatchSetupError {
+    /// A player is using a different version of the match setup protocol.
+    ProtocolMismatch { player: usize, version: u16 },
+    /// A player is running a different version of the game.
+    GameMismatch { player: usize, version: String },
+    /// A player has different game assets.
+    AssetMismatch { player: usize },
+}
+
+impl MatchSetupError {
+    /// Get the localization query for the error message shown in the UI.
+    pub fn localization_query(&self) -> String {
+        match self {
+            MatchSetupError::ProtocolMismatch { player, version } => format!(
+                "match-setup-protocol-mismatch?player={}&remote={version}&local={MATCH_SETUP_PROTOCOL_VERSION}",
+                player + 1
+            ),
+            MatchSetupError::GameMismatch { player, version } => format!(
+                "match-setup-game-mismatch?player={}&remote={version}&local={}",
+                player + 1,
+                env!("CARGO_PKG_VERSION")
+            ),
+            MatchSetupError::AssetMismatch { player } => {
+                format!("match-setup-asset-mismatch?player={}", player + 1)
+            }
+        }
+    }
+}
+
+impl MatchSetupInbox {
+    /// Receive all pending messages from the socket and route them to their channels.
+    ///
+    /// On the first call, our hello message is sent to the other players, with the version
+    /// returned by `local_version`.
+    pub fn poll(
+        &mut self,
+        socket: &dyn NetworkSocket,
+        local_version: impl FnOnce() -> PeerVersion,
+    ) {
+        if self.local_version.is_none() {
+            let version = local_version();
+            socket.send_match_setup(
+                SocketTarget::All,
+                &MatchSetupMessage::Hello(version.clone()),
+            );
+            self.local_version = Some(version);
+            if let Some(verified) = self.verified.get_mut(socket.client_idx()) {
+                *verified = true;
+            }
+        }
+
+        // Measure the round-trip time to the other clients
+        let now = Instant::now();
+        if self
+            .last_ping
+            .map_or(true, |x| now - x >= MATCH_SETUP_PING_INTERVAL)
+        {
+            let id = self.next_ping_id;
+            self.next_ping_id = id.wrapping_add(1);
+            self.last_ping = Some(now);
+            self.pending_pings
+                .retain(|_, sent| now - *sent < MATCH_SETUP_PING_TIMEOUT);
+            self.pending_pings.insert(id, now);
+            socket.send_match_setup(SocketTarget::All, &MatchSetupMessage::Ping(id));
+        }
+
+        for (client, data) in socket.recv_reliable() {
+            // The errors are reported for the first player of the client
+            let player = socket.client_players(client).start;
+            let message = match MatchSetupMessage::decode(&data) {
+                Ok(message) => message,
+                Err(MatchSetupDecodeError::ProtocolMismatch(version)) => {
+                    warn!(%client, %version, "Client is using an incompatible protocol version");
+                    // Spectators can't prevent the match from starting
+                    if client >= MAX_PLAYERS {
+                        continue;
+                    }
+                    self.error
+                        .get_or_insert(MatchSetupError::ProtocolMismatch { player, version });
+                    continue;
+                }
+                Err(e) => {
+                    warn!("Ignoring network message that was not understood: {e}");
+                    continue;
+                }
+            };
+
+            match message {
+                MatchSetupMessage::Hello(version) => self.verify_peer(client, player, version),
+                MatchSetupMessage::Ping(id) => socket
+                    .send_match_setup(SocketTarget::Client(client), &MatchSetupMessage::Pong(id)),
+                MatchSetupMessage::Pong(id) => {
+                    let (Some(sent), Some(rtt)) =
+                        (self.pending_pings.get(&id), self.round_trip_times.get_mut(client)) else {
+                        continue;
+                    };
+                    let sample = now - *sent;
+                    // Smooth out the measurements, so that one slow ping doesn't count too much.
+                    *rtt = Some(rtt.map_or(sample, |rtt| (rtt * 3 + sample) / 4));
+                }
+                message => self
+                    .queues
+                    .entry(message.channel())
+                    .or_default()
+                    .push((client, message)),
+            }
+        }
+    }
+
+    /// Check the version sent by another client, whose first player is `player`, against our own.
+    fn verify_peer(&mut self, client: usize, player: usize, version: PeerVersion) {
+        let local_version = self.local_version.as_ref().unwrap();
+
+        let error = if version.game != local_version.game {
+            Some(MatchSetupError::GameMismatch {
+                player,
+                version: version.game,
+            })
+        } else if version.assets != local_version.assets {
+            Some(MatchSetupError::AssetMismatch { player })
+        } else {
+            None
+        };
+
+        if let Some(error) = error {
+            warn!(%client, ?error, "Rejecting incompatible network client");
+            // Spectators can't prevent the match from starting
+            if client < MAX_PLAYERS {
+                self.error.get_or_insert(error);
+            }
+        } else if let Some(verified) = self.verified.get_mut(client) {
+            *verified = true;
+        }
+    }
+
+    /// Whether or not every client has sent a compatible hello message.
+    pub fn is_verified(&self, client_count: usize) -> bool {
+        self.error.is_none() && self.verified[0..client_count].iter().all(|x| *x)
+    }
+
+    /// Get the round-trip time to the slowest of the other clients, if it has been measured.
+    pub fn round_trip_time(&self) -> Option<Duration> {
+        self.round_trip_times.iter().flatten().max().copied()
+    }
+
+    /// Get the error that aborted the match setup, if any.
+    pub fn error(&self) -> Option<&MatchSetupError> {
+        self.error.as_ref()
+    }
+
+    /// Take the player selection messages that have been received, along with the client that
+    /// sent them.
+    pub fn take_player_select(&mut self) -> Vec<(usize, PlayerSelectMessage)> {
+        self.take(MatchSetupChannel::PlayerSelect)
+            .filter_map(|(client, message)| match message {
+                MatchSetupMessage::PlayerSelect(message) => Some((client, message)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Take the lobby messages that have been received, along with the client that sent them.
+    pub fn take_lobby(&mut self) -> Vec<(usize, LobbyMessage)> {
+        self.take(MatchSetupChannel::Lobby)
+            .filter_map(|(client, message)| match message {
+                MatchSetupMessage::Lobby(message) => Some((client, message)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Take the map selection messages that have been received.
+    pub fn take_map_select(&mut self) -> Vec<(usize, MapSelectMessage)> {
+        self.take(MatchSetupChannel::MapSelect)
+            .filter_map(|(player, message)| match message {
+                MatchSetupMessage::MapSelect(message) => Some((player, message)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn take(
+        &mut self,
+        channel: MatchSetupChannel,
+    ) -> impl Iterator<Item = (usize, MatchSetupMessage)> {
+        self.queues.remove(&channel).unwrap_or_default().into_iter()
+    }
+}
+
 /// [`SessionRunner`] implementation that uses [`ggrs`][crate::external::ggrs] for network play.
 ///
 /// This is where the whole `ggrs` integration is implemented.
 pub struct GgrsSessionRunner {
-    /// The last player input we detected.
-    pub last_player_input: PlayerControl,
+    /// The last input we detected for each local player.
+    pub local_player_inputs: [PlayerControl; MAX_PLAYERS],
     /// The core game session.
     pub core: CoreSession,
     /// The GGRS peer-to-peer session.
     pub session: P2PSession<GgrsConfig>,
     /// Array containing a flag indicating, for each player, whether they are a local player.
     pub player_is_local: [bool; MAX_PLAYERS],
+    /// The client that each player plays on.
+    pub player_clients: [usize; MAX_PLAYERS],
     /// The frame time delta.
     pub delta: f32,
     /// The frame time accumulator, used to produce a fixed refresh rate.
     pub accumulator: f32,
+    /// What to do with the players that disconnect during the match.
+    pub disconnect_policy: DisconnectPolicy,
+    /// Array containing a flag indicating, for each player, whether they have disconnected.
+    pub player_is_disconnected: [bool; MAX_PLAYERS],
+    /// The player count.
+    pub player_count: usize,
+    /// The duration of a simulation frame.
+    pub step: f32,
+    /// The number of frames that local inputs are currently delayed by.
+    ///
+    /// We delay the inputs ourselves, instead of letting GGRS do it, so that the delay can change
+    /// during the match.
+    pub input_delay: usize,
+    /// Whether the input delay is adjusted to the measured round-trip time during the match.
+    pub auto_input_delay: bool,
+    /// The local inputs that are waiting for their frame, with the one for the current frame in
+    /// front.
+    pub local_input_queue: VecDeque<[PlayerControl; MAX_PLAYERS]>,
+    /// The frame that the input in front of the [`local_input_queue`][Self::local_input_queue] is
+    /// for.
+    pub queued_frame: Option<ggrs::Frame>,
+    /// The time left before the input delay is re-evaluated.
+    pub timing_update_timer: f32,
+    /// The first frame that our game state was found to be different from another client's.
+    pub desync_frame: Option<ggrs::Frame>,
+    /// The latest network stats of each remote player, by player handle.
+    pub network_stats: Vec<(PlayerHandle, NetworkStats)>,
+    /// Array containing a flag indicating, for each player, whether their connection is currently
+    /// interrupted.
+    pub player_is_interrupted: [bool; MAX_PLAYERS],
+    /// The number of frames that were re-simulated by the latest rollback.
+    pub rollback_frames: usize,
+    /// Whether the game is frozen because we can't predict any further ahead of the other players.
+    pub is_frozen: bool,
+    /// The frame that the core session simulates next.
+    pub simulated_frame: ggrs::Frame,
+    /// The sounds emitted since they were last taken, see [`SessionRunner::take_sounds`].
+    ///
+    /// [`SessionRunner::take_sounds`]: crate::session::SessionRunner::take_sounds
+    pub sounds: Vec<FrameSound>,
+    /// The first frame rolled back to since the sounds were last taken.
+    pub sound_rollback_frame: Option<ggrs::Frame>,
 }
 
 /// The info required to create a [`GgrsSessionRunner`].
@@ -146,8 +660,24 @@ pub struct GgrsSessionRunnerInfo {
     pub socket: BoxedNonBlockingSocket,
     /// The list of local players.
     pub player_is_local: [bool; MAX_PLAYERS],
+    /// The client that each player plays on, which is used as the GGRS address of remote players.
+    pub player_clients: [usize; MAX_PLAYERS],
     /// the player count.
     pub player_count: usize,
+    /// The addresses of the spectators that we send the confirmed inputs to.
+    pub spectator_addrs: Vec<usize>,
+    /// Whether we are spectating the match, in which case a [`GgrsSpectatorRunner`] must be used.
+    pub is_spectator: bool,
+    /// What to do with the players that disconnect during the match. This must be the same for all
+    /// the players.
+    pub disconnect_policy: DisconnectPolicy,
+    /// The multiplier for the [`jumpy_core::FPS`] that the match runs at. This must be the same for
+    /// all the players.
+    pub frame_rate_factor: f32,
+    /// The input delay and prediction window to start the match with.
+    pub timing: NetworkTiming,
+    /// Whether the input delay should keep adapting to the round-trip time during the match.
+    pub auto_input_delay: bool,
 }
 
 impl GgrsSessionRunner {
@@ -156,36 +686,264 @@ impl GgrsSessionRunner {
     where
         Self: Sized,
     {
-        core.time_step = 1.0 / (jumpy_core::FPS * NETWORK_FRAME_RATE_FACTOR);
+        let fps = jumpy_core::FPS * info.frame_rate_factor;
+        core.time_step = 1.0 / fps;
+        info!(?info.timing, %fps, "Starting network session");
         let mut builder = ggrs::SessionBuilder::new()
             .with_num_players(info.player_count)
-            .with_max_prediction_window(NETWORK_MAX_PREDICTION_WINDOW)
-            .with_input_delay(1)
-            .with_fps((jumpy_core::FPS * NETWORK_FRAME_RATE_FACTOR) as usize)
+            .with_max_prediction_window(info.timing.max_prediction_window)
+            // We delay local inputs ourselves, see `input_delay`.
+            .with_input_delay(0)
+            .with_desync_detection_mode(ggrs::DesyncDetection::On {
+                interval: DESYNC_DETECTION_INTERVAL,
+            })
+            .with_fps(fps as usize)
             .unwrap();
 
         for i in 0..info.player_count {
             if info.player_is_local[i] {
                 builder = builder.add_player(ggrs::PlayerType::Local, i).unwrap();
             } else {
-                builder = builder.add_player(ggrs::PlayerType::Remote(i), i).unwrap();
+                let addr = info.player_clients[i];
+                builder = builder
+                    .add_player(ggrs::PlayerType::Remote(addr), i)
+                    .unwrap();
             }
         }
+        for (i, addr) in info.spectator_addrs.into_iter().enumerate() {
+            builder = builder
+                .add_player(ggrs::PlayerType::Spectator(addr), info.player_count + i)
+                .unwrap();
+        }
 
         let session = builder.start_p2p_session(info.socket).unwrap();
 
         Self {
-            last_player_input: PlayerControl::default(),
+            local_player_inputs: default(),
             core,
             session,
             player_is_local: info.player_is_local,
+            player_clients: info.player_clients,
             accumulator: default(),
             delta: default(),
+            disconnect_policy: info.disconnect_policy,
+            player_is_disconnected: default(),
+            player_count: info.player_count,
+            step: 1.0 / fps,
+            input_delay: info.timing.input_delay,
+            auto_input_delay: info.auto_input_delay,
+            local_input_queue: default(),
+            queued_frame: None,
+            timing_update_timer: NETWORK_TIMING_UPDATE_INTERVAL,
+            desync_frame: None,
+            network_stats: default(),
+            player_is_interrupted: default(),
+            rollback_frames: 0,
+            is_frozen: false,
+            simulated_frame: 0,
+            sounds: default(),
+            sound_rollback_frame: None,
+        }
+    }
+
+    /// Get the remote players that the game is frozen waiting for, if it is frozen.
+    ///
+    /// These are the players whose connection is interrupted or, if there are none, the players
+    /// that we are the furthest ahead of.
+    pub fn waiting_for(&self) -> Vec<usize> {
+        if !self.is_frozen {
+            return Vec::new();
+        }
+
+        let interrupted = (0..self.player_count)
+            .filter(|i| self.player_is_interrupted[*i] && !self.player_is_disconnected[*i])
+            .collect::<Vec<_>>();
+        if !interrupted.is_empty() {
+            return interrupted;
+        }
+
+        let stats = self
+            .network_stats
+            .iter()
+            .filter(|(handle, _)| !self.player_is_disconnected[*handle]);
+        let Some(most_behind) = stats.clone().map(|(_, x)| x.local_frames_behind).min() else {
+            return Vec::new();
+        };
+        stats
+            .filter(|(_, x)| x.local_frames_behind == most_behind)
+            .map(|(handle, _)| *handle)
+            .collect()
+    }
+
+    /// Add our local inputs for the current frame to the session, delayed by the
+    /// [`input_delay`][Self::input_delay].
+    fn add_local_inputs(&mut self) {
+        // Only queue one input per frame, since GGRS may refuse to advance, but keep it up to date
+        // until the frame is advanced.
+        let frame = self.session.current_frame();
+        if self.queued_frame == Some(frame) {
+            if let Some(latest) = self.local_input_queue.back_mut() {
+                *latest = self.local_player_inputs.clone();
+            }
+        } else {
+            self.queued_frame = Some(frame);
+            self.local_input_queue
+                .push_back(self.local_player_inputs.clone());
+
+            // Drop inputs when the delay got shorter, and repeat the oldest one when it got longer.
+            //
+            // The input in front was already sent on the last frame, but the other dropped inputs
+            // never were, so their presses are carried over to the next input to not lose them.
+            let mut front_was_sent = true;
+            while self.local_input_queue.len() > self.input_delay + 1 {
+                let dropped = self.local_input_queue.pop_front().unwrap();
+                if !front_was_sent {
+                    for (dropped, next) in dropped
+                        .iter()
+                        .zip(self.local_input_queue.front_mut().unwrap())
+                    {
+                        carry_presses(dropped, next);
+                    }
+                }
+                front_was_sent = false;
+            }
+            while self.local_input_queue.len() < self.input_delay + 1 {
+                // The repeated input holds the buttons, without pressing them again
+                let mut oldest = self.local_input_queue.front().cloned().unwrap_or_default();
+                for control in &mut oldest {
+                    clear_presses(control);
+                }
+                self.local_input_queue.push_front(oldest);
+            }
+        }
+
+        let inputs = self.local_input_queue.front().unwrap();
+        for i in 0..self.player_count {
+            if self.player_is_local[i] {
+                self.session
+                    .add_local_input(i, get_dense_input(&inputs[i]))
+                    .unwrap();
+            }
+        }
+    }
+
+    /// Move the input delay one frame closer to the one that suits the current round-trip time to
+    /// the slowest client.
+    fn update_input_delay(&mut self) {
+        self.timing_update_timer -= self.delta;
+        if !self.auto_input_delay || self.timing_update_timer > 0.0 {
+            return;
         }
+        self.timing_update_timer = NETWORK_TIMING_UPDATE_INTERVAL;
+
+        let Some(ping) = self
+            .session
+            .remote_player_handles()
+            .into_iter()
+            .filter_map(|handle| self.session.network_stats(handle).ok())
+            .map(|stats| stats.ping)
+            .max() else {
+            return;
+        };
+        let rtt = Duration::from_millis(ping as u64);
+        let target = NetworkTiming::auto_input_delay(rtt, 1.0 / self.step);
+
+        let input_delay = match target.cmp(&self.input_delay) {
+            std::cmp::Ordering::Less => self.input_delay - 1,
+            std::cmp::Ordering::Equal => return,
+            std::cmp::Ordering::Greater => self.input_delay + 1,
+        };
+        info!(?rtt, %input_delay, "Adjusting network input delay");
+        self.input_delay = input_delay;
     }
 }
 
+/// Update the player inputs of the core session with the inputs for a frame received from GGRS.
+fn apply_network_inputs(
+    core: &mut CoreSession,
+    network_inputs: Vec<(proto::DensePlayerControl, ggrs::InputStatus)>,
+    disconnect_policy: DisconnectPolicy,
+) {
+    core.update_input(|inputs| {
+        for (player_idx, (input, status)) in network_inputs.into_iter().enumerate() {
+            let player = &mut inputs.players[player_idx];
+
+            // GGRS only reports players as disconnected from the frame that all the remaining
+            // players agreed on, and rolls back if needed, so handling it here in the simulation
+            // keeps every player in sync.
+            if status == ggrs::InputStatus::Disconnected {
+                match disconnect_policy {
+                    DisconnectPolicy::AiTakeover => player.is_ai = true,
+                    DisconnectPolicy::RemoveAtNextRound => {
+                        player.left_match = true;
+                        player.control = default();
+                        continue;
+                    }
+                }
+            }
+
+            let control = &mut player.control;
+
+            let jump_pressed = input.jump_pressed();
+            control.jump_just_pressed = jump_pressed && !control.jump_pressed;
+            control.jump_pressed = jump_pressed;
+
+            let grab_pressed = input.grab_pressed();
+            control.grab_just_pressed = grab_pressed && !control.grab_pressed;
+            control.grab_pressed = grab_pressed;
+
+            let shoot_pressed = input.shoot_pressed();
+            control.shoot_just_pressed = shoot_pressed && !control.shoot_pressed;
+            control.shoot_pressed = shoot_pressed;
+
+            let was_moving = control.move_direction.length_squared() > f32::MIN_POSITIVE;
+            control.move_direction = input.move_direction().0;
+            let is_moving = control.move_direction.length_squared() > f32::MIN_POSITIVE;
+            control.just_moved = !was_moving && is_moving;
+        }
+    });
+}
+
 /// Get a [`proto::DensePlayerControl`] from a normal [`PlayerControl`].
+/// Add the buttons pressed in the `dropped` input to the `next` one.
+fn carry_presses(dropped: &PlayerControl, next: &mut PlayerControl) {
+    for (dropped_just_pressed, just_pressed, pressed) in [
+        (
+            dropped.jump_just_pressed,
+            &mut next.jump_just_pressed,
+            &mut next.jump_pressed,
+        ),
+        (
+            dropped.shoot_just_pressed,
+            &mut next.shoot_just_pressed,
+            &mut next.shoot_pressed,
+        ),
+        (
+            dropped.grab_just_pressed,
+            &mut next.grab_just_pressed,
+            &mut next.grab_pressed,
+        ),
+        (
+            dropped.slide_just_pressed,
+            &mut next.slide_just_pressed,
+            &mut next.slide_pressed,
+        ),
+    ] {
+        if dropped_just_pressed {
+            *just_pressed = true;
+            *pressed = true;
+        }
+    }
+}
+
+/// Clear the `*_just_pressed` flags of an input, so that it only holds the buttons.
+fn clear_presses(control: &mut PlayerControl) {
+    control.jump_just_pressed = false;
+    control.shoot_just_pressed = false;
+    control.grab_just_pressed = false;
+    control.slide_just_pressed = false;
+}
+
 fn get_dense_input(control: &PlayerControl) -> proto::DensePlayerControl {
     let mut dense_control = proto::DensePlayerControl::default();
     dense_control.set_jump_pressed(control.jump_just_pressed);
@@ -209,13 +967,12 @@ impl crate::session::SessionRunner for GgrsSessionRunner {
         if !self.player_is_local[player_idx] {
             return;
         }
-        self.last_player_input = control;
+        self.local_player_inputs[player_idx] = control;
     }
 
     fn advance(&mut self, bevy_world: &mut World) -> Result<(), SessionError> {
-        const STEP: f32 = 1.0 / (jumpy_core::FPS * NETWORK_FRAME_RATE_FACTOR);
+        let step = self.step;
         let delta = self.delta;
-        let local_player_idx = self.network_player_idx().unwrap();
 
         self.accumulator += delta;
 
@@ -231,12 +988,44 @@ impl crate::session::SessionRunner for GgrsSessionRunner {
                 ggrs::GGRSEvent::Synchronized { addr } => {
                     info!(player=%addr, "Syncrhonized network client");
                 }
-                ggrs::GGRSEvent::Disconnected { .. } => return Err(SessionError::Disconnected),
+                ggrs::GGRSEvent::Disconnected { addr } if addr >= MAX_PLAYERS => {
+                    info!(spectator=%addr, "Network spectator disconnected");
+                }
+                ggrs::GGRSEvent::Disconnected { addr } => {
+                    // All the players of the client are gone
+                    let players =
+                        (0..self.player_count).filter(|i| self.player_clients[*i] == addr);
+                    let first_player = players.clone().next().unwrap_or_default();
+                    for i in players {
+                        self.player_is_disconnected[i] = true;
+                    }
+
+                    // End the match if there is nobody left to play with
+                    if (0..self.player_count)
+                        .all(|i| self.player_is_local[i] || self.player_is_disconnected[i])
+                    {
+                        return Err(SessionError::Disconnected(
+                            NetworkError::PlayerDisconnected(first_player),
+                        ));
+                    }
+
+                    warn!(
+                        client=%addr,
+                        policy=?self.disconnect_policy,
+                        "Network player disconnected, continuing without them"
+                    );
+                }
                 ggrs::GGRSEvent::NetworkInterrupted { addr, .. } => {
                     info!(player=%addr, "Network player interrupted");
+                    for i in (0..self.player_count).filter(|i| self.player_clients[*i] == addr) {
+                        self.player_is_interrupted[i] = true;
+                    }
                 }
                 ggrs::GGRSEvent::NetworkResumed { addr } => {
                     info!(player=%addr, "Network player re-connected");
+                    for i in (0..self.player_count).filter(|i| self.player_clients[*i] == addr) {
+                        self.player_is_interrupted[i] = false;
+                    }
                 }
                 ggrs::GGRSEvent::WaitRecommendation {
                     skip_frames: skip_count,
@@ -260,16 +1049,17 @@ impl crate::session::SessionRunner for GgrsSessionRunner {
                     addr,
                 } => {
                     error!(%frame, %local_checksum, %remote_checksum, player=%addr, "Network de-sync detected");
+                    self.desync_frame.get_or_insert(frame);
                 }
             }
         }
 
+        self.update_input_delay();
+
         loop {
-            self.session
-                .add_local_input(local_player_idx, get_dense_input(&self.last_player_input))
-                .unwrap();
-            if self.accumulator >= STEP {
-                self.accumulator -= STEP;
+            self.add_local_inputs();
+            if self.accumulator >= step {
+                self.accumulator -= step;
 
                 let current_frame = self.session.current_frame();
                 let confirmed_frame = self.session.confirmed_frame();
@@ -288,49 +1078,44 @@ impl crate::session::SessionRunner for GgrsSessionRunner {
 
                 match self.session.advance_frame() {
                     Ok(requests) => {
+                        self.is_frozen = false;
+                        // The input in front of the queue has been used for its frame
+                        self.local_input_queue.pop_front();
                         for request in requests {
                             match request {
-                                ggrs::GGRSRequest::SaveGameState { cell, frame } => {
-                                    cell.save(frame, Some(self.core.world.clone()), None)
-                                }
-                                ggrs::GGRSRequest::LoadGameState { cell, .. } => {
-                                    let world = cell.load().unwrap_or_default();
-                                    self.core.world = world;
+                                ggrs::GGRSRequest::SaveGameState { cell, frame } => cell.save(
+                                    frame,
+                                    Some(self.core.snapshot()),
+                                    Some(self.core.checksum()),
+                                ),
+                                ggrs::GGRSRequest::LoadGameState { cell, frame } => {
+                                    self.rollback_frames = (current_frame - frame) as usize;
+                                    self.core.restore(cell.load().unwrap_or_default());
+
+                                    // The sounds of the rolled back frames are emitted again
+                                    self.simulated_frame = frame;
+                                    self.sounds.retain(|x| x.frame < frame);
+                                    self.sound_rollback_frame = Some(
+                                        self.sound_rollback_frame.map_or(frame, |x| x.min(frame)),
+                                    );
                                 }
                                 ggrs::GGRSRequest::AdvanceFrame {
                                     inputs: network_inputs,
                                 } => {
-                                    self.core.update_input(|inputs| {
-                                        for (player_idx, (input, _status)) in
-                                            network_inputs.into_iter().enumerate()
-                                        {
-                                            let control = &mut inputs.players[player_idx].control;
-
-                                            let jump_pressed = input.jump_pressed();
-                                            control.jump_just_pressed =
-                                                jump_pressed && !control.jump_pressed;
-                                            control.jump_pressed = jump_pressed;
-
-                                            let grab_pressed = input.grab_pressed();
-                                            control.grab_just_pressed =
-                                                grab_pressed && !control.grab_pressed;
-                                            control.grab_pressed = grab_pressed;
-
-                                            let shoot_pressed = input.shoot_pressed();
-                                            control.shoot_just_pressed =
-                                                shoot_pressed && !control.shoot_pressed;
-                                            control.shoot_pressed = shoot_pressed;
-
-                                            let was_moving =
-                                                control.move_direction.length_squared()
-                                                    > f32::MIN_POSITIVE;
-                                            control.move_direction = input.move_direction().0;
-                                            let is_moving = control.move_direction.length_squared()
-                                                > f32::MIN_POSITIVE;
-                                            control.just_moved = !was_moving && is_moving;
-                                        }
-                                    });
+                                    apply_network_inputs(
+                                        &mut self.core,
+                                        network_inputs,
+                                        self.disconnect_policy,
+                                    );
                                     self.core.advance(bevy_world);
+
+                                    let frame = self.simulated_frame;
+                                    self.sounds.extend(
+                                        drain_audio_events(&mut self.core.world)
+                                            .into_iter()
+                                            .map(|event| FrameSound { frame, event }),
+                                    );
+                                    self.simulated_frame += 1;
                                 }
                             }
                         }
@@ -341,6 +1126,7 @@ impl crate::session::SessionRunner for GgrsSessionRunner {
                         }
                         ggrs::GGRSError::PredictionThreshold => {
                             warn!("Freezing game while waiting for network to catch-up.");
+                            self.is_frozen = true;
                             NETWORK_DEBUG_CHANNEL
                                 .sender
                                 .try_send(NetworkDebugMessage::FrameFroze {
@@ -366,9 +1152,12 @@ impl crate::session::SessionRunner for GgrsSessionRunner {
         if !network_stats.is_empty() {
             NETWORK_DEBUG_CHANNEL
                 .sender
-                .try_send(NetworkDebugMessage::NetworkStats { network_stats })
+                .try_send(NetworkDebugMessage::NetworkStats {
+                    network_stats: network_stats.clone(),
+                })
                 .unwrap();
         }
+        self.network_stats = network_stats;
 
         Ok(())
     }
@@ -378,13 +1167,166 @@ impl crate::session::SessionRunner for GgrsSessionRunner {
         ShouldRun::Yes
     }
 
-    fn network_player_idx(&mut self) -> Option<usize> {
-        // We are the first local player
-        for i in 0..MAX_PLAYERS {
-            if self.player_is_local[i] {
-                return Some(i);
+    fn network_local_players(&mut self) -> Option<Vec<usize>> {
+        Some(
+            (0..MAX_PLAYERS)
+                .filter(|i| self.player_is_local[*i])
+                .collect(),
+        )
+    }
+
+    fn take_sounds(&mut self) -> SessionSounds {
+        SessionSounds {
+            sounds: std::mem::take(&mut self.sounds),
+            rollback_frame: self.sound_rollback_frame.take(),
+            confirmed_frame: Some(self.session.confirmed_frame()),
+        }
+    }
+}
+
+/// How many frames behind the host spectators watch the match.
+///
+/// Spectators only ever simulate confirmed frames, so this gives them some room to keep playing
+/// smoothly when the inputs from the host arrive late.
+pub const SPECTATOR_DELAY_FRAMES: i32 = 20;
+
+/// How many frames behind the host spectators may get before they speed up to catch up.
+const SPECTATOR_MAX_FRAMES_BEHIND: usize = 40;
+
+/// [`SessionRunner`] implementation that uses a [`ggrs`][crate::external::ggrs] spectator session
+/// to watch a network match.
+///
+/// The spectator receives the confirmed inputs of all players from the host, and never has to roll
+/// back.
+pub struct GgrsSpectatorRunner {
+    /// The core game session.
+    pub core: CoreSession,
+    /// The GGRS spectator session.
+    pub session: SpectatorSession<GgrsConfig>,
+    /// The frame time delta.
+    pub delta: f32,
+    /// The frame time accumulator, used to produce a fixed refresh rate.
+    pub accumulator: f32,
+    /// What to do with the players that disconnect during the match.
+    pub disconnect_policy: DisconnectPolicy,
+    /// Whether we are far enough behind the host to start watching, see
+    /// [`SPECTATOR_DELAY_FRAMES`].
+    pub is_watching: bool,
+    /// The duration of a simulation frame.
+    pub step: f32,
+}
+
+impl GgrsSpectatorRunner {
+    /// Create a new spectator session runner.
+    pub fn new(mut core: CoreSession, info: GgrsSessionRunnerInfo) -> Self {
+        let fps = jumpy_core::FPS * info.frame_rate_factor;
+        core.time_step = 1.0 / fps;
+        let session = ggrs::SessionBuilder::new()
+            .with_num_players(info.player_count)
+            .with_max_frames_behind(SPECTATOR_MAX_FRAMES_BEHIND)
+            .unwrap()
+            .with_catchup_speed(2)
+            .unwrap()
+            .with_fps(fps as usize)
+            .unwrap()
+            // The host is the one sending us the inputs
+            .start_spectator_session(0, info.socket);
+
+        Self {
+            core,
+            session,
+            delta: default(),
+            accumulator: default(),
+            disconnect_policy: info.disconnect_policy,
+            is_watching: false,
+            step: 1.0 / fps,
+        }
+    }
+}
+
+impl crate::session::SessionRunner for GgrsSpectatorRunner {
+    fn core_session(&mut self) -> &mut CoreSession {
+        &mut self.core
+    }
+
+    fn restart(&mut self) {
+        self.core.restart()
+    }
+
+    fn set_player_input(&mut self, _player_idx: usize, _control: PlayerControl) {
+        // Spectators don't have any input
+    }
+
+    fn advance(&mut self, bevy_world: &mut World) -> Result<(), SessionError> {
+        let step = self.step;
+
+        self.session.poll_remote_clients();
+
+        for event in self.session.events() {
+            match event {
+                ggrs::GGRSEvent::Synchronizing { total, count, .. } => {
+                    info!(%total, progress=%count, "Syncing with network host");
+                }
+                ggrs::GGRSEvent::Synchronized { .. } => {
+                    info!("Synchronized with network host");
+                }
+                ggrs::GGRSEvent::Disconnected { addr } => {
+                    return Err(SessionError::Disconnected(
+                        NetworkError::PlayerDisconnected(addr),
+                    ));
+                }
+                ggrs::GGRSEvent::NetworkInterrupted { .. } => {
+                    info!("Network host interrupted");
+                }
+                ggrs::GGRSEvent::NetworkResumed { .. } => {
+                    info!("Network host re-connected");
+                }
+                _ => (),
             }
         }
-        unreachable!();
+
+        if !self.is_watching {
+            self.is_watching = self.session.frames_behind_host() >= SPECTATOR_DELAY_FRAMES;
+            return Ok(());
+        }
+
+        self.accumulator += self.delta;
+        while self.accumulator >= step {
+            self.accumulator -= step;
+
+            match self.session.advance_frame() {
+                Ok(requests) => {
+                    for request in requests {
+                        if let ggrs::GGRSRequest::AdvanceFrame {
+                            inputs: network_inputs,
+                        } = request
+                        {
+                            apply_network_inputs(
+                                &mut self.core,
+                                network_inputs,
+                                self.disconnect_policy,
+                            );
+                            self.core.advance(bevy_world);
+                        }
+                    }
+                }
+                Err(ggrs::GGRSError::NotSynchronized | ggrs::GGRSError::PredictionThreshold) => {
+                    debug!("Waiting for inputs from network host");
+                }
+                Err(e) => error!("Network protocol error: {e}"),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn run_criteria(&mut self, time: &Time) -> ShouldRun {
+        self.delta = time.delta_seconds();
+        ShouldRun::Yes
+    }
+
+    fn network_local_players(&mut self) -> Option<Vec<usize>> {
+        // Spectators don't have any local players
+        Some(Vec::new())
     }
 }
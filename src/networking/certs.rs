@@ -1,34 +1,267 @@
 //! QUIC certificate utilities.
 //!
+//! Connections to the matchmaking server are verified against the certificate fingerprint pinned
+//! in the [`Settings`][crate::metadata::Settings]. The public matchmaker creates a new self-signed
+//! certificate every time it starts, so there is no fingerprint that could be pinned for it by
+//! default, and its certificate isn't verified unless one is set.
 //!
+//! LAN games use self-signed certificates, so LAN hosts are trusted the first time we connect to
+//! them, and their certificate is remembered so that we notice if it changes. Our own certificate
+//! is kept between runs for the same reason.
 
-use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    fs,
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
 
-/// Implementation of `ServerCertVerifier` that verifies everything as trustworthy.
-///
-/// This allows us to connect to servers without having to worry about domain verification.
-///
-/// TODO: In the future we may want to do domain verification, but allow opting out of it.
-pub struct SkipServerVerification;
+use bevy::prelude::{info, warn};
+use once_cell::sync::Lazy;
+use sha2::{Digest, Sha256};
+
+use crate::config::ENGINE_CONFIG;
+
+use super::{network_endpoint, NetworkError};
+
+/// The file, in the data dir, that the certificates of the LAN hosts we know are stored in.
+const KNOWN_HOSTS_FILE: &str = "known_hosts.yml";
+
+/// The certificates of the LAN hosts we have connected to before, by IP address.
+static KNOWN_HOSTS: Lazy<Mutex<HashMap<IpAddr, Fingerprint>>> =
+    Lazy::new(|| Mutex::new(load_known_hosts()));
+
+/// The SHA-256 fingerprint of a certificate.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Fingerprint([u8; 32]);
+
+impl Fingerprint {
+    /// Get the fingerprint of a certificate.
+    pub fn of(cert: &rustls::Certificate) -> Self {
+        Self(Sha256::digest(&cert.0).into())
+    }
+}
+
+impl std::fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, byte) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ":")?;
+            }
+            write!(f, "{byte:02X}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Fingerprint {
+    type Err = NetworkError;
+
+    /// Parse a fingerprint from hex, ignoring any `:` separators and whitespace.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid =
+            || NetworkError::UntrustedCertificate(format!("invalid certificate fingerprint `{s}`"));
+
+        let hex = s
+            .chars()
+            .filter(|x| *x != ':' && !x.is_whitespace())
+            .collect::<String>();
+        if hex.len() != 64 || !hex.chars().all(|x| x.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+
+        let mut bytes = [0; 32];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+        }
+        Ok(Self(bytes))
+    }
+}
+
+/// How a [`ServerVerifier`] decides whether to trust the certificate of a server.
+enum Trust {
+    /// Trust only the certificate with this fingerprint.
+    Pinned(Fingerprint),
+    /// Trust the first certificate of the LAN host at this IP address, and then only that one.
+    FirstUse(IpAddr),
+    /// Trust any certificate, see [`verification_disabled()`].
+    Any,
+}
+
+/// A [`rustls::client::ServerCertVerifier`] that remembers why it rejected a certificate, so that we
+/// can show a useful error instead of a failed handshake.
+pub struct ServerVerifier {
+    trust: Trust,
+    rejection: Mutex<Option<NetworkError>>,
+}
+
+impl ServerVerifier {
+    /// Verify the matchmaking server against the pinned `fingerprint`.
+    ///
+    /// When the fingerprint is empty, the certificate isn't verified, since the public matchmaker
+    /// uses a self-signed certificate that changes whenever it restarts.
+    pub fn matchmaker(fingerprint: &str) -> Result<Arc<Self>, NetworkError> {
+        let trust = if fingerprint.trim().is_empty() {
+            warn!(
+                "The matchmaking server's certificate won't be verified, because no certificate \
+                fingerprint is set"
+            );
+            Trust::Any
+        } else {
+            Trust::Pinned(fingerprint.parse()?)
+        };
+        Ok(Self::new(trust))
+    }
+
+    /// Verify a LAN host, trusting it on first use.
+    ///
+    /// Hosts on the loopback address are always trusted, since they are running on this machine.
+    pub fn lan_host(ip: IpAddr) -> Arc<Self> {
+        let trust = if ip.is_loopback() {
+            Trust::Any
+        } else {
+            Trust::FirstUse(ip)
+        };
+        Self::new(trust)
+    }
+
+    fn new(trust: Trust) -> Arc<Self> {
+        let trust = if verification_disabled() {
+            Trust::Any
+        } else {
+            trust
+        };
+        Arc::new(Self {
+            trust,
+            rejection: Mutex::new(None),
+        })
+    }
+
+    /// Connect to the server at `addr`, verifying its certificate.
+    pub async fn connect(
+        self: Arc<Self>,
+        addr: SocketAddr,
+        server_name: &str,
+    ) -> Result<quinn::Connection, NetworkError> {
+        let client_config = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_custom_certificate_verifier(self.clone())
+            .with_no_client_auth();
+        let client_config = quinn::ClientConfig::new(Arc::new(client_config));
+
+        network_endpoint()?
+            .connect_with(client_config, addr, server_name)?
+            .await
+            .map_err(|e| {
+                self.rejection
+                    .lock()
+                    .unwrap()
+                    .take()
+                    .unwrap_or_else(|| NetworkError::ConnectionFailed(e.to_string()))
+            })
+    }
 
-impl SkipServerVerification {
-    pub fn new() -> Arc<Self> {
-        Arc::new(Self)
+    /// Reject a certificate, remembering the `error` for [`connect()`][Self::connect].
+    fn reject(&self, error: NetworkError) -> rustls::Error {
+        warn!("Rejected server certificate: {error}");
+        *self.rejection.lock().unwrap() = Some(error);
+        rustls::Error::InvalidCertificate(rustls::CertificateError::ApplicationVerificationFailure)
     }
 }
 
-impl rustls::client::ServerCertVerifier for SkipServerVerification {
+impl rustls::client::ServerCertVerifier for ServerVerifier {
     fn verify_server_cert(
         &self,
-        _end_entity: &rustls::Certificate,
+        end_entity: &rustls::Certificate,
         _intermediates: &[rustls::Certificate],
         _server_name: &rustls::ServerName,
         _scts: &mut dyn Iterator<Item = &[u8]>,
         _ocsp_response: &[u8],
         _now: std::time::SystemTime,
     ) -> Result<rustls::client::ServerCertVerified, rustls::Error> {
-        Ok(rustls::client::ServerCertVerified::assertion())
+        let fingerprint = Fingerprint::of(end_entity);
+
+        match &self.trust {
+            Trust::Pinned(pinned) => {
+                if *pinned == fingerprint {
+                    Ok(rustls::client::ServerCertVerified::assertion())
+                } else {
+                    Err(self.reject(NetworkError::UntrustedCertificate(format!(
+                        "expected certificate {pinned}, but got {fingerprint}"
+                    ))))
+                }
+            }
+            Trust::FirstUse(ip) => {
+                let mut known_hosts = KNOWN_HOSTS.lock().unwrap();
+                match known_hosts.get(ip) {
+                    Some(known) if *known == fingerprint => {
+                        Ok(rustls::client::ServerCertVerified::assertion())
+                    }
+                    Some(known) => Err(self.reject(NetworkError::CertificateChanged(format!(
+                        "the certificate of {ip} changed from {known} to {fingerprint}"
+                    )))),
+                    None => {
+                        info!(%ip, %fingerprint, "Trusting new LAN host");
+                        known_hosts.insert(*ip, fingerprint);
+                        save_known_hosts(&known_hosts);
+                        Ok(rustls::client::ServerCertVerified::assertion())
+                    }
+                }
+            }
+            Trust::Any => Ok(rustls::client::ServerCertVerified::assertion()),
+        }
+    }
+}
+
+/// Whether certificate verification was turned off for development with the
+/// `--insecure-skip-cert-verification` flag.
+///
+/// The flag is ignored in release builds.
+pub fn verification_disabled() -> bool {
+    if !ENGINE_CONFIG.insecure_skip_cert_verification {
+        false
+    } else if cfg!(debug_assertions) {
+        warn!("Skipping certificate verification, connections are not secure");
+        true
+    } else {
+        warn!("Ignoring `--insecure-skip-cert-verification` in release build");
+        false
+    }
+}
+
+/// Forget the certificates of all the LAN hosts we have connected to, so that they are trusted
+/// again the next time we connect to them.
+pub fn forget_known_hosts() {
+    let mut known_hosts = KNOWN_HOSTS.lock().unwrap();
+    known_hosts.clear();
+    save_known_hosts(&known_hosts);
+    info!("Forgot known LAN hosts");
+}
+
+/// Load the certificate that we present to other players, or generate one the first time.
+///
+/// The certificate is kept between runs so that LAN players that trusted it keep trusting us.
+pub fn load_or_generate_identity() -> anyhow::Result<(rustls::Certificate, rustls::PrivateKey)> {
+    let dir = data_dir();
+    let cert_path = dir.join("identity.crt");
+    let key_path = dir.join("identity.key");
+
+    if let (Ok(cert), Ok(key)) = (fs::read(&cert_path), fs::read(&key_path)) {
+        return Ok((rustls::Certificate(cert), rustls::PrivateKey(key)));
+    }
+
+    let (cert, key) = generate_self_signed_cert()?;
+    let saved = fs::create_dir_all(&dir)
+        .and_then(|_| fs::write(&cert_path, &cert.0))
+        .and_then(|_| fs::write(&key_path, &key.0));
+    if let Err(e) = saved {
+        warn!("Couldn't save network certificate, LAN players won't recognize us next time: {e}");
     }
+    info!(fingerprint = %Fingerprint::of(&cert), "Generated network certificate");
+
+    Ok((cert, key))
 }
 
 /// Generates a self-signed cert for use in QUIC connections.
@@ -37,3 +270,48 @@ pub fn generate_self_signed_cert() -> anyhow::Result<(rustls::Certificate, rustl
     let key = rustls::PrivateKey(cert.serialize_private_key_der());
     Ok((rustls::Certificate(cert.serialize_der()?), key))
 }
+
+/// Get the directory the certificates are stored in.
+fn data_dir() -> PathBuf {
+    directories::ProjectDirs::from("org", "FishFolk", "Jumpy")
+        .expect("Identify system data dir path")
+        .data_dir()
+        .to_owned()
+}
+
+fn load_known_hosts() -> HashMap<IpAddr, Fingerprint> {
+    let Ok(data) = fs::read(data_dir().join(KNOWN_HOSTS_FILE)) else {
+        return HashMap::new();
+    };
+    let hosts: HashMap<String, String> = match serde_yaml::from_slice(&data) {
+        Ok(hosts) => hosts,
+        Err(e) => {
+            warn!("Couldn't read known LAN hosts, they will be trusted again: {e}");
+            return HashMap::new();
+        }
+    };
+
+    hosts
+        .into_iter()
+        .filter_map(|(ip, fingerprint)| Some((ip.parse().ok()?, fingerprint.parse().ok()?)))
+        .collect()
+}
+
+fn save_known_hosts(known_hosts: &HashMap<IpAddr, Fingerprint>) {
+    let hosts = known_hosts
+        .iter()
+        .map(|(ip, fingerprint)| (ip.to_string(), fingerprint.to_string()))
+        .collect::<HashMap<_, _>>();
+
+    let dir = data_dir();
+    let saved = serde_yaml::to_string(&hosts)
+        .map_err(|e| e.to_string())
+        .and_then(|data| {
+            fs::create_dir_all(&dir)
+                .and_then(|_| fs::write(dir.join(KNOWN_HOSTS_FILE), data))
+                .map_err(|e| e.to_string())
+        });
+    if let Err(e) = saved {
+        warn!("Couldn't save known LAN hosts: {e}");
+    }
+}
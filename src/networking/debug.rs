@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use async_channel::{Receiver, Sender};
 use bevy::prelude::{Res, ResMut, Resource};
 use bevy_egui::{
@@ -11,9 +13,11 @@ use bevy_fluent::Localization;
 use ggrs::{NetworkStats, PlayerHandle};
 use once_cell::sync::Lazy;
 
-use crate::prelude::{debug_tools::ShowDebugWindows, LocalizationExt};
-
-use super::NETWORK_MAX_PREDICTION_WINDOW;
+use crate::{
+    metadata::NetworkTimingSettings,
+    networking::simulator::NETWORK_CONDITIONS,
+    prelude::{debug_tools::ShowDebugWindows, LocalizationExt},
+};
 
 /// Messages used by network debug channel
 pub enum NetworkDebugMessage {
@@ -224,10 +228,10 @@ pub fn network_debug_window(
                         .include_x(min_display_frame)
                         .include_x(max_display_frame)
                         .auto_bounds_y()
-                        .include_y(NETWORK_MAX_PREDICTION_WINDOW as f64)
+                        .include_y(NetworkTimingSettings::MAX_PREDICTION_WINDOW as f64)
                         .show_axes([false, true])
                         .y_grid_spacer(|_grid_input| {
-                            (0..NETWORK_MAX_PREDICTION_WINDOW + 1)
+                            (0..NetworkTimingSettings::MAX_PREDICTION_WINDOW + 1)
                                 .into_iter()
                                 .map(|y| GridMark {
                                     step_size: 1.0,
@@ -279,7 +283,52 @@ pub fn network_debug_window(
                             ui.monospace(&format!("{stats:?}"));
                         });
                     }
+
+                    ui.collapsing(localization.get("network-conditions"), |ui| {
+                        network_conditions_ui(ui, &localization);
+                    });
                 });
         }
     }
 }
+
+/// Show the controls for the [`NETWORK_CONDITIONS`] simulated by the network sockets.
+fn network_conditions_ui(ui: &mut egui::Ui, localization: &Localization) {
+    let mut conditions = NETWORK_CONDITIONS.write().unwrap();
+
+    ui.checkbox(
+        &mut conditions.enabled,
+        localization.get("simulate-network-conditions"),
+    );
+    ui.add_enabled_ui(conditions.enabled, |ui| {
+        let mut latency = conditions.latency.as_millis() as u64;
+        ui.add(
+            egui::Slider::new(&mut latency, 0..=500)
+                .suffix(" ms")
+                .text(localization.get("latency")),
+        );
+        conditions.latency = Duration::from_millis(latency);
+
+        let mut jitter = conditions.jitter.as_millis() as u64;
+        ui.add(
+            egui::Slider::new(&mut jitter, 0..=200)
+                .suffix(" ms")
+                .text(localization.get("jitter")),
+        );
+        conditions.jitter = Duration::from_millis(jitter);
+
+        for (value, label) in [
+            (&mut conditions.packet_loss, "packet-loss"),
+            (&mut conditions.duplication, "duplication"),
+            (&mut conditions.reordering, "reordering"),
+        ] {
+            let mut percent = *value * 100.0;
+            ui.add(
+                egui::Slider::new(&mut percent, 0.0..=50.0)
+                    .suffix("%")
+                    .text(localization.get(label)),
+            );
+            *value = percent / 100.0;
+        }
+    });
+}
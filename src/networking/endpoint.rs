@@ -0,0 +1,233 @@
+//! The QUIC network endpoint, which isn't available in the web build.
+
+use std::sync::Mutex;
+
+use rand::Rng;
+
+use super::*;
+
+/// The network endpoint used for all QUIC network communications, with the binding it was created
+/// with, see [`network_endpoint()`].
+static NETWORK_ENDPOINT: Lazy<Mutex<Option<(EndpointBinding, quinn::Endpoint)>>> =
+    Lazy::new(default);
+
+/// The settings that the [`network_endpoint()`] is bound with, see [`set_endpoint_settings()`].
+static ENDPOINT_SETTINGS: Lazy<Mutex<NetworkBindSettings>> = Lazy::new(default);
+
+/// Set the address and ports the [`network_endpoint()`] is bound to.
+///
+/// The endpoint is recreated the next time it is used if these settings changed. The
+/// `--bind-address` and `--port` flags of the [`EngineConfig`][crate::config::EngineConfig] take
+/// priority over the settings.
+pub fn set_endpoint_settings(settings: &NetworkBindSettings) {
+    *ENDPOINT_SETTINGS.lock().unwrap() = settings.clone();
+}
+
+/// Get the network endpoint used for all QUIC network communications, binding it if it wasn't
+/// bound yet or if its [settings][set_endpoint_settings] changed.
+///
+/// Existing connections keep using the endpoint they were made with.
+pub fn network_endpoint() -> Result<quinn::Endpoint, NetworkError> {
+    let binding = EndpointBinding::new(&ENDPOINT_SETTINGS.lock().unwrap())?;
+
+    let mut endpoint = NETWORK_ENDPOINT.lock().unwrap();
+    if let Some((current, endpoint)) = &*endpoint {
+        if *current == binding {
+            return Ok(endpoint.clone());
+        }
+    }
+
+    // Drop the old endpoint first, so that its port is freed if it isn't in use anymore.
+    *endpoint = None;
+    let new_endpoint = create_endpoint(&binding)?;
+    *endpoint = Some((binding, new_endpoint.clone()));
+
+    Ok(new_endpoint)
+}
+
+/// Get the address of the [`network_endpoint()`], if it is bound, without binding it.
+pub fn endpoint_addr() -> Option<std::net::SocketAddr> {
+    let endpoint = NETWORK_ENDPOINT.lock().unwrap();
+    endpoint.as_ref()?.1.local_addr().ok()
+}
+
+/// The IP address and port range that the [`network_endpoint()`] is bound to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct EndpointBinding {
+    /// The IP address to bind, or `None` to bind all interfaces.
+    ip: Option<std::net::IpAddr>,
+    /// The ports to try binding, in order.
+    ports: std::ops::RangeInclusive<u16>,
+}
+
+impl EndpointBinding {
+    /// Parse the binding from the settings, with the overrides from the [`ENGINE_CONFIG`].
+    fn new(settings: &NetworkBindSettings) -> Result<Self, NetworkError> {
+        let address = ENGINE_CONFIG
+            .bind_address
+            .as_deref()
+            .unwrap_or(&settings.address)
+            .trim();
+        let ports = ENGINE_CONFIG
+            .port
+            .as_deref()
+            .unwrap_or(&settings.ports)
+            .trim();
+
+        let ip = if address.is_empty() {
+            None
+        } else {
+            Some(address.parse().map_err(|_| {
+                NetworkError::BindFailed(format!("Invalid bind address `{address}`"))
+            })?)
+        };
+
+        let invalid_ports = || {
+            NetworkError::BindFailed(format!(
+                "Invalid port `{ports}`, it must be a port or a range of ports like `10000-11000`"
+            ))
+        };
+        let parse_port = |port: &str| port.trim().parse::<u16>().map_err(|_| invalid_ports());
+        let ports = match ports.split_once('-') {
+            Some((first, last)) => parse_port(first)?..=parse_port(last)?,
+            None => parse_port(ports).map(|port| port..=port)?,
+        };
+        if ports.is_empty() {
+            return Err(invalid_ports());
+        }
+
+        Ok(Self { ip, ports })
+    }
+
+    /// Bind a UDP socket to the first free port, starting from a random port in the range so that
+    /// several game instances on the same machine are unlikely to try the same ports.
+    fn bind(&self) -> Result<std::net::UdpSocket, NetworkError> {
+        let (first, last) = (*self.ports.start(), *self.ports.end());
+        let start = rand::thread_rng().gen_range(first..=last);
+        let ports = (start..=last).chain(first..start);
+
+        for port in ports {
+            let result = match self.ip {
+                Some(ip) => std::net::UdpSocket::bind((ip, port)),
+                None => bind_dual_stack_socket(port).or_else(|e| {
+                    if e.kind() == std::io::ErrorKind::AddrInUse {
+                        return Err(e);
+                    }
+                    warn!("Couldn't bind IPv6 socket, falling back to IPv4 only: {e}");
+                    std::net::UdpSocket::bind((std::net::Ipv4Addr::UNSPECIFIED, port))
+                }),
+            };
+
+            match result {
+                Ok(socket) => return Ok(socket),
+                Err(e) if e.kind() == std::io::ErrorKind::AddrInUse => continue,
+                Err(e) => {
+                    return Err(NetworkError::BindFailed(format!(
+                        "Couldn't bind port {port}: {e}"
+                    )))
+                }
+            }
+        }
+
+        Err(NetworkError::BindFailed(format!(
+            "Ports {first}-{last} are already in use"
+        )))
+    }
+}
+
+/// Create the QUIC network endpoint, bound to the first free port of the `binding`.
+fn create_endpoint(binding: &EndpointBinding) -> Result<quinn::Endpoint, NetworkError> {
+    // Load our certificate
+    let (cert, key) = certs::load_or_generate_identity()
+        .map_err(|e| NetworkError::Service(format!("Couldn't create certificate: {e}")))?;
+
+    let mut transport_config = quinn::TransportConfig::default();
+    transport_config.keep_alive_interval(Some(std::time::Duration::from_secs(5)));
+
+    let mut server_config = quinn::ServerConfig::with_single_cert([cert].to_vec(), key)
+        .map_err(|e| NetworkError::Service(format!("Invalid certificate: {e}")))?;
+    server_config.transport = Arc::new(transport_config);
+
+    // Open Socket and create endpoint
+    let socket = binding.bind()?;
+    info!(addr=?socket.local_addr(), "Started network endpoint");
+
+    // There is no default client config, every connection picks how to verify the server with a
+    // `certs::ServerVerifier`.
+    quinn::Endpoint::new(
+        quinn::EndpointConfig::default(),
+        Some(server_config),
+        socket,
+        Arc::new(quinn_runtime_bevy::BevyIoTaskPoolExecutor),
+    )
+    .map_err(|e| NetworkError::BindFailed(e.to_string()))
+}
+
+/// Bind a UDP socket that accepts both IPv6 and IPv4 traffic.
+///
+/// IPv4 peers show up with IPv4-mapped IPv6 addresses on this socket.
+fn bind_dual_stack_socket(port: u16) -> std::io::Result<std::net::UdpSocket> {
+    use socket2::{Domain, Protocol, Socket, Type};
+
+    let socket = Socket::new(Domain::IPV6, Type::DGRAM, Some(Protocol::UDP))?;
+    // Windows defaults to IPv6 only, so this must be turned off explicitly.
+    socket.set_only_v6(false)?;
+    socket.bind(&std::net::SocketAddr::from((std::net::Ipv6Addr::UNSPECIFIED, port)).into())?;
+
+    Ok(socket.into())
+}
+
+/// Resolve a `host:port` address, where the host may be a host name, an IPv4 address, or an IPv6
+/// address in brackets, such as `[::1]:10500`.
+///
+/// If the [`network_endpoint()`] could only be bound to IPv4, IPv6 addresses are skipped.
+///
+/// Note: This may block the thread while doing a DNS lookup.
+pub fn resolve_addr_blocking(addr: &str) -> Result<std::net::SocketAddr, NetworkError> {
+    use std::net::ToSocketAddrs;
+
+    let supports_ipv6 = network_endpoint()?
+        .local_addr()
+        .map(|x| x.is_ipv6())
+        .unwrap_or_default();
+
+    addr.trim()
+        .to_socket_addrs()
+        .map_err(|e| {
+            NetworkError::InvalidAddress(format!(
+                "Couldn't resolve `{addr}`, addresses must be in the format `host:port`: {e}"
+            ))
+        })?
+        .find(|x| supports_ipv6 || x.is_ipv4())
+        .ok_or_else(|| NetworkError::InvalidAddress(format!("Couldn't resolve `{addr}`")))
+}
+
+impl From<quinn::ConnectError> for NetworkError {
+    fn from(e: quinn::ConnectError) -> Self {
+        NetworkError::ConnectionFailed(e.to_string())
+    }
+}
+
+impl From<quinn::ConnectionError> for NetworkError {
+    fn from(e: quinn::ConnectionError) -> Self {
+        NetworkError::ConnectionLost(e.to_string())
+    }
+}
+
+impl From<quinn::WriteError> for NetworkError {
+    fn from(e: quinn::WriteError) -> Self {
+        NetworkError::ConnectionLost(e.to_string())
+    }
+}
+
+impl From<quinn::ReadToEndError> for NetworkError {
+    fn from(e: quinn::ReadToEndError) -> Self {
+        NetworkError::ConnectionLost(e.to_string())
+    }
+}
+
+impl From<quinn::ReadExactError> for NetworkError {
+    fn from(e: quinn::ReadExactError) -> Self {
+        NetworkError::ConnectionLost(e.to_string())
+    }
+}
@@ -9,10 +9,18 @@
 //! the [`ui::main_menu::network_game`] module, in the menu code. This probably isn't the best place
 //! for it, and it should be moved into here to be a part of the [`lan_matchmaker`] task.
 //!
+//! Because MDNS doesn't work on every network, or over VPNs and the internet, players may also
+//! join a hosted match directly by typing in the host's `host:port` address, using
+//! [`join_server_at`]. Both paths go through the same matchmaking handshake, and both IPv4 and IPv6
+//! addresses are supported.
+//!
+//! Hosts pick a random free port in the range from the network settings by default. To host behind
+//! a firewall or port forward, set a single port in the network settings or with the `--port` flag.
+//!
 //! Communication happens directly between LAN peers over the QUIC protocol.
 
 use std::{
-    net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4},
+    net::{IpAddr, SocketAddr},
     time::Duration,
 };
 
@@ -30,6 +38,14 @@ pub struct ServerInfo {
     pub ping: Option<u16>,
 }
 
+impl ServerInfo {
+    /// Get the address to connect to the server at.
+    pub fn addr(&self) -> Option<SocketAddr> {
+        let ip = *self.service.get_addresses().iter().next()?;
+        Some(SocketAddr::new(ip.into(), self.service.get_port()))
+    }
+}
+
 /// Channel used to do matchmaking over LAN.
 ///
 /// Spawns a task to handle the actual matchmaking.
@@ -49,8 +65,8 @@ const MDNS_SERVICE_TYPE: &str = "_jumpy._udp.local.";
 #[derive(DerefMut, Deref)]
 struct Pinger(BiChannelClient<PingerRequest, PingerResponse>);
 
-type PingerRequest = SmallVec<[Ipv4Addr; 10]>;
-type PingerResponse = SmallVec<[(Ipv4Addr, Option<u16>); 10]>;
+type PingerRequest = SmallVec<[IpAddr; 10]>;
+type PingerResponse = SmallVec<[(IpAddr, Option<u16>); 10]>;
 
 static PINGER: Lazy<Pinger> = Lazy::new(|| {
     let (client, server) = bi_channel();
@@ -60,13 +76,30 @@ static PINGER: Lazy<Pinger> = Lazy::new(|| {
     Pinger(client)
 });
 
-/// Host a server.
-pub fn start_server(service_info: ServiceInfo, player_count: usize) {
+/// Host a server for a match with `player_count` players in total, `local_player_count` of which
+/// play on our client.
+pub fn start_server(
+    service_info: ServiceInfo,
+    player_count: usize,
+    local_player_count: usize,
+) -> Result<(), NetworkError> {
+    // Bind the endpoint before advertising it, in case its port changed
+    let port = host_port()?;
+    let service_info = if service_info.get_port() == port {
+        service_info
+    } else {
+        create_service_info(service_info.get_hostname(), port)
+    };
+
     MDNS.register(service_info)
-        .expect("Could not register MDNS service.");
+        .map_err(|e| NetworkError::Service(format!("Could not register MDNS service: {e}")))?;
     LAN_MATCHMAKER
-        .try_send(LanMatchmakerRequest::StartServer { player_count })
+        .try_send(LanMatchmakerRequest::StartServer {
+            player_count,
+            local_player_count,
+        })
         .unwrap();
+    Ok(())
 }
 
 /// Stop hosting a server.
@@ -76,48 +109,87 @@ pub fn stop_server(service_info: &ServiceInfo) {
             Ok(_) => break,
             Err(mdns_sd::Error::Again) => (),
             Err(e) => {
-                panic!("Error unregistering MDNS service: {e}")
+                error!("Error unregistering MDNS service: {e}");
+                break;
             }
         }
     }
 }
 
-/// Wait for players to join a hosted server.
-pub fn wait_players(joined_players: &mut usize, service_info: &ServiceInfo) -> Option<LanSocket> {
+/// Wait for players and spectators to join a hosted server.
+///
+/// If hosting fails, the server is stopped and the error is returned.
+pub fn wait_players(
+    joined_players: &mut usize,
+    joined_spectators: &mut usize,
+    service_info: &ServiceInfo,
+) -> Result<Option<LanSocket>, NetworkError> {
     while let Ok(response) = LAN_MATCHMAKER.try_recv() {
         match response {
             LanMatchmakerResponse::ServerStarted => {}
             LanMatchmakerResponse::PlayerCount(count) => {
                 *joined_players = count;
             }
+            LanMatchmakerResponse::SpectatorCount(count) => {
+                *joined_spectators = count;
+            }
             LanMatchmakerResponse::GameStarting {
                 lan_socket,
-                player_idx,
+                client_idx,
                 player_count: _,
             } => {
-                info!(?player_idx, "Starting network game");
-                loop {
-                    match MDNS.unregister(service_info.get_fullname()) {
-                        Ok(_) => break,
-                        Err(mdns_sd::Error::Again) => (),
-                        Err(e) => panic!("Error unregistering MDNS service: {e}"),
-                    }
-                }
-                return Some(lan_socket);
+                info!(?client_idx, "Starting network game");
+                stop_server(service_info);
+                return Ok(Some(lan_socket));
+            }
+            LanMatchmakerResponse::Error(err) => {
+                stop_server(service_info);
+                return Err(err);
             }
         }
     }
-    None
+    Ok(None)
+}
+
+/// Join a server hosted by someone else, with `local_player_count` players playing on our client.
+///
+/// Joining with no local players spectates the match.
+pub fn join_server(server: &ServerInfo, local_player_count: usize) -> Result<(), NetworkError> {
+    let addr = server.addr().ok_or_else(|| {
+        NetworkError::InvalidAddress(format!(
+            "Server `{}` doesn't have an address",
+            server.service.get_hostname()
+        ))
+    })?;
+    LAN_MATCHMAKER
+        .try_send(networking::lan::LanMatchmakerRequest::JoinServer {
+            addr,
+            local_player_count,
+        })
+        .unwrap();
+    Ok(())
 }
 
-/// Join a server hosted by someone else.
-pub fn join_server(server: &ServerInfo) {
+/// Join a server directly by its `host:port` address, without discovering it over MDNS.
+///
+/// Note: This may block the thread while resolving a host name.
+pub fn join_server_at(addr: &str, local_player_count: usize) -> Result<(), NetworkError> {
+    let addr = resolve_addr_blocking(addr)?;
     LAN_MATCHMAKER
         .try_send(networking::lan::LanMatchmakerRequest::JoinServer {
-            ip: *server.service.get_addresses().iter().next().unwrap(),
-            port: server.service.get_port(),
+            addr,
+            local_player_count,
         })
         .unwrap();
+    Ok(())
+}
+
+/// Get the port that the server is hosted on, for players joining directly by address.
+pub fn host_port() -> Result<u16, NetworkError> {
+    network_endpoint()?
+        .local_addr()
+        .map(|x| x.port())
+        .map_err(|e| NetworkError::BindFailed(e.to_string()))
 }
 
 /// Leave a joined server.
@@ -128,21 +200,24 @@ pub fn leave_server() {
 }
 
 /// Wait for a joined game to start.
-pub fn wait_game_start() -> Option<LanSocket> {
+pub fn wait_game_start() -> Result<Option<LanSocket>, NetworkError> {
     while let Ok(message) = LAN_MATCHMAKER.try_recv() {
         match message {
-            LanMatchmakerResponse::ServerStarted | LanMatchmakerResponse::PlayerCount(_) => {}
+            LanMatchmakerResponse::ServerStarted
+            | LanMatchmakerResponse::PlayerCount(_)
+            | LanMatchmakerResponse::SpectatorCount(_) => {}
             LanMatchmakerResponse::GameStarting {
                 lan_socket,
-                player_idx,
+                client_idx,
                 player_count: _,
             } => {
-                info!(?player_idx, "Starting network game");
-                return Some(lan_socket);
+                info!(?client_idx, "Starting network game");
+                return Ok(Some(lan_socket));
             }
+            LanMatchmakerResponse::Error(err) => return Err(err),
         }
     }
-    None
+    Ok(None)
 }
 
 /// Update server pings and turn on service discovery.
@@ -157,7 +232,7 @@ pub fn prepare_to_join(
             .try_send(
                 servers
                     .iter()
-                    .map(|x| *x.service.get_addresses().iter().next().unwrap())
+                    .filter_map(|x| Some(x.addr()?.ip()))
                     .collect(),
             )
             .ok();
@@ -165,7 +240,7 @@ pub fn prepare_to_join(
     if let Ok(pings) = PINGER.try_recv() {
         for (server, ping) in pings {
             for info in servers.iter_mut() {
-                if info.service.get_addresses().contains(&server) {
+                if info.addr().map(|x| x.ip()) == Some(server) {
                     info.ping = ping;
                 }
             }
@@ -191,6 +266,20 @@ pub fn prepare_to_join(
     }
 }
 
+/// Create the MDNS service info for a server hosted on `port`.
+fn create_service_info(service_name: &str, port: u16) -> ServiceInfo {
+    mdns_sd::ServiceInfo::new(
+        MDNS_SERVICE_TYPE,
+        service_name,
+        service_name,
+        "",
+        port,
+        None,
+    )
+    .unwrap()
+    .enable_addr_auto()
+}
+
 /// Get the current host info or create a new one. When there's an existing
 /// service but its `service_name` is different, the service is recreated and
 /// only then the returned `bool` is `true`.
@@ -198,18 +287,10 @@ pub fn prepare_to_host<'a>(
     host_info: &'a mut Option<ServiceInfo>,
     service_name: &str,
 ) -> (bool, &'a mut ServiceInfo) {
+    // The port is updated when the server is started, in case binding the endpoint fails here.
     let create_service_info = || {
-        let port = NETWORK_ENDPOINT.local_addr().unwrap().port();
-        mdns_sd::ServiceInfo::new(
-            MDNS_SERVICE_TYPE,
-            service_name,
-            service_name,
-            "",
-            port,
-            None,
-        )
-        .unwrap()
-        .enable_addr_auto()
+        let port = endpoint_addr().map(|x| x.port()).unwrap_or_default();
+        create_service_info(service_name, port)
     };
 
     let service_info = host_info.get_or_insert_with(create_service_info);
@@ -223,6 +304,36 @@ pub fn prepare_to_host<'a>(
     (is_recreated, service_info)
 }
 
+/// Message sent between the host and the other players during LAN matchmaking.
+#[derive(Serialize, Deserialize)]
+enum MatchmakerNetMsg {
+    /// Sent to the host when connecting to it.
+    Join {
+        /// The number of players playing on the joining client. Spectators join with no players.
+        local_player_count: usize,
+    },
+    /// Sent from the host to the other clients when the match is ready.
+    MatchReady {
+        /// The peers they have for the match, with the index in the array being the client index of the peer.
+        peers: [Option<SocketAddr>; MAX_PLAYERS],
+        /// The client index of the client getting the message.
+        client_idx: usize,
+        /// The number of players on each client.
+        client_player_counts: [usize; MAX_PLAYERS],
+        spectator_count: usize,
+    },
+    /// Sent from the host to the spectators when the match is ready.
+    ///
+    /// Spectators only ever connect to the host.
+    SpectateReady {
+        /// The spectator index of the spectator getting the message.
+        spectator_idx: usize,
+        /// The number of players on each client.
+        client_player_counts: [usize; MAX_PLAYERS],
+        spectator_count: usize,
+    },
+}
+
 /// Implementation of the lan matchmaker task.
 ///
 /// This is a long-running tasks that listens for messages sent through the [`LAN_MATCHMAKER`]
@@ -230,233 +341,399 @@ pub fn prepare_to_host<'a>(
 async fn lan_matchmaker(
     matchmaker_channel: BiChannelServer<LanMatchmakerRequest, LanMatchmakerResponse>,
 ) {
-    #[derive(Serialize, Deserialize)]
-    enum MatchmakerNetMsg {
-        MatchReady {
-            /// The peers they have for the match, with the index in the array being the player index of the peer.
-            peers: [Option<SocketAddrV4>; MAX_PLAYERS],
-            /// The player index of the player getting the message.
-            player_idx: usize,
-            player_count: usize,
-        },
-    }
-
     while let Ok(request) = matchmaker_channel.recv().await {
-        match request {
+        let result = match request {
             // Start server
-            LanMatchmakerRequest::StartServer { mut player_count } => {
-                info!("Starting LAN server");
-                matchmaker_channel
-                    .send(LanMatchmakerResponse::ServerStarted)
-                    .await
-                    .unwrap();
-
-                let mut connections = Vec::new();
-
-                loop {
-                    let next_request = async { either::Left(matchmaker_channel.recv().await) };
-                    let next_conn = async { either::Right(NETWORK_ENDPOINT.accept().await) };
-
-                    match next_request.or(next_conn).await {
-                        // Handle more matchmaker requests
-                        either::Either::Left(next_request) => {
-                            let Ok(next_request) = next_request else { break; };
-
-                            match next_request {
-                                LanMatchmakerRequest::StartServer {
-                                    player_count: new_player_count,
-                                } => {
-                                    connections.clear();
-                                    player_count = new_player_count;
-                                }
-                                LanMatchmakerRequest::StopServer => {
-                                    break;
-                                }
-                                LanMatchmakerRequest::StopJoin => {} // Not joining, so don't do anything
-                                LanMatchmakerRequest::JoinServer { .. } => {
-                                    error!("Cannot join server while hosting server");
-                                }
-                            }
-                        }
+            LanMatchmakerRequest::StartServer {
+                player_count,
+                local_player_count,
+            } => host_match(&matchmaker_channel, player_count, local_player_count).await,
+            // Server not running or joining so do nothing
+            LanMatchmakerRequest::StopServer => Ok(()),
+            LanMatchmakerRequest::StopJoin => Ok(()),
 
-                        // Handle new connections
-                        either::Either::Right(Some(new_connection)) => {
-                            let Some(conn) = new_connection.await.ok() else { continue };
-                            connections.push(conn);
-                            let current_players = connections.len() + 1;
-                            info!(%current_players, "New player connection");
-                        }
-                        _ => (),
+            // Join a hosted match
+            LanMatchmakerRequest::JoinServer {
+                addr,
+                local_player_count,
+            } => join_match(&matchmaker_channel, addr, local_player_count).await,
+        };
+
+        if let Err(err) = result {
+            error!(%err, "LAN matchmaking failed");
+            matchmaker_channel
+                .try_send(LanMatchmakerResponse::Error(err))
+                .ok();
+        }
+    }
+}
+
+/// Wait for players to connect to our server, and start the match once there are enough of them.
+async fn host_match(
+    matchmaker_channel: &BiChannelServer<LanMatchmakerRequest, LanMatchmakerResponse>,
+    mut player_count: usize,
+    mut local_player_count: usize,
+) -> Result<(), NetworkError> {
+    info!("Starting LAN server");
+    let endpoint = network_endpoint()?;
+    matchmaker_channel
+        .try_send(LanMatchmakerResponse::ServerStarted)
+        .ok();
+
+    // The connections to the other clients, with their number of players
+    let mut connections: Vec<(quinn::Connection, usize)> = Vec::new();
+    let mut spectators = Vec::new();
+
+    loop {
+        let next_request = async { either::Left(matchmaker_channel.recv().await) };
+        let next_conn = async { either::Right(endpoint.accept().await) };
+
+        match next_request.or(next_conn).await {
+            // Handle more matchmaker requests
+            either::Either::Left(next_request) => {
+                let Ok(next_request) = next_request else { break; };
+
+                match next_request {
+                    LanMatchmakerRequest::StartServer {
+                        player_count: new_player_count,
+                        local_player_count: new_local_player_count,
+                    } => {
+                        connections.clear();
+                        spectators.clear();
+                        player_count = new_player_count;
+                        local_player_count = new_local_player_count;
                     }
+                    LanMatchmakerRequest::StopServer => {
+                        break;
+                    }
+                    LanMatchmakerRequest::StopJoin => {} // Not joining, so don't do anything
+                    LanMatchmakerRequest::JoinServer { .. } => {
+                        error!("Cannot join server while hosting server");
+                    }
+                }
+            }
 
-                    // Discard closed connections
-                    connections.retain(|conn| {
-                        if conn.close_reason().is_some() {
-                            info!("Player closed connection");
-                            false
-                        } else {
-                            true
-                        }
-                    });
-
-                    let current_players = connections.len();
-                    let target_players = player_count;
-                    info!(%current_players, %target_players);
-
-                    // If we're ready to start a match
-                    if connections.len() == player_count - 1 {
-                        info!("All players joined.");
-
-                        // Tell all clients we're ready
-                        for (i, conn) in connections.iter().enumerate() {
-                            let mut peers = [None; MAX_PLAYERS];
-                            connections
-                                .iter()
-                                .enumerate()
-                                .filter(|x| x.0 != i)
-                                .for_each(|(i, conn)| {
-                                    if let SocketAddr::V4(addr) = conn.remote_address() {
-                                        peers[i + 1] = Some(addr);
-                                    } else {
-                                        unreachable!("IPV6 not supported in LAN matchmaking");
-                                    };
-                                });
-
-                            let mut uni = conn.open_uni().await.unwrap();
-                            uni.write_all(
-                                &postcard::to_vec::<_, 20>(&MatchmakerNetMsg::MatchReady {
-                                    player_idx: i + 1,
-                                    peers,
-                                    player_count,
-                                })
-                                .unwrap(),
-                            )
-                            .await
-                            .unwrap();
-                            uni.finish().await.unwrap();
+            // Handle new connections
+            either::Either::Right(Some(new_connection)) => {
+                let conn = match new_connection.await {
+                    Ok(conn) => conn,
+                    Err(e) => {
+                        warn!("Player failed to connect: {e}");
+                        continue;
+                    }
+                };
+
+                // Find out how many players they have, if they don't just want to watch
+                let join = async {
+                    let mut uni = conn.accept_uni().await?;
+                    let bytes = uni.read_to_end(256).await?;
+                    Ok::<_, NetworkError>(postcard::from_bytes(&bytes)?)
+                };
+                match join.await {
+                    Ok(MatchmakerNetMsg::Join {
+                        local_player_count: 0,
+                    }) => {
+                        spectators.push(conn);
+                        let current_spectators = spectators.len();
+                        info!(%current_spectators, "New spectator connection");
+                    }
+                    Ok(MatchmakerNetMsg::Join {
+                        local_player_count: client_players,
+                    }) => {
+                        let current_players = local_player_count
+                            + connections.iter().map(|x| x.1).sum::<usize>()
+                            + client_players;
+                        if current_players > player_count {
+                            info!(%client_players, "Rejecting client, the match is full");
+                            conn.close(0u8.into(), b"The match is full");
+                            continue;
                         }
+                        connections.push((conn, client_players));
+                        info!(%current_players, "New player connection");
+                    }
+                    Ok(_) => {
+                        warn!("Ignoring connection with invalid join message");
+                        conn.close(0u8.into(), &[]);
+                        continue;
+                    }
+                    Err(e) => {
+                        warn!("Player failed to join: {e}");
+                        continue;
+                    }
+                }
+            }
+            _ => (),
+        }
 
-                        // Collect the list of client connections
-                        let connections = std::array::from_fn(|i| {
-                            if i == 0 {
-                                None
-                            } else {
-                                connections.get(i - 1).cloned()
-                            }
-                        });
-
-                        // Send the connections to the game so that it can start the network match.
-                        matchmaker_channel
-                            .try_send(LanMatchmakerResponse::GameStarting {
-                                lan_socket: LanSocket::new(0, connections),
-                                player_idx: 0,
-                                player_count,
-                            })
-                            .ok();
-                        info!(player_idx=0, %player_count, "Matchmaking finished");
+        // Discard closed connections
+        connections.retain(|(conn, _)| {
+            if conn.close_reason().is_some() {
+                info!("Player closed connection");
+                false
+            } else {
+                true
+            }
+        });
+        spectators.retain(|conn| {
+            if conn.close_reason().is_some() {
+                info!("Spectator closed connection");
+                false
+            } else {
+                true
+            }
+        });
 
-                        // Break out of the server loop
-                        break;
+        let current_players = local_player_count + connections.iter().map(|x| x.1).sum::<usize>();
+        let target_players = player_count;
+        info!(%current_players, %target_players);
 
-                    // If we don't have enough players yet, send the updated player count to the game.
-                    } else if matchmaker_channel
-                        .try_send(LanMatchmakerResponse::PlayerCount(current_players))
-                        .is_err()
-                    {
-                        break;
-                    }
+        // If we're ready to start a match
+        if current_players == player_count {
+            info!("All players joined.");
+
+            let mut client_player_counts = [0; MAX_PLAYERS];
+            client_player_counts[0] = local_player_count;
+            for (i, (_, count)) in connections.iter().enumerate() {
+                client_player_counts[i + 1] = *count;
+            }
+
+            // Tell all clients we're ready
+            for (i, (conn, _)) in connections.iter().enumerate() {
+                let mut peers = [None; MAX_PLAYERS];
+                for (j, (peer, _)) in connections.iter().enumerate().filter(|x| x.0 != i) {
+                    peers[j + 1] = Some(canonical_addr(peer.remote_address()));
                 }
 
-                // Once we are done with server matchmaking
+                let mut uni = conn.open_uni().await?;
+                uni.write_all(&postcard::to_allocvec(&MatchmakerNetMsg::MatchReady {
+                    client_idx: i + 1,
+                    peers,
+                    client_player_counts,
+                    spectator_count: spectators.len(),
+                })?)
+                .await?;
+                uni.finish().await?;
             }
-            // Server not running or joining so do nothing
-            LanMatchmakerRequest::StopServer => (),
-            LanMatchmakerRequest::StopJoin => (),
 
-            // Join a hosted match
-            LanMatchmakerRequest::JoinServer { ip, port } => {
-                let conn = NETWORK_ENDPOINT
-                    .connect((ip, port).into(), "jumpy-host")
-                    .unwrap()
-                    .await
-                    .expect("Could not connect to server");
-
-                // Wait for match to start
-                let mut uni = conn.accept_uni().await.unwrap();
-                let bytes = uni.read_to_end(20).await.unwrap();
-                let message: MatchmakerNetMsg = postcard::from_bytes(&bytes).unwrap();
-
-                match message {
-                    MatchmakerNetMsg::MatchReady {
-                        peers: peer_addrs,
-                        player_idx,
-                        player_count,
-                    } => {
-                        info!(%player_count, %player_idx, ?peer_addrs, "Matchmaking finished");
-                        let mut peer_connections = std::array::from_fn(|_| None);
-
-                        // Set the connection to the matchmaker for player 0
-                        peer_connections[0] = Some(conn.clone());
-
-                        // For every peer with a player index that is higher than ours, wait for
-                        // them to connect to us.
-                        let range = (player_idx + 1)..player_count;
-                        info!(players=?range, "Waiting for {} peer connections", range.len());
-                        for _ in range {
-                            // Wait for connection
-                            let conn = NETWORK_ENDPOINT
-                                .accept()
-                                .await
-                                .unwrap()
-                                .await
-                                .expect("Could not accept incomming connection");
-
-                            // Receive the player index
-                            let idx = {
-                                let mut buf = [0; 1];
-                                let mut channel = conn.accept_uni().await.unwrap();
-                                channel.read_exact(&mut buf).await.unwrap();
-
-                                buf[0] as usize
-                            };
-                            assert!(idx < MAX_PLAYERS, "Invalid player index");
-
-                            peer_connections[idx] = Some(conn);
-                        }
+            // Tell all spectators we're ready
+            for (i, conn) in spectators.iter().enumerate() {
+                let mut uni = conn.open_uni().await?;
+                uni.write_all(&postcard::to_allocvec(&MatchmakerNetMsg::SpectateReady {
+                    spectator_idx: i,
+                    client_player_counts,
+                    spectator_count: spectators.len(),
+                })?)
+                .await?;
+                uni.finish().await?;
+            }
 
-                        // For every peer with a player index lower than ours, connect to them.
-                        let range = 1..player_idx;
-                        info!(players=?range, "Connecting to {} peers", range.len());
-                        for i in range {
-                            let addr = peer_addrs[i].unwrap();
-                            let conn = NETWORK_ENDPOINT
-                                .connect(addr.into(), "jumpy-peer")
-                                .unwrap()
-                                .await
-                                .expect("Could not connect to peer");
-
-                            // Send player index
-                            let mut channel = conn.open_uni().await.unwrap();
-                            channel.write(&[player_idx as u8]).await.unwrap();
-                            channel.finish().await.unwrap();
-
-                            peer_connections[i] = Some(conn);
-                        }
+            // Collect the list of client connections
+            let connections = std::array::from_fn(|i| {
+                if i == 0 {
+                    None
+                } else {
+                    connections.get(i - 1).map(|x| x.0.clone())
+                }
+            });
+            let spectator_count = spectators.len();
+
+            // Send the connections to the game so that it can start the network match.
+            matchmaker_channel
+                .try_send(LanMatchmakerResponse::GameStarting {
+                    lan_socket: LanSocket::new(
+                        0,
+                        client_player_counts,
+                        connections,
+                        spectators,
+                        spectator_count,
+                    ),
+                    client_idx: 0,
+                    player_count,
+                })
+                .ok();
+            info!(client_idx=0, %player_count, "Matchmaking finished");
 
-                        let lan_socket = LanSocket::new(player_idx, peer_connections);
-                        info!("Connections established.");
+            // Break out of the server loop
+            break;
 
-                        matchmaker_channel
-                            .try_send(LanMatchmakerResponse::GameStarting {
-                                lan_socket,
-                                player_idx,
-                                player_count,
-                            })
-                            .ok();
-                    }
+        // If we don't have enough players yet, send the updated player and spectator counts to the
+        // game.
+        } else if matchmaker_channel
+            .try_send(LanMatchmakerResponse::PlayerCount(current_players))
+            .is_err()
+            || matchmaker_channel
+                .try_send(LanMatchmakerResponse::SpectatorCount(spectators.len()))
+                .is_err()
+        {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Join the server hosted at the given address, and connect to the other clients once the host
+/// starts the match.
+///
+/// Spectators, which join with no local players, only stay connected to the host.
+async fn join_match(
+    matchmaker_channel: &BiChannelServer<LanMatchmakerRequest, LanMatchmakerResponse>,
+    addr: SocketAddr,
+    local_player_count: usize,
+) -> Result<(), NetworkError> {
+    info!(%addr, "Joining LAN server");
+    let conn = certs::ServerVerifier::lan_host(canonical_addr(addr).ip())
+        .connect(addr, "jumpy-host")
+        .await?;
+
+    // Tell the host how many players we have
+    let mut uni = conn.open_uni().await?;
+    uni.write_all(&postcard::to_allocvec(&MatchmakerNetMsg::Join {
+        local_player_count,
+    })?)
+    .await?;
+    uni.finish().await?;
+
+    // Wait for match to start
+    let mut uni = conn.accept_uni().await?;
+    let bytes = uni.read_to_end(256).await?;
+    let message: MatchmakerNetMsg = postcard::from_bytes(&bytes)?;
+
+    match message {
+        MatchmakerNetMsg::SpectateReady {
+            spectator_idx,
+            client_player_counts,
+            spectator_count,
+        } => {
+            info!(?client_player_counts, %spectator_idx, "Matchmaking finished");
+            let player_count = client_player_counts.iter().sum::<usize>();
+            if player_count > MAX_PLAYERS || spectator_idx >= spectator_count {
+                return Err(NetworkError::InvalidMessage(format!(
+                    "Invalid spectator index {spectator_idx} for {player_count} players"
+                )));
+            }
+            let mut connections = std::array::from_fn(|_| None);
+            connections[0] = Some(conn);
+
+            let client_idx = spectator_addr(spectator_idx);
+            matchmaker_channel
+                .try_send(LanMatchmakerResponse::GameStarting {
+                    lan_socket: LanSocket::new(
+                        client_idx,
+                        client_player_counts,
+                        connections,
+                        Vec::new(),
+                        spectator_count,
+                    ),
+                    client_idx,
+                    player_count,
+                })
+                .ok();
+        }
+        MatchmakerNetMsg::MatchReady {
+            peers: peer_addrs,
+            client_idx,
+            client_player_counts,
+            spectator_count,
+        } => {
+            info!(?client_player_counts, %client_idx, ?peer_addrs, "Matchmaking finished");
+            let client_count = client_player_counts.iter().take_while(|x| **x > 0).count();
+            let player_count = client_player_counts.iter().sum::<usize>();
+            if client_idx >= client_count || player_count > MAX_PLAYERS {
+                return Err(NetworkError::InvalidMessage(format!(
+                    "Invalid client index {client_idx} for {client_count} clients"
+                )));
+            }
+            let mut peer_connections = std::array::from_fn(|_| None);
+
+            // Set the connection to the matchmaker for client 0
+            peer_connections[0] = Some(conn.clone());
+
+            // For every peer with a client index that is higher than ours, wait for
+            // them to connect to us.
+            let range = (client_idx + 1)..client_count;
+            info!(clients=?range, "Waiting for {} peer connections", range.len());
+            for _ in range {
+                // Wait for connection
+                let conn = network_endpoint()?
+                    .accept()
+                    .await
+                    .ok_or_else(|| NetworkError::Service("Network endpoint closed".into()))?
+                    .await
+                    .map_err(|e| NetworkError::ConnectionFailed(e.to_string()))?;
+
+                // Receive the client index
+                let idx = {
+                    let mut buf = [0; 1];
+                    let mut channel = conn.accept_uni().await?;
+                    channel.read_exact(&mut buf).await?;
+
+                    buf[0] as usize
+                };
+                if idx >= MAX_PLAYERS {
+                    return Err(NetworkError::InvalidMessage(format!(
+                        "Invalid client index {idx} from peer"
+                    )));
                 }
+
+                peer_connections[idx] = Some(conn);
             }
+
+            // For every peer with a client index lower than ours, connect to them.
+            let range = 1..client_idx;
+            info!(clients=?range, "Connecting to {} peers", range.len());
+            for i in range {
+                let addr = peer_addrs[i].ok_or_else(|| {
+                    NetworkError::InvalidMessage(format!("Missing address for client {i}"))
+                })?;
+                let conn = certs::ServerVerifier::lan_host(canonical_addr(addr).ip())
+                    .connect(addr, "jumpy-peer")
+                    .await?;
+
+                // Send client index
+                let mut channel = conn.open_uni().await?;
+                channel.write_all(&[client_idx as u8]).await?;
+                channel.finish().await?;
+
+                peer_connections[i] = Some(conn);
+            }
+
+            let lan_socket = LanSocket::new(
+                client_idx,
+                client_player_counts,
+                peer_connections,
+                Vec::new(),
+                spectator_count,
+            );
+            info!("Connections established.");
+
+            matchmaker_channel
+                .try_send(LanMatchmakerResponse::GameStarting {
+                    lan_socket,
+                    client_idx,
+                    player_count,
+                })
+                .ok();
         }
+        MatchmakerNetMsg::Join { .. } => {
+            return Err(NetworkError::InvalidMessage(
+                "Unexpected join message from host".into(),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Convert IPv4-mapped IPv6 addresses, which IPv4 peers have on our dual-stack socket, back to
+/// IPv4 addresses, so that peers that could only bind an IPv4 socket can still connect to them.
+fn canonical_addr(addr: SocketAddr) -> SocketAddr {
+    match addr {
+        SocketAddr::V6(v6) => match v6.ip().to_ipv4_mapped() {
+            Some(ip) => SocketAddr::new(ip.into(), v6.port()),
+            None => addr,
+        },
+        SocketAddr::V4(_) => addr,
     }
 }
 
@@ -467,8 +744,14 @@ pub struct LanMatchmaker(BiChannelClient<LanMatchmakerRequest, LanMatchmakerResp
 /// A request that may be sent to the [`LAN_MATCHMAKER`].
 #[derive(Debug)]
 pub enum LanMatchmakerRequest {
-    StartServer { player_count: usize },
-    JoinServer { ip: Ipv4Addr, port: u16 },
+    StartServer {
+        player_count: usize,
+        local_player_count: usize,
+    },
+    JoinServer {
+        addr: SocketAddr,
+        local_player_count: usize,
+    },
     StopServer,
     StopJoin,
 }
@@ -476,152 +759,186 @@ pub enum LanMatchmakerRequest {
 /// A response that may come from the [`LAN_MATCHMAKER`].
 pub enum LanMatchmakerResponse {
     ServerStarted,
+    /// The number of players that have joined so far, including the host's local players.
     PlayerCount(usize),
+    SpectatorCount(usize),
     GameStarting {
         lan_socket: LanSocket,
-        player_idx: usize,
+        client_idx: usize,
         player_count: usize,
     },
+    /// Matchmaking failed, and the server was stopped or the join was canceled.
+    Error(NetworkError),
 }
 
 /// The LAN [`NetworkSocket`] implementation.
 #[derive(Debug, Clone)]
 pub struct LanSocket {
     pub connections: [Option<quinn::Connection>; MAX_PLAYERS],
+    /// The connections to the spectators, if we are the host.
+    pub spectators: Vec<quinn::Connection>,
     pub ggrs_receiver: async_channel::Receiver<(usize, ggrs::Message)>,
     pub reliable_receiver: async_channel::Receiver<(usize, Vec<u8>)>,
-    pub player_idx: usize,
-    pub player_count: usize,
+    pub error_receiver: async_channel::Receiver<NetworkError>,
+    pub client_idx: usize,
+    pub client_player_counts: [usize; MAX_PLAYERS],
+    pub spectator_count: usize,
 }
 
 impl LanSocket {
-    pub fn new(player_idx: usize, connections: [Option<quinn::Connection>; MAX_PLAYERS]) -> Self {
+    pub fn new(
+        client_idx: usize,
+        client_player_counts: [usize; MAX_PLAYERS],
+        connections: [Option<quinn::Connection>; MAX_PLAYERS],
+        spectators: Vec<quinn::Connection>,
+        spectator_count: usize,
+    ) -> Self {
         let (ggrs_sender, ggrs_receiver) = async_channel::unbounded();
         let (reliable_sender, reliable_receiver) = async_channel::unbounded();
-
-        let pool = bevy::tasks::IoTaskPool::get();
-
-        // Spawn tasks to receive network messages from each peer
-        #[allow(clippy::needless_range_loop)]
-        for i in 0..MAX_PLAYERS {
-            if let Some(conn) = connections[i].clone() {
-                let ggrs_sender = ggrs_sender.clone();
-
-                // Unreliable message receiver
-                let conn_ = conn.clone();
-                pool.spawn(async move {
-                    let conn = conn_;
-
-                    #[cfg(feature = "debug-network-slowdown")]
-                    use turborand::prelude::*;
-                    #[cfg(feature = "debug-network-slowdown")]
-                    let rng = AtomicRng::new();
-
-                    loop {
-                        let event =
-                            future::or(async { either::Left(conn.closed().await) }, async {
-                                either::Right(conn.read_datagram().await)
-                            })
-                            .await;
-
-                        match event {
-                            either::Either::Left(closed) => {
-                                warn!("Connection error: {closed}");
-                                break;
-                            }
-                            either::Either::Right(datagram_result) => match datagram_result {
-                                Ok(data) => {
-                                    let message: ggrs::Message = postcard::from_bytes(&data)
-                                        .expect("Could not deserialize net message");
-
-                                    // Debugging code to introduce artificial latency
-                                    #[cfg(feature = "debug-network-slowdown")]
-                                    {
-                                        use async_timer::Oneshot;
-                                        async_timer::oneshot::Timer::new(
-                                            std::time::Duration::from_millis(
-                                                (rng.f32_normalized() * 100.0) as u64 + 1,
-                                            ),
-                                        )
-                                        .await;
-                                    }
-                                    if ggrs_sender.send((i, message)).await.is_err() {
-                                        break;
-                                    }
-                                }
-                                Err(e) => {
-                                    warn!("Connection error: {e}");
-                                }
-                            },
-                        }
-                    }
-                })
-                .detach();
-
-                // Reliable message receiver
-                let reliable_sender = reliable_sender.clone();
-                pool.spawn(async move {
-                    #[cfg(feature = "debug-network-slowdown")]
-                    use turborand::prelude::*;
-                    #[cfg(feature = "debug-network-slowdown")]
-                    let rng = AtomicRng::new();
-
-                    loop {
-                        let event =
-                            future::or(async { either::Left(conn.closed().await) }, async {
-                                either::Right(conn.accept_uni().await)
-                            })
-                            .await;
-
-                        match event {
-                            either::Either::Left(closed) => {
-                                warn!("Connection error: {closed}");
-                                break;
-                            }
-                            either::Either::Right(result) => match result {
-                                Ok(mut stream) => {
-                                    let data =
-                                        stream.read_to_end(4096).await.expect("Network read error");
-
-                                    // Debugging code to introduce artificial latency
-                                    #[cfg(feature = "debug-network-slowdown")]
-                                    {
-                                        use async_timer::Oneshot;
-                                        async_timer::oneshot::Timer::new(
-                                            std::time::Duration::from_millis(
-                                                (rng.f32_normalized() * 100.0) as u64 + 1,
-                                            ),
-                                        )
-                                        .await;
-                                    }
-                                    if reliable_sender.send((i, data)).await.is_err() {
-                                        break;
-                                    }
-                                }
-                                Err(e) => {
-                                    warn!("Connection error: {e}");
-                                }
-                            },
-                        }
-                    }
-                })
-                .detach();
-            }
+        let (error_sender, error_receiver) = async_channel::unbounded();
+
+        // Spawn tasks to receive network messages from each peer. Disconnects are reported for the
+        // first player of the client, and spectators leaving doesn't affect the match.
+        let peers = connections.iter().enumerate().filter_map(|(i, conn)| {
+            let first_player = client_player_counts[0..i].iter().sum();
+            Some((i, Some(first_player), conn.clone()?))
+        });
+        let spectator_peers = spectators
+            .iter()
+            .enumerate()
+            .map(|(i, conn)| (spectator_addr(i), None, conn.clone()));
+        for (addr, disconnect_player, conn) in peers.chain(spectator_peers) {
+            spawn_receivers(
+                addr,
+                disconnect_player,
+                conn,
+                ggrs_sender.clone(),
+                reliable_sender.clone(),
+                error_sender.clone(),
+            );
         }
 
         Self {
-            player_idx,
-            player_count: connections.iter().flatten().count() + 1,
+            client_idx,
+            client_player_counts,
+            spectator_count,
             connections,
+            spectators,
             ggrs_receiver,
             reliable_receiver,
+            error_receiver,
         }
     }
+
+    /// Get the connection to the client or spectator with the given address.
+    fn connection(&self, addr: usize) -> Option<&quinn::Connection> {
+        if addr < MAX_PLAYERS {
+            self.connections[addr].as_ref()
+        } else {
+            self.spectators.get(addr - MAX_PLAYERS)
+        }
+    }
+}
+
+/// Spawn the tasks receiving the network messages from the peer with the given address.
+///
+/// If the peer disconnects, a [`NetworkError::PlayerDisconnected`] error is reported for
+/// `disconnect_player`.
+fn spawn_receivers(
+    addr: usize,
+    disconnect_player: Option<usize>,
+    conn: quinn::Connection,
+    ggrs_sender: async_channel::Sender<(usize, ggrs::Message)>,
+    reliable_sender: async_channel::Sender<(usize, Vec<u8>)>,
+    error_sender: async_channel::Sender<NetworkError>,
+) {
+    let pool = bevy::tasks::IoTaskPool::get();
+
+    // Unreliable message receiver
+    let conn_ = conn.clone();
+    pool.spawn(async move {
+        let conn = conn_;
+
+        loop {
+            let event = future::or(async { either::Left(conn.closed().await) }, async {
+                either::Right(conn.read_datagram().await)
+            })
+            .await;
+
+            match event {
+                either::Either::Left(closed) => {
+                    warn!("Connection error: {closed}");
+                    break;
+                }
+                either::Either::Right(datagram_result) => match datagram_result {
+                    Ok(data) => {
+                        let Ok(message) = postcard::from_bytes::<ggrs::Message>(&data) else {
+                            warn!(player=%addr, "Ignoring invalid network message");
+                            continue;
+                        };
+
+                        if ggrs_sender.send((addr, message)).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        warn!("Connection error: {e}");
+                    }
+                },
+            }
+        }
+    })
+    .detach();
+
+    // Reliable message receiver
+    pool.spawn(async move {
+        loop {
+            let event = future::or(async { either::Left(conn.closed().await) }, async {
+                either::Right(conn.accept_uni().await)
+            })
+            .await;
+
+            match event {
+                either::Either::Left(closed) => {
+                    warn!("Connection error: {closed}");
+                    if let Some(player) = disconnect_player
+                        .filter(|_| !matches!(closed, quinn::ConnectionError::LocallyClosed))
+                    {
+                        error_sender
+                            .try_send(NetworkError::PlayerDisconnected(player))
+                            .ok();
+                    }
+                    break;
+                }
+                either::Either::Right(result) => match result {
+                    Ok(mut stream) => {
+                        let data = match stream.read_to_end(4096).await {
+                            Ok(data) => data,
+                            Err(e) => {
+                                warn!(player=%addr, "Network read error: {e}");
+                                continue;
+                            }
+                        };
+
+                        if reliable_sender.send((addr, data)).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        warn!("Connection error: {e}");
+                    }
+                },
+            }
+        }
+    })
+    .detach();
 }
 
 impl ggrs::NonBlockingSocket<usize> for LanSocket {
     fn send_to(&mut self, msg: &ggrs::Message, addr: &usize) {
-        let conn = self.connections[*addr].as_ref().unwrap();
+        let Some(conn) = self.connection(*addr) else {
+            return;
+        };
 
         // TODO: determine a reasonable size for this buffer.
         let msg_bytes = postcard::to_allocvec(msg).unwrap();
@@ -643,32 +960,33 @@ impl NetworkSocket for LanSocket {
         let task_pool = IoTaskPool::get();
         let message = Bytes::copy_from_slice(message);
 
-        match target {
-            SocketTarget::Player(i) => {
-                let conn = self.connections[i].as_ref().unwrap().clone();
-
-                task_pool
-                    .spawn(async move {
-                        let mut stream = conn.open_uni().await.unwrap();
-                        stream.write_chunk(message).await.unwrap();
-                        stream.finish().await.unwrap();
-                    })
-                    .detach();
-            }
-            SocketTarget::All => {
-                for conn in &self.connections {
-                    if let Some(conn) = conn.clone() {
-                        let message = message.clone();
-                        task_pool
-                            .spawn(async move {
-                                let mut stream = conn.open_uni().await.unwrap();
-                                stream.write_chunk(message).await.unwrap();
-                                stream.finish().await.unwrap();
-                            })
-                            .detach();
+        let connections: SmallVec<[_; MAX_PLAYERS]> = match target {
+            SocketTarget::Client(i) => self.connection(i).cloned().into_iter().collect(),
+            SocketTarget::All => self
+                .connections
+                .iter()
+                .flatten()
+                .chain(&self.spectators)
+                .cloned()
+                .collect(),
+        };
+
+        for conn in connections {
+            let message = message.clone();
+            task_pool
+                .spawn(async move {
+                    let result = async {
+                        let mut stream = conn.open_uni().await?;
+                        stream.write_chunk(message).await?;
+                        stream.finish().await?;
+
+                        Ok::<_, NetworkError>(())
+                    };
+                    if let Err(e) = result.await {
+                        warn!("Couldn't send reliable network message: {e}");
                     }
-                }
-            }
+                })
+                .detach();
         }
     }
 
@@ -685,21 +1003,29 @@ impl NetworkSocket for LanSocket {
     }
 
     fn close(&self) {
-        for conn in self.connections.iter().flatten() {
+        for conn in self.connections.iter().flatten().chain(&self.spectators) {
             conn.close(0u8.into(), &[]);
         }
     }
 
-    fn player_idx(&self) -> usize {
-        self.player_idx
+    fn client_idx(&self) -> usize {
+        self.client_idx
     }
 
-    fn player_count(&self) -> usize {
-        self.player_count
+    fn client_player_counts(&self) -> [usize; MAX_PLAYERS] {
+        self.client_player_counts
     }
 
-    fn player_is_local(&self) -> [bool; MAX_PLAYERS] {
-        std::array::from_fn(|i| self.connections[i].is_none() && i < self.player_count)
+    fn spectator_count(&self) -> usize {
+        self.spectator_count
+    }
+
+    fn spectator_addrs(&self) -> Vec<usize> {
+        (0..self.spectators.len()).map(spectator_addr).collect()
+    }
+
+    fn take_error(&self) -> Option<NetworkError> {
+        self.error_receiver.try_recv().ok()
     }
 }
 
@@ -708,12 +1034,8 @@ fn pinger(server: BiChannelServer<PingerRequest, PingerResponse>) {
         let mut pings = SmallVec::new();
         for server in servers {
             let start = Instant::now();
-            let ping_result = ping_rs::send_ping(
-                &IpAddr::V4(server),
-                Duration::from_secs(2),
-                &[1, 2, 3, 4],
-                None,
-            );
+            let ping_result =
+                ping_rs::send_ping(&server, Duration::from_secs(2), &[1, 2, 3, 4], None);
 
             let ping = if let Err(e) = ping_result {
                 warn!("Error pinging {server}: {e:?}");
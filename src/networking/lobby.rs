@@ -0,0 +1,433 @@
+//! The lobby of a network match, where the players chat and get ready while the host picks the
+//! rules of the match.
+//!
+//! The lobby comes between the player selection and the map selection. Every client toggles
+//! whether it is ready, and once they all are, the host starts the match and goes on to pick the
+//! map from the map pool.
+//!
+//! The host may also kick clients. Kicked clients are hidden from the rest of the match by the
+//! [`LobbySocket`] that every [`NetworkMatchSocket`] is wrapped in: it reports them as having no
+//! players, so the remaining players are numbered as if they had never joined, and it ignores
+//! their messages and their disconnection.
+//!
+//! [`NetworkMatchSocket`]: super::NetworkMatchSocket
+
+use std::sync::RwLock;
+
+use super::{
+    proto::{map_vote_winner, LobbyMessage, MapSelectMessage, MatchRules, QuickChat},
+    *,
+};
+
+/// The longest chat message that may be sent, in characters.
+pub const MAX_CHAT_MESSAGE_LEN: usize = 120;
+
+/// The number of chat messages that are kept in the [`Lobby`].
+const MAX_CHAT_LINES: usize = 50;
+
+/// Whether or not each client was kicked, shared between the [`Lobby`] and its [`LobbySocket`].
+type KickedClients = Arc<RwLock<[bool; MAX_PLAYERS]>>;
+
+/// A [`NetworkSocket`] that hides the clients that were kicked from the match.
+pub struct LobbySocket {
+    socket: Box<dyn NetworkSocket>,
+    kicked: KickedClients,
+}
+
+impl LobbySocket {
+    /// Wrap a socket, and create the [`Lobby`] that kicks clients from it.
+    pub fn new(socket: Box<dyn NetworkSocket>) -> (Self, Lobby) {
+        let kicked = KickedClients::default();
+        let lobby = Lobby {
+            rules: default(),
+            ready: default(),
+            chat: default(),
+            seed: 0,
+            map_votes: default(),
+            vote_result: None,
+            kicked: kicked.clone(),
+        };
+        (Self { socket, kicked }, lobby)
+    }
+
+    fn is_kicked(&self, client: usize) -> bool {
+        self.kicked
+            .read()
+            .unwrap()
+            .get(client)
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+impl NetworkSocket for LobbySocket {
+    fn ggrs_socket(&self) -> BoxedNonBlockingSocket {
+        // Kicked clients have no players, so they are never added to the GGRS session, which
+        // ignores any messages from them.
+        self.socket.ggrs_socket()
+    }
+
+    fn send_reliable(&self, target: SocketTarget, message: &[u8]) {
+        self.socket.send_reliable(target, message)
+    }
+
+    fn recv_reliable(&self) -> Vec<(usize, Vec<u8>)> {
+        let mut messages = self.socket.recv_reliable();
+        messages.retain(|(client, _)| !self.is_kicked(*client));
+        messages
+    }
+
+    fn close(&self) {
+        self.socket.close()
+    }
+
+    fn client_idx(&self) -> usize {
+        self.socket.client_idx()
+    }
+
+    fn client_player_counts(&self) -> [usize; MAX_PLAYERS] {
+        let mut counts = self.socket.client_player_counts();
+        for (count, kicked) in counts.iter_mut().zip(*self.kicked.read().unwrap()) {
+            if kicked {
+                *count = 0;
+            }
+        }
+        counts
+    }
+
+    fn spectator_count(&self) -> usize {
+        self.socket.spectator_count()
+    }
+
+    fn spectator_addrs(&self) -> Vec<usize> {
+        self.socket.spectator_addrs()
+    }
+
+    fn take_error(&self) -> Option<NetworkError> {
+        while let Some(error) = self.socket.take_error() {
+            let NetworkError::PlayerDisconnected(player) = error else {
+                return Some(error);
+            };
+
+            // The wrapped socket reports the player numbered as if nobody had been kicked
+            let Some(client) = self.socket.player_client(player) else {
+                return Some(error);
+            };
+            if self.is_kicked(client) {
+                info!(%client, "Kicked client disconnected");
+                continue;
+            }
+            return Some(NetworkError::PlayerDisconnected(
+                self.client_players(client).start,
+            ));
+        }
+        None
+    }
+}
+
+/// A message in the lobby chat.
+#[derive(Debug, Clone)]
+pub struct ChatLine {
+    /// The client that sent the message.
+    pub client: usize,
+    pub text: ChatText,
+}
+
+/// The content of a [`ChatLine`].
+#[derive(Debug, Clone)]
+pub enum ChatText {
+    Text(String),
+    QuickChat(QuickChat),
+}
+
+/// Something that happened in the lobby that the UI must react to, returned by
+/// [`Lobby::handle_message`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LobbyEvent {
+    /// The host kicked us from the match.
+    Kicked,
+    /// The host started the match, and is picking the map.
+    Start,
+}
+
+/// Resource containing the state of the lobby for the current [`NetworkMatchSocket`].
+///
+/// This is reset whenever a new socket is inserted with [`NetworkMatchSocket::insert`].
+///
+/// [`NetworkMatchSocket`]: crate::networking::NetworkMatchSocket
+/// [`NetworkMatchSocket::insert`]: crate::networking::NetworkMatchSocket::insert
+#[derive(Resource)]
+pub struct Lobby {
+    /// The rules of the match, as last sent by the host.
+    pub rules: MatchRules,
+    /// Whether or not each client is ready to start.
+    pub ready: [bool; MAX_PLAYERS],
+    /// The latest chat messages, oldest first.
+    pub chat: VecDeque<ChatLine>,
+    /// The random seed shared by everybody in the match, sent by the host when it starts the
+    /// match.
+    pub seed: u64,
+    /// The map that each client voted for, when [`MatchRules::map_voting`] is enabled.
+    pub map_votes: [Option<bones::Handle<MapMeta>>; MAX_PLAYERS],
+    /// The map that won the vote, once the host counted the votes.
+    pub vote_result: Option<bones::Handle<MapMeta>>,
+    kicked: KickedClients,
+}
+
+impl Lobby {
+    /// Whether or not the client was kicked from the match.
+    pub fn is_kicked(&self, client: usize) -> bool {
+        self.kicked
+            .read()
+            .unwrap()
+            .get(client)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Get whether or not each client was kicked from the match.
+    pub fn kicked_clients(&self) -> [bool; MAX_PLAYERS] {
+        *self.kicked.read().unwrap()
+    }
+
+    /// Kick the clients that the host kicked, when we didn't get the kick messages in time, such
+    /// as when we are spectating.
+    pub fn apply_kicked_clients(&mut self, kicked_clients: [bool; MAX_PLAYERS]) {
+        let mut kicked = self.kicked.write().unwrap();
+        for (kicked, was_kicked) in kicked.iter_mut().zip(kicked_clients) {
+            *kicked |= was_kicked;
+        }
+    }
+
+    /// Whether or not every client that is still in the match is ready.
+    pub fn all_ready(&self, socket: &dyn NetworkSocket) -> bool {
+        (0..socket.client_count()).all(|i| self.ready[i] || self.is_kicked(i))
+    }
+
+    /// Send a chat message to the other clients.
+    pub fn send_chat(&mut self, socket: &dyn NetworkSocket, text: &str) {
+        let Some(text) = chat_text(text) else { return };
+        socket.send_match_setup(
+            SocketTarget::All,
+            &MatchSetupMessage::Lobby(LobbyMessage::Chat(text.clone())),
+        );
+        self.push_chat(socket.client_idx(), ChatText::Text(text));
+    }
+
+    /// Send a quick chat message to the other clients.
+    pub fn send_quick_chat(&mut self, socket: &dyn NetworkSocket, message: QuickChat) {
+        socket.send_match_setup(
+            SocketTarget::All,
+            &MatchSetupMessage::Lobby(LobbyMessage::QuickChat(message)),
+        );
+        self.push_chat(socket.client_idx(), ChatText::QuickChat(message));
+    }
+
+    /// Tell the other clients whether we are ready to start.
+    pub fn set_ready(&mut self, socket: &dyn NetworkSocket, ready: bool) {
+        let Some(is_ready) = self.ready.get_mut(socket.client_idx()) else {
+            return;
+        };
+        *is_ready = ready;
+        socket.send_match_setup(
+            SocketTarget::All,
+            &MatchSetupMessage::Lobby(LobbyMessage::Ready(ready)),
+        );
+    }
+
+    /// Change the rules of the match, as the host.
+    ///
+    /// The other clients have to get ready again, so that nobody starts with rules they didn't see.
+    pub fn set_rules(&mut self, socket: &dyn NetworkSocket, rules: MatchRules) {
+        socket.send_match_setup(
+            SocketTarget::All,
+            &MatchSetupMessage::Lobby(LobbyMessage::Rules(rules.clone())),
+        );
+        self.rules = rules;
+        self.ready[1..].fill(false);
+    }
+
+    /// Kick a client from the match, as the host.
+    pub fn kick(&mut self, socket: &dyn NetworkSocket, client: usize) {
+        if client == socket.client_idx() || client >= MAX_PLAYERS {
+            return;
+        }
+        info!(%client, "Kicking client from the match");
+        socket.send_match_setup(
+            SocketTarget::All,
+            &MatchSetupMessage::Lobby(LobbyMessage::Kick { client }),
+        );
+        self.kicked.write().unwrap()[client] = true;
+    }
+
+    /// Start the match, as the host, once everybody is ready.
+    pub fn start(&mut self, socket: &dyn NetworkSocket) {
+        self.seed = rand::random();
+        self.reset_vote();
+        socket.send_match_setup(
+            SocketTarget::All,
+            &MatchSetupMessage::Lobby(LobbyMessage::Start {
+                rules: self.rules.clone(),
+                seed: self.seed,
+            }),
+        );
+    }
+
+    /// Vote for the map that the match is played on, or change our vote.
+    pub fn vote_for_map(&mut self, socket: &dyn NetworkSocket, handle: bones::Handle<MapMeta>) {
+        if self.vote_result.is_some() {
+            return;
+        }
+        self.map_votes[socket.client_idx()] = Some(handle.clone());
+        socket.send_match_setup(
+            SocketTarget::All,
+            &MatchSetupMessage::MapSelect(MapSelectMessage::VoteMap { handle }),
+        );
+    }
+
+    /// The number of votes for the core map with the given handle.
+    pub fn map_vote_count(&self, handle: &bones::Handle<MapMeta>) -> usize {
+        self.map_votes
+            .iter()
+            .flatten()
+            .filter(|x| x.path == handle.path)
+            .count()
+    }
+
+    /// Handle a [`MapSelectMessage::VoteMap`] or [`MapSelectMessage::VoteResult`] received from
+    /// another client.
+    ///
+    /// Returns the message back if it isn't one of those.
+    pub fn handle_vote_message(
+        &mut self,
+        client: usize,
+        message: MapSelectMessage,
+    ) -> Option<MapSelectMessage> {
+        match message {
+            MapSelectMessage::VoteMap { handle } => {
+                if self.vote_result.is_none() && !self.is_kicked(client) {
+                    if let Some(vote) = self.map_votes.get_mut(client) {
+                        *vote = Some(handle);
+                    }
+                }
+            }
+            MapSelectMessage::VoteResult { handle } if client == 0 => {
+                info!(map=?handle.path, "Map vote finished");
+                self.vote_result = Some(handle);
+            }
+            MapSelectMessage::VoteResult { .. } => {
+                warn!(%client, "Ignoring map vote result that wasn't sent by the host");
+            }
+            message => return Some(message),
+        }
+        None
+    }
+
+    /// Count the votes, as the host, once every client voted for a map out of `maps`.
+    ///
+    /// The map that won is sent to the other clients and returned.
+    pub fn finish_vote(
+        &mut self,
+        socket: &dyn NetworkSocket,
+        maps: &[bones::Handle<MapMeta>],
+    ) -> Option<bones::Handle<MapMeta>> {
+        if self.vote_result.is_some()
+            || !(0..socket.client_count()).all(|i| self.map_votes[i].is_some() || self.is_kicked(i))
+        {
+            return None;
+        }
+
+        let votes = self
+            .map_votes
+            .iter()
+            .map(|vote| {
+                let vote = vote.as_ref()?;
+                maps.iter().position(|x| x.path == vote.path)
+            })
+            .collect::<Vec<_>>();
+        let winner = maps[map_vote_winner(&votes, maps.len(), self.seed)?].clone();
+        info!(map=?winner.path, "Map vote finished");
+        socket.send_match_setup(
+            SocketTarget::All,
+            &MatchSetupMessage::MapSelect(MapSelectMessage::VoteResult {
+                handle: winner.clone(),
+            }),
+        );
+        self.vote_result = Some(winner.clone());
+        Some(winner)
+    }
+
+    fn reset_vote(&mut self) {
+        self.map_votes = default();
+        self.vote_result = None;
+    }
+
+    /// Handle a lobby message received from another client.
+    pub fn handle_message(
+        &mut self,
+        socket: &dyn NetworkSocket,
+        client: usize,
+        message: LobbyMessage,
+    ) -> Option<LobbyEvent> {
+        let is_host = client == 0;
+        match message {
+            LobbyMessage::Chat(text) => {
+                if let Some(text) = chat_text(&text) {
+                    self.push_chat(client, ChatText::Text(text));
+                }
+            }
+            LobbyMessage::QuickChat(message) => {
+                self.push_chat(client, ChatText::QuickChat(message));
+            }
+            LobbyMessage::Ready(ready) => {
+                if let Some(is_ready) = self.ready.get_mut(client) {
+                    *is_ready = ready;
+                }
+            }
+            LobbyMessage::Rules(rules) if is_host => {
+                self.rules = rules;
+                self.ready[1..].fill(false);
+            }
+            LobbyMessage::Kick { client: kicked } if is_host => {
+                if kicked == socket.client_idx() {
+                    warn!("Kicked from the match by the host");
+                    return Some(LobbyEvent::Kicked);
+                }
+                if let Some(is_kicked) = self.kicked.write().unwrap().get_mut(kicked) {
+                    info!(client=%kicked, "Host kicked client from the match");
+                    *is_kicked = true;
+                }
+            }
+            LobbyMessage::Start { rules, seed } if is_host => {
+                self.rules = rules;
+                // The votes aren't reset here, since another client's vote may arrive before this
+                self.seed = seed;
+                return Some(LobbyEvent::Start);
+            }
+            message => {
+                warn!(%client, ?message, "Ignoring lobby message that may only be sent by the host");
+            }
+        }
+
+        None
+    }
+
+    fn push_chat(&mut self, client: usize, text: ChatText) {
+        if self.chat.len() >= MAX_CHAT_LINES {
+            self.chat.pop_front();
+        }
+        self.chat.push_back(ChatLine { client, text });
+    }
+}
+
+/// Clean up a chat message, cutting it to [`MAX_CHAT_MESSAGE_LEN`] characters, or return `None` if
+/// there is nothing left of it.
+fn chat_text(text: &str) -> Option<String> {
+    let text = text
+        .chars()
+        .filter(|x| !x.is_control())
+        .take(MAX_CHAT_MESSAGE_LEN)
+        .collect::<String>();
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
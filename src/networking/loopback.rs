@@ -0,0 +1,409 @@
+//! In-process [`NetworkSocket`] implementation, for testing network matches without a network.
+//!
+//! [`LoopbackSocket::connect()`] creates the sockets for all of the clients of a match, which pass
+//! their messages to each other through in-memory channels. The [`LoopbackMatch`] harness uses them
+//! to run a [`GgrsSessionRunner`] for every client in the same process, and checks that their game
+//! states stay identical frame by frame.
+
+use async_channel::{Receiver, Sender};
+
+use super::*;
+
+/// A [`NetworkSocket`] connected to the other clients of a match in the same process.
+pub struct LoopbackSocket {
+    client_idx: usize,
+    client_player_counts: [usize; MAX_PLAYERS],
+    /// The GGRS message channel of every client, by client index.
+    ggrs_senders: Vec<Sender<(usize, ggrs::Message)>>,
+    ggrs_receiver: Receiver<(usize, ggrs::Message)>,
+    /// The reliable message channel of every client, by client index.
+    reliable_senders: Vec<Sender<(usize, Vec<u8>)>>,
+    reliable_receiver: Receiver<(usize, Vec<u8>)>,
+}
+
+impl LoopbackSocket {
+    /// Create the sockets of a match with `client_player_counts.len()` clients, which have the
+    /// given numbers of players.
+    ///
+    /// The returned sockets are in client index order.
+    pub fn connect(client_player_counts: &[usize]) -> Vec<Self> {
+        assert!(
+            client_player_counts.iter().sum::<usize>() <= MAX_PLAYERS,
+            "Too many players for a match"
+        );
+        let mut player_counts = [0; MAX_PLAYERS];
+        player_counts[..client_player_counts.len()].copy_from_slice(client_player_counts);
+
+        let (ggrs_senders, ggrs_receivers): (Vec<_>, Vec<_>) = client_player_counts
+            .iter()
+            .map(|_| async_channel::unbounded())
+            .unzip();
+        let (reliable_senders, reliable_receivers): (Vec<_>, Vec<_>) = client_player_counts
+            .iter()
+            .map(|_| async_channel::unbounded())
+            .unzip();
+
+        ggrs_receivers
+            .into_iter()
+            .zip(reliable_receivers)
+            .enumerate()
+            .map(|(client_idx, (ggrs_receiver, reliable_receiver))| Self {
+                client_idx,
+                client_player_counts: player_counts,
+                ggrs_senders: ggrs_senders.clone(),
+                ggrs_receiver,
+                reliable_senders: reliable_senders.clone(),
+                reliable_receiver,
+            })
+            .collect()
+    }
+}
+
+impl NetworkSocket for LoopbackSocket {
+    fn ggrs_socket(&self) -> BoxedNonBlockingSocket {
+        BoxedNonBlockingSocket(Box::new(LoopbackGgrsSocket {
+            client_idx: self.client_idx,
+            senders: self.ggrs_senders.clone(),
+            receiver: self.ggrs_receiver.clone(),
+        }))
+    }
+
+    fn send_reliable(&self, target: SocketTarget, message: &[u8]) {
+        let senders = self.reliable_senders.iter().enumerate();
+        for (i, sender) in senders {
+            let is_target = match target {
+                SocketTarget::Client(client) => i == client,
+                SocketTarget::All => i != self.client_idx,
+            };
+            if is_target {
+                sender.try_send((self.client_idx, message.to_vec())).ok();
+            }
+        }
+    }
+
+    fn recv_reliable(&self) -> Vec<(usize, Vec<u8>)> {
+        std::iter::from_fn(|| self.reliable_receiver.try_recv().ok()).collect()
+    }
+
+    fn close(&self) {
+        self.ggrs_receiver.close();
+        self.reliable_receiver.close();
+    }
+
+    fn client_idx(&self) -> usize {
+        self.client_idx
+    }
+
+    fn client_player_counts(&self) -> [usize; MAX_PLAYERS] {
+        self.client_player_counts
+    }
+
+    fn spectator_count(&self) -> usize {
+        0
+    }
+
+    fn spectator_addrs(&self) -> Vec<usize> {
+        Vec::new()
+    }
+
+    fn take_error(&self) -> Option<NetworkError> {
+        None
+    }
+}
+
+/// The GGRS socket of a [`LoopbackSocket`].
+struct LoopbackGgrsSocket {
+    client_idx: usize,
+    senders: Vec<Sender<(usize, ggrs::Message)>>,
+    receiver: Receiver<(usize, ggrs::Message)>,
+}
+
+impl ggrs::NonBlockingSocket<usize> for LoopbackGgrsSocket {
+    fn send_to(&mut self, msg: &ggrs::Message, addr: &usize) {
+        if let Some(sender) = self.senders.get(*addr) {
+            sender.try_send((self.client_idx, msg.clone())).ok();
+        }
+    }
+
+    fn receive_all_messages(&mut self) -> Vec<(usize, ggrs::Message)> {
+        std::iter::from_fn(|| self.receiver.try_recv().ok()).collect()
+    }
+}
+
+/// An error that stopped a [`LoopbackMatch`].
+#[derive(Debug)]
+pub enum LoopbackError {
+    /// The clients didn't synchronize with each other.
+    NotSynchronized,
+    /// A client's game state was different from another client's.
+    Desync {
+        /// The client that noticed the de-sync.
+        client_idx: usize,
+        /// The first frame that the game states were different on.
+        frame: ggrs::Frame,
+    },
+    /// The clients didn't confirm the frames they played in time to compare their game states.
+    NotConfirmed,
+    /// A client's session stopped with an error.
+    Session {
+        client_idx: usize,
+        error: NetworkError,
+    },
+}
+
+/// A network match between clients running in the same process, connected by
+/// [`LoopbackSocket`]s.
+///
+/// Every client's [`GgrsSessionRunner`] is advanced one frame at a time, with the local inputs
+/// given by a script, and the match stops as soon as the game state of one client differs from the
+/// others. This lets rollback determinism be tested in `cargo test`.
+pub struct LoopbackMatch {
+    /// The session runner of every client, by client index.
+    pub runners: Vec<GgrsSessionRunner>,
+    /// The bevy world that the core sessions are advanced with.
+    pub bevy_world: World,
+}
+
+impl LoopbackMatch {
+    /// The number of frames to wait for the clients to synchronize with each other.
+    const MAX_SYNC_FRAMES: usize = 600;
+
+    /// Start a match with `client_player_counts.len()` clients, with the given numbers of players,
+    /// creating the core session of each client with `new_core`.
+    ///
+    /// The `bevy_world` holds the assets that the core sessions use.
+    ///
+    /// An `input_delay` of `0` makes the clients predict every remote input, which makes them roll
+    /// back on almost every frame.
+    pub fn new(
+        client_player_counts: &[usize],
+        input_delay: usize,
+        mut new_core: impl FnMut() -> CoreSession,
+        bevy_world: World,
+    ) -> Self {
+        let runners = LoopbackSocket::connect(client_player_counts)
+            .into_iter()
+            .map(|socket| {
+                let info = GgrsSessionRunnerInfo {
+                    socket: socket.ggrs_socket(),
+                    player_is_local: socket.player_is_local(),
+                    player_clients: std::array::from_fn(|i| {
+                        socket.player_client(i).unwrap_or_default()
+                    }),
+                    player_count: socket.player_count(),
+                    spectator_addrs: socket.spectator_addrs(),
+                    is_spectator: false,
+                    disconnect_policy: default(),
+                    frame_rate_factor: 1.0,
+                    timing: NetworkTiming {
+                        input_delay,
+                        max_prediction_window: NetworkTimingSettings::MAX_PREDICTION_WINDOW,
+                    },
+                    auto_input_delay: false,
+                };
+                GgrsSessionRunner::new(new_core(), info)
+            })
+            .collect();
+
+        Self {
+            runners,
+            bevy_world,
+        }
+    }
+
+    /// Synchronize the clients, and then advance them by `frames` frames with the local inputs
+    /// returned by `inputs` for each frame and player.
+    ///
+    /// Afterwards, the clients keep playing until all of their inputs have been confirmed, and
+    /// then their game states are compared, see [`settle()`][Self::settle].
+    pub fn run(
+        &mut self,
+        frames: usize,
+        mut inputs: impl FnMut(ggrs::Frame, usize) -> PlayerControl,
+    ) -> Result<(), LoopbackError> {
+        self.synchronize()?;
+        for _ in 0..frames {
+            self.advance(&mut inputs)?;
+        }
+        self.settle()
+    }
+
+    /// Keep advancing the clients with default inputs until every input given so far has been
+    /// confirmed by all of them, and then compare their game states.
+    ///
+    /// The clients are advanced one after the other, so every client except the last is always
+    /// predicting some of the inputs of the latest frame, and their current game states can't be
+    /// compared directly. Once a default input has been confirmed after the last given inputs
+    /// though, the remaining predictions repeat the default input, which is what the clients play,
+    /// so the game states are final.
+    pub fn settle(&mut self) -> Result<(), LoopbackError> {
+        // Inputs are delayed by the runners before they are given to GGRS, so this is the first
+        // frame of GGRS that only has default inputs.
+        let default_input_frame = self
+            .runners
+            .iter()
+            .map(|x| x.session.current_frame() + x.input_delay as ggrs::Frame)
+            .max()
+            .unwrap_or_default();
+
+        for _ in 0..Self::MAX_SYNC_FRAMES {
+            self.advance(&mut |_, _| default())?;
+
+            let frame = self.runners.first().map(|x| x.session.current_frame());
+            let is_confirmed = self.runners.iter().all(|x| {
+                Some(x.session.current_frame()) == frame
+                    && x.session.confirmed_frame() >= default_input_frame
+            });
+            if is_confirmed {
+                return self.compare_checksums();
+            }
+        }
+        Err(LoopbackError::NotConfirmed)
+    }
+
+    /// Advance the clients until they are synchronized with each other.
+    pub fn synchronize(&mut self) -> Result<(), LoopbackError> {
+        for _ in 0..Self::MAX_SYNC_FRAMES {
+            if self
+                .runners
+                .iter()
+                .all(|x| x.session.current_state() == ggrs::SessionState::Running)
+            {
+                return Ok(());
+            }
+            self.advance(&mut |_, _| default())?;
+        }
+        Err(LoopbackError::NotSynchronized)
+    }
+
+    /// Advance every client by one frame, with the local inputs returned by `inputs` for the frame
+    /// and player.
+    pub fn advance(
+        &mut self,
+        inputs: &mut impl FnMut(ggrs::Frame, usize) -> PlayerControl,
+    ) -> Result<(), LoopbackError> {
+        for (client_idx, runner) in self.runners.iter_mut().enumerate() {
+            let frame = runner.session.current_frame();
+            for player in 0..runner.player_count {
+                runner.set_player_input(player, inputs(frame, player));
+            }
+
+            runner.delta = runner.step;
+            if let Err(SessionError::Disconnected(error)) = runner.advance(&mut self.bevy_world) {
+                return Err(LoopbackError::Session { client_idx, error });
+            }
+
+            if let Some(frame) = runner.desync_frame {
+                return Err(LoopbackError::Desync { client_idx, frame });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Compare the game states of the clients directly.
+    ///
+    /// The clients must all be on the same frame, with no mispredicted inputs left, like after
+    /// [`settle()`][Self::settle].
+    pub fn compare_checksums(&self) -> Result<(), LoopbackError> {
+        let Some(first) = self.runners.first() else { return Ok(()) };
+        let frame = first.session.current_frame();
+        if self
+            .runners
+            .iter()
+            .any(|x| x.session.current_frame() != frame)
+        {
+            return Err(LoopbackError::NotConfirmed);
+        }
+
+        let checksum = first.core.checksum();
+        match self
+            .runners
+            .iter()
+            .position(|x| x.core.checksum() != checksum)
+        {
+            Some(client_idx) => Err(LoopbackError::Desync {
+                client_idx,
+                frame: frame - 1,
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use jumpy_core::test_support::four_player_session_info;
+
+    use super::*;
+
+    /// Start a match on the [`jumpy_core::test_support::MAP`] with four players, split between the
+    /// clients as given by `client_player_counts`.
+    fn four_player_match(client_player_counts: &[usize], input_delay: usize) -> LoopbackMatch {
+        assert_eq!(client_player_counts.iter().sum::<usize>(), 4);
+
+        let (info, bevy_world) = four_player_session_info();
+        LoopbackMatch::new(
+            client_player_counts,
+            input_delay,
+            || CoreSession::new(info.clone()),
+            bevy_world,
+        )
+    }
+
+    /// Scripted inputs that are different for every player, and change every few frames.
+    fn scripted_input(frame: ggrs::Frame, player: usize) -> PlayerControl {
+        let phase = (frame as usize / 7 + player) % 4;
+        PlayerControl {
+            move_direction: Vec2::new(phase as f32 - 1.5, 0.0).normalize_or_zero(),
+            jump_pressed: phase == 0,
+            jump_just_pressed: phase == 0 && frame % 7 == 0,
+            shoot_pressed: phase == 2,
+            ..default()
+        }
+    }
+
+    #[test]
+    fn test_reliable_messages() {
+        let sockets = LoopbackSocket::connect(&[1, 1, 2]);
+
+        sockets[0].send_reliable(SocketTarget::Client(2), b"to two");
+        sockets[1].send_reliable(SocketTarget::All, b"to all");
+
+        assert_eq!(sockets[0].recv_reliable(), vec![(1, b"to all".to_vec())]);
+        assert_eq!(sockets[1].recv_reliable(), vec![]);
+        assert_eq!(
+            sockets[2].recv_reliable(),
+            vec![(0, b"to two".to_vec()), (1, b"to all".to_vec())]
+        );
+    }
+
+    #[test]
+    fn test_client_players() {
+        let sockets = LoopbackSocket::connect(&[1, 2]);
+
+        assert_eq!(sockets[1].player_count(), 3);
+        assert_eq!(sockets[1].client_players(1), 1..3);
+        assert_eq!(
+            sockets[1].player_is_local()[..3].to_vec(),
+            vec![false, true, true],
+            "client 1 plays players 1 and 2"
+        );
+    }
+
+    #[test]
+    fn test_match_stays_in_sync() {
+        let mut network_match = four_player_match(&[2, 2], 2);
+        network_match.run(300, scripted_input).unwrap();
+
+        for runner in &network_match.runners {
+            assert!(runner.session.current_frame() >= 300);
+        }
+    }
+
+    #[test]
+    fn test_match_stays_in_sync_with_rollbacks() {
+        let mut network_match = four_player_match(&[2, 1, 1], 0);
+        network_match.run(300, scripted_input).unwrap();
+    }
+}
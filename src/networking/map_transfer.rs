@@ -0,0 +1,318 @@
+//! Transfer of the selected map to the other players of a network match.
+//!
+//! When the host selects a map, it offers it to the other players with its content hash. Players
+//! that already have an identical copy, either because it is a core map or because they have the
+//! same user map, use their own copy. Other players request the map from the host, which sends it
+//! to them compressed and split into [`MAP_CHUNK_SIZE`] chunks over the reliable channel. Received
+//! maps are verified against the offered hash before they are used.
+
+use std::io::{Read, Write};
+
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+
+use crate::{
+    networking::{
+        proto::{map_hash, MapSelectMessage, MatchSettings, MatchSetupMessage},
+        NetworkError, NetworkSocket, SocketTarget,
+    },
+    prelude::*,
+};
+
+/// The maximum size of the map data in a single [`MapSelectMessage::MapChunk`].
+///
+/// Reliable messages are limited to 4096 bytes by the sockets, and this leaves room for the rest of
+/// the message.
+pub const MAP_CHUNK_SIZE: usize = 3 * 1024;
+
+/// The maximum size of a decompressed map, to protect against malicious peers.
+const MAX_MAP_SIZE: u64 = 16 * 1024 * 1024;
+
+/// The maximum number of chunks in a map transfer, so that a malicious host can't make us allocate
+/// room for more chunks than a map of [`MAX_MAP_SIZE`] needs.
+///
+/// This is `MAX_MAP_SIZE.div_ceil(MAP_CHUNK_SIZE)`, which isn't stable in our Rust version yet.
+const MAX_MAP_CHUNKS: usize = (MAX_MAP_SIZE as usize + MAP_CHUNK_SIZE - 1) / MAP_CHUNK_SIZE;
+
+/// The number of times we request the map again after receiving an invalid one, before giving up.
+const MAX_MAP_RETRIES: u32 = 3;
+
+/// Resource containing the state of the map transfer for the current [`NetworkMatchSocket`].
+///
+/// This is reset whenever a new socket is inserted with [`NetworkMatchSocket::insert`].
+///
+/// [`NetworkMatchSocket`]: crate::networking::NetworkMatchSocket
+/// [`NetworkMatchSocket::insert`]: crate::networking::NetworkMatchSocket::insert
+#[derive(Resource, Default)]
+pub struct MapTransfer {
+    /// The map that we are offering, if we are the host.
+    offer: Option<MapOffer>,
+    /// The map that we are receiving from the host.
+    download: Option<MapDownload>,
+}
+
+/// A network match that should be started, returned by [`MapTransfer::handle_message`].
+pub struct MatchStart {
+    /// The map to play on.
+    pub map_meta: MapMeta,
+    /// The host's settings for the match.
+    pub settings: MatchSettings,
+}
+
+/// A map offered by the host to the other players.
+struct MapOffer {
+    hash: u64,
+    map_meta: MapMeta,
+    settings: MatchSettings,
+    /// The compressed map, split into chunks.
+    chunks: Vec<Vec<u8>>,
+    /// Whether or not each client has the map.
+    ready: [bool; MAX_PLAYERS],
+    /// Whether or not the match has started.
+    ///
+    /// The offer is kept after the match starts, so that we can still send the map to spectators
+    /// that are late.
+    started: bool,
+}
+
+/// A map that has been offered to us by the host.
+struct MapDownload {
+    hash: u64,
+    /// The chunks of the compressed map that have been received so far.
+    chunks: Vec<Option<Vec<u8>>>,
+    /// The map, once we have it.
+    map_meta: Option<MapMeta>,
+    /// The settings of the match, if the host started it before we got the map. This only happens
+    /// to spectators, because the host doesn't wait for them.
+    pending_start: Option<MatchSettings>,
+    /// The number of times we have requested the map again after receiving an invalid one.
+    retries: u32,
+}
+
+impl MapTransfer {
+    /// Offer the map selected by the host to the other players.
+    ///
+    /// `handle` should be set if the map is one of the core maps. The `settings` are sent to the
+    /// other players when the match is started.
+    pub fn offer(
+        &mut self,
+        socket: &dyn NetworkSocket,
+        map_meta: MapMeta,
+        handle: Option<bones::Handle<MapMeta>>,
+        settings: MatchSettings,
+    ) {
+        let hash = map_hash(&map_meta);
+        let chunks = compress_map(&map_meta)
+            .chunks(MAP_CHUNK_SIZE)
+            .map(|x| x.to_vec())
+            .collect();
+        let mut ready = [false; MAX_PLAYERS];
+        ready[socket.client_idx()] = true;
+
+        info!(%hash, "Offering map to network players");
+        socket.send_match_setup(
+            SocketTarget::All,
+            &MatchSetupMessage::MapSelect(MapSelectMessage::OfferMap { hash, handle }),
+        );
+        self.offer = Some(MapOffer {
+            hash,
+            map_meta,
+            settings,
+            chunks,
+            ready,
+            started: false,
+        });
+    }
+
+    /// Whether or not we are waiting for the other players to get the map we offered.
+    pub fn is_offering(&self) -> bool {
+        self.offer.as_ref().map_or(false, |x| !x.started)
+    }
+
+    /// Get the number of chunks received and the total number of chunks of the map we are
+    /// downloading, if we are downloading one.
+    pub fn download_progress(&self) -> Option<(usize, usize)> {
+        self.download
+            .as_ref()
+            .filter(|x| x.map_meta.is_none() && !x.chunks.is_empty())
+            .map(|x| (x.chunks.iter().flatten().count(), x.chunks.len()))
+    }
+
+    /// Handle a map select message received from another client.
+    ///
+    /// `find_local_map` is used to look for a local copy of an offered map, given its hash and
+    /// handle.
+    ///
+    /// Returns the match to start, once it should be started, or an error if the host keeps
+    /// sending us an invalid map.
+    pub fn handle_message(
+        &mut self,
+        socket: &dyn NetworkSocket,
+        client: usize,
+        message: MapSelectMessage,
+        find_local_map: impl FnOnce(u64, Option<&bones::Handle<MapMeta>>) -> Option<MapMeta>,
+    ) -> Result<Option<MatchStart>, NetworkError> {
+        let is_from_host = client == 0;
+        let send_to_host = |message| {
+            socket.send_match_setup(
+                SocketTarget::Client(0),
+                &MatchSetupMessage::MapSelect(message),
+            );
+        };
+
+        match message {
+            MapSelectMessage::OfferMap { hash, handle } if is_from_host => {
+                let map_meta = find_local_map(hash, handle.as_ref());
+                if map_meta.is_some() {
+                    send_to_host(MapSelectMessage::HaveMap { hash });
+                } else {
+                    info!(%hash, "Requesting map from host");
+                    send_to_host(MapSelectMessage::RequestMap { hash });
+                }
+                self.download = Some(MapDownload {
+                    hash,
+                    chunks: default(),
+                    map_meta,
+                    pending_start: None,
+                    retries: 0,
+                });
+            }
+            MapSelectMessage::MapChunk {
+                hash,
+                index,
+                count,
+                data,
+            } if is_from_host => {
+                let Some(download) = self.download.as_mut().filter(|x| x.hash == hash) else {
+                    return Ok(None);
+                };
+                if download.map_meta.is_some() {
+                    return Ok(None);
+                }
+                if count == 0 || count as usize > MAX_MAP_CHUNKS {
+                    warn!(%count, "Ignoring map chunk with invalid chunk count");
+                    return Ok(None);
+                }
+                if download.chunks.len() != count as usize {
+                    download.chunks = vec![None; count as usize];
+                }
+                let Some(chunk) = download.chunks.get_mut(index as usize) else {
+                    warn!(%index, %count, "Ignoring map chunk with invalid index");
+                    return Ok(None);
+                };
+                *chunk = Some(data);
+
+                if download.chunks.iter().all(|x| x.is_some()) {
+                    let compressed = download.chunks.iter().flatten().flatten().copied();
+                    match decompress_map(&compressed.collect::<Vec<_>>()) {
+                        Some(map_meta) if map_hash(&map_meta) == hash => {
+                            info!(%hash, "Received map from host");
+                            send_to_host(MapSelectMessage::HaveMap { hash });
+                            if let Some(settings) = download.pending_start.take() {
+                                self.download = None;
+                                return Ok(Some(MatchStart { map_meta, settings }));
+                            }
+                            download.map_meta = Some(map_meta);
+                        }
+                        _ if download.retries >= MAX_MAP_RETRIES => {
+                            warn!(%hash, "Received invalid map from host too many times, giving up");
+                            self.download = None;
+                            return Err(NetworkError::MapTransferFailed(format!(
+                                "received an invalid map from the host {} times",
+                                MAX_MAP_RETRIES + 1
+                            )));
+                        }
+                        _ => {
+                            warn!(%hash, "Received invalid map from host, requesting it again");
+                            download.retries += 1;
+                            download.chunks.clear();
+                            send_to_host(MapSelectMessage::RequestMap { hash });
+                        }
+                    }
+                }
+            }
+            MapSelectMessage::StartMatch { hash, settings } if is_from_host => {
+                let Some(download) = self.download.as_mut().filter(|x| x.hash == hash) else {
+                    warn!(%hash, "Host started the match with a map we don't have");
+                    return Ok(None);
+                };
+                match download.map_meta.take() {
+                    Some(map_meta) => {
+                        self.download = None;
+                        return Ok(Some(MatchStart { map_meta, settings }));
+                    }
+                    // Wait for the rest of the map
+                    None => download.pending_start = Some(settings),
+                }
+            }
+            MapSelectMessage::RequestMap { hash } => {
+                let Some(offer) = self.offer.as_ref().filter(|x| x.hash == hash) else {
+                    return Ok(None);
+                };
+                info!(%client, chunks = offer.chunks.len(), "Sending map to network client");
+                for (index, data) in offer.chunks.iter().enumerate() {
+                    socket.send_match_setup(
+                        SocketTarget::Client(client),
+                        &MatchSetupMessage::MapSelect(MapSelectMessage::MapChunk {
+                            hash,
+                            index: index as u32,
+                            count: offer.chunks.len() as u32,
+                            data: data.clone(),
+                        }),
+                    );
+                }
+            }
+            MapSelectMessage::HaveMap { hash } => {
+                let Some(offer) = self.offer.as_mut().filter(|x| x.hash == hash) else {
+                    return Ok(None);
+                };
+                // Spectators don't have to get the map before the match starts
+                let Some(ready) = offer.ready.get_mut(client) else {
+                    return Ok(None);
+                };
+                *ready = true;
+
+                // Kicked clients have no players, and don't take part in the match
+                let all_ready = (0..socket.client_count())
+                    .all(|i| offer.ready[i] || socket.client_players(i).is_empty());
+                if !offer.started && all_ready {
+                    offer.started = true;
+                    socket.send_match_setup(
+                        SocketTarget::All,
+                        &MatchSetupMessage::MapSelect(MapSelectMessage::StartMatch {
+                            hash,
+                            settings: offer.settings.clone(),
+                        }),
+                    );
+                    return Ok(Some(MatchStart {
+                        map_meta: offer.map_meta.clone(),
+                        settings: offer.settings.clone(),
+                    }));
+                }
+            }
+            message => {
+                warn!(%client, ?message, "Ignoring map select message that may only be sent by the host");
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/// Serialize and compress a map.
+fn compress_map(map_meta: &MapMeta) -> Vec<u8> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+    encoder
+        .write_all(&postcard::to_allocvec(map_meta).unwrap())
+        .unwrap();
+    encoder.finish().unwrap()
+}
+
+/// Decompress and deserialize a map, returning `None` if it is invalid.
+fn decompress_map(data: &[u8]) -> Option<MapMeta> {
+    let mut bytes = Vec::new();
+    DeflateDecoder::new(data)
+        .take(MAX_MAP_SIZE)
+        .read_to_end(&mut bytes)
+        .ok()?;
+    postcard::from_bytes(&bytes).ok()
+}
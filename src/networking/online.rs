@@ -1,16 +1,21 @@
 #![doc = include_str!("./online.md")]
 
-use std::net::{SocketAddr, ToSocketAddrs};
-
 use bevy::tasks::IoTaskPool;
+#[cfg(not(target_arch = "wasm32"))]
 use bones_matchmaker_proto::{MatchInfo, MatchmakerRequest, MatchmakerResponse};
+#[cfg(not(target_arch = "wasm32"))]
 use bytes::Bytes;
+#[cfg(not(target_arch = "wasm32"))]
 use futures_lite::future;
+#[cfg(not(target_arch = "wasm32"))]
 use quinn::Connection;
+use rand::Rng;
 
 use crate::prelude::*;
 
-use super::{NetworkSocket, NETWORK_ENDPOINT};
+#[cfg(not(target_arch = "wasm32"))]
+use super::{certs, resolve_addr_blocking};
+use super::{relay, NetworkError, NetworkSocket};
 
 pub static ONLINE_MATCHMAKER: Lazy<OnlineMatchmaker> = Lazy::new(|| {
     let (client, server) = bi_channel();
@@ -25,19 +30,127 @@ pub struct OnlineMatchmaker(BiChannelClient<OnlineMatchmakerRequest, OnlineMatch
 
 #[derive(Debug)]
 pub enum OnlineMatchmakerRequest {
-    SearchForGame { addr: String, player_count: usize },
+    SearchForGame {
+        addr: String,
+        /// The fingerprint of the certificate the matchmaker must have, or an empty string to not
+        /// verify it.
+        fingerprint: String,
+        /// The total number of players in the match, which must be a multiple of
+        /// `local_player_count`.
+        player_count: usize,
+        /// The number of players playing on this client.
+        local_player_count: usize,
+        /// The private lobby to join, or `None` to search the public queue.
+        lobby: Option<LobbyCode>,
+    },
     StopSearch,
 }
 
-#[derive(Debug)]
+/// The join code of a private online lobby.
+///
+/// Players only get matched with players that use the same code, and because the matchmaker needs
+/// everybody to ask for the same number of clients, the code also carries the player counts of the
+/// match. Codes are shown as `XXXXX-P-L`, where `P` is the total and `L` is the local player count.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LobbyCode {
+    pub id: String,
+    pub player_count: usize,
+    pub local_player_count: usize,
+}
+
+impl LobbyCode {
+    /// The characters used in lobby ids, leaving out the ones that are easy to mix up.
+    const ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+    const ID_LEN: usize = 5;
+
+    /// Generate a new random lobby code for a match.
+    pub fn generate(player_count: usize, local_player_count: usize) -> Self {
+        let mut rng = rand::thread_rng();
+        let id = (0..Self::ID_LEN)
+            .map(|_| Self::ALPHABET[rng.gen_range(0..Self::ALPHABET.len())] as char)
+            .collect();
+        Self {
+            id,
+            player_count,
+            local_player_count,
+        }
+    }
+
+    /// Parse a lobby code typed in by a player.
+    pub fn parse(code: &str) -> Result<Self, NetworkError> {
+        let invalid = || NetworkError::InvalidLobbyCode(code.trim().into());
+        let code = code.trim().to_ascii_uppercase();
+        let mut parts = code.split('-');
+        let (Some(id), Some(player_count), Some(local_player_count), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next()) else {
+            return Err(invalid());
+        };
+        let player_count: usize = player_count.parse().map_err(|_| invalid())?;
+        let local_player_count: usize = local_player_count.parse().map_err(|_| invalid())?;
+
+        let valid = id.len() == Self::ID_LEN
+            && id.bytes().all(|x| Self::ALPHABET.contains(&x))
+            && player_count <= MAX_PLAYERS
+            && local_player_count > 0
+            && player_count % local_player_count == 0
+            && player_count / local_player_count >= 2;
+        if !valid {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            id: id.into(),
+            player_count,
+            local_player_count,
+        })
+    }
+}
+
+impl std::fmt::Display for LobbyCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}-{}-{}",
+            self.id, self.player_count, self.local_player_count
+        )
+    }
+}
+
+/// Build the `match_data` sent to the matchmaker.
+///
+/// Only clients with the exact same `match_data` get matched together, so it contains everything
+/// that must be the same for the players of a match.
+fn match_data(
+    lobby: Option<&LobbyCode>,
+    player_count: usize,
+    local_player_count: usize,
+) -> Vec<u8> {
+    let lobby = lobby.map(|x| x.id.as_str()).unwrap_or("public");
+    format!(
+        "jumpy/{}/lobby={lobby}/players={player_count}/local_players={local_player_count}",
+        env!("CARGO_PKG_VERSION")
+    )
+    .into_bytes()
+}
+
+/// Get the host part of a `host:port` address, which the matchmaker's certificate must be valid for.
+#[cfg(not(target_arch = "wasm32"))]
+fn host_name(addr: &str) -> &str {
+    let addr = addr.trim();
+    let host = addr.rsplit_once(':').map(|(host, _)| host).unwrap_or(addr);
+    host.trim_start_matches('[').trim_end_matches(']')
+}
+
 pub enum OnlineMatchmakerResponse {
     Searching,
     PlayerCount(usize),
     GameStarting {
-        online_socket: OnlineSocket,
-        player_idx: usize,
+        socket: Box<dyn NetworkSocket>,
+        client_idx: usize,
         player_count: usize,
     },
+    /// Matchmaking failed, and the search has been stopped.
+    Error(NetworkError),
 }
 
 async fn online_matchmaker(
@@ -45,150 +158,243 @@ async fn online_matchmaker(
 ) {
     while let Ok(message) = matchmaker_channel.recv().await {
         match message {
-            OnlineMatchmakerRequest::SearchForGame { addr, player_count } => {
-                info!("Connecting to online matchmaker");
-                let addr = resolve_addr_blocking(&addr).unwrap();
-                let conn = NETWORK_ENDPOINT
-                    .connect(addr, "matchmaker")
-                    .unwrap()
-                    .await
-                    .unwrap();
-                info!("Connected to online matchmaker");
-
-                matchmaker_channel
-                    .try_send(OnlineMatchmakerResponse::Searching)
-                    .unwrap();
-
-                // Send a match request to the server
-                let (mut send, mut recv) = conn.open_bi().await.unwrap();
-
-                let message = MatchmakerRequest::RequestMatch(MatchInfo {
-                    client_count: player_count.try_into().unwrap(),
-                    match_data: b"jumpy_default_game".to_vec(),
-                });
-                info!(request=?message, "Sending match request");
-                let message = postcard::to_allocvec(&message).unwrap();
-                send.write_all(&message).await.unwrap();
-                send.finish().await.unwrap();
-
-                let response = recv.read_to_end(256).await.unwrap();
-                let message: MatchmakerResponse = postcard::from_bytes(&response).unwrap();
-
-                if let MatchmakerResponse::Accepted = message {
-                    info!("Waiting for match...");
-                } else {
-                    panic!("Invalid response from matchmaker");
+            OnlineMatchmakerRequest::SearchForGame {
+                addr,
+                fingerprint,
+                player_count,
+                local_player_count,
+                lobby,
+            } => {
+                let search = search_for_game(
+                    &matchmaker_channel,
+                    &addr,
+                    &fingerprint,
+                    player_count,
+                    local_player_count,
+                    lobby.as_ref(),
+                );
+                if let Err(err) = search.await {
+                    error!(%err, "Online matchmaking failed");
+                    matchmaker_channel
+                        .try_send(OnlineMatchmakerResponse::Error(err))
+                        .ok();
                 }
+            }
+            OnlineMatchmakerRequest::StopSearch => (), // Not searching, don't do anything
+        }
+    }
+}
 
-                loop {
-                    let recv_ui_message = matchmaker_channel.recv();
-                    let recv_online_matchmaker = conn.accept_uni();
+/// Connect to the matchmaker at `addr` and wait for a match, until it is found or the search is
+/// canceled from the UI.
+///
+/// The matchmaker only knows about clients, so we only match with clients that have the same
+/// number of local players as us. With a `lobby` code, we only match with clients that use the same
+/// code.
+///
+/// Matchmaking server addresses that are `ws://` or `wss://` URLs are [`relay`]s, and anything else
+/// is a QUIC matchmaker, which the web build can't connect to.
+async fn search_for_game(
+    matchmaker_channel: &BiChannelServer<OnlineMatchmakerRequest, OnlineMatchmakerResponse>,
+    addr: &str,
+    #[cfg_attr(target_arch = "wasm32", allow(unused_variables))] fingerprint: &str,
+    player_count: usize,
+    local_player_count: usize,
+    lobby: Option<&LobbyCode>,
+) -> Result<(), NetworkError> {
+    if local_player_count == 0 || player_count % local_player_count != 0 {
+        return Err(NetworkError::InvalidMessage(format!(
+            "Can't split {player_count} players between clients with {local_player_count} \
+            local players"
+        )));
+    }
+    let client_count = player_count / local_player_count;
+    let match_data = match_data(lobby, player_count, local_player_count);
+
+    if relay::is_relay_url(addr) {
+        return relay::search_for_game(
+            matchmaker_channel,
+            addr,
+            client_count,
+            local_player_count,
+            match_data,
+        )
+        .await;
+    }
 
-                    let next_message = futures_lite::future::or(
-                        async move { either::Left(recv_ui_message.await) },
-                        async move { either::Right(recv_online_matchmaker.await) },
-                    )
-                    .await;
+    #[cfg(target_arch = "wasm32")]
+    return Err(NetworkError::InvalidAddress(format!(
+        "Browsers can only connect to relays, so the matchmaking server must be a `ws://` or \
+        `wss://` URL, not `{addr}`"
+    )));
+
+    #[cfg(not(target_arch = "wasm32"))]
+    return search_with_quic(
+        matchmaker_channel,
+        addr,
+        fingerprint,
+        client_count,
+        local_player_count,
+        match_data,
+    )
+    .await;
+}
 
-                    match next_message {
-                        // UI message
-                        either::Either::Left(message) => {
-                            let message = message.unwrap();
+/// Connect to the QUIC matchmaker at `addr` and wait for a match, see [`search_for_game()`].
+#[cfg(not(target_arch = "wasm32"))]
+async fn search_with_quic(
+    matchmaker_channel: &BiChannelServer<OnlineMatchmakerRequest, OnlineMatchmakerResponse>,
+    addr: &str,
+    fingerprint: &str,
+    client_count: usize,
+    local_player_count: usize,
+    match_data: Vec<u8>,
+) -> Result<(), NetworkError> {
+    info!("Connecting to online matchmaker");
+    let verifier = certs::ServerVerifier::matchmaker(fingerprint)?;
+    let server_name = host_name(addr);
+    let addr = resolve_addr_blocking(addr)?;
+    let conn = verifier.connect(addr, server_name).await?;
+    info!("Connected to online matchmaker");
+
+    matchmaker_channel
+        .try_send(OnlineMatchmakerResponse::Searching)
+        .ok();
+
+    // Send a match request to the server
+    let (mut send, mut recv) = conn.open_bi().await?;
+
+    let message = MatchmakerRequest::RequestMatch(MatchInfo {
+        // There can't be more clients than players
+        client_count: client_count.min(MAX_PLAYERS) as _,
+        match_data,
+    });
+    info!(request=?message, "Sending match request");
+    let message = postcard::to_allocvec(&message)?;
+    send.write_all(&message).await?;
+    send.finish().await?;
+
+    let response = recv.read_to_end(256).await?;
+    let message: MatchmakerResponse = postcard::from_bytes(&response)?;
+
+    if let MatchmakerResponse::Accepted = message {
+        info!("Waiting for match...");
+    } else {
+        return Err(NetworkError::InvalidMessage(
+            "Matchmaker did not accept the match request".into(),
+        ));
+    }
 
-                            match message {
-                                OnlineMatchmakerRequest::SearchForGame { .. } => {
-                                    panic!("Unexpected message from UI");
-                                }
-                                OnlineMatchmakerRequest::StopSearch => {
-                                    info!("Canceling online search");
-                                    break;
-                                }
-                            }
-                        }
+    loop {
+        let recv_ui_message = matchmaker_channel.recv();
+        let recv_online_matchmaker = conn.accept_uni();
+
+        let next_message = futures_lite::future::or(
+            async move { either::Left(recv_ui_message.await) },
+            async move { either::Right(recv_online_matchmaker.await) },
+        )
+        .await;
+
+        match next_message {
+            // UI message
+            either::Either::Left(message) => {
+                // The UI side of the channel is gone, so nobody is waiting for the match anymore.
+                let Ok(message) = message else {
+                    return Ok(());
+                };
+
+                match message {
+                    OnlineMatchmakerRequest::SearchForGame { .. } => {
+                        warn!("Ignoring search request while already searching for a game");
+                    }
+                    OnlineMatchmakerRequest::StopSearch => {
+                        info!("Canceling online search");
+                        conn.close(0u8.into(), b"canceled");
+                        return Ok(());
+                    }
+                }
+            }
 
-                        // Matchmaker message
-                        either::Either::Right(recv) => {
-                            let mut recv = recv.unwrap();
-                            let message = recv.read_to_end(256).await.unwrap();
-                            let message: MatchmakerResponse =
-                                postcard::from_bytes(&message).unwrap();
-
-                            match message {
-                                MatchmakerResponse::ClientCount(count) => {
-                                    info!("Online match player count: {count}");
-                                    matchmaker_channel
-                                        .try_send(OnlineMatchmakerResponse::PlayerCount(count as _))
-                                        .unwrap();
-                                }
-                                MatchmakerResponse::Success {
-                                    random_seed,
-                                    player_idx,
-                                    client_count,
-                                } => {
-                                    info!(%random_seed, %player_idx, player_count=%client_count, "Online match complete");
-                                    let online_socket = OnlineSocket::new(
-                                        player_idx as usize,
-                                        client_count as usize,
-                                        conn,
-                                    );
-
-                                    matchmaker_channel
-                                        .try_send(OnlineMatchmakerResponse::GameStarting {
-                                            online_socket,
-                                            player_idx: player_idx as _,
-                                            player_count: client_count as _,
-                                        })
-                                        .unwrap();
-                                    break;
-                                }
-                                _ => panic!("Unexpected message from matchmaker"),
-                            }
+            // Matchmaker message
+            either::Either::Right(recv) => {
+                let mut recv = recv?;
+                let message = recv.read_to_end(256).await?;
+                let message: MatchmakerResponse = postcard::from_bytes(&message)?;
+
+                match message {
+                    MatchmakerResponse::ClientCount(count) => {
+                        let count = count as usize * local_player_count;
+                        info!("Online match player count: {count}");
+                        matchmaker_channel
+                            .try_send(OnlineMatchmakerResponse::PlayerCount(count))
+                            .ok();
+                    }
+                    MatchmakerResponse::Success {
+                        random_seed,
+                        player_idx,
+                        client_count,
+                    } => {
+                        let client_idx = player_idx as usize;
+                        let client_count = client_count as usize;
+                        info!(%random_seed, %client_idx, %client_count, "Online match complete");
+                        if client_idx >= client_count
+                            || client_count * local_player_count > MAX_PLAYERS
+                        {
+                            return Err(NetworkError::InvalidMessage(format!(
+                                "Invalid client index {client_idx} for {client_count} clients"
+                            )));
                         }
+                        let client_player_counts = std::array::from_fn(|i| {
+                            if i < client_count {
+                                local_player_count
+                            } else {
+                                0
+                            }
+                        });
+                        let online_socket =
+                            OnlineSocket::new(client_idx, client_player_counts, conn);
+
+                        matchmaker_channel
+                            .try_send(OnlineMatchmakerResponse::GameStarting {
+                                socket: Box::new(online_socket),
+                                client_idx,
+                                player_count: client_count * local_player_count,
+                            })
+                            .ok();
+                        return Ok(());
+                    }
+                    _ => {
+                        return Err(NetworkError::InvalidMessage(
+                            "Unexpected message from matchmaker".into(),
+                        ))
                     }
                 }
             }
-            OnlineMatchmakerRequest::StopSearch => (), // Not searching, don't do anything
         }
     }
 }
 
-/// Resolve a server address.
-///
-/// Note: This may block the thread
-fn resolve_addr_blocking(addr: &str) -> anyhow::Result<SocketAddr> {
-    let formatting_err =
-        || anyhow::format_err!("Matchmaking server must be in the format `host:port`");
-
-    let mut iter = addr.split(':');
-    let host = iter.next().ok_or_else(formatting_err)?;
-    let port = iter.next().ok_or_else(formatting_err)?;
-    let port: u16 = port.parse().context("Couldn't parse port number")?;
-    if iter.next().is_some() {
-        return Err(formatting_err());
-    }
-
-    let addr = (host, port)
-        .to_socket_addrs()
-        .context("Couldn't resolve matchmaker address")?
-        .find(|x| x.is_ipv4()) // For now, only support IpV4. I don't think IpV6 works right.
-        .ok_or_else(|| anyhow::format_err!("Couldn't resolve matchmaker address"))?;
-
-    Ok(addr)
-}
-
+#[cfg(not(target_arch = "wasm32"))]
 #[derive(Debug, Clone)]
 pub struct OnlineSocket {
     pub conn: Connection,
     pub ggrs_receiver: async_channel::Receiver<(usize, ggrs::Message)>,
     pub reliable_receiver: async_channel::Receiver<(usize, Vec<u8>)>,
-    pub player_idx: usize,
-    pub player_count: usize,
+    pub error_receiver: async_channel::Receiver<NetworkError>,
+    pub client_idx: usize,
+    pub client_player_counts: [usize; MAX_PLAYERS],
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 impl OnlineSocket {
-    pub fn new(player_idx: usize, player_count: usize, conn: Connection) -> Self {
+    pub fn new(
+        client_idx: usize,
+        client_player_counts: [usize; MAX_PLAYERS],
+        conn: Connection,
+    ) -> Self {
         let (ggrs_sender, ggrs_receiver) = async_channel::unbounded();
         let (reliable_sender, reliable_receiver) = async_channel::unbounded();
+        let (error_sender, error_receiver) = async_channel::unbounded();
 
         let task_pool = IoTaskPool::get();
 
@@ -209,11 +415,17 @@ impl OnlineSocket {
                         }
                         either::Either::Right(datagram_result) => match datagram_result {
                             Ok(data) => {
-                                let message: bones_matchmaker_proto::RecvProxyMessage =
-                                    postcard::from_bytes(&data)
-                                        .expect("Could not deserialize net message");
+                                let Ok(message) = postcard::from_bytes::<
+                                    bones_matchmaker_proto::RecvProxyMessage,
+                                >(&data) else {
+                                    warn!("Ignoring invalid network message");
+                                    continue;
+                                };
                                 let player = message.from_client;
-                                let message = postcard::from_bytes(&message.message).unwrap();
+                                let Ok(message) = postcard::from_bytes(&message.message) else {
+                                    warn!(%player, "Ignoring invalid network message");
+                                    continue;
+                                };
 
                                 if ggrs_sender.send((player as _, message)).await.is_err() {
                                     break;
@@ -241,14 +453,26 @@ impl OnlineSocket {
                     match event {
                         either::Either::Left(closed) => {
                             warn!("Connection error: {closed}");
+                            if !matches!(closed, quinn::ConnectionError::LocallyClosed) {
+                                error_sender.try_send(closed.into()).ok();
+                            }
                             break;
                         }
                         either::Either::Right(result) => match result {
                             Ok(mut stream) => {
-                                let data =
-                                    stream.read_to_end(4096).await.expect("Network read error");
-                                let message: bones_matchmaker_proto::RecvProxyMessage =
-                                    postcard::from_bytes(&data).unwrap();
+                                let data = match stream.read_to_end(4096).await {
+                                    Ok(data) => data,
+                                    Err(e) => {
+                                        warn!("Network read error: {e}");
+                                        continue;
+                                    }
+                                };
+                                let Ok(message) = postcard::from_bytes::<
+                                    bones_matchmaker_proto::RecvProxyMessage,
+                                >(&data) else {
+                                    warn!("Ignoring invalid reliable network message");
+                                    continue;
+                                };
 
                                 if reliable_sender
                                     .send((message.from_client as usize, message.message))
@@ -271,12 +495,14 @@ impl OnlineSocket {
             conn,
             ggrs_receiver,
             reliable_receiver,
-            player_idx,
-            player_count,
+            error_receiver,
+            client_idx,
+            client_player_counts,
         }
     }
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 impl NetworkSocket for OnlineSocket {
     fn ggrs_socket(&self) -> networking::BoxedNonBlockingSocket {
         networking::BoxedNonBlockingSocket(Box::new(self.clone()))
@@ -285,8 +511,8 @@ impl NetworkSocket for OnlineSocket {
     fn send_reliable(&self, target: networking::SocketTarget, message: &[u8]) {
         let task_pool = IoTaskPool::get();
         let target_client = match target {
-            networking::SocketTarget::Player(player) => {
-                bones_matchmaker_proto::TargetClient::One(player as _)
+            networking::SocketTarget::Client(client) => {
+                bones_matchmaker_proto::TargetClient::One(client as _)
             }
             networking::SocketTarget::All => bones_matchmaker_proto::TargetClient::All,
         };
@@ -298,12 +524,17 @@ impl NetworkSocket for OnlineSocket {
         let conn = self.conn.clone();
         task_pool
             .spawn(async move {
-                let mut send = conn.open_uni().await.unwrap();
+                let result = async {
+                    let mut send = conn.open_uni().await?;
 
-                send.write_all(&postcard::to_allocvec(&message).unwrap())
-                    .await
-                    .unwrap();
-                send.finish().await.unwrap();
+                    send.write_all(&postcard::to_allocvec(&message)?).await?;
+                    send.finish().await?;
+
+                    Ok::<_, NetworkError>(())
+                };
+                if let Err(e) = result.await {
+                    warn!("Couldn't send reliable network message: {e}");
+                }
             })
             .detach();
     }
@@ -320,19 +551,29 @@ impl NetworkSocket for OnlineSocket {
         self.conn.close(0u8.into(), &[]);
     }
 
-    fn player_idx(&self) -> usize {
-        self.player_idx
+    fn client_idx(&self) -> usize {
+        self.client_idx
+    }
+
+    fn client_player_counts(&self) -> [usize; MAX_PLAYERS] {
+        self.client_player_counts
+    }
+
+    fn spectator_count(&self) -> usize {
+        // The online matchmaker doesn't support spectators.
+        0
     }
 
-    fn player_is_local(&self) -> [bool; MAX_PLAYERS] {
-        std::array::from_fn(|i| i == self.player_idx)
+    fn spectator_addrs(&self) -> Vec<usize> {
+        Vec::new()
     }
 
-    fn player_count(&self) -> usize {
-        self.player_count
+    fn take_error(&self) -> Option<NetworkError> {
+        self.error_receiver.try_recv().ok()
     }
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 impl ggrs::NonBlockingSocket<usize> for OnlineSocket {
     fn send_to(&mut self, msg: &ggrs::Message, addr: &usize) {
         let message = bones_matchmaker_proto::SendProxyMessage {
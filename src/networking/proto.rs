@@ -1,5 +1,6 @@
 //! Serializable data types for network messages used by the game.
 
+use jumpy_core::utils::FnvHasher;
 use numquant::{IntRange, Quantized};
 
 use crate::prelude::*;
@@ -67,3 +68,481 @@ impl From<DenseMoveDirection> for u16 {
         x_bits | (y_bits << 6)
     }
 }
+
+/// The version of the match setup protocol.
+///
+/// This must be bumped whenever [`MatchSetupMessage`] or any of the messages that it contains
+/// change, so that peers running an incompatible version can be detected before anything else is
+/// decoded.
+pub const MATCH_SETUP_PROTOCOL_VERSION: u16 = 8;
+
+/// The envelope for all reliable messages sent while setting up a network match.
+///
+/// Every message is encoded with [`MATCH_SETUP_PROTOCOL_VERSION`] in front of it, so that the
+/// version can always be read, even if the rest of the message can't be decoded.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum MatchSetupMessage {
+    /// Sent to every other player as soon as the match socket is established, to make sure that
+    /// all players are running compatible versions of the game.
+    Hello(PeerVersion),
+    /// Sent regularly to the other players to measure the round-trip time to them. It is answered
+    /// with a [`Pong`][Self::Pong] with the same id.
+    Ping(u32),
+    /// The answer to a [`Ping`][Self::Ping].
+    Pong(u32),
+    PlayerSelect(PlayerSelectMessage),
+    Lobby(LobbyMessage),
+    MapSelect(MapSelectMessage),
+}
+
+/// The channels that [`MatchSetupMessage`]s are routed to.
+///
+/// Each UI screen only receives the messages on its own channel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MatchSetupChannel {
+    Handshake,
+    PlayerSelect,
+    Lobby,
+    MapSelect,
+}
+
+impl MatchSetupMessage {
+    /// Get the channel that this message should be routed to.
+    pub fn channel(&self) -> MatchSetupChannel {
+        match self {
+            MatchSetupMessage::Hello(_)
+            | MatchSetupMessage::Ping(_)
+            | MatchSetupMessage::Pong(_) => MatchSetupChannel::Handshake,
+            MatchSetupMessage::PlayerSelect(_) => MatchSetupChannel::PlayerSelect,
+            MatchSetupMessage::Lobby(_) => MatchSetupChannel::Lobby,
+            MatchSetupMessage::MapSelect(_) => MatchSetupChannel::MapSelect,
+        }
+    }
+
+    /// Encode the message, prefixed with the protocol version.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = postcard::to_allocvec(&MATCH_SETUP_PROTOCOL_VERSION).unwrap();
+        bytes.extend(postcard::to_allocvec(self).unwrap());
+        bytes
+    }
+
+    /// Decode a message encoded with [`encode()`][Self::encode].
+    pub fn decode(bytes: &[u8]) -> Result<Self, MatchSetupDecodeError> {
+        let (version, rest) =
+            postcard::take_from_bytes::<u16>(bytes).map_err(MatchSetupDecodeError::Malformed)?;
+        if version != MATCH_SETUP_PROTOCOL_VERSION {
+            return Err(MatchSetupDecodeError::ProtocolMismatch(version));
+        }
+        postcard::from_bytes(rest).map_err(MatchSetupDecodeError::Malformed)
+    }
+}
+
+/// Error returned when a [`MatchSetupMessage`] could not be decoded.
+#[derive(Debug)]
+pub enum MatchSetupDecodeError {
+    /// The message was sent using a different protocol version.
+    ProtocolMismatch(u16),
+    /// The message could not be decoded.
+    Malformed(postcard::Error),
+}
+
+impl std::fmt::Display for MatchSetupDecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MatchSetupDecodeError::ProtocolMismatch(version) => write!(
+                f,
+                "message uses protocol version {version}, \
+                expected {MATCH_SETUP_PROTOCOL_VERSION}"
+            ),
+            MatchSetupDecodeError::Malformed(e) => write!(f, "malformed message: {e}"),
+        }
+    }
+}
+
+/// The versions that a player sends in its [`MatchSetupMessage::Hello`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PeerVersion {
+    /// The game version, from the crate version.
+    pub game: String,
+    /// A hash of the core game assets, see [`PeerVersion::new`].
+    pub assets: u64,
+}
+
+impl PeerVersion {
+    /// Get the version of the running game.
+    ///
+    /// The asset hash covers the player, hat, and element lists and the contents of the network
+    /// playable maps, which is what has to match for a network game to stay in sync.
+    pub fn new(core: &CoreMeta, map_assets: &Assets<MapMeta>) -> Self {
+        let mut hasher = FnvHasher::default();
+        hasher.write_serialized(&core.players);
+        hasher.write_serialized(&core.player_hats);
+        hasher.write_serialized(&core.map_elements);
+        for map_handle in core.stable_maps.iter().chain(&core.experimental_maps) {
+            hasher.write_serialized(map_handle);
+            if let Some(map_meta) = map_assets.get(&map_handle.get_bevy_handle()) {
+                hasher.write_serialized(map_meta);
+            }
+        }
+
+        Self {
+            game: env!("CARGO_PKG_VERSION").into(),
+            assets: hasher.finish(),
+        }
+    }
+}
+
+/// Network message that may be sent during player selection.
+///
+/// Clients may have several local players, so every message contains the index of the player it
+/// is about, which must be one of the players of the client that sent it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum PlayerSelectMessage {
+    SelectPlayer {
+        player: usize,
+        handle: bones::Handle<PlayerMeta>,
+    },
+    SelectHat {
+        player: usize,
+        hat: Option<bones::Handle<HatMeta>>,
+    },
+    ConfirmSelection {
+        player: usize,
+        confirmed: bool,
+    },
+}
+
+impl PlayerSelectMessage {
+    /// Get the index of the player that the message is about.
+    pub fn player(&self) -> usize {
+        match self {
+            PlayerSelectMessage::SelectPlayer { player, .. }
+            | PlayerSelectMessage::SelectHat { player, .. }
+            | PlayerSelectMessage::ConfirmSelection { player, .. } => *player,
+        }
+    }
+}
+
+/// Network message that may be sent in the lobby, between the player selection and the map
+/// selection.
+///
+/// Only the host may change the [`MatchRules`], kick clients, and start the match.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum LobbyMessage {
+    /// A chat message typed in by a player.
+    Chat(String),
+    /// One of the preset chat messages.
+    QuickChat(QuickChat),
+    /// Whether the client that sent it is ready to start the match.
+    Ready(bool),
+    /// Sent by the host whenever it changes the rules of the match.
+    Rules(MatchRules),
+    /// Sent by the host to remove a client from the match.
+    Kick { client: usize },
+    /// Sent by the host to go on to the map selection, with the final rules of the match.
+    Start {
+        rules: MatchRules,
+        /// A random seed shared by everybody in the match, which breaks ties in the map vote.
+        seed: u64,
+    },
+}
+
+/// The preset messages of the lobby chat, which are shown in each player's own language.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuickChat {
+    Hello,
+    GoodLuck,
+    Ready,
+    Wait,
+    GoodGame,
+    Thanks,
+}
+
+impl QuickChat {
+    /// All of the quick chat messages, in the order they are shown in.
+    pub const ALL: [QuickChat; 6] = [
+        QuickChat::Hello,
+        QuickChat::GoodLuck,
+        QuickChat::Ready,
+        QuickChat::Wait,
+        QuickChat::GoodGame,
+        QuickChat::Thanks,
+    ];
+
+    /// Get the localization key of the message.
+    pub fn localization_key(&self) -> &'static str {
+        match self {
+            QuickChat::Hello => "quick-chat-hello",
+            QuickChat::GoodLuck => "quick-chat-good-luck",
+            QuickChat::Ready => "quick-chat-ready",
+            QuickChat::Wait => "quick-chat-wait",
+            QuickChat::GoodGame => "quick-chat-good-game",
+            QuickChat::Thanks => "quick-chat-thanks",
+        }
+    }
+}
+
+/// The rules of a network match, which the host picks in the lobby.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MatchRules {
+    /// The core maps that may be played. When empty, all of them may be played.
+    pub map_pool: Vec<bones::Handle<MapMeta>>,
+    /// The number of lives of the players. When not set, the lives from the game config are used.
+    pub lives: Option<u32>,
+    /// The number of rounds in the match. The map is reset at the start of every round.
+    pub rounds: u32,
+    /// Whether or not items are spawned on the map.
+    pub items_enabled: bool,
+    /// Whether the map is picked by a vote of every client, instead of by the host.
+    pub map_voting: bool,
+}
+
+impl Default for MatchRules {
+    fn default() -> Self {
+        Self {
+            map_pool: Vec::new(),
+            lives: None,
+            rounds: 1,
+            items_enabled: true,
+            map_voting: false,
+        }
+    }
+}
+
+impl MatchRules {
+    /// The most lives the host can pick.
+    pub const MAX_LIVES: u32 = 99;
+    /// The most rounds the host can pick.
+    pub const MAX_ROUNDS: u32 = 15;
+
+    /// Whether or not the core map with the given handle may be played.
+    pub fn allows_map(&self, handle: &bones::Handle<MapMeta>) -> bool {
+        self.map_pool.is_empty() || self.map_pool.iter().any(|x| x.path == handle.path)
+    }
+
+    /// Add the core map with the given handle to the map pool, or remove it if it's already in it.
+    ///
+    /// `all_maps` are all of the core maps, which the pool is filled with before the first map is
+    /// removed from it, since an empty pool allows every map.
+    pub fn toggle_map(
+        &mut self,
+        handle: &bones::Handle<MapMeta>,
+        all_maps: &[bones::Handle<MapMeta>],
+    ) {
+        if self.map_pool.is_empty() {
+            self.map_pool = all_maps.to_vec();
+        }
+        if let Some(i) = self.map_pool.iter().position(|x| x.path == handle.path) {
+            self.map_pool.remove(i);
+        } else {
+            self.map_pool.push(handle.clone());
+        }
+        if self.map_pool.len() == all_maps.len() {
+            self.map_pool.clear();
+        }
+    }
+
+    /// Get the core metadata that a match with these rules is played with.
+    ///
+    /// A round only ends once a single player is left standing, so when there are several rounds
+    /// and neither these rules nor the game config limit the respawns, the players get one life.
+    pub fn apply(&self, meta: &Arc<CoreMeta>) -> Arc<CoreMeta> {
+        if self.lives.is_none() && self.rounds == meta.config.rounds.count && self.items_enabled {
+            return meta.clone();
+        }
+
+        let mut meta = CoreMeta::clone(meta);
+        let respawn = &mut meta.config.respawn;
+        if let Some(lives) = self.lives {
+            respawn.lives = Some(lives);
+        } else if self.rounds > 1 && respawn.lives.is_none() && respawn.time_limit.is_none() {
+            respawn.lives = Some(1);
+        }
+        meta.config.rounds.count = self.rounds;
+        meta.config.disable_items = !self.items_enabled;
+        Arc::new(meta)
+    }
+}
+
+/// Pick the winner of a map vote, given the index of the map that each client voted for, out of
+/// `map_count` maps.
+///
+/// Ties between the maps with the most votes are broken with the `seed` shared by everybody in the
+/// match, so that every client would pick the same map. Returns `None` if nobody voted.
+pub fn map_vote_winner(votes: &[Option<usize>], map_count: usize, seed: u64) -> Option<usize> {
+    let mut counts = vec![0; map_count];
+    for &map in votes.iter().flatten() {
+        if let Some(count) = counts.get_mut(map) {
+            *count += 1;
+        }
+    }
+    let most_votes = counts.iter().copied().max().filter(|x| *x > 0)?;
+    let tied = (0..map_count)
+        .filter(|x| counts[*x] == most_votes)
+        .collect::<Vec<_>>();
+    Some(tied[(seed % tied.len() as u64) as usize])
+}
+
+/// Get the hash used to check that two players have identical copies of a map.
+pub fn map_hash(map_meta: &MapMeta) -> u64 {
+    let mut hasher = FnvHasher::default();
+    hasher.write_serialized(map_meta);
+    hasher.finish()
+}
+
+/// Network message that may be sent when selecting a map.
+///
+/// The host offers the map it selected to the other players, who either already have an identical
+/// copy of it, or request it from the host. Once every player has the map, the host starts the
+/// match.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum MapSelectMessage {
+    /// Sent by the host when it has selected a map. The handle is set if the map is one of the core
+    /// maps.
+    OfferMap {
+        hash: u64,
+        handle: Option<bones::Handle<MapMeta>>,
+    },
+    /// Sent to the host by a player that doesn't have the offered map.
+    RequestMap { hash: u64 },
+    /// A chunk of the compressed map, sent by the host to the players that requested it.
+    MapChunk {
+        hash: u64,
+        index: u32,
+        count: u32,
+        data: Vec<u8>,
+    },
+    /// Sent to the host by a player once it has the offered map.
+    HaveMap { hash: u64 },
+    /// Sent by the host once every player has the map, to start the match with the host's
+    /// settings.
+    StartMatch { hash: u64, settings: MatchSettings },
+    /// Sent by every client to the others when voting for the map, see [`MatchRules::map_voting`].
+    ///
+    /// A client may change its vote until the host sends the [`MapSelectMessage::VoteResult`].
+    VoteMap { handle: bones::Handle<MapMeta> },
+    /// Sent by the host once every client voted, with the map that won the vote. The host offers
+    /// the map right after this.
+    VoteResult { handle: bones::Handle<MapMeta> },
+}
+
+/// The settings of a network match, decided by the host and sent to everybody when the match
+/// starts.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MatchSettings {
+    /// The players selected for each player slot.
+    ///
+    /// Spectators didn't see the player selection, so they rely on this to know who is playing.
+    pub players: [Option<MatchPlayer>; MAX_PLAYERS],
+    /// What happens to players that disconnect during the match.
+    pub disconnect_policy: DisconnectPolicy,
+    /// The multiplier for the [`jumpy_core::FPS`] that the match runs at, see
+    /// [`network_frame_rate_factor`][super::network_frame_rate_factor].
+    pub frame_rate_factor: f32,
+    /// The rules picked by the host in the lobby.
+    pub rules: MatchRules,
+    /// Whether or not each client was kicked from the match in the lobby.
+    ///
+    /// Spectators and players that missed the kick rely on this to leave the kicked clients out of
+    /// the match.
+    pub kicked_clients: [bool; MAX_PLAYERS],
+}
+
+/// A player of a network match, in the [`MatchSettings`].
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MatchPlayer {
+    pub player: bones::Handle<PlayerMeta>,
+    pub hat: Option<bones::Handle<HatMeta>>,
+    pub is_ai: bool,
+}
+
+impl From<MatchPlayer> for GameSessionPlayerInfo {
+    fn from(player: MatchPlayer) -> Self {
+        Self {
+            player: player.player,
+            hat: player.hat,
+            is_ai: player.is_ai,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use jumpy_core::metadata::AimMode;
+
+    use super::*;
+
+    /// Encode a movement direction the way it is sent over the network, and decode it again.
+    fn round_trip(direction: Vec2) -> Vec2 {
+        let bits: u16 = DenseMoveDirection(direction).into();
+        DenseMoveDirection::from(bits).0
+    }
+
+    #[test]
+    fn test_eight_way_aim_round_trip() {
+        let diagonal = std::f32::consts::FRAC_1_SQRT_2;
+        for x in [-1.0, 0.0, 1.0] {
+            for y in [-1.0, 0.0, 1.0] {
+                for stick in [Vec2::new(x, y), Vec2::new(x, y) * diagonal] {
+                    assert_eq!(
+                        AimMode::EightWay.direction(stick),
+                        AimMode::EightWay.direction(round_trip(stick)),
+                        "stick direction: {stick}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_analog_aim_round_trip() {
+        for i in 0..32 {
+            let angle = i as f32 / 32.0 * std::f32::consts::TAU;
+            let stick = Vec2::from_angle(angle);
+            let local = AimMode::Analog.direction(stick).unwrap();
+            let remote = AimMode::Analog.direction(round_trip(stick)).unwrap();
+            assert!(
+                local.angle_between(remote).abs() < 0.05,
+                "stick direction: {stick}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_match_setup_protocol_version() {
+        let message = MatchSetupMessage::PlayerSelect(PlayerSelectMessage::ConfirmSelection {
+            player: 1,
+            confirmed: true,
+        });
+        let bytes = message.encode();
+        assert!(matches!(
+            MatchSetupMessage::decode(&bytes),
+            Ok(MatchSetupMessage::PlayerSelect(
+                PlayerSelectMessage::ConfirmSelection {
+                    player: 1,
+                    confirmed: true
+                }
+            ))
+        ));
+
+        let mut other_version = postcard::to_allocvec(&(MATCH_SETUP_PROTOCOL_VERSION + 1)).unwrap();
+        other_version.extend(postcard::to_allocvec(&message).unwrap());
+        assert!(matches!(
+            MatchSetupMessage::decode(&other_version),
+            Err(MatchSetupDecodeError::ProtocolMismatch(version))
+                if version == MATCH_SETUP_PROTOCOL_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn test_map_vote_winner() {
+        assert_eq!(map_vote_winner(&[None, None], 3, 0), None);
+        assert_eq!(map_vote_winner(&[Some(2), Some(1), Some(2)], 3, 0), Some(2));
+
+        // Ties are broken by the seed, the same way for everybody
+        let votes = [Some(0), Some(2), Some(1), Some(2), Some(0)];
+        assert_eq!(map_vote_winner(&votes, 3, 0), Some(0));
+        assert_eq!(map_vote_winner(&votes, 3, 1), Some(2));
+        assert_eq!(map_vote_winner(&votes, 3, 7), Some(2));
+    }
+}
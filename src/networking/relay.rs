@@ -0,0 +1,379 @@
+//! WebSocket relay transport, for playing online with players that can't use QUIC.
+//!
+//! When the matchmaking server in the settings is a `ws://` or `wss://` URL, online matches are
+//! found through a `jumpy_relay` server instead of the QUIC matchmaker. The relay speaks the same
+//! [`bones_matchmaker_proto`] protocol, with every message sent as a binary WebSocket message, and
+//! proxies all of the messages of the match once it starts.
+//!
+//! The connection is made with [`ewebsock`], which uses the browser's WebSockets in the web build,
+//! so nothing here depends on QUIC.
+
+use std::ops::ControlFlow;
+
+use bevy::tasks::IoTaskPool;
+use bones_matchmaker_proto::{
+    MatchInfo, MatchmakerRequest, MatchmakerResponse, RecvProxyMessage, SendProxyMessage,
+    TargetClient,
+};
+use ewebsock::{WsEvent, WsMessage};
+use serde::de::DeserializeOwned;
+
+use super::{
+    online::{OnlineMatchmakerRequest, OnlineMatchmakerResponse},
+    *,
+};
+
+/// Whether a matchmaking server address is the URL of a relay.
+pub fn is_relay_url(addr: &str) -> bool {
+    let addr = addr.trim();
+    addr.starts_with("ws://") || addr.starts_with("wss://")
+}
+
+/// The tag in front of every message proxied by the relay, which says what it is for.
+///
+/// WebSockets only have one channel, so the GGRS messages and the reliable messages are told apart
+/// with this.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+enum MessageKind {
+    Ggrs = 0,
+    Reliable = 1,
+}
+
+/// An open WebSocket connection to a relay.
+#[derive(Debug, Clone)]
+struct RelayConnection {
+    /// The messages to send, which are sent by a task that owns the WebSocket.
+    outgoing: async_channel::Sender<Vec<u8>>,
+    events: async_channel::Receiver<WsEvent>,
+}
+
+impl RelayConnection {
+    /// Connect to the relay at `url`, and wait for the connection to open.
+    async fn connect(url: &str) -> Result<Self, NetworkError> {
+        let (event_sender, events) = async_channel::unbounded();
+        let mut ws_sender = ewebsock::ws_connect(
+            url.trim().into(),
+            Box::new(move |event| match event_sender.try_send(event) {
+                Ok(()) => ControlFlow::Continue(()),
+                Err(_) => ControlFlow::Break(()),
+            }),
+        )
+        .map_err(NetworkError::ConnectionFailed)?;
+
+        match events.recv().await {
+            Ok(WsEvent::Opened) => (),
+            Ok(WsEvent::Error(e)) => return Err(NetworkError::ConnectionFailed(e)),
+            _ => {
+                return Err(NetworkError::ConnectionFailed(
+                    "the relay closed the connection".into(),
+                ))
+            }
+        }
+
+        let (outgoing, outgoing_receiver) = async_channel::unbounded::<Vec<u8>>();
+        IoTaskPool::get()
+            .spawn(async move {
+                // The WebSocket is closed when the sender is dropped
+                while let Ok(data) = outgoing_receiver.recv().await {
+                    ws_sender.send(WsMessage::Binary(data));
+                }
+            })
+            .detach();
+
+        Ok(Self { outgoing, events })
+    }
+
+    /// Send a message to the relay.
+    fn send(&self, message: &impl Serialize) -> Result<(), NetworkError> {
+        self.outgoing
+            .try_send(postcard::to_allocvec(message)?)
+            .map_err(|_| NetworkError::ConnectionLost("the relay connection is closed".into()))
+    }
+
+    /// Receive the next binary message from the relay.
+    async fn recv_binary(&self) -> Result<Vec<u8>, NetworkError> {
+        loop {
+            match self.events.recv().await {
+                Ok(WsEvent::Message(WsMessage::Binary(data))) => return Ok(data),
+                Ok(WsEvent::Message(_) | WsEvent::Opened) => (),
+                Ok(WsEvent::Error(e)) => return Err(NetworkError::ConnectionLost(e)),
+                Ok(WsEvent::Closed) | Err(_) => {
+                    return Err(NetworkError::ConnectionLost(
+                        "the relay closed the connection".into(),
+                    ))
+                }
+            }
+        }
+    }
+
+    /// Receive the next message from the relay.
+    async fn recv<T: DeserializeOwned>(&self) -> Result<T, NetworkError> {
+        Ok(postcard::from_bytes(&self.recv_binary().await?)?)
+    }
+
+    fn close(&self) {
+        self.outgoing.close();
+        self.events.close();
+    }
+}
+
+/// Connect to the relay at `url` and wait for a match, until it is found or the search is canceled
+/// from the UI.
+///
+/// This works like searching with the QUIC matchmaker, see [`online`].
+pub(super) async fn search_for_game(
+    matchmaker_channel: &BiChannelServer<OnlineMatchmakerRequest, OnlineMatchmakerResponse>,
+    url: &str,
+    client_count: usize,
+    local_player_count: usize,
+    match_data: Vec<u8>,
+) -> Result<(), NetworkError> {
+    info!(%url, "Connecting to relay");
+    let conn = RelayConnection::connect(url).await?;
+    info!("Connected to relay");
+
+    matchmaker_channel
+        .try_send(OnlineMatchmakerResponse::Searching)
+        .ok();
+
+    conn.send(&MatchmakerRequest::RequestMatch(MatchInfo {
+        // There can't be more clients than players
+        client_count: client_count.min(MAX_PLAYERS) as _,
+        match_data,
+    }))?;
+    if !matches!(conn.recv().await?, MatchmakerResponse::Accepted) {
+        return Err(NetworkError::InvalidMessage(
+            "Relay did not accept the match request".into(),
+        ));
+    }
+    info!("Waiting for match...");
+
+    loop {
+        let next_message = futures_lite::future::or(
+            async { either::Left(matchmaker_channel.recv().await) },
+            async { either::Right(conn.recv::<MatchmakerResponse>().await) },
+        )
+        .await;
+
+        match next_message {
+            // UI message
+            either::Either::Left(message) => {
+                let Ok(message) = message else {
+                    conn.close();
+                    return Ok(());
+                };
+
+                match message {
+                    OnlineMatchmakerRequest::SearchForGame { .. } => {
+                        warn!("Ignoring search request while already searching for a game");
+                    }
+                    OnlineMatchmakerRequest::StopSearch => {
+                        info!("Canceling relay search");
+                        conn.close();
+                        return Ok(());
+                    }
+                }
+            }
+
+            // Relay message
+            either::Either::Right(message) => match message? {
+                MatchmakerResponse::ClientCount(count) => {
+                    let count = count as usize * local_player_count;
+                    info!("Relay match player count: {count}");
+                    matchmaker_channel
+                        .try_send(OnlineMatchmakerResponse::PlayerCount(count))
+                        .ok();
+                }
+                MatchmakerResponse::Success {
+                    random_seed,
+                    player_idx,
+                    client_count,
+                } => {
+                    let client_idx = player_idx as usize;
+                    let client_count = client_count as usize;
+                    info!(%random_seed, %client_idx, %client_count, "Relay match complete");
+                    if client_idx >= client_count || client_count * local_player_count > MAX_PLAYERS
+                    {
+                        return Err(NetworkError::InvalidMessage(format!(
+                            "Invalid client index {client_idx} for {client_count} clients"
+                        )));
+                    }
+                    let client_player_counts = std::array::from_fn(|i| {
+                        if i < client_count {
+                            local_player_count
+                        } else {
+                            0
+                        }
+                    });
+
+                    matchmaker_channel
+                        .try_send(OnlineMatchmakerResponse::GameStarting {
+                            socket: Box::new(RelaySocket::new(
+                                client_idx,
+                                client_player_counts,
+                                conn,
+                            )),
+                            client_idx,
+                            player_count: client_count * local_player_count,
+                        })
+                        .ok();
+                    return Ok(());
+                }
+                _ => {
+                    return Err(NetworkError::InvalidMessage(
+                        "Unexpected message from relay".into(),
+                    ))
+                }
+            },
+        }
+    }
+}
+
+/// A [`NetworkSocket`] that sends all of its messages through a relay.
+#[derive(Debug, Clone)]
+pub struct RelaySocket {
+    conn: RelayConnection,
+    ggrs_receiver: async_channel::Receiver<(usize, ggrs::Message)>,
+    reliable_receiver: async_channel::Receiver<(usize, Vec<u8>)>,
+    error_receiver: async_channel::Receiver<NetworkError>,
+    client_idx: usize,
+    client_player_counts: [usize; MAX_PLAYERS],
+}
+
+impl RelaySocket {
+    fn new(
+        client_idx: usize,
+        client_player_counts: [usize; MAX_PLAYERS],
+        conn: RelayConnection,
+    ) -> Self {
+        let (ggrs_sender, ggrs_receiver) = async_channel::unbounded();
+        let (reliable_sender, reliable_receiver) = async_channel::unbounded();
+        let (error_sender, error_receiver) = async_channel::unbounded();
+
+        let conn_ = conn.clone();
+        IoTaskPool::get()
+            .spawn(async move {
+                let conn = conn_;
+                loop {
+                    let message = match conn.recv::<RecvProxyMessage>().await {
+                        Ok(message) => message,
+                        Err(NetworkError::InvalidMessage(e)) => {
+                            warn!("Ignoring invalid relay message: {e}");
+                            continue;
+                        }
+                        Err(e) => {
+                            // The socket was closed on our side if the channels are closed
+                            if !conn.events.is_closed() {
+                                warn!("Relay connection error: {e}");
+                                error_sender.try_send(e).ok();
+                            }
+                            break;
+                        }
+                    };
+                    let client = message.from_client as usize;
+
+                    let sent = match message.message.split_first() {
+                        Some((kind, data)) if *kind == MessageKind::Ggrs as u8 => {
+                            let Ok(message) = postcard::from_bytes(data) else {
+                                warn!(%client, "Ignoring invalid network message");
+                                continue;
+                            };
+                            ggrs_sender.send((client, message)).await.is_ok()
+                        }
+                        Some((kind, data)) if *kind == MessageKind::Reliable as u8 => {
+                            reliable_sender.send((client, data.to_vec())).await.is_ok()
+                        }
+                        _ => {
+                            warn!(%client, "Ignoring network message of unknown kind");
+                            continue;
+                        }
+                    };
+                    if !sent {
+                        break;
+                    }
+                }
+            })
+            .detach();
+
+        Self {
+            conn,
+            ggrs_receiver,
+            reliable_receiver,
+            error_receiver,
+            client_idx,
+            client_player_counts,
+        }
+    }
+
+    /// Send a message of the given `kind` to the `target_client` through the relay.
+    fn send(&self, target_client: TargetClient, kind: MessageKind, data: &[u8]) {
+        let mut message = Vec::with_capacity(data.len() + 1);
+        message.push(kind as u8);
+        message.extend_from_slice(data);
+
+        if let Err(e) = self.conn.send(&SendProxyMessage {
+            target_client,
+            message,
+        }) {
+            warn!("Couldn't send network message: {e}");
+        }
+    }
+}
+
+impl NetworkSocket for RelaySocket {
+    fn ggrs_socket(&self) -> BoxedNonBlockingSocket {
+        BoxedNonBlockingSocket(Box::new(self.clone()))
+    }
+
+    fn send_reliable(&self, target: SocketTarget, message: &[u8]) {
+        let target_client = match target {
+            SocketTarget::Client(client) => TargetClient::One(client as _),
+            SocketTarget::All => TargetClient::All,
+        };
+        self.send(target_client, MessageKind::Reliable, message);
+    }
+
+    fn recv_reliable(&self) -> Vec<(usize, Vec<u8>)> {
+        std::iter::from_fn(|| self.reliable_receiver.try_recv().ok()).collect()
+    }
+
+    fn close(&self) {
+        self.conn.close();
+    }
+
+    fn client_idx(&self) -> usize {
+        self.client_idx
+    }
+
+    fn client_player_counts(&self) -> [usize; MAX_PLAYERS] {
+        self.client_player_counts
+    }
+
+    fn spectator_count(&self) -> usize {
+        // Like the matchmaker, the relay doesn't support spectators.
+        0
+    }
+
+    fn spectator_addrs(&self) -> Vec<usize> {
+        Vec::new()
+    }
+
+    fn take_error(&self) -> Option<NetworkError> {
+        self.error_receiver.try_recv().ok()
+    }
+}
+
+impl ggrs::NonBlockingSocket<usize> for RelaySocket {
+    fn send_to(&mut self, msg: &ggrs::Message, addr: &usize) {
+        self.send(
+            TargetClient::One(*addr as u8),
+            MessageKind::Ggrs,
+            &postcard::to_allocvec(msg).unwrap(),
+        );
+    }
+
+    fn receive_all_messages(&mut self) -> Vec<(usize, ggrs::Message)> {
+        std::iter::from_fn(|| self.ggrs_receiver.try_recv().ok()).collect()
+    }
+}
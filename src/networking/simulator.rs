@@ -0,0 +1,238 @@
+//! Network condition simulator, for reproducing bad connections locally.
+//!
+//! Every [`NetworkMatchSocket`] is wrapped in a [`SimulatedSocket`], which applies the current
+//! [`NETWORK_CONDITIONS`] to the messages we receive. The conditions can be changed at any time
+//! from the network debug window, and while they are disabled, messages pass straight through.
+//!
+//! Because the conditions are only applied when receiving, running two local clients with the same
+//! conditions makes both directions of the connection equally bad.
+
+use std::{
+    collections::VecDeque,
+    sync::{Mutex, RwLock},
+    time::Duration,
+};
+
+use bevy::utils::Instant;
+use rand::Rng;
+
+use super::*;
+
+/// The network conditions applied by all [`SimulatedSocket`]s.
+pub static NETWORK_CONDITIONS: Lazy<RwLock<NetworkConditions>> = Lazy::new(default);
+
+/// Bad network conditions to simulate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NetworkConditions {
+    /// Whether the conditions are applied at all.
+    pub enabled: bool,
+    /// The delay added to every message.
+    pub latency: Duration,
+    /// The largest random delay added on top of the [`latency`][Self::latency].
+    pub jitter: Duration,
+    /// The chance, from `0` to `1`, that an unreliable message is lost.
+    ///
+    /// Reliable messages can't be lost, so they are delayed as if they had to be re-sent instead.
+    pub packet_loss: f32,
+    /// The chance, from `0` to `1`, that an unreliable message is received twice.
+    pub duplication: f32,
+    /// The chance, from `0` to `1`, that a message is held back long enough to arrive after the
+    /// messages sent after it.
+    pub reordering: f32,
+}
+
+impl Default for NetworkConditions {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            latency: Duration::from_millis(50),
+            jitter: Duration::from_millis(10),
+            packet_loss: 0.0,
+            duplication: 0.0,
+            reordering: 0.0,
+        }
+    }
+}
+
+impl NetworkConditions {
+    /// Get the current network conditions, or `None` if they are disabled.
+    pub fn get() -> Option<Self> {
+        let conditions = *NETWORK_CONDITIONS.read().unwrap();
+        conditions.enabled.then_some(conditions)
+    }
+
+    /// Get a random delay for a message.
+    fn delay(&self, rng: &mut impl Rng) -> Duration {
+        let mut delay = self.latency + self.jitter.mul_f32(rng.gen());
+        if rng.gen::<f32>() < self.reordering {
+            // Hold the message back by a whole round trip, so it's overtaken by the next ones
+            delay += self.latency * 2 + self.jitter;
+        }
+        delay
+    }
+
+    /// Get the delay of a reliable message, which is re-sent until it gets through.
+    fn reliable_delay(&self, rng: &mut impl Rng) -> Duration {
+        let mut delay = self.delay(rng);
+        // Give up on re-sending eventually, in case the loss is set to 100%
+        for _ in 0..10 {
+            if rng.gen::<f32>() >= self.packet_loss {
+                break;
+            }
+            // It takes a round trip to notice that the message was lost
+            delay += self.latency * 2 + self.delay(rng);
+        }
+        delay
+    }
+}
+
+/// A queue of messages that are released once their delay is over.
+struct DelayQueue<T> {
+    /// The messages, with the time they are received at.
+    messages: VecDeque<(Instant, T)>,
+}
+
+impl<T> Default for DelayQueue<T> {
+    fn default() -> Self {
+        Self {
+            messages: default(),
+        }
+    }
+}
+
+impl<T> DelayQueue<T> {
+    /// Add a message that will be received after `delay`.
+    fn push(&mut self, delay: Duration, message: T) {
+        self.messages.push_back((Instant::now() + delay, message));
+    }
+
+    /// Take the messages that have been delayed long enough, in the order they are received.
+    fn take_ready(&mut self) -> Vec<T> {
+        let now = Instant::now();
+        let mut ready = Vec::new();
+        let mut waiting = VecDeque::new();
+        for (time, message) in self.messages.drain(..) {
+            if time <= now {
+                ready.push((time, message));
+            } else {
+                waiting.push_back((time, message));
+            }
+        }
+        self.messages = waiting;
+        ready.sort_by_key(|(time, _)| *time);
+        ready.into_iter().map(|(_, message)| message).collect()
+    }
+
+    /// Take all of the messages, without waiting for their delay.
+    fn take_all(&mut self) -> Vec<T> {
+        self.messages
+            .drain(..)
+            .map(|(_, message)| message)
+            .collect()
+    }
+}
+
+/// A [`NetworkSocket`] that applies the [`NETWORK_CONDITIONS`] to the messages received by another
+/// socket.
+pub struct SimulatedSocket {
+    socket: Box<dyn NetworkSocket>,
+    reliable_queue: Mutex<DelayQueue<(usize, Vec<u8>)>>,
+}
+
+impl SimulatedSocket {
+    pub fn new(socket: Box<dyn NetworkSocket>) -> Self {
+        Self {
+            socket,
+            reliable_queue: default(),
+        }
+    }
+}
+
+impl NetworkSocket for SimulatedSocket {
+    fn ggrs_socket(&self) -> BoxedNonBlockingSocket {
+        BoxedNonBlockingSocket(Box::new(SimulatedGgrsSocket {
+            socket: self.socket.ggrs_socket(),
+            queue: default(),
+        }))
+    }
+
+    fn send_reliable(&self, target: SocketTarget, message: &[u8]) {
+        self.socket.send_reliable(target, message)
+    }
+
+    fn recv_reliable(&self) -> Vec<(usize, Vec<u8>)> {
+        let messages = self.socket.recv_reliable();
+        let mut queue = self.reliable_queue.lock().unwrap();
+
+        let Some(conditions) = NetworkConditions::get() else {
+            // Don't hold on to the delayed messages when the simulation is turned off
+            let mut delayed = queue.take_all();
+            delayed.extend(messages);
+            return delayed;
+        };
+
+        let mut rng = rand::thread_rng();
+        for message in messages {
+            queue.push(conditions.reliable_delay(&mut rng), message);
+        }
+        queue.take_ready()
+    }
+
+    fn close(&self) {
+        self.socket.close()
+    }
+
+    fn client_idx(&self) -> usize {
+        self.socket.client_idx()
+    }
+
+    fn client_player_counts(&self) -> [usize; MAX_PLAYERS] {
+        self.socket.client_player_counts()
+    }
+
+    fn spectator_count(&self) -> usize {
+        self.socket.spectator_count()
+    }
+
+    fn spectator_addrs(&self) -> Vec<usize> {
+        self.socket.spectator_addrs()
+    }
+
+    fn take_error(&self) -> Option<NetworkError> {
+        self.socket.take_error()
+    }
+}
+
+/// The GGRS socket of a [`SimulatedSocket`].
+struct SimulatedGgrsSocket {
+    socket: BoxedNonBlockingSocket,
+    queue: DelayQueue<(usize, ggrs::Message)>,
+}
+
+impl ggrs::NonBlockingSocket<usize> for SimulatedGgrsSocket {
+    fn send_to(&mut self, msg: &ggrs::Message, addr: &usize) {
+        self.socket.send_to(msg, addr)
+    }
+
+    fn receive_all_messages(&mut self) -> Vec<(usize, ggrs::Message)> {
+        let messages = self.socket.receive_all_messages();
+
+        let Some(conditions) = NetworkConditions::get() else {
+            let mut delayed = self.queue.take_all();
+            delayed.extend(messages);
+            return delayed;
+        };
+
+        let mut rng = rand::thread_rng();
+        for message in messages {
+            if rng.gen::<f32>() < conditions.packet_loss {
+                continue;
+            }
+            if rng.gen::<f32>() < conditions.duplication {
+                self.queue.push(conditions.delay(&mut rng), message.clone());
+            }
+            self.queue.push(conditions.delay(&mut rng), message);
+        }
+        self.queue.take_ready()
+    }
+}
@@ -14,7 +14,7 @@ use bevy::utils::Instant;
 use downcast_rs::{impl_downcast, Downcast};
 use jumpy_core::input::{PlayerControl, PlayerInputs};
 
-use crate::{main_menu::MenuPage, prelude::*};
+use crate::prelude::*;
 
 /// Session plugin.
 pub struct JumpySessionPlugin;
@@ -109,19 +109,33 @@ pub trait SessionRunner: Sync + Send + Downcast {
     ///
     /// This is used to created fixed refresh rates.
     fn run_criteria(&mut self, time: &Time) -> ShouldRun;
-    /// Returns the player index of the player if we are in a network game.
+    /// Returns the player indexes of the local players if we are in a network game.
     ///
-    /// In a network game, we currently only allow for one local player, so this allows the session
-    /// to find out which player we are playing as so it can map the local player 1's input to the
-    /// appropriate network player.
-    fn network_player_idx(&mut self) -> Option<usize>;
+    /// The network players don't necessarily start at player 1, so this allows the session to map
+    /// the local player 1's input to the first local network player, the local player 2's input to
+    /// the second one, and so on.
+    fn network_local_players(&mut self) -> Option<Vec<usize>>;
+    /// Take the sounds emitted by the game simulation since they were last taken.
+    ///
+    /// Sessions that roll back must tag the sounds with their frame, so that [`play_sounds`] can
+    /// tell the sounds that are emitted again after a rollback from new ones. By default, the
+    /// sounds are taken straight from the [`bones::AudioEvents`] of the world.
+    fn take_sounds(&mut self) -> SessionSounds {
+        SessionSounds {
+            sounds: drain_audio_events(self.world())
+                .into_iter()
+                .map(|event| FrameSound { frame: 0, event })
+                .collect(),
+            ..default()
+        }
+    }
 }
 impl_downcast!(SessionRunner);
 
 /// Possible errors returned by [`SessionRunner::advance`].
 pub enum SessionError {
-    /// The session was disconnected.
-    Disconnected,
+    /// The network session was disconnected.
+    Disconnected(crate::networking::NetworkError),
 }
 
 /// Implementation of [`SessionRunner`] for local games.
@@ -201,7 +215,7 @@ impl SessionRunner for LocalSessionRunner {
             ShouldRun::No
         }
     }
-    fn network_player_idx(&mut self) -> Option<usize> {
+    fn network_local_players(&mut self) -> Option<Vec<usize>> {
         None
     }
 }
@@ -231,16 +245,21 @@ impl<'w, 's> SessionManager<'w, 's> {
     }
 
     /// Start a network game session.
-    #[cfg(not(target_arch = "wasm32"))]
     pub fn start_network(
         &mut self,
         core_info: CoreSessionInfo,
         ggrs_info: crate::networking::GgrsSessionRunnerInfo,
     ) {
-        let session = Session(Box::new(crate::networking::GgrsSessionRunner::new(
-            CoreSession::new(core_info),
-            ggrs_info,
-        )));
+        let core = CoreSession::new(core_info);
+        let session = if ggrs_info.is_spectator {
+            Session(Box::new(crate::networking::GgrsSpectatorRunner::new(
+                core, ggrs_info,
+            )))
+        } else {
+            Session(Box::new(crate::networking::GgrsSessionRunner::new(
+                core, ggrs_info,
+            )))
+        };
         self.commands.insert_resource(session);
         self.menu_camera.for_each_mut(|mut x| x.is_active = false);
         self.commands
@@ -288,7 +307,7 @@ fn collect_local_input(
     player_input_collectors: Query<(&PlayerInputCollector, &ActionState<PlayerAction>)>,
     mut current_editor_input: ResMut<CurrentEditorInput>,
 ) {
-    let network_player_idx = session.network_player_idx();
+    let network_local_players = session.network_local_players();
 
     if let Some(local_session) = session.downcast_mut::<LocalSessionRunner>() {
         // TODO: Handle editor input for non-local sessions.
@@ -298,18 +317,26 @@ fn collect_local_input(
         });
     }
 
-    for (player_idx, action_state) in &player_input_collectors {
+    for (input_idx, action_state) in &player_input_collectors {
+        // In network games, the local inputs are mapped to the local network players in order
+        let player_idx = match &network_local_players {
+            Some(local_players) => match local_players.get(input_idx.0) {
+                Some(player_idx) => *player_idx,
+                None => continue,
+            },
+            None => input_idx.0,
+        };
         let is_ai = {
             let world = &session.core_session().world;
             let inputs = world.resource::<PlayerInputs>();
             let inputs = inputs.borrow();
-            inputs.players[player_idx.0].is_ai
+            inputs.players[player_idx].is_ai
         };
-        if (player_idx.0 != 0 && network_player_idx.is_some()) || is_ai {
+        if is_ai {
             continue;
         }
 
-        let mut control = session.0.get_player_input(player_idx.0);
+        let mut control = session.0.get_player_input(player_idx);
 
         let jump_pressed = action_state.pressed(PlayerAction::Jump);
         control.jump_just_pressed = jump_pressed && !control.jump_pressed;
@@ -328,7 +355,7 @@ fn collect_local_input(
         let is_moving = control.move_direction.length_squared() > f32::MIN_POSITIVE;
         control.just_moved = !was_moving && is_moving;
 
-        session.set_player_input(network_player_idx.unwrap_or(player_idx.0), control);
+        session.set_player_input(player_idx, control);
     }
 }
 
@@ -339,16 +366,19 @@ fn update_game(world: &mut World) {
     // Advance the game session
     if let Err(e) = session.advance(world) {
         match e {
-            SessionError::Disconnected => {
-                error!("Network session disconnected");
-                // Don't return the session to the world
-
-                // Go back to the menu
-                let mut cameras = world.query_filtered::<&mut Camera, With<MenuCamera>>();
-                cameras.for_each_mut(world, |mut camera| camera.is_active = true);
-                world.insert_resource(MenuPage::Home);
-                world.insert_resource(NextState(Some(EngineState::MainMenu)));
-                world.insert_resource(NextState(Some(InGameState::Playing)));
+            // Once the match is over, the other players going back to the menu from the results
+            // isn't an error, so the results stay up with the match continuing locally.
+            SessionError::Disconnected(error) if is_match_over(&mut session) => {
+                info!(%error, "Network session disconnected after the match was over");
+                let info = session.core_session().info.clone();
+                let core = std::mem::replace(session.core_session(), CoreSession::new(info));
+                world.insert_resource(Session(Box::new(LocalSessionRunner::new(core))));
+            }
+            SessionError::Disconnected(error) => {
+                error!(%error, "Network session disconnected");
+                // Don't return the session to the world, and let the player go back to the menu
+                // from the error dialog.
+                world.insert_resource(crate::ui::network_error::NetworkErrorNotice(error));
             }
         }
 
@@ -359,27 +389,133 @@ fn update_game(world: &mut World) {
     }
 }
 
-/// Play sounds from the game session.
-pub fn play_sounds(audio: Res<AudioChannel<EffectsChannel>>, mut session: ResMut<Session>) {
-    // Get the sound queue out of the world
-    let queue = session
+/// Whether or not the last round of the session's match is over.
+pub fn is_match_over(session: &mut Session) -> bool {
+    session
         .world()
+        .resource::<jumpy_core::rounds::MatchRounds>()
+        .borrow()
+        .outcome
+        .is_some()
+}
+
+/// A sound emitted by the game simulation, with the frame it was emitted on.
+pub struct FrameSound {
+    pub frame: i32,
+    pub event: bones::AudioEvent,
+}
+
+/// The sounds emitted by a session, returned by [`SessionRunner::take_sounds`].
+#[derive(Default)]
+pub struct SessionSounds {
+    /// The sounds in the order they were emitted.
+    pub sounds: Vec<FrameSound>,
+    /// The first frame that was simulated again because of a rollback, since the sounds were last
+    /// taken.
+    ///
+    /// The sounds of this frame and the frames after it that were already played were predicted.
+    /// The ones that still happen have been emitted again, and the others must be stopped.
+    pub rollback_frame: Option<i32>,
+    /// The last frame that can't be rolled back anymore, or `None` if the session never rolls back.
+    pub confirmed_frame: Option<i32>,
+}
+
+/// Take the sounds out of the [`bones::AudioEvents`] of a game world.
+pub fn drain_audio_events(world: &mut bones::World) -> Vec<bones::AudioEvent> {
+    world
         .run_initialized_system(move |mut audio_events: bones::ResMut<bones::AudioEvents>| {
             Ok(audio_events.queue.drain(..).collect::<Vec<_>>())
         })
-        .unwrap();
+        .unwrap()
+}
+
+/// A sound that was played for a frame that may still be rolled back.
+struct PlayedSound {
+    frame: i32,
+    sound_source: bones::Handle<bones::AudioSource>,
+    volume: f64,
+    instance: Handle<AudioInstance>,
+}
+
+/// The sounds played by [`play_sounds`] that may still be rolled back.
+#[derive(Default)]
+struct PlayedSounds {
+    sounds: Vec<PlayedSound>,
+    confirmed_frame: Option<i32>,
+}
 
-    // Play all the sounds in the queue
-    for event in queue {
+/// Play sounds from the game session.
+///
+/// In network games, sounds are played as soon as they are predicted. When a rollback re-simulates
+/// their frame, they aren't played again, and they are stopped if they don't happen anymore.
+pub fn play_sounds(
+    audio: Res<AudioChannel<EffectsChannel>>,
+    mut audio_instances: ResMut<Assets<AudioInstance>>,
+    mut played: Local<PlayedSounds>,
+    mut session: ResMut<Session>,
+) {
+    let SessionSounds {
+        sounds,
+        rollback_frame,
+        confirmed_frame,
+    } = session.take_sounds();
+
+    // The frames start over in a new session
+    if confirmed_frame < played.confirmed_frame {
+        played.sounds.clear();
+    }
+    played.confirmed_frame = confirmed_frame;
+
+    // The sounds of the rolled back frames are only kept if they are emitted again
+    let mut rolled_back = Vec::new();
+    if let Some(rollback_frame) = rollback_frame {
+        let (sounds, kept) = std::mem::take(&mut played.sounds)
+            .into_iter()
+            .partition(|x| x.frame >= rollback_frame);
+        rolled_back = sounds;
+        played.sounds = kept;
+    }
+
+    for FrameSound { frame, event } in sounds {
         match event {
             bones::AudioEvent::PlaySound {
                 sound_source,
                 volume,
             } => {
-                audio
+                let already_played = rolled_back.iter().position(|x: &PlayedSound| {
+                    x.frame == frame
+                        && x.sound_source.path == sound_source.path
+                        && x.volume == volume
+                });
+                if let Some(i) = already_played {
+                    played.sounds.push(rolled_back.swap_remove(i));
+                    continue;
+                }
+
+                let instance = audio
                     .play(sound_source.get_bevy_handle_untyped().typed())
-                    .with_volume(volume);
+                    .with_volume(volume)
+                    .handle();
+                played.sounds.push(PlayedSound {
+                    frame,
+                    sound_source,
+                    volume,
+                    instance,
+                });
             }
         }
     }
+
+    // Stop the predicted sounds that didn't happen after all
+    for sound in rolled_back {
+        if let Some(instance) = audio_instances.get_mut(&sound.instance) {
+            instance.stop(AudioTween::default());
+        }
+    }
+
+    // Forget the sounds that can't be rolled back anymore
+    match confirmed_frame {
+        Some(confirmed_frame) => played.sounds.retain(|x| x.frame > confirmed_frame),
+        None => played.sounds.clear(),
+    }
 }
@@ -15,7 +15,10 @@ pub mod widgets;
 
 pub mod debug_tools;
 pub mod editor;
+pub mod hud;
 pub mod main_menu;
+pub mod match_results;
+pub mod network_error;
 pub mod pause_menu;
 
 pub struct JumpyUiPlugin;
@@ -28,6 +31,8 @@ impl Plugin for JumpyUiPlugin {
             .add_plugin(editor::EditorPlugin)
             .add_plugin(debug_tools::DebugToolsPlugin)
             .add_plugin(pause_menu::PausePlugin)
+            .add_plugin(hud::HudPlugin)
+            .add_plugin(match_results::MatchResultsPlugin)
             .init_resource::<WidgetAdjacencies>()
             .init_resource::<DisableMenuInput>()
             .add_system(
@@ -40,6 +45,8 @@ impl Plugin for JumpyUiPlugin {
             )
             .add_system(update_egui_fonts)
             .add_system(update_ui_scale.run_if(resource_exists::<GameMeta>()));
+
+        app.add_plugin(network_error::NetworkErrorPlugin);
     }
 }
 
@@ -2,12 +2,11 @@ use bevy::{
     diagnostic::{Diagnostics, FrameTimeDiagnosticsPlugin},
     prelude::*,
 };
-use bevy_egui::{egui::Checkbox, *};
+use bevy_egui::*;
 use bevy_fluent::Localization;
 
 use crate::prelude::*;
 
-#[cfg(not(target_arch = "wasm32"))]
 use crate::networking::debug::{network_debug_window, NetworkDebug};
 
 pub struct DebugToolsPlugin;
@@ -24,7 +23,6 @@ impl Plugin for DebugToolsPlugin {
             .add_system(frame_diagnostic_window)
             .add_system(profiler_window);
 
-        #[cfg(not(target_arch = "wasm32"))]
         app.init_resource::<NetworkDebug>().add_system(
             network_debug_window
                 .in_base_set(CoreSet::Last)
@@ -56,7 +54,7 @@ pub struct ShowDebugWindows {
 
 /// Resource containing the bones snapshot.
 #[derive(Default, Resource)]
-struct BonesSnapshot(Option<bones::World>);
+struct BonesSnapshot(Option<jumpy_core::session::Snapshot>);
 
 /// System that renders the debug tools window which can be toggled by pressing F12
 fn debug_tools_window(
@@ -146,16 +144,11 @@ fn debug_tools_window(
                 format!("{} ( F7 )", localization.get("profiler")),
             );
 
-            let networking_enabled = cfg!(not(target_arch = "wasm32"));
             // Show network diagnostics
-            ui.add_enabled(
-                networking_enabled,
-                Checkbox::new(
-                    &mut show_debug_windows.network_debug,
-                    format!("{} ( F6 )", localization.get("network-diagnostics")),
-                ),
-            )
-            .on_disabled_hover_text(localization.get("networking-disabled-message"));
+            ui.checkbox(
+                &mut show_debug_windows.network_debug,
+                format!("{} ( F6 )", localization.get("network-diagnostics")),
+            );
 
             // Snapshot/Restore buttons
             ui.add_space(2.0);
@@ -175,8 +168,8 @@ fn debug_tools_window(
 
                         if ui.button(localization.get("restore-snapshot")).clicked() {
                             if let Some(mut session) = session {
-                                if let Some(snapshot) = &mut bones_world_snapshot.0 {
-                                    session.core_session().restore(&mut snapshot.clone())
+                                if let Some(snapshot) = &bones_world_snapshot.0 {
+                                    session.core_session().restore(snapshot.clone())
                                 }
                             }
                         }
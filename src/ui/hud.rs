@@ -0,0 +1,528 @@
+//! The in-game heads-up display.
+
+use bevy::window::PrimaryWindow;
+use bevy_egui::*;
+use bevy_fluent::Localization;
+use bones_bevy_renderer::BevyBonesEntity;
+use jumpy_core::{
+    elements::player_spawner::{MatchOutcome, PlayerRespawns},
+    input::PlayerInputs,
+    item::{Inventory, ItemUses},
+    metadata::ItemUsesKind,
+    rounds::MatchRounds,
+    status_effect::{StatusEffectKind, StatusEffects},
+    MAX_PLAYERS,
+};
+
+use crate::prelude::*;
+
+use super::widgets::EguiUiExt;
+
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(
+            player_status_hud
+                .run_if(in_state(EngineState::InGame))
+                .run_if(in_state(GameEditorState::Hidden))
+                .run_if(resource_exists::<Session>()),
+        )
+        .add_system(
+            match_hud
+                .run_if(in_state(EngineState::InGame))
+                .run_if(in_state(GameEditorState::Hidden))
+                .run_if(resource_exists::<Session>()),
+        );
+
+        app.add_system(
+            spectator_hud
+                .run_if(in_state(EngineState::InGame))
+                .run_if(resource_exists::<Session>())
+                .run_if(resource_exists::<crate::networking::NetworkMatchSocket>()),
+        )
+        .add_system(
+            network_quality_hud
+                .run_if(in_state(EngineState::InGame))
+                .run_if(in_state(GameEditorState::Hidden))
+                .run_if(resource_exists::<Session>())
+                .run_if(resource_exists::<crate::networking::NetworkMatchSocket>()),
+        );
+    }
+}
+
+/// The vertical offset, in world units, from the center of a player to where its status is drawn.
+const PLAYER_STATUS_OFFSET: f32 = 28.0;
+/// The size, in UI points, of a single round in the ammo indicator.
+const AMMO_ROUND_SIZE: f32 = 4.0;
+/// The size, in UI points, of the durability bar.
+const DURABILITY_BAR_SIZE: egui::Vec2 = egui::vec2(24.0, 4.0);
+/// The size, in UI points, of the tallest bar in the connection quality indicator.
+const CONNECTION_BAR_SIZE: egui::Vec2 = egui::vec2(3.0, 10.0);
+
+/// The HUD info collected from the game session for a single player.
+struct PlayerStatus {
+    /// The position in the world to draw the status at.
+    pos: Vec3,
+    /// The active effects and their remaining time in seconds.
+    effects: Vec<(StatusEffectKind, f32)>,
+    /// The uses left in the item the player is holding, if it has limited uses.
+    item_uses: Option<ItemUsesStatus>,
+}
+
+/// The uses left in an item, as shown in the HUD.
+struct ItemUsesStatus {
+    kind: ItemUsesKind,
+    remaining: usize,
+    max: usize,
+}
+
+/// Draws the active status effects, and the ammo of the item being held, above each player.
+fn player_status_hud(
+    game: Res<GameMeta>,
+    localization: Res<Localization>,
+    egui_settings: Res<EguiSettings>,
+    mut session: ResMut<Session>,
+    mut egui_ctx: EguiContexts,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &Transform), (With<BevyBonesEntity>, Without<MenuCamera>)>,
+) {
+    let Ok(window) = windows.get_single() else { return };
+    let Ok((camera, camera_transform)) = cameras.get_single() else { return };
+    let camera_transform = GlobalTransform::from(*camera_transform);
+
+    let statuses = session
+        .world()
+        .run_initialized_system(
+            |entities: bones::Res<bones::Entities>,
+             transforms: bones::Comp<bones::Transform>,
+             status_effects: bones::Comp<StatusEffects>,
+             inventories: bones::Comp<Inventory>,
+             item_uses: bones::Comp<ItemUses>| {
+                Ok(entities
+                    .iter_with((&transforms, &status_effects))
+                    .map(|(player_ent, (transform, effects))| PlayerStatus {
+                        pos: transform.translation + Vec3::Y * PLAYER_STATUS_OFFSET,
+                        effects: effects
+                            .iter()
+                            .map(|x| (x.kind, x.timer.remaining_secs()))
+                            .collect(),
+                        item_uses: inventories
+                            .get(player_ent)
+                            .and_then(|inventory| inventory.0)
+                            .and_then(|item| item_uses.get(item))
+                            // Single-use items, like grenades, don't need a HUD
+                            .filter(|uses| uses.meta.max > 1)
+                            .map(|uses| ItemUsesStatus {
+                                kind: uses.meta.kind,
+                                remaining: uses.remaining,
+                                max: uses.meta.max,
+                            }),
+                    })
+                    .filter(|status| !status.effects.is_empty() || status.item_uses.is_some())
+                    .collect::<Vec<_>>())
+            },
+        )
+        .unwrap();
+
+    let hud_font = &game.ui_theme.hud.font;
+    let scale = egui_settings.scale_factor as f32;
+    let ctx = egui_ctx.ctx_mut();
+
+    for (i, status) in statuses.into_iter().enumerate() {
+        let Some(viewport_pos) = camera.world_to_viewport(&camera_transform, status.pos) else {
+            continue;
+        };
+        // The viewport origin is the bottom left, but egui's is the top left.
+        let screen_pos = egui::pos2(
+            viewport_pos.x / scale,
+            (window.height() - viewport_pos.y) / scale,
+        );
+
+        egui::Area::new(egui::Id::new("player-status-hud").with(i))
+            .fixed_pos(screen_pos)
+            .pivot(egui::Align2::CENTER_BOTTOM)
+            .interactable(false)
+            .show(ctx, |ui| {
+                ui.vertical_centered(|ui| {
+                    if let Some(item_uses) = &status.item_uses {
+                        item_uses_indicator(ui, item_uses, hud_font.color.into_egui());
+                    }
+
+                    for (kind, remaining) in status.effects {
+                        let font = match kind.tint() {
+                            Some(tint) => hud_font.colored(ColorMeta(tint)),
+                            None => hud_font.clone(),
+                        };
+                        ui.themed_label(
+                            &font,
+                            &format!(
+                                "{} {:.0}",
+                                localization.get(kind.localization_key()),
+                                remaining.ceil()
+                            ),
+                        );
+                    }
+                });
+            });
+    }
+}
+
+/// Draws the uses left in an item as a row of rounds, or as a durability bar.
+fn item_uses_indicator(ui: &mut egui::Ui, item_uses: &ItemUsesStatus, color: egui::Color32) {
+    let empty_color = color.linear_multiply(0.3);
+
+    match item_uses.kind {
+        ItemUsesKind::Ammo => {
+            let spacing = AMMO_ROUND_SIZE * 0.5;
+            let width = item_uses.max as f32 * (AMMO_ROUND_SIZE + spacing) - spacing;
+            let (rect, _) = ui.allocate_exact_size(
+                egui::vec2(width.max(0.0), AMMO_ROUND_SIZE),
+                egui::Sense::hover(),
+            );
+
+            for i in 0..item_uses.max {
+                let round = egui::Rect::from_min_size(
+                    rect.min + egui::vec2(i as f32 * (AMMO_ROUND_SIZE + spacing), 0.0),
+                    egui::Vec2::splat(AMMO_ROUND_SIZE),
+                );
+                let fill = if i < item_uses.remaining {
+                    color
+                } else {
+                    empty_color
+                };
+                ui.painter().rect_filled(round, 1.0, fill);
+            }
+        }
+        ItemUsesKind::Durability => {
+            let (rect, _) = ui.allocate_exact_size(DURABILITY_BAR_SIZE, egui::Sense::hover());
+            let fraction = if item_uses.max == 0 {
+                0.0
+            } else {
+                item_uses.remaining as f32 / item_uses.max as f32
+            };
+            let mut filled = rect;
+            filled.set_width(rect.width() * fraction);
+
+            ui.painter().rect_filled(rect, 1.0, empty_color);
+            ui.painter().rect_filled(filled, 1.0, color);
+        }
+    }
+}
+
+/// The rounds of the match, as shown in the HUD.
+struct RoundsStatus {
+    /// The index of the current round.
+    round: u32,
+    /// The number of rounds in the match.
+    count: u32,
+    /// The rounds won by each active player.
+    wins: Vec<(usize, u32)>,
+}
+
+/// Shows the lives left for each player, the rounds won in matches with several rounds, and who
+/// won once a round is over, until the results screen is shown at the end of the match.
+///
+/// Nothing is shown unless the players have limited lives or a respawn time limit, or the match has
+/// several rounds.
+fn match_hud(
+    game: Res<GameMeta>,
+    localization: Res<Localization>,
+    mut session: ResMut<Session>,
+    mut egui_ctx: EguiContexts,
+) {
+    let (lives, rounds, round_outcome, is_last_round, is_match_over) = session
+        .world()
+        .run_initialized_system(
+            |core_meta: bones::Res<CoreMetaArc>,
+             player_inputs: bones::Res<PlayerInputs>,
+             respawns: bones::Res<PlayerRespawns>,
+             match_rounds: bones::Res<MatchRounds>| {
+                let respawn_meta = &core_meta.config.respawn;
+                let rounds_meta = &core_meta.config.rounds;
+                let active_players = (0..MAX_PLAYERS)
+                    .filter(|i| player_inputs.players[*i].active)
+                    .collect::<Vec<_>>();
+                let lives = active_players
+                    .iter()
+                    .filter_map(|&i| Some((i, respawns.lives_remaining(i, respawn_meta)?)))
+                    .collect::<Vec<_>>();
+                let rounds = (rounds_meta.count > 1).then(|| RoundsStatus {
+                    round: match_rounds.round,
+                    count: rounds_meta.count,
+                    wins: active_players
+                        .iter()
+                        .map(|&i| (i, match_rounds.wins[i]))
+                        .collect(),
+                });
+                Ok((
+                    lives,
+                    rounds,
+                    respawns.outcome,
+                    match_rounds.is_last_round(rounds_meta),
+                    match_rounds.outcome.is_some(),
+                ))
+            },
+        )
+        .unwrap();
+
+    let hud_font = &game.ui_theme.hud.font;
+    let ctx = egui_ctx.ctx_mut();
+
+    if !lives.is_empty() || rounds.is_some() {
+        egui::Area::new("lives-hud")
+            .anchor(
+                egui::Align2::LEFT_TOP,
+                egui::vec2(hud_font.size, hud_font.size),
+            )
+            .interactable(false)
+            .show(ctx, |ui| {
+                if let Some(rounds) = &rounds {
+                    ui.themed_label(
+                        hud_font,
+                        &localization.get(&format!(
+                            "match-round?round={}&count={}",
+                            rounds.round + 1,
+                            rounds.count
+                        )),
+                    );
+                    for (player, wins) in &rounds.wins {
+                        ui.themed_label(
+                            hud_font,
+                            &localization
+                                .get(&format!("player-wins?player={}&wins={wins}", player + 1)),
+                        );
+                    }
+                }
+                for (player, lives) in lives {
+                    ui.themed_label(
+                        hud_font,
+                        &localization
+                            .get(&format!("player-lives?player={}&lives={lives}", player + 1)),
+                    );
+                }
+            });
+    }
+
+    // Once the match is over, the outcome is shown on the results screen instead
+    if is_match_over {
+        return;
+    }
+
+    if let Some(outcome) = round_outcome {
+        // The round outcome is only shown as the outcome of the match when there is one round
+        let title = match (outcome, rounds.is_some()) {
+            (MatchOutcome::Winner(player), false) => {
+                localization.get(&format!("match-winner?player={}", player + 1))
+            }
+            (MatchOutcome::NoWinner, false) => localization.get("match-no-winner"),
+            (MatchOutcome::Winner(player), true) => {
+                localization.get(&format!("round-winner?player={}", player + 1))
+            }
+            (MatchOutcome::NoWinner, true) => localization.get("round-no-winner"),
+        };
+        egui::Area::new("match-outcome-hud")
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .interactable(false)
+            .show(ctx, |ui| {
+                ui.vertical_centered(|ui| {
+                    ui.themed_label(&game.main_menu.title_font, &title);
+                    if !is_last_round {
+                        ui.themed_label(hud_font, &localization.get("next-round-hint"));
+                    }
+                });
+            });
+    }
+}
+
+/// Shows which player a network spectator is watching, and lets them switch between following
+/// a single player and following everybody.
+fn spectator_hud(
+    game: Res<GameMeta>,
+    localization: Res<Localization>,
+    socket: Res<crate::networking::NetworkMatchSocket>,
+    menu_input: Query<&ActionState<MenuAction>>,
+    mut session: ResMut<Session>,
+    mut egui_ctx: EguiContexts,
+) {
+    if !socket.is_spectator() {
+        return;
+    }
+    let Ok(menu_input) = menu_input.get_single() else { return };
+    let direction = if menu_input.just_pressed(MenuAction::Right) {
+        1
+    } else if menu_input.just_pressed(MenuAction::Left) {
+        -1
+    } else {
+        0
+    };
+
+    let focused_player = session
+        .world()
+        .run_initialized_system(
+            move |entities: bones::Res<bones::Entities>,
+                  player_indexes: bones::Comp<jumpy_core::player::PlayerIdx>,
+                  mut camera_states: bones::CompMut<jumpy_core::camera::CameraState>| {
+                let Some(camera_state) = camera_states.iter_mut().next() else {
+                    return Ok(None);
+                };
+                let mut players = entities
+                    .iter_with(&player_indexes)
+                    .map(|x| x.1 .0)
+                    .collect::<Vec<_>>();
+                players.sort_unstable();
+
+                // Cycle through following everybody and following each player
+                if direction != 0 {
+                    let current = camera_state
+                        .focused_player
+                        .and_then(|x| players.iter().position(|y| *y == x))
+                        .map_or(0, |x| x as i32 + 1);
+                    let count = players.len() as i32 + 1;
+                    let next = (current + direction).rem_euclid(count);
+                    camera_state.focused_player = (next > 0).then(|| players[next as usize - 1]);
+                }
+
+                Ok(camera_state.focused_player.filter(|x| players.contains(x)))
+            },
+        )
+        .unwrap();
+
+    let hud_font = &game.ui_theme.hud.font;
+    let spectating = match focused_player {
+        Some(player) => localization.get(&format!("spectating?player={}", player + 1)),
+        None => localization.get("spectating?player=all"),
+    };
+
+    egui::Area::new("spectator-hud")
+        .anchor(egui::Align2::CENTER_TOP, egui::vec2(0.0, hud_font.size))
+        .interactable(false)
+        .show(egui_ctx.ctx_mut(), |ui| {
+            ui.vertical_centered(|ui| {
+                ui.themed_label(hud_font, &spectating);
+                ui.themed_label(hud_font, &localization.get("spectator-controls"));
+            });
+        });
+}
+
+/// Shows the ping and connection quality of each remote player, and who we are waiting for when
+/// the game freezes for the network to catch up.
+fn network_quality_hud(
+    game: Res<GameMeta>,
+    localization: Res<Localization>,
+    mut storage: ResMut<Storage>,
+    player_select_state: Res<super::main_menu::player_select::PlayerSelectState>,
+    player_meta_assets: Res<Assets<PlayerMeta>>,
+    session: Res<Session>,
+    mut egui_ctx: EguiContexts,
+) {
+    use crate::networking::{ConnectionQuality, GgrsSessionRunner};
+
+    if !Settings::get_stored_or_default(&game, &mut storage).show_network_hud {
+        return;
+    }
+    let Some(runner) = session.downcast_ref::<GgrsSessionRunner>() else { return };
+
+    let player_name = |player: usize| {
+        let name = player_meta_assets
+            .get(
+                &player_select_state.slots[player]
+                    .selected_player
+                    .get_bevy_handle(),
+            )
+            .map(|x| x.name.as_str())
+            .unwrap_or_default();
+        localization.get(&format!("network-player?player={}&name={name}", player + 1))
+    };
+
+    let hud_font = &game.ui_theme.hud.font;
+    let ctx = egui_ctx.ctx_mut();
+
+    egui::Area::new("network-quality-hud")
+        .anchor(
+            egui::Align2::RIGHT_TOP,
+            egui::vec2(-hud_font.size, hud_font.size),
+        )
+        .interactable(false)
+        .show(ctx, |ui| {
+            for (player, stats) in &runner.network_stats {
+                let player = *player;
+                if runner.player_is_disconnected[player] {
+                    continue;
+                }
+                let quality = ConnectionQuality::new(stats, runner.player_is_interrupted[player]);
+
+                ui.horizontal(|ui| {
+                    connection_quality_indicator(ui, quality, hud_font.color.into_egui());
+                    ui.themed_label(
+                        hud_font,
+                        &format!(
+                            "{} {}",
+                            player_name(player),
+                            localization.get(&format!("ping?ms={}", stats.ping))
+                        ),
+                    );
+                });
+            }
+            ui.themed_label(
+                hud_font,
+                &localization.get(&format!(
+                    "rollback-frames?frames={}",
+                    runner.rollback_frames
+                )),
+            );
+        });
+
+    let waiting_for = runner.waiting_for();
+    if !waiting_for.is_empty() {
+        let players = waiting_for
+            .into_iter()
+            .map(player_name)
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        egui::Area::new("network-waiting-banner")
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .interactable(false)
+            .show(ctx, |ui| {
+                ui.themed_label(
+                    hud_font,
+                    &localization.get(&format!("waiting-for-player?players={players}")),
+                );
+            });
+    }
+}
+
+/// Draws the connection quality as signal bars, with one bar lit for a poor connection and all of
+/// them for a good one.
+fn connection_quality_indicator(
+    ui: &mut egui::Ui,
+    quality: crate::networking::ConnectionQuality,
+    color: egui::Color32,
+) {
+    const BARS: usize = 3;
+    let empty_color = color.linear_multiply(0.3);
+    let spacing = CONNECTION_BAR_SIZE.x * 0.5;
+    let width = BARS as f32 * (CONNECTION_BAR_SIZE.x + spacing) - spacing;
+    let (rect, _) = ui.allocate_exact_size(
+        egui::vec2(width, CONNECTION_BAR_SIZE.y),
+        egui::Sense::hover(),
+    );
+
+    let lit_bars = quality as usize + 1;
+    for i in 0..BARS {
+        let height = CONNECTION_BAR_SIZE.y * (i + 1) as f32 / BARS as f32;
+        let bar = egui::Rect::from_min_max(
+            egui::pos2(
+                rect.min.x + i as f32 * (CONNECTION_BAR_SIZE.x + spacing),
+                rect.max.y - height,
+            ),
+            egui::pos2(
+                rect.min.x + i as f32 * (CONNECTION_BAR_SIZE.x + spacing) + CONNECTION_BAR_SIZE.x,
+                rect.max.y,
+            ),
+        );
+        let fill = if i < lit_bars { color } else { empty_color };
+        ui.painter().rect_filled(bar, 1.0, fill);
+    }
+}
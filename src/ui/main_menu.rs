@@ -28,8 +28,8 @@ use super::{
 };
 
 pub mod credits;
+pub mod lobby;
 pub mod map_select;
-#[cfg(not(target_arch = "wasm32"))]
 pub mod network_game;
 pub mod player_select;
 pub mod settings;
@@ -135,6 +135,8 @@ pub enum MenuPage {
     Home,
     Settings,
     PlayerSelect,
+    /// The lobby of a network match, after the player selection.
+    Lobby,
     MapSelect {
         /// Indicates the client is waiting for the map to be selected, not actually picking the
         /// map.
@@ -191,14 +193,13 @@ impl<'w, 's> WidgetSystem for MainMenu<'w, 's> {
         // Render the menu based on the current menu selection
         match *params.menu_page {
             MenuPage::Home => widget::<HomeMenu>(world, ui, id.with("home"), ()),
-            MenuPage::NetworkGame =>
-            {
-                #[cfg(not(target_arch = "wasm32"))]
+            MenuPage::NetworkGame => {
                 widget::<network_game::MatchmakingMenu>(world, ui, id.with("network-game"), ())
             }
             MenuPage::PlayerSelect => {
                 widget::<player_select::PlayerSelectMenu>(world, ui, id.with("player-select"), ())
             }
+            MenuPage::Lobby => widget::<lobby::LobbyMenu>(world, ui, id.with("lobby"), ()),
             MenuPage::MapSelect { is_waiting } => {
                 widget::<map_select::MapSelectMenu>(world, ui, id.with("map-select"), is_waiting)
             }
@@ -282,21 +283,18 @@ impl<'w, 's> WidgetSystem for HomeMenu<'w, 's> {
                     }
 
                     // Network Game
-                    #[cfg(not(target_arch = "wasm32"))]
-                    {
-                        ui.scope(|ui| {
-                            let online_game_button = BorderedButton::themed(
-                                &ui_theme.button_styles.normal,
-                                &params.localization.get("network-game"),
-                            )
-                            .min_size(min_button_size)
-                            .show(ui);
-
-                            if online_game_button.clicked() {
-                                *params.menu_page = MenuPage::NetworkGame;
-                            }
-                        });
-                    }
+                    ui.scope(|ui| {
+                        let online_game_button = BorderedButton::themed(
+                            &ui_theme.button_styles.normal,
+                            &params.localization.get("network-game"),
+                        )
+                        .min_size(min_button_size)
+                        .show(ui);
+
+                        if online_game_button.clicked() {
+                            *params.menu_page = MenuPage::NetworkGame;
+                        }
+                    });
 
                     // Map editor
                     ui.scope(|ui| {
@@ -0,0 +1,545 @@
+use crate::networking::{
+    lobby::{ChatText, Lobby, LobbyEvent, MAX_CHAT_MESSAGE_LEN},
+    proto::{MatchRules, PeerVersion, QuickChat},
+    MatchSetupInbox, NetworkError, NetworkMatchSocket,
+};
+use crate::ui::network_error::NetworkErrorNotice;
+
+use super::{
+    player_select::{player_image, PlayerSelectState},
+    *,
+};
+
+#[derive(SystemParam)]
+pub struct LobbyMenu<'w, 's> {
+    menu_page: ResMut<'w, MenuPage>,
+    game: Res<'w, GameMeta>,
+    core: Res<'w, CoreMetaArc>,
+    localization: Res<'w, Localization>,
+    commands: Commands<'w, 's>,
+    menu_input: Query<'w, 's, &'static mut ActionState<MenuAction>>,
+    player_select_state: ResMut<'w, PlayerSelectState>,
+    player_meta_assets: Res<'w, Assets<PlayerMeta>>,
+    hat_assets: Res<'w, Assets<HatMeta>>,
+    map_assets: Res<'w, Assets<MapMeta>>,
+    atlas_meta_assets: Res<'w, Assets<TextureAtlas>>,
+    player_atlas_egui_textures: Res<'w, AtlasEguiTextures>,
+    network_socket: Option<Res<'w, NetworkMatchSocket>>,
+    match_setup_inbox: Option<ResMut<'w, MatchSetupInbox>>,
+    lobby: Option<ResMut<'w, Lobby>>,
+    /// The chat message being typed in.
+    chat_input: Local<'s, String>,
+}
+
+impl<'w, 's> WidgetSystem for LobbyMenu<'w, 's> {
+    type Args = ();
+    fn system(
+        world: &mut World,
+        state: &mut SystemState<Self>,
+        ui: &mut egui::Ui,
+        _: WidgetId,
+        _: (),
+    ) {
+        let mut params: LobbyMenu = state.get_mut(world);
+
+        handle_lobby_messages(&mut params);
+
+        let LobbyMenu {
+            menu_page,
+            game,
+            core,
+            localization,
+            menu_input,
+            player_select_state,
+            player_meta_assets,
+            hat_assets,
+            map_assets,
+            atlas_meta_assets,
+            player_atlas_egui_textures,
+            network_socket,
+            lobby,
+            chat_input,
+            ..
+        } = &mut params;
+        let (Some(socket), Some(lobby)) = (network_socket.as_ref(), lobby.as_mut()) else {
+            return;
+        };
+        let is_host = socket.client_idx() == 0;
+
+        if menu_input.single().just_pressed(MenuAction::Back) {
+            socket.close();
+            **menu_page = MenuPage::Home;
+            return;
+        }
+
+        let bigger_text_style = &game.ui_theme.font_styles.bigger;
+        let normal_text_style = &game.ui_theme.font_styles.normal;
+        let smaller_text_style = &game.ui_theme.font_styles.smaller;
+        let heading_text_style = &game.ui_theme.font_styles.heading;
+        let normal_button_style = &game.ui_theme.button_styles.normal;
+        let small_button_style = &game.ui_theme.button_styles.small;
+
+        // The name of the first player of a client, which is what the client is called in the chat.
+        let kicked_clients = lobby.kicked_clients();
+        let client_name = |client: usize| {
+            if kicked_clients[client] {
+                return localization.get("kicked-player");
+            }
+            let player = socket.client_players(client).start;
+            let name = player_meta_assets
+                .get(
+                    &player_select_state.slots[player]
+                        .selected_player
+                        .get_bevy_handle(),
+                )
+                .map(|x| x.name.as_str())
+                .unwrap_or_default();
+            localization.get(&format!("network-player?player={}&name={name}", player + 1))
+        };
+
+        ui.vertical_centered(|ui| {
+            ui.add_space(heading_text_style.size / 4.0);
+            ui.themed_label(heading_text_style, &localization.get("online-game"));
+            ui.themed_label(bigger_text_style, &localization.get("lobby-title"));
+            ui.add_space(normal_button_style.font.size);
+        });
+
+        // Leave, ready, and start buttons
+        ui.with_layout(egui::Layout::bottom_up(egui::Align::Center), |ui| {
+            ui.add_space(normal_button_style.font.size * 2.0);
+            ui.horizontal(|ui| {
+                let width = ui.available_width();
+                let button_count = if is_host { 3.0 } else { 2.0 };
+                let button_width = width / (button_count + 1.0);
+                let button_min_size = egui::vec2(button_width, 0.0);
+                let button_spacing = (width - button_count * button_width) / (button_count + 1.0);
+
+                ui.add_space(button_spacing);
+                if BorderedButton::themed(normal_button_style, &localization.get("leave"))
+                    .min_size(button_min_size)
+                    .show(ui)
+                    .clicked()
+                {
+                    socket.close();
+                    **menu_page = MenuPage::Home;
+                    ui.ctx().clear_focus();
+                }
+
+                ui.add_space(button_spacing);
+                let is_ready = lobby.ready[socket.client_idx()];
+                let ready_label = if is_ready {
+                    "lobby-unready"
+                } else {
+                    "lobby-ready"
+                };
+                if BorderedButton::themed(normal_button_style, &localization.get(ready_label))
+                    .min_size(button_min_size)
+                    .show(ui)
+                    .focus_by_default(ui)
+                    .clicked()
+                {
+                    lobby.set_ready(socket.0.as_ref(), !is_ready);
+                }
+
+                if is_host {
+                    ui.add_space(button_spacing);
+                    let may_start = lobby.all_ready(socket.0.as_ref());
+                    let start_button = ui
+                        .scope(|ui| {
+                            ui.set_enabled(may_start);
+                            BorderedButton::themed(
+                                normal_button_style,
+                                &localization.get("start-match"),
+                            )
+                            .min_size(button_min_size)
+                            .show(ui)
+                        })
+                        .inner;
+                    if start_button.clicked() {
+                        lobby.start(socket.0.as_ref());
+                        **menu_page = MenuPage::MapSelect { is_waiting: false };
+                    }
+                }
+            });
+
+            ui.add_space(normal_button_style.font.size);
+
+            ui.vertical_centered(|ui| {
+                ui.set_width(ui.available_width() - normal_button_style.font.size * 2.0);
+
+                ui.columns(2, |columns| {
+                    // Players and match rules
+                    BorderedFrame::new(&game.ui_theme.panel.border)
+                        .padding(game.ui_theme.panel.padding.into())
+                        .show(&mut columns[0], |ui| {
+                            ui.set_width(ui.available_width());
+                            ui.set_height(ui.available_height());
+
+                            egui::ScrollArea::vertical().show(ui, |ui| {
+                                ui.themed_label(bigger_text_style, &localization.get("players"));
+                                for client in 0..socket.client_count() {
+                                    if lobby.is_kicked(client) {
+                                        continue;
+                                    }
+                                    for player in socket.client_players(client) {
+                                        let slot = &player_select_state.slots[player];
+                                        let Some(player_meta) = player_meta_assets
+                                            .get(&slot.selected_player.get_bevy_handle()) else {
+                                            continue;
+                                        };
+                                        let hat_meta = slot
+                                            .selected_hat
+                                            .as_ref()
+                                            .and_then(|x| hat_assets.get(&x.get_bevy_handle()));
+
+                                        ui.horizontal(|ui| {
+                                            let image_size = normal_text_style.size * 3.0;
+                                            ui.allocate_ui(
+                                                egui::vec2(image_size, image_size),
+                                                |ui| {
+                                                    player_image(
+                                                        ui,
+                                                        player_meta,
+                                                        hat_meta,
+                                                        atlas_meta_assets,
+                                                        player_atlas_egui_textures,
+                                                    );
+                                                },
+                                            );
+                                            ui.vertical(|ui| {
+                                                ui.themed_label(
+                                                    normal_text_style,
+                                                    &localization.get(&format!(
+                                                        "network-player?player={}&name={}",
+                                                        player + 1,
+                                                        player_meta.name
+                                                    )),
+                                                );
+                                                let hat_name = hat_meta.map_or_else(
+                                                    || localization.get("no-hat"),
+                                                    |x| x.name.to_string(),
+                                                );
+                                                ui.themed_label(smaller_text_style, &hat_name);
+                                            });
+                                        });
+                                    }
+
+                                    ui.horizontal(|ui| {
+                                        let (label, color) = if lobby.ready[client] {
+                                            ("player-select-ready", game.ui_theme.colors.positive)
+                                        } else {
+                                            ("lobby-not-ready", game.ui_theme.panel.font_color)
+                                        };
+                                        ui.themed_label(
+                                            &normal_text_style.colored(color),
+                                            &localization.get(label),
+                                        );
+
+                                        if is_host
+                                            && client != socket.client_idx()
+                                            && BorderedButton::themed(
+                                                small_button_style,
+                                                &localization.get("kick"),
+                                            )
+                                            .show(ui)
+                                            .clicked()
+                                        {
+                                            lobby.kick(socket.0.as_ref(), client);
+                                        }
+                                    });
+                                    ui.add_space(normal_text_style.size / 2.0);
+                                }
+
+                                ui.add_space(normal_text_style.size);
+                                ui.themed_label(
+                                    bigger_text_style,
+                                    &localization.get("match-rules"),
+                                );
+                                if let Some(rules) = match_rules_ui(
+                                    ui,
+                                    game,
+                                    localization,
+                                    core,
+                                    map_assets,
+                                    &lobby.rules,
+                                    is_host,
+                                ) {
+                                    lobby.set_rules(socket.0.as_ref(), rules);
+                                }
+                            });
+                        });
+
+                    // Chat
+                    BorderedFrame::new(&game.ui_theme.panel.border)
+                        .padding(game.ui_theme.panel.padding.into())
+                        .show(&mut columns[1], |ui| {
+                            ui.set_width(ui.available_width());
+                            ui.set_height(ui.available_height());
+
+                            ui.with_layout(egui::Layout::bottom_up(egui::Align::Min), |ui| {
+                                ui.horizontal(|ui| {
+                                    let send_button = BorderedButton::themed(
+                                        small_button_style,
+                                        &localization.get("send"),
+                                    )
+                                    .show(ui);
+                                    let text_box = ui.add(
+                                        egui::TextEdit::singleline(&mut **chat_input)
+                                            .char_limit(MAX_CHAT_MESSAGE_LEN)
+                                            .font(normal_text_style.font_id())
+                                            .hint_text(localization.get("chat-hint"))
+                                            .desired_width(ui.available_width()),
+                                    );
+                                    let pressed_enter = text_box.lost_focus()
+                                        && ui.input(|i| i.key_pressed(egui::Key::Enter));
+
+                                    if send_button.clicked() || pressed_enter {
+                                        lobby.send_chat(socket.0.as_ref(), chat_input);
+                                        chat_input.clear();
+                                        if pressed_enter {
+                                            text_box.request_focus();
+                                        }
+                                    }
+                                });
+
+                                ui.horizontal_wrapped(|ui| {
+                                    for message in QuickChat::ALL {
+                                        if BorderedButton::themed(
+                                            small_button_style,
+                                            &localization.get(message.localization_key()),
+                                        )
+                                        .show(ui)
+                                        .clicked()
+                                        {
+                                            lobby.send_quick_chat(socket.0.as_ref(), message);
+                                        }
+                                    }
+                                });
+
+                                ui.add_space(normal_text_style.size / 2.0);
+
+                                ui.with_layout(egui::Layout::top_down(egui::Align::Min), |ui| {
+                                    ui.themed_label(bigger_text_style, &localization.get("chat"));
+                                    egui::ScrollArea::vertical().stick_to_bottom(true).show(
+                                        ui,
+                                        |ui| {
+                                            ui.set_width(ui.available_width());
+                                            for line in &lobby.chat {
+                                                let text = match &line.text {
+                                                    ChatText::Text(text) => text.clone(),
+                                                    ChatText::QuickChat(message) => {
+                                                        localization.get(message.localization_key())
+                                                    }
+                                                };
+                                                ui.themed_label(
+                                                    normal_text_style,
+                                                    &format!(
+                                                        "{}: {text}",
+                                                        client_name(line.client)
+                                                    ),
+                                                );
+                                            }
+                                        },
+                                    );
+                                });
+                            });
+                        });
+                });
+            });
+        });
+    }
+}
+
+/// Show the rules of the match, and let the host change them.
+///
+/// Returns the new rules if the host changed them.
+fn match_rules_ui(
+    ui: &mut egui::Ui,
+    game: &GameMeta,
+    localization: &Localization,
+    core: &CoreMeta,
+    map_assets: &Assets<MapMeta>,
+    rules: &MatchRules,
+    is_host: bool,
+) -> Option<MatchRules> {
+    let normal_text_style = &game.ui_theme.font_styles.normal;
+    let small_button_style = &game.ui_theme.button_styles.small;
+    let toggle_label = |enabled: bool| localization.get(if enabled { "on" } else { "off" });
+
+    let mut new_rules = rules.clone();
+    let mut changed = false;
+
+    ui.scope(|ui| {
+        ui.set_enabled(is_host);
+
+        // Map pool
+        ui.themed_label(normal_text_style, &localization.get("map-pool"));
+        let all_maps = core
+            .stable_maps
+            .iter()
+            .chain(&core.experimental_maps)
+            .cloned()
+            .collect::<Vec<_>>();
+        for map_handle in &all_maps {
+            let Some(map_meta) = map_assets.get(&map_handle.get_bevy_handle()) else {
+                continue;
+            };
+            ui.horizontal(|ui| {
+                let allowed = rules.allows_map(map_handle);
+                if BorderedButton::themed(small_button_style, &toggle_label(allowed))
+                    .show(ui)
+                    .clicked()
+                {
+                    new_rules.toggle_map(map_handle, &all_maps);
+                    changed = true;
+                }
+                ui.themed_label(normal_text_style, &map_meta.name);
+            });
+        }
+
+        // Lives
+        ui.add_space(normal_text_style.size / 2.0);
+        ui.horizontal(|ui| {
+            ui.themed_label(
+                normal_text_style,
+                &format!("{}:", localization.get("lives")),
+            );
+            if BorderedButton::themed(small_button_style, "-")
+                .show(ui)
+                .clicked()
+            {
+                new_rules.lives = match rules.lives {
+                    Some(1) | None => None,
+                    Some(lives) => Some(lives - 1),
+                };
+                changed = true;
+            }
+            let lives = match rules.lives {
+                Some(lives) => lives.to_string(),
+                None => localization.get("lives-default"),
+            };
+            ui.themed_label(normal_text_style, &lives);
+            if BorderedButton::themed(small_button_style, "+")
+                .show(ui)
+                .clicked()
+            {
+                new_rules.lives = Some(rules.lives.map_or(1, |x| x + 1).min(MatchRules::MAX_LIVES));
+                changed = true;
+            }
+        });
+
+        // Rounds
+        ui.horizontal(|ui| {
+            ui.themed_label(
+                normal_text_style,
+                &format!("{}:", localization.get("rounds")),
+            );
+            if BorderedButton::themed(small_button_style, "-")
+                .show(ui)
+                .clicked()
+            {
+                new_rules.rounds = rules.rounds.saturating_sub(1).max(1);
+                changed = true;
+            }
+            ui.themed_label(normal_text_style, &rules.rounds.to_string());
+            if BorderedButton::themed(small_button_style, "+")
+                .show(ui)
+                .clicked()
+            {
+                new_rules.rounds = (rules.rounds + 1).min(MatchRules::MAX_ROUNDS);
+                changed = true;
+            }
+        });
+
+        // Items
+        ui.horizontal(|ui| {
+            ui.themed_label(
+                normal_text_style,
+                &format!("{}:", localization.get("items")),
+            );
+            if BorderedButton::themed(small_button_style, &toggle_label(rules.items_enabled))
+                .show(ui)
+                .clicked()
+            {
+                new_rules.items_enabled = !rules.items_enabled;
+                changed = true;
+            }
+        });
+
+        // Map voting
+        ui.horizontal(|ui| {
+            ui.themed_label(
+                normal_text_style,
+                &format!("{}:", localization.get("map-voting")),
+            );
+            if BorderedButton::themed(small_button_style, &toggle_label(rules.map_voting))
+                .show(ui)
+                .clicked()
+            {
+                new_rules.map_voting = !rules.map_voting;
+                changed = true;
+            }
+        });
+    });
+
+    changed.then_some(new_rules)
+}
+
+/// Receive the lobby messages from the other clients.
+fn handle_lobby_messages(params: &mut LobbyMenu) {
+    let (Some(socket), Some(inbox), Some(lobby)) = (
+        &params.network_socket,
+        &mut params.match_setup_inbox,
+        &mut params.lobby,
+    ) else {
+        return;
+    };
+    inbox.poll(socket.0.as_ref(), || {
+        PeerVersion::new(&params.core, &params.map_assets)
+    });
+
+    let player_counts = socket.client_player_counts();
+    for (client, message) in inbox.take_lobby() {
+        match lobby.handle_message(socket.0.as_ref(), client, message) {
+            Some(LobbyEvent::Kicked) => {
+                // Closing the error dialog leaves the match
+                params
+                    .commands
+                    .insert_resource(NetworkErrorNotice(NetworkError::Kicked));
+                return;
+            }
+            Some(LobbyEvent::Start) => {
+                *params.menu_page = MenuPage::MapSelect { is_waiting: true };
+            }
+            None => (),
+        }
+    }
+
+    remap_player_slots(
+        &mut params.player_select_state,
+        player_counts,
+        socket.client_player_counts(),
+    );
+}
+
+/// Move the player slots down when clients have been kicked, to follow the new player indexes of
+/// the players after them.
+fn remap_player_slots(
+    state: &mut PlayerSelectState,
+    old_counts: [usize; MAX_PLAYERS],
+    new_counts: [usize; MAX_PLAYERS],
+) {
+    if old_counts == new_counts {
+        return;
+    }
+
+    let mut old_slots = std::mem::take(&mut state.slots);
+    let (mut old_start, mut new_start) = (0, 0);
+    for (old_count, new_count) in old_counts.into_iter().zip(new_counts) {
+        for i in 0..new_count {
+            state.slots[new_start + i] = std::mem::take(&mut old_slots[old_start + i]);
+        }
+        old_start += old_count;
+        new_start += new_count;
+    }
+}
@@ -1,16 +1,16 @@
 use crate::{editor::UserMapStorage, ui::pause_menu::PauseMenuPage};
 
-#[cfg(not(target_arch = "wasm32"))]
-use crate::networking::{GgrsSessionRunnerInfo, NetworkMatchSocket, SocketTarget};
+use crate::networking::{
+    lobby::Lobby,
+    map_transfer::{MapTransfer, MatchStart},
+    network_frame_rate_factor,
+    proto::{map_hash, MatchPlayer, MatchSettings, PeerVersion},
+    GgrsSessionRunnerInfo, MatchSetupInbox, NetworkMatchSocket, NetworkTiming,
+};
+use crate::ui::network_error::NetworkErrorNotice;
 
 use super::*;
 
-/// Network message that may be sent when selecting a map.
-#[derive(Serialize, Deserialize)]
-pub enum MapSelectMessage {
-    SelectMap(bones::Handle<MapMeta>),
-}
-
 #[derive(SystemParam)]
 pub struct MapSelectMenu<'w, 's> {
     menu_input: Query<'w, 's, &'static mut ActionState<MenuAction>>,
@@ -25,8 +25,10 @@ pub struct MapSelectMenu<'w, 's> {
     localization: Res<'w, Localization>,
     map_assets: Res<'w, Assets<MapMeta>>,
     storage: ResMut<'w, Storage>,
-    #[cfg(not(target_arch = "wasm32"))]
     network_socket: Option<Res<'w, NetworkMatchSocket>>,
+    match_setup_inbox: Option<ResMut<'w, MatchSetupInbox>>,
+    map_transfer: Option<ResMut<'w, MapTransfer>>,
+    lobby: Option<ResMut<'w, Lobby>>,
 }
 
 impl<'w, 's> WidgetSystem for MapSelectMenu<'w, 's> {
@@ -41,12 +43,18 @@ impl<'w, 's> WidgetSystem for MapSelectMenu<'w, 's> {
     ) {
         let mut params: MapSelectMenu = state.get_mut(world);
 
-        #[cfg(not(target_arch = "wasm32"))]
         handle_match_setup_messages(&mut params);
 
         let in_game = params.game_state.0 == EngineState::InGame;
 
         if params.menu_input.single().just_pressed(MenuAction::Back) {
+            // Spectators didn't go through the player selection, so they leave the match instead
+            if let Some(socket) = params.network_socket.as_ref().filter(|x| x.is_spectator()) {
+                socket.close();
+                *params.menu_page = MenuPage::Home;
+                return;
+            }
+
             // If we are on the main menu
             if params.game_state.0 == EngineState::MainMenu {
                 *params.menu_page = MenuPage::PlayerSelect;
@@ -57,6 +65,44 @@ impl<'w, 's> WidgetSystem for MapSelectMenu<'w, 's> {
             }
         }
 
+        // The status of the map transfer to or from the other network players, if any.
+        let mut transfer_status: Option<String> = None;
+        if let Some(map_transfer) = &params.map_transfer {
+            if map_transfer.is_offering() {
+                transfer_status = Some(params.localization.get("waiting-for-map-transfer"));
+            } else if let Some((current, total)) = map_transfer.download_progress() {
+                transfer_status = Some(
+                    params
+                        .localization
+                        .get(&format!("downloading-map?current={current}&total={total}")),
+                );
+            }
+        }
+
+        // The map that was clicked, and its handle if it is a core map.
+        let mut selected_map = None;
+
+        // Network matches are played on the maps in the host's map pool
+        let match_rules = params
+            .network_socket
+            .as_ref()
+            .and(params.lobby.as_ref())
+            .map(|x| x.rules.clone());
+        // When the map is voted for, every client sees the maps, and clicking one votes for it
+        let is_voting = match_rules.as_ref().map_or(false, |x| x.map_voting);
+        let mut voted_map = None;
+        let vote_result = params
+            .lobby
+            .as_ref()
+            .and_then(|x| x.vote_result.as_ref())
+            .filter(|_| is_voting)
+            .and_then(|x| params.map_assets.get(&x.get_bevy_handle()))
+            .map(|x| {
+                params
+                    .localization
+                    .get(&format!("map-vote-result?map={}", x.name))
+            });
+
         ui.vertical_centered_justified(|ui| {
             let bigger_text_style = &params.game.ui_theme.font_styles.bigger;
             let heading_text_style = &params.game.ui_theme.font_styles.heading;
@@ -77,11 +123,18 @@ impl<'w, 's> WidgetSystem for MapSelectMenu<'w, 's> {
             let x_margin = (available_size.x - menu_width) / 2.0;
             let outer_margin = egui::style::Margin::symmetric(x_margin, heading_text_style.size);
 
-            if is_waiting {
-                ui.themed_label(
-                    bigger_text_style,
-                    &params.localization.get("waiting-for-map"),
-                );
+            if (is_waiting && !is_voting) || vote_result.is_some() || transfer_status.is_some() {
+                if let Some(result) = &vote_result {
+                    ui.themed_label(bigger_text_style, result);
+                } else if is_waiting {
+                    ui.themed_label(
+                        bigger_text_style,
+                        &params.localization.get("waiting-for-map"),
+                    );
+                }
+                if let Some(status) = &transfer_status {
+                    ui.themed_label(bigger_text_style, status);
+                }
             } else {
                 BorderedFrame::new(&params.game.ui_theme.panel.border)
                     .margin(outer_margin)
@@ -94,32 +147,54 @@ impl<'w, 's> WidgetSystem for MapSelectMenu<'w, 's> {
                         egui::ScrollArea::vertical().show(ui, |ui| {
                             for (section_title, map_handles) in [
                                 (
-                                    &params.localization.get("default-maps"),
+                                    params.localization.get("default-maps"),
                                     &params.core.stable_maps,
                                 ),
                                 (
-                                    &params.localization.get("experimental-maps"),
+                                    params.localization.get("experimental-maps"),
                                     &params.core.experimental_maps,
                                 ),
                             ] {
+                                let mut map_handles = map_handles.iter().collect::<Vec<_>>();
+                                if let Some(rules) = &match_rules {
+                                    map_handles.retain(|x| rules.allows_map(x));
+                                }
                                 if map_handles.is_empty() {
                                     continue;
                                 }
                                 ui.add_space(bigger_text_style.size / 2.0);
-                                ui.themed_label(bigger_text_style, section_title);
+                                ui.themed_label(bigger_text_style, &section_title);
 
-                                // Clippy lint is a false alarm, necessary to avoid borrowing params
-                                #[allow(clippy::unnecessary_to_owned)]
-                                for map_handle in map_handles.to_vec().into_iter() {
+                                for map_handle in map_handles {
                                     let map_meta = params
                                         .map_assets
                                         .get(&map_handle.get_bevy_handle())
                                         .expect("Error loading map");
                                     ui.add_space(ui.spacing().item_spacing.y);
 
+                                    let mut label = map_meta.name.to_string();
+                                    if let Some(lobby) = params.lobby.as_ref().filter(|_| is_voting)
+                                    {
+                                        let votes = lobby.map_vote_count(map_handle);
+                                        let is_own_vote = params
+                                            .network_socket
+                                            .as_ref()
+                                            .and_then(|socket| {
+                                                lobby.map_votes[socket.client_idx()].as_ref()
+                                            })
+                                            .map_or(false, |x| x.path == map_handle.path);
+                                        let key = if is_own_vote {
+                                            "own-map-votes"
+                                        } else {
+                                            "map-votes"
+                                        };
+                                        label = params
+                                            .localization
+                                            .get(&format!("{key}?map={label}&votes={votes}"));
+                                    }
+
                                     let mut button =
-                                        BorderedButton::themed(small_button_style, &map_meta.name)
-                                            .show(ui);
+                                        BorderedButton::themed(small_button_style, &label).show(ui);
 
                                     if first_button {
                                         first_button = false;
@@ -127,81 +202,22 @@ impl<'w, 's> WidgetSystem for MapSelectMenu<'w, 's> {
                                     }
 
                                     if button.clicked() {
-                                        *params.pause_page = PauseMenuPage::Default;
-                                        *params.menu_page = MenuPage::Home;
-
-                                        // TODO: This code to start a game is duplicated 3 or 4
-                                        // times throughout this file, which isn't good. We should
-                                        // try to abstract it into a function or something.
-                                        let mut player_info = <[Option<GameSessionPlayerInfo>;
-                                            MAX_PLAYERS]>::default(
-                                        );
-                                        (0..MAX_PLAYERS).for_each(|i| {
-                                            let slot = &params.player_select_state.slots[i];
-                                            if slot.active {
-                                                player_info[i] = Some(GameSessionPlayerInfo {
-                                                    player: slot.selected_player.clone(),
-                                                    hat: slot.selected_hat.clone(),
-                                                    is_ai: slot.is_ai,
-                                                });
-                                            }
-                                        });
-                                        let core_info = CoreSessionInfo {
-                                            meta: params.core.0.clone(),
-                                            map_meta: map_meta.clone(),
-                                            player_info,
-                                        };
-                                        #[cfg(not(target_arch = "wasm32"))]
-                                        if let Some(socket) = &params.network_socket {
-                                            info!("Selected map, starting network game");
-                                            params.session_manager.start_network(
-                                                core_info,
-                                                GgrsSessionRunnerInfo {
-                                                    socket: socket.ggrs_socket(),
-                                                    player_is_local: socket.player_is_local(),
-                                                    player_count: socket.player_count(),
-                                                },
-                                            );
-                                        } else {
-                                            info!("Selected map, starting game");
-                                            params.session_manager.start_local(core_info);
-                                        }
-                                        #[cfg(target_arch = "wasm32")]
-                                        {
-                                            info!("Selected map, starting game");
-                                            params.session_manager.start_local(core_info);
-                                        }
-
-                                        params
-                                            .commands
-                                            .insert_resource(NextState(Some(EngineState::InGame)));
-                                        params
-                                            .commands
-                                            .insert_resource(NextState(Some(InGameState::Playing)));
-
-                                        #[cfg(not(target_arch = "wasm32"))]
-                                        if let Some(socket) = &params.network_socket {
-                                            socket.send_reliable(
-                                                SocketTarget::All,
-                                                &postcard::to_allocvec(
-                                                    &MapSelectMessage::SelectMap(map_handle),
-                                                )
-                                                .unwrap(),
-                                            );
+                                        if is_voting {
+                                            voted_map = Some(map_handle.clone());
+                                            continue;
                                         }
+                                        selected_map =
+                                            Some((map_meta.clone(), Some(map_handle.clone())));
                                     }
                                 }
 
+                                // Only the core maps may be voted for, since everybody has them
+                                if is_voting {
+                                    continue;
+                                }
                                 let user_maps: Option<UserMapStorage> =
                                     params.storage.get(UserMapStorage::STORAGE_KEY);
                                 if let Some(user_maps) = user_maps {
-                                    #[cfg(not(target_arch = "wasm32"))]
-                                    let is_network = params.network_socket.is_some();
-                                    #[cfg(target_arch = "wasm32")]
-                                    let is_network = false;
-
-                                    // For now, network games can only play core maps.
-                                    ui.set_enabled(!is_network);
                                     ui.add_space(bigger_text_style.size / 2.0);
                                     ui.themed_label(
                                         bigger_text_style,
@@ -218,33 +234,7 @@ impl<'w, 's> WidgetSystem for MapSelectMenu<'w, 's> {
                                             BorderedButton::themed(small_button_style, &name)
                                                 .show(ui);
                                         if button.clicked() {
-                                            *params.pause_page = PauseMenuPage::Default;
-                                            *params.menu_page = MenuPage::Home;
-
-                                            let mut player_info = <[Option<GameSessionPlayerInfo>;
-                                                MAX_PLAYERS]>::default(
-                                            );
-                                            (0..MAX_PLAYERS).for_each(|i| {
-                                                let slot = &params.player_select_state.slots[i];
-                                                if slot.active {
-                                                    player_info[i] = Some(GameSessionPlayerInfo {
-                                                        player: slot.selected_player.clone(),
-                                                        hat: slot.selected_hat.clone(),
-                                                        is_ai: slot.is_ai,
-                                                    });
-                                                }
-                                            });
-                                            params.session_manager.start_local(CoreSessionInfo {
-                                                meta: params.core.0.clone(),
-                                                map_meta,
-                                                player_info,
-                                            });
-                                            params.commands.insert_resource(NextState(Some(
-                                                EngineState::InGame,
-                                            )));
-                                            params.commands.insert_resource(NextState(Some(
-                                                InGameState::Playing,
-                                            )));
+                                            selected_map = Some((map_meta, None));
                                         };
                                     }
                                 }
@@ -253,68 +243,207 @@ impl<'w, 's> WidgetSystem for MapSelectMenu<'w, 's> {
                     });
             }
         });
+
+        if is_voting {
+            if let (Some(socket), Some(lobby)) = (&params.network_socket, &mut params.lobby) {
+                if let Some(map_handle) = voted_map {
+                    lobby.vote_for_map(socket.0.as_ref(), map_handle);
+                }
+
+                // The host counts the votes once everybody voted
+                if socket.client_idx() == 0 {
+                    let maps = params
+                        .core
+                        .stable_maps
+                        .iter()
+                        .chain(&params.core.experimental_maps)
+                        .filter(|x| lobby.rules.allows_map(x))
+                        .cloned()
+                        .collect::<Vec<_>>();
+                    if let Some(map_handle) = lobby.finish_vote(socket.0.as_ref(), &maps) {
+                        if let Some(map_meta) = params.map_assets.get(&map_handle.get_bevy_handle())
+                        {
+                            selected_map = Some((map_meta.clone(), Some(map_handle)));
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Some((map_meta, map_handle)) = selected_map {
+            select_map(&mut params, map_meta, map_handle);
+        }
+    }
+}
+
+/// Start a game on the selected map, or offer it to the other players if this is a network game.
+///
+/// `map_handle` is set if the map is one of the core maps.
+fn select_map(
+    params: &mut MapSelectMenu,
+    map_meta: MapMeta,
+    map_handle: Option<bones::Handle<MapMeta>>,
+) {
+    if let (Some(socket), Some(map_transfer)) = (&params.network_socket, &mut params.map_transfer) {
+        info!("Selected map, waiting for network players to get it");
+        let settings = MatchSettings {
+            players: std::array::from_fn(|i| {
+                let slot = &params.player_select_state.slots[i];
+                slot.active.then(|| MatchPlayer {
+                    player: slot.selected_player.clone(),
+                    hat: slot.selected_hat.clone(),
+                    is_ai: slot.is_ai,
+                })
+            }),
+            disconnect_policy: Settings::get_stored_or_default(&params.game, &mut params.storage)
+                .disconnect_policy,
+            frame_rate_factor: network_frame_rate_factor(
+                params
+                    .match_setup_inbox
+                    .as_ref()
+                    .and_then(|x| x.round_trip_time()),
+            ),
+            rules: params
+                .lobby
+                .as_ref()
+                .map(|x| x.rules.clone())
+                .unwrap_or_default(),
+            kicked_clients: params
+                .lobby
+                .as_ref()
+                .map(|x| x.kicked_clients())
+                .unwrap_or_default(),
+        };
+        map_transfer.offer(socket.0.as_ref(), map_meta, map_handle, settings);
+        return;
+    }
+
+    info!("Selected map, starting game");
+    let core_info = core_session_info(params, map_meta);
+    params.session_manager.start_local(core_info);
+    enter_game(params);
+}
+
+/// Get the info needed to start a game session on the given map with the selected players.
+fn core_session_info(params: &MapSelectMenu, map_meta: MapMeta) -> CoreSessionInfo {
+    let mut player_info = <[Option<GameSessionPlayerInfo>; MAX_PLAYERS]>::default();
+    (0..MAX_PLAYERS).for_each(|i| {
+        let slot = &params.player_select_state.slots[i];
+        if slot.active {
+            player_info[i] = Some(GameSessionPlayerInfo {
+                player: slot.selected_player.clone(),
+                hat: slot.selected_hat.clone(),
+                is_ai: slot.is_ai,
+            });
+        }
+    });
+
+    CoreSessionInfo {
+        meta: params.core.0.clone(),
+        map_meta,
+        player_info,
     }
 }
 
-#[cfg(not(target_arch = "wasm32"))]
+/// Leave the menus and go in game, after a game session has been started.
+fn enter_game(params: &mut MapSelectMenu) {
+    *params.pause_page = PauseMenuPage::Default;
+    *params.menu_page = MenuPage::Home;
+    params
+        .commands
+        .insert_resource(NextState(Some(EngineState::InGame)));
+    params
+        .commands
+        .insert_resource(NextState(Some(InGameState::Playing)));
+}
+
 fn handle_match_setup_messages(params: &mut MapSelectMenu) {
-    if let Some(socket) = &params.network_socket {
-        let datas: Vec<(usize, Vec<u8>)> = socket.recv_reliable();
-
-        for (player, data) in datas {
-            match postcard::from_bytes::<MapSelectMessage>(&data) {
-                Ok(message) => match message {
-                    MapSelectMessage::SelectMap(map_handle) => {
-                        assert_eq!(player, 0, "Only player 0 may select the map.");
-                        info!("Other player selected map, starting game");
-                        *params.pause_page = PauseMenuPage::Default;
-                        *params.menu_page = MenuPage::Home;
-
-                        let map_meta = params
-                            .map_assets
-                            .get(&map_handle.get_bevy_handle())
-                            .unwrap()
-                            .clone();
-
-                        let mut player_info =
-                            <[Option<GameSessionPlayerInfo>; MAX_PLAYERS]>::default();
-                        (0..MAX_PLAYERS).for_each(|i| {
-                            let slot = &params.player_select_state.slots[i];
-                            if slot.active {
-                                player_info[i] = Some(GameSessionPlayerInfo {
-                                    player: slot.selected_player.clone(),
-                                    hat: slot.selected_hat.clone(),
-                                    is_ai: slot.is_ai,
-                                });
-                            }
-                        });
-                        params.session_manager.start_network(
-                            CoreSessionInfo {
-                                meta: params.core.0.clone(),
-                                map_meta,
-                                player_info,
-                            },
-                            GgrsSessionRunnerInfo {
-                                socket: socket.ggrs_socket(),
-                                player_is_local: socket.player_is_local(),
-                                player_count: socket.player_count(),
-                            },
-                        );
-                        params
-                            .commands
-                            .insert_resource(NextState(Some(EngineState::InGame)));
-                        params
-                            .commands
-                            .insert_resource(NextState(Some(InGameState::Playing)));
-                    }
-                },
-                Err(e) => {
-                    // TODO: The second player in an online match is having this triggered by
-                    // picking up a `ConfirmSelection` message, that might have been sent to
-                    // _itself_.
-                    warn!("Ignoring network message that was not understood: {e}");
-                }
+    let (Some(socket), Some(inbox)) = (&params.network_socket, &mut params.match_setup_inbox) else {
+        return;
+    };
+    inbox.poll(socket.0.as_ref(), || {
+        PeerVersion::new(&params.core, &params.map_assets)
+    });
+    let Some(map_transfer) = &mut params.map_transfer else {
+        return;
+    };
+
+    let mut match_start = None;
+    for (player, message) in inbox.take_map_select() {
+        let message = match &mut params.lobby {
+            Some(lobby) => match lobby.handle_vote_message(player, message) {
+                Some(message) => message,
+                None => continue,
+            },
+            None => message,
+        };
+        let start =
+            map_transfer.handle_message(socket.0.as_ref(), player, message, |hash, handle| {
+                find_local_map(&params.map_assets, &mut params.storage, hash, handle)
+            });
+        match start {
+            Ok(Some(start)) => match_start = Some(start),
+            Ok(None) => (),
+            Err(error) => {
+                warn!(%error, "Map transfer failed");
+                params.commands.insert_resource(NetworkErrorNotice(error));
+                return;
             }
         }
     }
+
+    if let Some(MatchStart { map_meta, settings }) = match_start {
+        info!("All network players have the map, starting game");
+        // Leave out the clients we didn't know were kicked before working out who plays
+        if let Some(lobby) = &mut params.lobby {
+            lobby.apply_kicked_clients(settings.kicked_clients);
+        }
+        let timing_settings =
+            Settings::get_stored_or_default(&params.game, &mut params.storage).network_timing;
+        let timing = NetworkTiming::new(
+            &timing_settings,
+            inbox.round_trip_time(),
+            jumpy_core::FPS * settings.frame_rate_factor,
+        );
+        let ggrs_info = GgrsSessionRunnerInfo {
+            socket: socket.ggrs_socket(),
+            player_is_local: socket.player_is_local(),
+            player_clients: std::array::from_fn(|i| socket.player_client(i).unwrap_or_default()),
+            player_count: socket.player_count(),
+            spectator_addrs: socket.spectator_addrs(),
+            is_spectator: socket.is_spectator(),
+            disconnect_policy: settings.disconnect_policy,
+            frame_rate_factor: settings.frame_rate_factor,
+            timing,
+            auto_input_delay: timing_settings.input_delay.is_none(),
+        };
+        let core_info = CoreSessionInfo {
+            meta: settings.rules.apply(&params.core.0),
+            map_meta,
+            player_info: settings.players.map(|x| x.map(Into::into)),
+        };
+        params.session_manager.start_network(core_info, ggrs_info);
+        enter_game(params);
+    }
+}
+
+/// Look for a local copy of a map offered by the host, either in the core maps or in the user
+/// maps.
+fn find_local_map(
+    map_assets: &Assets<MapMeta>,
+    storage: &mut Storage,
+    hash: u64,
+    handle: Option<&bones::Handle<MapMeta>>,
+) -> Option<MapMeta> {
+    if let Some(map_meta) = handle.and_then(|x| map_assets.get(&x.get_bevy_handle())) {
+        if map_hash(map_meta) == hash {
+            return Some(map_meta.clone());
+        }
+    }
+
+    let user_maps: UserMapStorage = storage.get(UserMapStorage::STORAGE_KEY)?;
+    user_maps
+        .0
+        .into_values()
+        .find(|map_meta| map_hash(map_meta) == hash)
 }
@@ -1,15 +1,19 @@
+#[cfg(not(target_arch = "wasm32"))]
 use std::time::Duration;
 
+#[cfg(not(target_arch = "wasm32"))]
+use crate::networking::lan;
 use crate::networking::{
-    lan,
-    online::{OnlineMatchmakerRequest, OnlineMatchmakerResponse, ONLINE_MATCHMAKER},
-    NetworkMatchSocket,
+    online::{LobbyCode, OnlineMatchmakerRequest, OnlineMatchmakerResponse, ONLINE_MATCHMAKER},
+    NetworkMatchSocket, NetworkSocket,
 };
+use crate::ui::network_error::NetworkErrorNotice;
 
 use super::*;
 
 #[derive(SystemParam)]
 pub struct MatchmakingMenu<'w, 's> {
+    #[cfg(not(target_arch = "wasm32"))]
     time: Res<'w, Time>,
     menu_page: ResMut<'w, MenuPage>,
     game: Res<'w, GameMeta>,
@@ -22,11 +26,23 @@ pub struct MatchmakingMenu<'w, 's> {
 
 pub struct State {
     match_kind: MatchKind,
+    #[cfg(not(target_arch = "wasm32"))]
     lan_service_discovery_recv: Option<mdns_sd::Receiver<mdns_sd::ServiceEvent>>,
+    #[cfg(not(target_arch = "wasm32"))]
     service_info: Option<mdns_sd::ServiceInfo>,
     status: Status,
+    #[cfg(not(target_arch = "wasm32"))]
     joined_players: usize,
+    #[cfg(not(target_arch = "wasm32"))]
+    joined_spectators: usize,
+    /// The number of players playing on this computer.
+    local_player_count: usize,
+    #[cfg(not(target_arch = "wasm32"))]
     lan_servers: Vec<lan::ServerInfo>,
+    /// The `host:port` address typed in to join a server directly.
+    #[cfg(not(target_arch = "wasm32"))]
+    direct_connect_addr: String,
+    #[cfg(not(target_arch = "wasm32"))]
     ping_update_timer: Timer,
 }
 
@@ -34,7 +50,9 @@ pub struct State {
 pub enum Status {
     #[default]
     Idle,
+    #[cfg(not(target_arch = "wasm32"))]
     Joining,
+    #[cfg(not(target_arch = "wasm32"))]
     Hosting,
     Searching,
 }
@@ -43,28 +61,46 @@ impl Default for State {
     fn default() -> Self {
         Self {
             match_kind: default(),
+            #[cfg(not(target_arch = "wasm32"))]
             lan_service_discovery_recv: default(),
+            #[cfg(not(target_arch = "wasm32"))]
             service_info: default(),
             status: default(),
+            #[cfg(not(target_arch = "wasm32"))]
             lan_servers: default(),
+            #[cfg(not(target_arch = "wasm32"))]
             joined_players: default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            joined_spectators: default(),
+            local_player_count: 1,
+            #[cfg(not(target_arch = "wasm32"))]
+            direct_connect_addr: default(),
+            #[cfg(not(target_arch = "wasm32"))]
             ping_update_timer: Timer::new(Duration::from_secs(1), TimerMode::Repeating),
         }
     }
 }
 
+/// The kind of network match to look for. LAN games aren't available in the web build, which can
+/// only play online through a relay.
 #[derive(Clone)]
 pub enum MatchKind {
+    #[cfg(not(target_arch = "wasm32"))]
     Lan(LanMode),
     Online(OnlineState),
 }
 
 impl Default for MatchKind {
     fn default() -> Self {
-        MatchKind::Lan(LanMode::Join)
+        #[cfg(not(target_arch = "wasm32"))]
+        return MatchKind::Lan(LanMode::Join);
+
+        #[cfg(target_arch = "wasm32")]
+        return MatchKind::Online(default());
     }
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 #[derive(Default, Clone)]
 pub enum LanMode {
     #[default]
@@ -79,7 +115,14 @@ pub enum LanMode {
 pub struct OnlineState {
     player_count: usize,
     matchmaking_server: String,
+    /// The certificate fingerprint of the matchmaking server, see
+    /// [`Settings::matchmaking_server_fingerprint`].
+    matchmaking_server_fingerprint: String,
     search_state: SearchState,
+    /// The code of the private lobby we are in, if we aren't searching the public queue.
+    lobby_code: Option<LobbyCode>,
+    /// The lobby code typed in to join a private lobby.
+    lobby_code_input: String,
 }
 
 impl Default for OnlineState {
@@ -87,7 +130,10 @@ impl Default for OnlineState {
         Self {
             player_count: 2,
             matchmaking_server: String::new(),
+            matchmaking_server_fingerprint: String::new(),
             search_state: default(),
+            lobby_code: None,
+            lobby_code_input: String::new(),
         }
     }
 }
@@ -111,6 +157,7 @@ impl<'w, 's> WidgetSystem for MatchmakingMenu<'w, 's> {
     ) {
         let mut params: MatchmakingMenu = state.get_mut(world);
         let menu_input = params.menu_input.single();
+        #[cfg(not(target_arch = "wasm32"))]
         params.state.ping_update_timer.tick(params.time.delta());
 
         let bigger_text_style = &params.game.ui_theme.font_styles.bigger;
@@ -142,15 +189,18 @@ impl<'w, 's> WidgetSystem for MatchmakingMenu<'w, 's> {
 
                 ui.horizontal(|ui| {
                     // Lan tab
-                    let mut lan = egui::RichText::new(params.localization.get("lan"));
-                    if matches!(params.state.match_kind, MatchKind::Lan(..)) {
-                        lan = lan.underline();
-                    }
-                    if BorderedButton::themed(normal_button_style, lan)
-                        .show(ui)
-                        .clicked()
+                    #[cfg(not(target_arch = "wasm32"))]
                     {
-                        params.state.match_kind = MatchKind::Lan(default());
+                        let mut lan = egui::RichText::new(params.localization.get("lan"));
+                        if matches!(params.state.match_kind, MatchKind::Lan(..)) {
+                            lan = lan.underline();
+                        }
+                        if BorderedButton::themed(normal_button_style, lan)
+                            .show(ui)
+                            .clicked()
+                        {
+                            params.state.match_kind = MatchKind::Lan(default());
+                        }
                     }
 
                     // Online tab
@@ -166,6 +216,7 @@ impl<'w, 's> WidgetSystem for MatchmakingMenu<'w, 's> {
                     }
 
                     match &mut params.state.match_kind {
+                        #[cfg(not(target_arch = "wasm32"))]
                         MatchKind::Lan(mode) => {
                             ui.with_layout(
                                 egui::Layout::right_to_left(egui::Align::Center),
@@ -225,19 +276,63 @@ impl<'w, 's> WidgetSystem for MatchmakingMenu<'w, 's> {
 
                 let State {
                     match_kind,
+                    #[cfg(not(target_arch = "wasm32"))]
                     lan_service_discovery_recv,
+                    #[cfg(not(target_arch = "wasm32"))]
                     lan_servers,
+                    #[cfg(not(target_arch = "wasm32"))]
                     service_info: host_info,
                     status,
+                    #[cfg(not(target_arch = "wasm32"))]
                     ping_update_timer,
+                    #[cfg(not(target_arch = "wasm32"))]
                     joined_players,
+                    #[cfg(not(target_arch = "wasm32"))]
+                    joined_spectators,
+                    local_player_count,
+                    #[cfg(not(target_arch = "wasm32"))]
+                    direct_connect_addr,
                 } = &mut *params.state;
 
                 ui.separator();
                 ui.add_space(normal_text_style.size);
 
+                // The number of players on this computer
+                ui.horizontal(|ui| {
+                    ui.set_enabled(*status == Status::Idle);
+                    ui.themed_label(
+                        normal_text_style,
+                        &params.localization.get("local-player-count"),
+                    );
+                    ui.add_space(normal_text_style.size);
+                    ui.scope(|ui| {
+                        ui.set_enabled(*local_player_count > 1);
+                        if BorderedButton::themed(small_button_style, "-")
+                            .min_size(egui::vec2(normal_text_style.size * 2.0, 0.0))
+                            .show(ui)
+                            .clicked()
+                        {
+                            *local_player_count = local_player_count.saturating_sub(1).max(1);
+                        }
+                    });
+                    ui.themed_label(normal_text_style, &local_player_count.to_string());
+                    ui.scope(|ui| {
+                        ui.set_enabled(*local_player_count < MAX_PLAYERS - 1);
+                        if BorderedButton::themed(small_button_style, "+")
+                            .min_size(egui::vec2(normal_text_style.size * 2.0, 0.0))
+                            .show(ui)
+                            .clicked()
+                        {
+                            *local_player_count =
+                                local_player_count.saturating_add(1).min(MAX_PLAYERS - 1);
+                        }
+                    });
+                });
+                ui.add_space(normal_text_style.size / 2.0);
+
                 match match_kind {
                     // LAN game
+                    #[cfg(not(target_arch = "wasm32"))]
                     MatchKind::Lan(mode) => match mode {
                         LanMode::Join => {
                             // Stop any running server
@@ -261,12 +356,32 @@ impl<'w, 's> WidgetSystem for MatchmakingMenu<'w, 's> {
                                                 &params.game.ui_theme.button_styles.normal,
                                                 server.service.get_hostname().trim_end_matches('.'),
                                             )
-                                            .min_size(egui::vec2(ui.available_width() * 0.8, 0.0))
+                                            .min_size(egui::vec2(ui.available_width() * 0.6, 0.0))
+                                            .show(ui)
+                                            .clicked()
+                                            {
+                                                match lan::join_server(server, *local_player_count) {
+                                                    Ok(()) => *status = Status::Joining,
+                                                    Err(err) => params
+                                                        .commands
+                                                        .insert_resource(NetworkErrorNotice(err)),
+                                                }
+                                            }
+
+                                            if BorderedButton::themed(
+                                                small_button_style,
+                                                &params.localization.get("spectate"),
+                                            )
                                             .show(ui)
                                             .clicked()
                                             {
-                                                lan::join_server(server);
-                                                *status = Status::Joining;
+                                                // Spectators join without any players
+                                                match lan::join_server(server, 0) {
+                                                    Ok(()) => *status = Status::Joining,
+                                                    Err(err) => params
+                                                        .commands
+                                                        .insert_resource(NetworkErrorNotice(err)),
+                                                }
                                             }
 
                                             let label_text = egui::RichText::new(format!(
@@ -289,6 +404,44 @@ impl<'w, 's> WidgetSystem for MatchmakingMenu<'w, 's> {
                                     }
                                 });
 
+                                // Join a server by address, for when it can't be discovered
+                                ui.add_space(normal_text_style.size / 2.0);
+                                ui.horizontal(|ui| {
+                                    ui.themed_label(
+                                        normal_text_style,
+                                        &params.localization.get("direct-connect"),
+                                    );
+                                    ui.add(
+                                        egui::TextEdit::singleline(direct_connect_addr)
+                                            .hint_text("host:port")
+                                            .font(normal_text_style.font_id()),
+                                    );
+                                    ui.scope(|ui| {
+                                        ui.set_enabled(!direct_connect_addr.trim().is_empty());
+                                        for (label, local_players) in
+                                            [("join", *local_player_count), ("spectate", 0)]
+                                        {
+                                            if BorderedButton::themed(
+                                                small_button_style,
+                                                &params.localization.get(label),
+                                            )
+                                            .show(ui)
+                                            .clicked()
+                                            {
+                                                match lan::join_server_at(
+                                                    direct_connect_addr,
+                                                    local_players,
+                                                ) {
+                                                    Ok(()) => *status = Status::Joining,
+                                                    Err(err) => params
+                                                        .commands
+                                                        .insert_resource(NetworkErrorNotice(err)),
+                                                }
+                                            }
+                                        }
+                                    });
+                                });
+
                             // If we are trying to join a match.
                             } else {
                                 ui.themed_label(
@@ -296,13 +449,27 @@ impl<'w, 's> WidgetSystem for MatchmakingMenu<'w, 's> {
                                     &params.localization.get("joining"),
                                 );
 
-                                if let Some(lan_socket) = lan::wait_game_start() {
-                                    params.commands.insert_resource(NetworkMatchSocket(
-                                        Box::new(lan_socket),
-                                    ));
+                                match lan::wait_game_start() {
+                                    Ok(Some(lan_socket)) => {
+                                        // Spectators skip the player selection and wait for the
+                                        // host to start the match.
+                                        *params.menu_page = if lan_socket.is_spectator() {
+                                            MenuPage::MapSelect { is_waiting: true }
+                                        } else {
+                                            MenuPage::PlayerSelect
+                                        };
+                                        NetworkMatchSocket::insert(
+                                            &mut params.commands,
+                                            Box::new(lan_socket),
+                                        );
 
-                                    *status = default();
-                                    *params.menu_page = MenuPage::PlayerSelect;
+                                        *status = default();
+                                    }
+                                    Ok(None) => (),
+                                    Err(err) => {
+                                        *status = Status::Idle;
+                                        params.commands.insert_resource(NetworkErrorNotice(err));
+                                    }
                                 }
                             }
 
@@ -332,8 +499,11 @@ impl<'w, 's> WidgetSystem for MatchmakingMenu<'w, 's> {
                                         &params.localization.get("player-count"),
                                     );
                                     ui.add_space(normal_text_style.size);
+                                    // At least one other player must join
+                                    let min_players = *local_player_count + 1;
+                                    *player_count = (*player_count).clamp(min_players, MAX_PLAYERS);
                                     ui.scope(|ui| {
-                                        ui.set_enabled(*player_count > 2);
+                                        ui.set_enabled(*player_count > min_players);
                                         if BorderedButton::themed(small_button_style, "-")
                                             .min_size(egui::vec2(normal_text_style.size * 2.0, 0.0))
                                             .show(ui)
@@ -341,7 +511,7 @@ impl<'w, 's> WidgetSystem for MatchmakingMenu<'w, 's> {
                                         {
                                             *player_count = player_count
                                                 .saturating_sub(1)
-                                                .clamp(2, MAX_PLAYERS);
+                                                .clamp(min_players, MAX_PLAYERS);
                                         }
                                     });
                                     ui.themed_label(normal_text_style, &player_count.to_string());
@@ -354,7 +524,7 @@ impl<'w, 's> WidgetSystem for MatchmakingMenu<'w, 's> {
                                         {
                                             *player_count = player_count
                                                 .saturating_add(1)
-                                                .clamp(2, MAX_PLAYERS);
+                                                .clamp(min_players, MAX_PLAYERS);
                                         }
                                     });
 
@@ -377,19 +547,38 @@ impl<'w, 's> WidgetSystem for MatchmakingMenu<'w, 's> {
                                 .show(ui)
                                 .clicked()
                                 {
-                                    *status = Status::Hosting;
-                                    lan::start_server(service_info.clone(), *player_count);
+                                    match lan::start_server(
+                                        service_info.clone(),
+                                        *player_count,
+                                        *local_player_count,
+                                    ) {
+                                        Ok(()) => {
+                                            *status = Status::Hosting;
+                                            *joined_players = *local_player_count;
+                                        }
+                                        Err(err) => {
+                                            params.commands.insert_resource(NetworkErrorNotice(err))
+                                        }
+                                    }
                                 }
 
                             // If we are hosting a match currently
                             } else if *status == Status::Hosting {
-                                if let Some(lan_socket) = lan::wait_players(joined_players, service_info) {
-                                    params.commands.insert_resource(NetworkMatchSocket(
-                                        Box::new(lan_socket),
-                                    ));
+                                match lan::wait_players(joined_players, joined_spectators, service_info) {
+                                    Ok(Some(lan_socket)) => {
+                                        NetworkMatchSocket::insert(
+                                            &mut params.commands,
+                                            Box::new(lan_socket),
+                                        );
 
-                                    *status = default();
-                                    *params.menu_page = MenuPage::PlayerSelect;
+                                        *status = default();
+                                        *params.menu_page = MenuPage::PlayerSelect;
+                                    }
+                                    Ok(None) => (),
+                                    Err(err) => {
+                                        *status = Status::Idle;
+                                        params.commands.insert_resource(NetworkErrorNotice(err));
+                                    }
                                 }
 
                                 ui.horizontal(|ui| {
@@ -409,11 +598,29 @@ impl<'w, 's> WidgetSystem for MatchmakingMenu<'w, 's> {
                                         &format!(
                                             "{} {} / {}",
                                             &params.localization.get("players"),
-                                            *joined_players + 1, // Add one to count the host.
+                                            *joined_players,
                                             player_count
                                         ),
                                     );
                                 });
+
+                                if let Ok(port) = lan::host_port() {
+                                    ui.themed_label(
+                                        normal_text_style,
+                                        &params
+                                            .localization
+                                            .get(&format!("host-port?port={port}")),
+                                    );
+                                }
+
+                                if *joined_spectators > 0 {
+                                    ui.themed_label(
+                                        normal_text_style,
+                                        &params.localization.get(&format!(
+                                            "spectators?count={joined_spectators}"
+                                        )),
+                                    );
+                                }
                             }
                         }
                     },
@@ -422,15 +629,20 @@ impl<'w, 's> WidgetSystem for MatchmakingMenu<'w, 's> {
                     MatchKind::Online(OnlineState {
                         player_count,
                         matchmaking_server,
+                        matchmaking_server_fingerprint,
                         mut search_state,
+                        lobby_code,
+                        lobby_code_input,
                     }) => {
                         // Get the matchmaking server from the settings.
                         if matchmaking_server.is_empty() {
-                            *matchmaking_server = params
+                            let settings = params
                                 .storage
                                 .get::<Settings>(Settings::STORAGE_KEY)
-                                .unwrap_or_else(|| params.game.default_settings.clone())
-                                .matchmaking_server;
+                                .unwrap_or_else(|| params.game.default_settings.clone());
+                            *matchmaking_server = settings.matchmaking_server;
+                            *matchmaking_server_fingerprint =
+                                settings.matchmaking_server_fingerprint;
                         }
 
                         ui.horizontal(|ui| {
@@ -440,27 +652,36 @@ impl<'w, 's> WidgetSystem for MatchmakingMenu<'w, 's> {
                                 &params.localization.get("player-count"),
                             );
 
+                            // Every client has the same number of local players in online matches,
+                            // so the player count goes up by our local player count.
+                            let step = *local_player_count;
+                            let min_players = step * 2;
+                            let max_players = MAX_PLAYERS / step * step;
+                            *player_count = (*player_count / step * step).clamp(min_players, max_players);
+
                             ui.scope(|ui| {
-                                ui.set_enabled(*player_count > 2);
+                                ui.set_enabled(*player_count > min_players);
                                 if BorderedButton::themed(small_button_style, "-")
                                     .min_size(egui::vec2(normal_text_style.size * 2.0, 0.0))
                                     .show(ui)
                                     .clicked()
                                 {
-                                    *player_count =
-                                        player_count.saturating_sub(1).clamp(2, MAX_PLAYERS);
+                                    *player_count = player_count
+                                        .saturating_sub(step)
+                                        .clamp(min_players, max_players);
                                 }
                             });
                             ui.themed_label(normal_text_style, &player_count.to_string());
                             ui.scope(|ui| {
-                                ui.set_enabled(*player_count < MAX_PLAYERS);
+                                ui.set_enabled(*player_count < max_players);
                                 if BorderedButton::themed(small_button_style, "+")
                                     .min_size(egui::vec2(normal_text_style.size * 2.0, 0.0))
                                     .show(ui)
                                     .clicked()
                                 {
-                                    *player_count =
-                                        player_count.saturating_add(1).clamp(2, MAX_PLAYERS);
+                                    *player_count = player_count
+                                        .saturating_add(step)
+                                        .clamp(min_players, max_players);
                                 }
                             });
                         });
@@ -468,18 +689,79 @@ impl<'w, 's> WidgetSystem for MatchmakingMenu<'w, 's> {
                         ui.add_space(normal_text_style.size);
 
                         if *status == Status::Idle {
-                            if BorderedButton::themed(
-                                small_button_style,
-                                &params.localization.get("search"),
-                            )
-                            .show(ui)
-                            .clicked()
-                            {
+                            // The lobby to search for, with `None` being the public queue
+                            let mut search_request = None;
+
+                            ui.horizontal(|ui| {
+                                if BorderedButton::themed(
+                                    small_button_style,
+                                    &params.localization.get("search"),
+                                )
+                                .show(ui)
+                                .clicked()
+                                {
+                                    search_request = Some(None);
+                                }
+
+                                if BorderedButton::themed(
+                                    small_button_style,
+                                    &params.localization.get("create-lobby"),
+                                )
+                                .show(ui)
+                                .clicked()
+                                {
+                                    search_request = Some(Some(LobbyCode::generate(
+                                        *player_count,
+                                        *local_player_count,
+                                    )));
+                                }
+                            });
+                            ui.add_space(normal_text_style.size / 2.0);
+
+                            ui.horizontal(|ui| {
+                                ui.themed_label(
+                                    normal_text_style,
+                                    &params.localization.get("lobby-code"),
+                                );
+                                ui.add(
+                                    egui::TextEdit::singleline(lobby_code_input)
+                                        .hint_text("ABCDE-4-1")
+                                        .font(normal_text_style.font_id()),
+                                );
+                                ui.scope(|ui| {
+                                    ui.set_enabled(!lobby_code_input.trim().is_empty());
+                                    if BorderedButton::themed(
+                                        small_button_style,
+                                        &params.localization.get("join-lobby"),
+                                    )
+                                    .show(ui)
+                                    .clicked()
+                                    {
+                                        match LobbyCode::parse(lobby_code_input) {
+                                            // The lobby decides the player counts
+                                            Ok(code) => {
+                                                *player_count = code.player_count;
+                                                *local_player_count = code.local_player_count;
+                                                search_request = Some(Some(code));
+                                            }
+                                            Err(err) => params
+                                                .commands
+                                                .insert_resource(NetworkErrorNotice(err)),
+                                        }
+                                    }
+                                });
+                            });
+
+                            if let Some(lobby) = search_request {
                                 *status = Status::Searching;
+                                *lobby_code = lobby.clone();
                                 ONLINE_MATCHMAKER
                                     .try_send(OnlineMatchmakerRequest::SearchForGame {
                                         addr: matchmaking_server.clone(),
+                                        fingerprint: matchmaking_server_fingerprint.clone(),
                                         player_count: *player_count,
+                                        local_player_count: *local_player_count,
+                                        lobby,
                                     })
                                     .unwrap();
                             }
@@ -493,19 +775,22 @@ impl<'w, 's> WidgetSystem for MatchmakingMenu<'w, 's> {
                                         search_state = SearchState::WaitingForPlayers(count)
                                     }
                                     OnlineMatchmakerResponse::GameStarting {
-                                        online_socket,
-                                        player_idx,
+                                        socket,
+                                        client_idx,
                                         player_count: _,
                                     } => {
-                                        info!(?player_idx, "Starting network game");
-                                        params.commands.insert_resource(NetworkMatchSocket(
-                                            Box::new(online_socket),
-                                        ));
+                                        info!(?client_idx, "Starting network game");
+                                        NetworkMatchSocket::insert(&mut params.commands, socket);
 
                                         *status = default();
                                         search_state = default();
                                         *params.menu_page = MenuPage::PlayerSelect;
                                     }
+                                    OnlineMatchmakerResponse::Error(err) => {
+                                        *status = Status::Idle;
+                                        search_state = default();
+                                        params.commands.insert_resource(NetworkErrorNotice(err));
+                                    }
                                 }
                             }
 
@@ -541,6 +826,27 @@ impl<'w, 's> WidgetSystem for MatchmakingMenu<'w, 's> {
                                     },
                                 );
                             });
+
+                            // Show the code for friends to join our private lobby
+                            if let Some(code) = lobby_code {
+                                let code = code.to_string();
+                                ui.add_space(normal_text_style.size / 2.0);
+                                ui.horizontal(|ui| {
+                                    ui.themed_label(
+                                        normal_text_style,
+                                        &params.localization.get(&format!("lobby-code-is?code={code}")),
+                                    );
+                                    if BorderedButton::themed(
+                                        small_button_style,
+                                        &params.localization.get("copy-to-clipboard"),
+                                    )
+                                    .show(ui)
+                                    .clicked()
+                                    {
+                                        ui.ctx().output_mut(|output| output.copied_text = code);
+                                    }
+                                });
+                            }
                         }
                     }
                 }
@@ -560,10 +866,12 @@ impl<'w, 's> WidgetSystem for MatchmakingMenu<'w, 's> {
 
                                 *status = Status::Idle;
                             }
+                            #[cfg(not(target_arch = "wasm32"))]
                             Status::Joining => {
                                 lan::leave_server();
                                 *status = Status::Idle;
                             }
+                            #[cfg(not(target_arch = "wasm32"))]
                             Status::Hosting => {
                                 if let Some(service_info) = host_info.take() {
                                     lan::stop_server(&service_info);
@@ -1,6 +1,8 @@
 use crate::loading::PlayerInputCollector;
-#[cfg(not(target_arch = "wasm32"))]
-use crate::networking::{NetworkMatchSocket, SocketTarget};
+use crate::networking::{
+    proto::{MatchSetupMessage, PeerVersion, PlayerSelectMessage},
+    MatchSetupInbox, NetworkMatchSocket, SocketTarget,
+};
 
 use bones_lib::prelude::{key, Key, KeyError};
 use rand::Rng;
@@ -24,14 +26,6 @@ pub struct PlayerSlot {
     pub is_ai: bool,
 }
 
-/// Network message that may be sent during player selection.
-#[derive(Serialize, Deserialize)]
-pub enum PlayerSelectMessage {
-    SelectPlayer(bones::Handle<PlayerMeta>),
-    SelectHat(Option<bones::Handle<HatMeta>>),
-    ConfirmSelection(bool),
-}
-
 #[derive(SystemParam)]
 pub struct PlayerSelectMenu<'w, 's> {
     game: Res<'w, GameMeta>,
@@ -40,8 +34,10 @@ pub struct PlayerSelectMenu<'w, 's> {
     keyboard_input: Res<'w, Input<KeyCode>>,
     player_select_state: ResMut<'w, PlayerSelectState>,
     menu_input: Query<'w, 's, &'static mut ActionState<MenuAction>>,
-    #[cfg(not(target_arch = "wasm32"))]
     network_socket: Option<Res<'w, NetworkMatchSocket>>,
+    match_setup_inbox: Option<ResMut<'w, MatchSetupInbox>>,
+    core: Res<'w, CoreMetaArc>,
+    map_assets: Res<'w, Assets<MapMeta>>,
 }
 
 impl<'w, 's> WidgetSystem for PlayerSelectMenu<'w, 's> {
@@ -53,11 +49,9 @@ impl<'w, 's> WidgetSystem for PlayerSelectMenu<'w, 's> {
         id: WidgetId,
         _: (),
     ) {
-        #[cfg_attr(target_arch = "wasm32", allow(unused_mut))]
         let mut params: PlayerSelectMenu = state.get_mut(world);
         let is_online = false;
 
-        #[cfg(not(target_arch = "wasm32"))]
         handle_match_setup_messages(&mut params);
 
         // Whether or not the continue button should be enabled
@@ -71,18 +65,27 @@ impl<'w, 's> WidgetSystem for PlayerSelectMenu<'w, 's> {
                 unconfirmed_players += 1;
             }
         }
-        let may_continue = ready_players >= 1 && unconfirmed_players == 0;
-
-        #[cfg(not(target_arch = "wasm32"))]
-        if let Some(socket) = &params.network_socket {
-            if may_continue {
-                // The first player picks the map
-                let is_waiting = socket.player_idx() != 0;
-
-                *params.menu_page = MenuPage::MapSelect { is_waiting };
+        let mut may_continue = ready_players >= 1 && unconfirmed_players == 0;
+
+        // The status of the network match setup, shown under the title.
+        let mut setup_status: Option<String> = None;
+        let mut setup_failed = false;
+        if let (Some(socket), Some(inbox)) = (&params.network_socket, &params.match_setup_inbox) {
+            if let Some(error) = inbox.error() {
+                setup_status = Some(params.localization.get(&error.localization_query()));
+                setup_failed = true;
+                may_continue = false;
+            } else if !inbox.is_verified(socket.client_count()) {
+                setup_status = Some(params.localization.get("verifying-players"));
+                may_continue = false;
             }
         }
 
+        // The network players meet in the lobby before the host picks the map
+        if params.network_socket.is_some() && may_continue {
+            *params.menu_page = MenuPage::Lobby;
+        }
+
         ui.vertical_centered(|ui| {
             let params: PlayerSelectMenu = state.get_mut(world);
 
@@ -103,6 +106,22 @@ impl<'w, 's> WidgetSystem for PlayerSelectMenu<'w, 's> {
                 bigger_text_style,
                 &params.localization.get("player-select-title"),
             );
+            if let Some(status) = &setup_status {
+                ui.themed_label(&params.game.ui_theme.font_styles.normal, status);
+            }
+            if let Some(count) = params
+                .network_socket
+                .as_ref()
+                .map(|x| x.spectator_count())
+                .filter(|x| *x > 0)
+            {
+                ui.themed_label(
+                    &params.game.ui_theme.font_styles.normal,
+                    &params
+                        .localization
+                        .get(&format!("spectators?count={count}")),
+                );
+            }
             ui.add_space(normal_button_style.font.size);
 
             ui.with_layout(egui::Layout::bottom_up(egui::Align::Center), |ui| {
@@ -141,7 +160,6 @@ impl<'w, 's> WidgetSystem for PlayerSelectMenu<'w, 's> {
                         } else {
                             *params.menu_page = MenuPage::Home;
 
-                            #[cfg(not(target_arch = "wasm32"))]
                             if let Some(socket) = params.network_socket {
                                 socket.close();
                             }
@@ -177,6 +195,11 @@ impl<'w, 's> WidgetSystem for PlayerSelectMenu<'w, 's> {
 
                 ui.add_space(normal_button_style.font.size);
 
+                // Players can't be selected if the match can't be set up.
+                if setup_failed {
+                    return;
+                }
+
                 ui.vertical_centered(|ui| {
                     let params: PlayerSelectMenu = state.get_mut(world);
 
@@ -199,25 +222,32 @@ impl<'w, 's> WidgetSystem for PlayerSelectMenu<'w, 's> {
     }
 }
 
-#[cfg(not(target_arch = "wasm32"))]
 fn handle_match_setup_messages(params: &mut PlayerSelectMenu) {
-    if let Some(socket) = &params.network_socket {
-        let datas: Vec<(usize, Vec<u8>)> = socket.recv_reliable();
-
-        for (player, data) in datas {
-            match postcard::from_bytes::<PlayerSelectMessage>(&data) {
-                Ok(message) => match message {
-                    PlayerSelectMessage::SelectPlayer(player_handle) => {
-                        params.player_select_state.slots[player].selected_player = player_handle;
-                    }
-                    PlayerSelectMessage::ConfirmSelection(confirmed) => {
-                        params.player_select_state.slots[player].confirmed = confirmed;
-                    }
-                    PlayerSelectMessage::SelectHat(hat) => {
-                        params.player_select_state.slots[player].selected_hat = hat;
-                    }
-                },
-                Err(e) => warn!("Ignoring network message that was not understood: {e}"),
+    let (Some(socket), Some(inbox)) = (&params.network_socket, &mut params.match_setup_inbox) else {
+        return;
+    };
+    inbox.poll(socket.0.as_ref(), || {
+        PeerVersion::new(&params.core, &params.map_assets)
+    });
+
+    for (client, message) in inbox.take_player_select() {
+        // Clients may only select for their own players
+        let player = message.player();
+        if socket.player_client(player) != Some(client) {
+            warn!(%client, %player, "Ignoring player selection for another client's player");
+            continue;
+        }
+        let slot = &mut params.player_select_state.slots[player];
+
+        match message {
+            PlayerSelectMessage::SelectPlayer { handle, .. } => {
+                slot.selected_player = handle;
+            }
+            PlayerSelectMessage::ConfirmSelection { confirmed, .. } => {
+                slot.confirmed = confirmed;
+            }
+            PlayerSelectMessage::SelectHat { hat, .. } => {
+                slot.selected_hat = hat;
             }
         }
     }
@@ -297,7 +327,6 @@ struct PlayerSelectPanel<'w, 's> {
             &'static InputMap<PlayerAction>,
         ),
     >,
-    #[cfg(not(target_arch = "wasm32"))]
     network_socket: Option<Res<'w, NetworkMatchSocket>>,
 }
 
@@ -312,56 +341,39 @@ impl<'w, 's> WidgetSystem for PlayerSelectPanel<'w, 's> {
     ) {
         let mut params: PlayerSelectPanel = state.get_mut(world);
 
-        #[cfg(target_arch = "wasm32")]
-        let is_network = false;
-        #[cfg(not(target_arch = "wasm32"))]
         let is_network = params.network_socket.is_some();
 
         let player_id = args;
 
-        let player_map = params
-            .players
-            .iter()
-            .find(|(player_idx, _, _)| player_idx.0 == player_id)
-            .unwrap()
-            .2;
-
-        #[cfg(not(target_arch = "wasm32"))]
         let dummy_actions = default();
         let (player_actions, player_action_map) = if is_network {
-            #[cfg(not(target_arch = "wasm32"))]
             if let Some(socket) = &params.network_socket {
-                let actions = if player_id == socket.player_idx() {
-                    params
+                // Our local players are controlled by the local inputs, in order
+                let local_players = socket.client_players(socket.client_idx());
+                if local_players.contains(&player_id) {
+                    let input_idx = player_id - local_players.start;
+                    let (_, actions, map) = params
                         .players
                         .iter()
-                        .find(|(player_idx, _, _)| player_idx.0 == 0)
-                        .unwrap()
-                        .1
+                        .find(|(player_idx, _, _)| player_idx.0 == input_idx)
+                        .unwrap();
+                    (actions, Some(get_player_actions(input_idx, map)))
                 } else {
-                    &dummy_actions
-                };
-                let map = None;
-                (actions, map)
+                    (&dummy_actions, None)
+                }
             } else {
                 unreachable!();
             }
-
-            #[cfg(target_arch = "wasm32")]
-            unreachable!()
         } else {
-            let actions = params
+            let (_, actions, map) = params
                 .players
                 .iter()
                 .find(|(player_idx, _, _)| player_idx.0 == player_id)
-                .unwrap()
-                .1;
-            let map = Some(get_player_actions(player_id, player_map));
-            (actions, map)
+                .unwrap();
+            (actions, Some(get_player_actions(player_id, map)))
         };
 
         let slot = &mut params.player_select_state.slots[player_id];
-        #[cfg(not(target_arch = "wasm32"))]
         if let Some(socket) = &params.network_socket {
             // Don't show panels for non-connected players.
             if player_id + 1 > socket.player_count() {
@@ -391,12 +403,13 @@ impl<'w, 's> WidgetSystem for PlayerSelectPanel<'w, 's> {
                 slot.confirmed = true;
             }
 
-            #[cfg(not(target_arch = "wasm32"))]
             if let Some(socket) = &params.network_socket {
-                socket.send_reliable(
+                socket.send_match_setup(
                     SocketTarget::All,
-                    &postcard::to_allocvec(&PlayerSelectMessage::ConfirmSelection(slot.confirmed))
-                        .unwrap(),
+                    &MatchSetupMessage::PlayerSelect(PlayerSelectMessage::ConfirmSelection {
+                        player: player_id,
+                        confirmed: slot.confirmed,
+                    }),
                 );
             }
         } else if player_actions.just_pressed(PlayerAction::Grab) {
@@ -410,12 +423,13 @@ impl<'w, 's> WidgetSystem for PlayerSelectPanel<'w, 's> {
                 slot.confirmed = false;
             }
 
-            #[cfg(not(target_arch = "wasm32"))]
             if let Some(socket) = &params.network_socket {
-                socket.send_reliable(
+                socket.send_match_setup(
                     SocketTarget::All,
-                    &postcard::to_allocvec(&PlayerSelectMessage::ConfirmSelection(slot.confirmed))
-                        .unwrap(),
+                    &MatchSetupMessage::PlayerSelect(PlayerSelectMessage::ConfirmSelection {
+                        player: player_id,
+                        confirmed: slot.confirmed,
+                    }),
                 );
             }
         } else if player_actions.just_pressed(PlayerAction::Move) {
@@ -453,12 +467,13 @@ impl<'w, 's> WidgetSystem for PlayerSelectPanel<'w, 's> {
                 };
                 *player_hat = next_idx.map(|idx| params.core.player_hats.get(idx).unwrap().clone());
 
-                #[cfg(not(target_arch = "wasm32"))]
                 if let Some(socket) = &params.network_socket {
-                    socket.send_reliable(
+                    socket.send_match_setup(
                         SocketTarget::All,
-                        &postcard::to_allocvec(&PlayerSelectMessage::SelectHat(player_hat.clone()))
-                            .unwrap(),
+                        &MatchSetupMessage::PlayerSelect(PlayerSelectMessage::SelectHat {
+                            player: player_id,
+                            hat: player_hat.clone(),
+                        }),
                     );
                 }
 
@@ -493,14 +508,13 @@ impl<'w, 's> WidgetSystem for PlayerSelectPanel<'w, 's> {
                     }
                 }
 
-                #[cfg(not(target_arch = "wasm32"))]
                 if let Some(socket) = &params.network_socket {
-                    socket.send_reliable(
+                    socket.send_match_setup(
                         SocketTarget::All,
-                        &postcard::to_allocvec(&PlayerSelectMessage::SelectPlayer(
-                            player_handle.clone(),
-                        ))
-                        .unwrap(),
+                        &MatchSetupMessage::PlayerSelect(PlayerSelectMessage::SelectPlayer {
+                            player: player_id,
+                            handle: player_handle.clone(),
+                        }),
                     );
                 }
             }
@@ -516,10 +530,9 @@ impl<'w, 's> WidgetSystem for PlayerSelectPanel<'w, 's> {
                 let smaller_font = &params.game.ui_theme.font_styles.smaller;
                 let heading_font = &params.game.ui_theme.font_styles.heading;
 
-                // Marker for current player in online matches
-                #[cfg(not(target_arch = "wasm32"))]
+                // Marker for local players in online matches
                 if let Some(socket) = &params.network_socket {
-                    if socket.player_idx() == player_id {
+                    if socket.player_is_local()[player_id] {
                         ui.vertical_centered(|ui| {
                             ui.themed_label(normal_font, &params.localization.get("you-marker"));
                         });
@@ -529,8 +542,6 @@ impl<'w, 's> WidgetSystem for PlayerSelectPanel<'w, 's> {
                 } else {
                     ui.add_space(normal_font.size);
                 }
-                #[cfg(target_arch = "wasm32")]
-                ui.add_space(normal_font.size);
 
                 if slot.active {
                     ui.vertical_centered(|ui| {
@@ -669,7 +680,7 @@ impl<'w, 's> WidgetSystem for PlayerSelectPanel<'w, 's> {
     }
 }
 
-fn player_image(
+pub(super) fn player_image(
     ui: &mut egui::Ui,
     player_meta: &PlayerMeta,
     hat_meta: Option<&HatMeta>,
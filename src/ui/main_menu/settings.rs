@@ -170,11 +170,14 @@ impl<'w, 's> WidgetSystem for SettingsMenu<'w, 's> {
 
                                 // Save new settings if settings button clicked
                                 if save_button.clicked() {
-                                    // Update in-memory settings
-                                    params.storage.set(
-                                        Settings::STORAGE_KEY,
-                                        params.modified_settings.0.as_ref().unwrap(),
+                                    let settings = params.modified_settings.0.as_ref().unwrap();
+                                    // Rebind the network endpoint if needed
+                                    #[cfg(not(target_arch = "wasm32"))]
+                                    crate::networking::set_endpoint_settings(
+                                        &settings.network_bind,
                                     );
+                                    // Update in-memory settings
+                                    params.storage.set(Settings::STORAGE_KEY, settings);
                                     // Persist to storage
                                     params.storage.save();
 
@@ -14,46 +14,308 @@ pub fn networking_settings_ui(
 
     ui.add_space(bigger_font.size);
 
+    let text_box = ui
+        .horizontal(|ui| {
+            ui.add_space(bigger_font.size * 2.0);
+            ui.themed_label(
+                bigger_font,
+                &format!("{}:", params.localization.get("matchmaking-server")),
+            );
+
+            if should_reset {
+                settings.matchmaking_server =
+                    params.game.default_settings.matchmaking_server.clone();
+            }
+
+            let text_box = ui.add(
+                egui::TextEdit::singleline(&mut settings.matchmaking_server)
+                    .font(normal_font.clone())
+                    .desired_width(ui.available_width() - bigger_font.size * 2.0),
+            );
+            params.adjacencies.text_boxes.insert(text_box.id);
+            text_box
+        })
+        .inner;
+
+    ui.add_space(normal_font.size);
+
+    // Matchmaking server certificate fingerprint
+    if should_reset {
+        settings.matchmaking_server_fingerprint = params
+            .game
+            .default_settings
+            .matchmaking_server_fingerprint
+            .clone();
+    }
+    let fingerprint_box = text_setting(
+        &params.game,
+        &params.localization,
+        ui,
+        "server-fingerprint",
+        &mut settings.matchmaking_server_fingerprint,
+    );
+    params.adjacencies.text_boxes.insert(fingerprint_box.id);
+
+    ui.add_space(normal_font.size);
+
+    // Forget known LAN hosts button
+    let forget_button = ui
+        .horizontal(|ui| {
+            ui.add_space(bigger_font.size * 2.0);
+            ui.themed_label(
+                bigger_font,
+                &format!("{}:", params.localization.get("known-lan-hosts")),
+            );
+
+            let button = BorderedButton::themed(
+                &params.game.ui_theme.button_styles.small,
+                &params.localization.get("forget"),
+            )
+            .show(ui);
+
+            #[cfg(not(target_arch = "wasm32"))]
+            if button.clicked() {
+                crate::networking::certs::forget_known_hosts();
+            }
+
+            button
+        })
+        .inner;
+
+    ui.add_space(normal_font.size);
+
+    // Network endpoint address and ports
+    if should_reset {
+        settings.network_bind = params.game.default_settings.network_bind.clone();
+    }
+    let bind_address_box = text_setting(
+        &params.game,
+        &params.localization,
+        ui,
+        "bind-address",
+        &mut settings.network_bind.address,
+    );
+    params.adjacencies.text_boxes.insert(bind_address_box.id);
+
+    ui.add_space(normal_font.size);
+
+    let bind_ports_box = text_setting(
+        &params.game,
+        &params.localization,
+        ui,
+        "bind-ports",
+        &mut settings.network_bind.ports,
+    );
+    params.adjacencies.text_boxes.insert(bind_ports_box.id);
+
+    ui.add_space(normal_font.size);
+
+    // Disconnect policy button
+    let policy_button = ui
+        .horizontal(|ui| {
+            ui.add_space(bigger_font.size * 2.0);
+            ui.themed_label(
+                bigger_font,
+                &format!("{}:", params.localization.get("disconnected-players")),
+            );
+
+            if should_reset {
+                settings.disconnect_policy = params.game.default_settings.disconnect_policy;
+            }
+
+            let label = match settings.disconnect_policy {
+                DisconnectPolicy::AiTakeover => "disconnect-policy-ai-takeover",
+                DisconnectPolicy::RemoveAtNextRound => "disconnect-policy-remove",
+            };
+            let button = BorderedButton::themed(
+                &params.game.ui_theme.button_styles.small,
+                &params.localization.get(label),
+            )
+            .show(ui);
+
+            if button.clicked() {
+                settings.disconnect_policy = match settings.disconnect_policy {
+                    DisconnectPolicy::AiTakeover => DisconnectPolicy::RemoveAtNextRound,
+                    DisconnectPolicy::RemoveAtNextRound => DisconnectPolicy::AiTakeover,
+                };
+            }
+
+            button
+        })
+        .inner;
+
+    ui.add_space(normal_font.size);
+
+    if should_reset {
+        settings.network_timing = params.game.default_settings.network_timing;
+    }
+
+    // Input delay button
+    let input_delay_button = timing_button(
+        &params.game,
+        &params.localization,
+        ui,
+        "input-delay",
+        &mut settings.network_timing.input_delay,
+        0..=NetworkTimingSettings::MAX_INPUT_DELAY,
+    );
+
+    ui.add_space(normal_font.size);
+
+    // Prediction window button
+    let prediction_window_button = timing_button(
+        &params.game,
+        &params.localization,
+        ui,
+        "prediction-window",
+        &mut settings.network_timing.max_prediction_window,
+        NetworkTimingSettings::MIN_PREDICTION_WINDOW..=NetworkTimingSettings::MAX_PREDICTION_WINDOW,
+    );
+
+    ui.add_space(normal_font.size);
+
+    // Network HUD button
+    let network_hud_button = ui
+        .horizontal(|ui| {
+            ui.add_space(bigger_font.size * 2.0);
+            ui.themed_label(
+                bigger_font,
+                &format!("{}:", params.localization.get("network-hud")),
+            );
+
+            if should_reset {
+                settings.show_network_hud = params.game.default_settings.show_network_hud;
+            }
+
+            let label = if settings.show_network_hud {
+                "shown"
+            } else {
+                "hidden"
+            };
+            let button = BorderedButton::themed(
+                &params.game.ui_theme.button_styles.small,
+                &params.localization.get(label),
+            )
+            .show(ui);
+
+            if button.clicked() {
+                settings.show_network_hud = !settings.show_network_hud;
+            }
+
+            button
+        })
+        .inner;
+
+    let first_bottom_button = bottom_buttons.iter().next().unwrap();
+    let last_bottom_button = bottom_buttons.iter().last().unwrap();
+    let first_top_tab = settings_tabs.iter().next().unwrap();
+    let last_top_tab = settings_tabs.iter().last().unwrap();
+
+    params
+        .adjacencies
+        .widget(&text_box)
+        .to_right_of(last_top_tab);
+    for tab in settings_tabs {
+        params.adjacencies.widget(&text_box).below(tab);
+        params.adjacencies.widget(tab).below(first_bottom_button);
+    }
+    params.adjacencies.widget(&fingerprint_box).below(&text_box);
+    params
+        .adjacencies
+        .widget(&forget_button)
+        .below(&fingerprint_box);
+    params
+        .adjacencies
+        .widget(&bind_address_box)
+        .below(&forget_button);
+    params
+        .adjacencies
+        .widget(&bind_ports_box)
+        .below(&bind_address_box);
+    params
+        .adjacencies
+        .widget(&policy_button)
+        .below(&bind_ports_box);
+    params
+        .adjacencies
+        .widget(&input_delay_button)
+        .below(&policy_button);
+    params
+        .adjacencies
+        .widget(&prediction_window_button)
+        .below(&input_delay_button);
+    params
+        .adjacencies
+        .widget(&network_hud_button)
+        .below(&prediction_window_button);
+    for button in bottom_buttons {
+        params.adjacencies.widget(button).below(&network_hud_button);
+    }
+    params
+        .adjacencies
+        .widget(&network_hud_button)
+        .above(first_bottom_button);
+    params
+        .adjacencies
+        .widget(last_bottom_button)
+        .to_left_of(first_top_tab);
+}
+
+/// Show a labeled text box for a text setting.
+fn text_setting(
+    game: &GameMeta,
+    localization: &Localization,
+    ui: &mut egui::Ui,
+    label: &str,
+    value: &mut String,
+) -> egui::Response {
+    let bigger_font = &game.ui_theme.font_styles.bigger;
+    let normal_font = &game.ui_theme.font_styles.normal;
+
     ui.horizontal(|ui| {
         ui.add_space(bigger_font.size * 2.0);
-        ui.themed_label(
-            bigger_font,
-            &format!("{}:", params.localization.get("matchmaking-server")),
-        );
+        ui.themed_label(bigger_font, &format!("{}:", localization.get(label)));
 
-        if should_reset {
-            settings.matchmaking_server = params.game.default_settings.matchmaking_server.clone();
-        }
-
-        let text_box = &ui.add(
-            egui::TextEdit::singleline(&mut settings.matchmaking_server)
+        ui.add(
+            egui::TextEdit::singleline(value)
                 .font(normal_font.clone())
                 .desired_width(ui.available_width() - bigger_font.size * 2.0),
-        );
-        let first_bottom_button = bottom_buttons.iter().next().unwrap();
-        let last_bottom_button = bottom_buttons.iter().last().unwrap();
-        let first_top_tab = settings_tabs.iter().next().unwrap();
-        let last_top_tab = settings_tabs.iter().last().unwrap();
-        params.adjacencies.text_boxes.insert(text_box.id);
-
-        params
-            .adjacencies
-            .widget(text_box)
-            .to_right_of(last_top_tab);
-        for tab in settings_tabs {
-            params.adjacencies.widget(text_box).below(tab);
-            params.adjacencies.widget(tab).below(first_bottom_button);
-        }
-        for button in bottom_buttons {
-            params.adjacencies.widget(button).below(text_box);
+        )
+    })
+    .inner
+}
+
+/// Show a button that cycles a network timing setting through automatic and each of the values in
+/// `range`.
+fn timing_button(
+    game: &GameMeta,
+    localization: &Localization,
+    ui: &mut egui::Ui,
+    label: &str,
+    value: &mut Option<usize>,
+    range: std::ops::RangeInclusive<usize>,
+) -> egui::Response {
+    let bigger_font = &game.ui_theme.font_styles.bigger;
+
+    ui.horizontal(|ui| {
+        ui.add_space(bigger_font.size * 2.0);
+        ui.themed_label(bigger_font, &format!("{}:", localization.get(label)));
+
+        let text = match value {
+            Some(frames) => frames.to_string(),
+            None => localization.get("automatic"),
+        };
+        let button = BorderedButton::themed(&game.ui_theme.button_styles.small, &text).show(ui);
+
+        if button.clicked() {
+            *value = match *value {
+                None => Some(*range.start()),
+                Some(frames) if frames < *range.end() => Some(frames + 1),
+                Some(_) => None,
+            };
         }
-        params
-            .adjacencies
-            .widget(text_box)
-            .above(first_bottom_button);
-        params
-            .adjacencies
-            .widget(last_bottom_button)
-            .to_left_of(first_top_tab);
-    });
+
+        button
+    })
+    .inner
 }
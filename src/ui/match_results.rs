@@ -0,0 +1,151 @@
+//! The results screen shown to everybody once the last round of a match is over.
+
+use bevy_egui::*;
+use bevy_fluent::Localization;
+use jumpy_core::{
+    elements::player_spawner::MatchOutcome, input::PlayerInputs, rounds::MatchRounds, MAX_PLAYERS,
+};
+
+use crate::{
+    networking::NetworkMatchSocket, prelude::*, session::is_match_over, widgets::EguiResponseExt,
+};
+
+use super::{
+    main_menu::MenuPage,
+    widgets::{bordered_button::BorderedButton, bordered_frame::BorderedFrame, EguiUiExt},
+};
+
+pub struct MatchResultsPlugin;
+
+impl Plugin for MatchResultsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(
+            match_results
+                .run_if(in_state(EngineState::InGame))
+                .run_if(in_state(InGameState::Playing))
+                .run_if(in_state(GameEditorState::Hidden))
+                .run_if(resource_exists::<Session>()),
+        );
+    }
+}
+
+/// Show who won the match, and let the player leave it.
+///
+/// Local matches can also be played again. Network matches can only be left, which closes the
+/// connection to the other players.
+#[allow(clippy::too_many_arguments)]
+fn match_results(
+    mut commands: Commands,
+    game: Res<GameMeta>,
+    localization: Res<Localization>,
+    socket: Option<Res<NetworkMatchSocket>>,
+    mut menu_page: ResMut<MenuPage>,
+    mut session_manager: SessionManager,
+    mut contexts: EguiContexts,
+) {
+    let Some(session) = session_manager.session.as_mut() else { return };
+    if !is_match_over(session) {
+        return;
+    }
+
+    let (outcome, wins) = session
+        .world()
+        .run_initialized_system(
+            |core_meta: bones::Res<CoreMetaArc>,
+             player_inputs: bones::Res<PlayerInputs>,
+             match_rounds: bones::Res<MatchRounds>| {
+                // The wins are only worth showing if there was more than one round
+                let wins = (core_meta.config.rounds.count > 1)
+                    .then(|| {
+                        (0..MAX_PLAYERS)
+                            .filter(|i| player_inputs.players[*i].active)
+                            .map(|i| (i, match_rounds.wins[i]))
+                            .collect::<Vec<_>>()
+                    })
+                    .unwrap_or_default();
+                Ok((match_rounds.outcome.unwrap(), wins))
+            },
+        )
+        .unwrap();
+
+    let ui_theme = &game.ui_theme;
+    let is_online = socket.is_some();
+
+    egui::Window::new("match_results")
+        .auto_sized()
+        .collapsible(false)
+        .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+        .frame(egui::Frame::none())
+        .title_bar(false)
+        .show(contexts.ctx_mut(), |ui| {
+            BorderedFrame::new(&ui_theme.panel.border)
+                .padding(ui_theme.panel.padding.into())
+                .show(ui, |ui| {
+                    ui.set_min_width(game.main_menu.menu_width);
+
+                    let heading_font = ui_theme
+                        .font_styles
+                        .heading
+                        .colored(ui_theme.panel.font_color);
+                    let normal_font = ui_theme
+                        .font_styles
+                        .normal
+                        .colored(ui_theme.panel.font_color);
+
+                    ui.vertical_centered(|ui| {
+                        let title = match outcome {
+                            MatchOutcome::Winner(player) => {
+                                localization.get(&format!("match-winner?player={}", player + 1))
+                            }
+                            MatchOutcome::NoWinner => localization.get("match-no-winner"),
+                        };
+                        ui.themed_label(&heading_font, &title);
+                        ui.add_space(normal_font.size);
+
+                        for (player, wins) in &wins {
+                            ui.themed_label(
+                                &normal_font,
+                                &localization
+                                    .get(&format!("player-wins?player={}&wins={wins}", player + 1)),
+                            );
+                        }
+                        if !wins.is_empty() {
+                            ui.add_space(normal_font.size);
+                        }
+
+                        let width = ui.available_width();
+
+                        if !is_online
+                            && BorderedButton::themed(
+                                &ui_theme.button_styles.normal,
+                                &localization.get("restart"),
+                            )
+                            .min_size(egui::vec2(width, 0.0))
+                            .show(ui)
+                            .clicked()
+                        {
+                            session_manager.restart();
+                        }
+
+                        if BorderedButton::themed(
+                            &ui_theme.button_styles.normal,
+                            &localization.get("main-menu"),
+                        )
+                        .min_size(egui::vec2(width, 0.0))
+                        .show(ui)
+                        .focus_by_default(ui)
+                        .clicked()
+                        {
+                            if let Some(socket) = &socket {
+                                socket.close();
+                                commands.remove_resource::<NetworkMatchSocket>();
+                            }
+                            session_manager.stop();
+                            *menu_page = MenuPage::Home;
+                            commands.insert_resource(NextState(Some(EngineState::MainMenu)));
+                            ui.ctx().clear_focus();
+                        }
+                    });
+                });
+        });
+}
@@ -0,0 +1,143 @@
+//! Dialog shown when a network match fails.
+
+use bevy_egui::*;
+use bevy_fluent::Localization;
+
+use crate::{
+    networking::{NetworkError, NetworkMatchSocket},
+    prelude::*,
+    widgets::EguiResponseExt,
+};
+
+use super::{
+    main_menu::MenuPage,
+    widgets::{bordered_button::BorderedButton, bordered_frame::BorderedFrame, EguiUiExt},
+};
+
+pub struct NetworkErrorPlugin;
+
+impl Plugin for NetworkErrorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems((
+            poll_network_errors.run_if(resource_exists::<NetworkMatchSocket>()),
+            network_error_dialog
+                .run_if(resource_exists::<NetworkErrorNotice>())
+                .run_if(resource_exists::<GameMeta>())
+                .after(poll_network_errors),
+        ));
+    }
+}
+
+/// Resource containing a network error that should be shown to the user.
+///
+/// While this resource exists, a dialog with the error is shown on top of the menu or the game.
+#[derive(Resource, Debug, Clone)]
+pub struct NetworkErrorNotice(pub NetworkError);
+
+/// Show the errors reported by the [`NetworkMatchSocket`].
+///
+/// Players disconnecting during a game are handled by the game session according to the
+/// [`DisconnectPolicy`], so they are not shown.
+fn poll_network_errors(
+    mut commands: Commands,
+    socket: Res<NetworkMatchSocket>,
+    notice: Option<Res<NetworkErrorNotice>>,
+    session: Option<Res<Session>>,
+) {
+    if let Some(error) = socket.take_error() {
+        warn!(%error, "Network match error");
+        if session.is_some() && matches!(error, NetworkError::PlayerDisconnected(_)) {
+            return;
+        }
+        if notice.is_none() {
+            commands.insert_resource(NetworkErrorNotice(error));
+        }
+    }
+}
+
+/// Render the network error dialog.
+///
+/// If the error happened during a network match, closing the dialog leaves the match and goes
+/// back to the main menu.
+#[allow(clippy::too_many_arguments)]
+fn network_error_dialog(
+    mut commands: Commands,
+    notice: Res<NetworkErrorNotice>,
+    game: Res<GameMeta>,
+    localization: Res<Localization>,
+    engine_state: Res<State<EngineState>>,
+    socket: Option<Res<NetworkMatchSocket>>,
+    mut menu_page: ResMut<MenuPage>,
+    mut session_manager: SessionManager,
+    mut contexts: EguiContexts,
+) {
+    let ui_theme = &game.ui_theme;
+    let in_match = socket.is_some() || session_manager.session.is_some();
+
+    egui::Window::new("network_error_dialog")
+        .auto_sized()
+        .collapsible(false)
+        .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+        .frame(egui::Frame::none())
+        .title_bar(false)
+        .show(contexts.ctx_mut(), |ui| {
+            BorderedFrame::new(&ui_theme.panel.border)
+                .padding(ui_theme.panel.padding.into())
+                .show(ui, |ui| {
+                    ui.set_max_width(game.main_menu.menu_width);
+
+                    let heading_font = ui_theme
+                        .font_styles
+                        .heading
+                        .colored(ui_theme.panel.font_color);
+                    let normal_font = ui_theme
+                        .font_styles
+                        .normal
+                        .colored(ui_theme.panel.font_color);
+                    let small_font = ui_theme
+                        .font_styles
+                        .smaller
+                        .colored(ui_theme.panel.font_color);
+
+                    ui.vertical_centered(|ui| {
+                        ui.themed_label(&heading_font, &localization.get("network-error"));
+                        ui.add_space(normal_font.size);
+                        ui.themed_label(
+                            &normal_font,
+                            &localization.get(&notice.0.localization_query()),
+                        );
+                        ui.themed_label(&small_font, &notice.0.to_string());
+                        ui.add_space(normal_font.size);
+
+                        let label = if in_match { "main-menu" } else { "close" };
+                        let button = BorderedButton::themed(
+                            &ui_theme.button_styles.normal,
+                            &localization.get(label),
+                        )
+                        .min_size(egui::vec2(ui.available_width(), 0.0))
+                        .show(ui)
+                        .focus_by_default(ui);
+
+                        if button.clicked() {
+                            commands.remove_resource::<NetworkErrorNotice>();
+
+                            if in_match {
+                                if let Some(socket) = &socket {
+                                    socket.close();
+                                    commands.remove_resource::<NetworkMatchSocket>();
+                                }
+                                session_manager.stop();
+                                *menu_page = MenuPage::Home;
+                                if engine_state.0 != EngineState::MainMenu {
+                                    commands
+                                        .insert_resource(NextState(Some(EngineState::MainMenu)));
+                                    commands.insert_resource(NextState(Some(InGameState::Playing)));
+                                }
+                            }
+
+                            ui.ctx().clear_focus();
+                        }
+                    });
+                });
+        });
+}